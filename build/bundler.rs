@@ -0,0 +1,173 @@
+use esbuild_client::{EsbuildServiceOptions, Format};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// esbuild release the bundle is built with. Must speak the protocol `esbuild_client` expects.
+pub const ESBUILD_VERSION: &str = "0.25.5";
+
+/// The `[build]` table of pkg.toml, settings for bundling a project.
+#[derive(Debug, Default, Deserialize)]
+pub struct BuildConfig {
+    /// Identifiers replaced at build time. Strings become string literals,
+    /// other values are inserted as they are.
+    #[serde(default)]
+    define: BTreeMap<String, toml::Value>,
+    /// Imports left in the bundle for the runtime to resolve, e.g. `node:*`
+    /// builtins or native packages that can't be inlined.
+    #[serde(default)]
+    external: Vec<String>,
+    /// `browser`, `node` or `neutral`; esbuild defaults to `browser`.
+    platform: Option<String>,
+    /// Syntax targets such as `es2022` or `deno2`.
+    #[serde(default)]
+    target: Vec<String>,
+    /// Extra package.json `exports` conditions to resolve with.
+    #[serde(default)]
+    conditions: Vec<String>,
+}
+
+fn target_flags(config: &BuildConfig) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut flags: Vec<String> = config
+        .external
+        .iter()
+        .map(|name| format!("--external:{name}"))
+        .collect();
+
+    if let Some(platform) = &config.platform {
+        if !["browser", "node", "neutral"].contains(&platform.as_str()) {
+            return Err(format!("[build] platform must be browser, node or neutral, got {platform:?}").into());
+        }
+        flags.push(format!("--platform={platform}"));
+    }
+
+    if !config.target.is_empty() {
+        flags.push(format!("--target={}", config.target.join(",")));
+    }
+
+    if !config.conditions.is_empty() {
+        flags.push(format!("--conditions={}", config.conditions.join(",")));
+    }
+
+    Ok(flags)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PkgToml {
+    #[serde(default)]
+    pub build: BuildConfig,
+}
+
+/// esbuild `--define` flags: built-in build metadata, then pkg.toml's
+/// `[build.define]`, then `MASS_DEFINE` (`KEY=VALUE,…`, values as strings).
+pub fn define_flags(config: &BuildConfig, manifest_dir: &Path) -> Vec<String> {
+    let mut defines = BTreeMap::new();
+
+    defines.insert("process.env.NODE_ENV".to_string(), "\"production\"".to_string());
+    defines.insert(
+        "MASS_BUILD_VERSION".to_string(),
+        serde_json::to_string(env!("CARGO_PKG_VERSION")).unwrap(),
+    );
+
+    let git_sha = std::process::Command::new("git")
+        .arg("-C")
+        .arg(manifest_dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    let git_sha = git_sha.map_or("undefined".to_string(), |sha| serde_json::to_string(&sha).unwrap());
+    defines.insert("MASS_GIT_SHA".to_string(), git_sha);
+
+    for (key, value) in &config.define {
+        let value = match value {
+            toml::Value::String(value) => serde_json::to_string(value).unwrap(),
+            value => value.to_string(),
+        };
+        defines.insert(key.clone(), value);
+    }
+
+    if let Ok(env) = std::env::var("MASS_DEFINE") {
+        for (key, value) in env.split(',').filter_map(|pair| pair.split_once('=')) {
+            defines.insert(key.trim().to_string(), serde_json::to_string(value).unwrap());
+        }
+    }
+
+    defines
+        .into_iter()
+        .map(|(key, value)| format!("--define:{key}={value}"))
+        .collect()
+}
+
+/// Where the build installs this release of esbuild (native, or esbuild-wasm
+/// under `esbuild-wasm/`), in the mass cache directory so the binary finds it
+/// wherever it runs from.
+pub fn esbuild_dir() -> Option<PathBuf> { Some(crate::npm::cache_root()?.join("esbuild").join(ESBUILD_VERSION)) }
+
+/// The esbuild the build installed, or else one on `PATH`.
+pub fn esbuild() -> Option<PathBuf> {
+    let name = if cfg!(windows) { "esbuild.exe" } else { "esbuild" };
+
+    if let Some(dir) = esbuild_dir() {
+        let installed = dir.join(name);
+        if installed.is_file() {
+            return Some(installed);
+        }
+
+        let wasm = dir.join("esbuild-wasm/bin/esbuild");
+        if wasm.is_file() {
+            return Some(wasm);
+        }
+    }
+
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Flags for bundling the project at `dir` into ESM, configured by the
+/// `[build]` table of its pkg.toml when it has one.
+pub fn flags(dir: &Path, minify: bool) -> Result<Vec<String>, Box<dyn Error>> {
+    let pkg: PkgToml = match std::fs::read_to_string(dir.join("pkg.toml")) {
+        Ok(contents) => toml::from_str(&contents)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => PkgToml::default(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut flags = esbuild_client::EsbuildFlagsBuilder::default()
+        .bundle(true)
+        .minify(minify)
+        .format(Format::Esm)
+        .build_with_defaults();
+    flags.extend(define_flags(&pkg.build, dir));
+    flags.extend(target_flags(&pkg.build)?);
+    Ok(flags)
+}
+
+/// Runs `entries`, (output path, entry point) pairs, through an esbuild
+/// service started from `esbuild`, returning what it wrote.
+pub async fn bundle(
+    esbuild: &Path, flags: Vec<String>, entries: Vec<(String, String)>,
+) -> Result<Vec<esbuild_client::protocol::BuildOutputFile>, Box<dyn Error>> {
+    let service = esbuild_client::EsbuildService::new(
+        esbuild.to_path_buf(),
+        ESBUILD_VERSION,
+        None,
+        EsbuildServiceOptions::default(),
+    )
+    .await?;
+
+    let response = service
+        .client()
+        .send_build_request(esbuild_client::protocol::BuildRequest {
+            flags,
+            entries,
+            ..Default::default()
+        })
+        .await?
+        .map_err(|err| format!("esbuild failed: {err:?}"))?;
+
+    Ok(response.output_files.unwrap_or_default())
+}
@@ -1,35 +1,136 @@
+mod bundler;
 mod esbuild;
 mod npm;
+mod typings;
 
 use std::{env, error::Error};
 include!("../mass/modules.rs");
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    if Ok("release".to_owned()) == env::var("PROFILE") {
-        let o = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
-        let snapshot_path = o.join("mass/runtime/snapshot");
+    let o = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let snapshot_path = o.join("mass/runtime/snapshot");
 
-        esbuild::bundle_server().await?;
-        create_snapshot(snapshot_path.join("RUNTIME.bin"));
+    // Installed whether or not the bundle is rebuilt: the binary runs it for
+    // `mass bundle` and `mass dev`.
+    let esbuild = esbuild::install().await?;
 
-        println!("cargo:rerun-if-changed=../mass/worker");
-        println!("cargo:rerun-if-changed=../mass/server");
-        println!("cargo:rerun-if-changed=../mass/runtime");
-        println!("cargo:rerun-if-changed=../mass/modules.rs");
+    // Bundling and snapshotting dominate build times, so both are skipped when
+    // none of their inputs changed since the last run.
+    let release = Ok("release".to_owned()) == env::var("PROFILE");
+    let stamp = snapshot_path.join("RUNTIME.inputs");
+    let inputs = || esbuild::inputs_hash(&o).map(|hash| format!("{release} {hash}"));
+    let up_to_date = ["RUNTIME.bin", "RUNTIME.json", "server.min.js"]
+        .iter()
+        .all(|output| snapshot_path.join(output).exists())
+        && std::fs::read_to_string(&stamp).is_ok_and(|previous| previous == inputs().unwrap_or_default());
+
+    if !up_to_date {
+        // Debug builds bundle the server too, so `cargo run` works, but skip the
+        // snapshot and let the runtime cold-initialize its extensions instead.
+        esbuild::bundle_server(&esbuild).await?;
+        if release {
+            create_snapshot(snapshot_path.join("RUNTIME.bin"), snapshot_target()?);
+            write_snapshot_metadata(snapshot_path.join("RUNTIME.json"), true)?;
+        } else {
+            if !snapshot_path.join("RUNTIME.bin").exists() {
+                std::fs::write(snapshot_path.join("RUNTIME.bin"), b"")?;
+            }
+            write_snapshot_metadata(snapshot_path.join("RUNTIME.json"), false)?;
+        }
+
+        // Hashed again since installing may have rewritten pkg.lock.
+        std::fs::write(&stamp, inputs()?)?;
     }
 
+    typings::generate(&o)?;
+
+    // The binary is built with `runtime` set and this script without it, so
+    // what only the binary uses in the module tree both include is gated on it.
+    println!("cargo:rustc-cfg=runtime");
+    println!("cargo:rerun-if-env-changed=PROFILE");
+    println!("cargo:rerun-if-changed=../mass/worker");
+    println!("cargo:rerun-if-changed=../mass/server");
+    println!("cargo:rerun-if-changed=../mass/runtime");
+    println!("cargo:rerun-if-changed=../mass/modules.rs");
+    println!("cargo:rerun-if-changed=../mass/modules");
+    println!("cargo:rerun-if-env-changed=MASS_FROZEN_LOCKFILE");
+    println!("cargo:rerun-if-env-changed=MASS_STORE_DIR");
+    println!("cargo:rerun-if-env-changed=MASS_NPM_REGISTRY");
+    println!("cargo:rerun-if-env-changed=MASS_NPM_TOKEN");
+    println!("cargo:rerun-if-env-changed=MASS_NPM_PRUNE");
+    println!("cargo:rerun-if-env-changed=MASS_NPM_VERIFY");
+    println!("cargo:rerun-if-env-changed=MASS_NPM_OFFLINE");
+    println!("cargo:rerun-if-env-changed=MASS_ESBUILD_INTEGRITY");
+    println!("cargo:rerun-if-env-changed=MASS_DEFINE");
+    println!("cargo:rerun-if-env-changed=MASS_ESBUILD_WASM");
+
     Ok(())
 }
 
-fn create_snapshot(snapshot_path: std::path::PathBuf) {
+/// Targets the runtime snapshot can be generated for. The snapshot is created
+/// by running V8 inside this build script, so it comes out for the host's
+/// architecture: cross builds work between targets sharing it (for example
+/// `x86_64-unknown-linux-gnu` to `x86_64-unknown-linux-musl`), not across them.
+const SNAPSHOT_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-gnu",
+    "aarch64-unknown-linux-musl",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "aarch64-pc-windows-msvc",
+];
+
+/// The target to generate the snapshot for, checked against the host.
+fn snapshot_target() -> Result<String, Box<dyn Error>> {
+    let target = env::var("TARGET")?;
+    let host = env::var("HOST")?;
+
+    if !SNAPSHOT_TARGETS.contains(&target.as_str()) {
+        return Err(format!(
+            "no runtime snapshot for {target}, supported targets: {}",
+            SNAPSHOT_TARGETS.join(", ")
+        )
+        .into());
+    }
+
+    let arch = |triple: &str| triple.split('-').next().unwrap_or_default().to_string();
+    if arch(&target) != arch(&host) {
+        return Err(format!(
+            "can't generate a {target} snapshot on {host}, V8 snapshots are specific to the architecture they're built on"
+        )
+        .into());
+    }
+
+    Ok(target)
+}
+
+fn create_snapshot(snapshot_path: std::path::PathBuf, target: String) {
     use deno_runtime::ops::bootstrap::SnapshotOptions;
 
     let snapshot_options = SnapshotOptions {
         ts_version: "5.9.2".to_string(),
         v8_version: deno_runtime::deno_core::v8::VERSION_STRING,
-        target: std::env::var("TARGET").unwrap(),
+        target,
     };
 
-    deno_runtime::snapshot::create_runtime_snapshot(snapshot_path, snapshot_options, init_extension());
+    deno_runtime::snapshot::create_runtime_snapshot(
+        snapshot_path,
+        snapshot_options,
+        init_extension(Default::default()),
+    );
+}
+
+/// Records what RUNTIME.bin was built against; a `null` fingerprint marks a
+/// build that has no usable snapshot at all.
+fn write_snapshot_metadata(metadata_path: std::path::PathBuf, snapshot: bool) -> Result<(), Box<dyn Error>> {
+    let metadata = serde_json::json!({
+        "v8": deno_runtime::deno_core::v8::VERSION_STRING,
+        "fingerprint": snapshot.then(fingerprint),
+    });
+
+    std::fs::write(metadata_path, serde_json::to_vec_pretty(&metadata)?)?;
+    Ok(())
 }
@@ -1,112 +1,389 @@
-use esbuild_client::{EsbuildServiceOptions, Format};
+use crate::bundler::{self, ESBUILD_VERSION, PkgToml};
 use flate2::read::GzDecoder;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::error::Error;
 use std::fs::File;
-use std::io::Cursor;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
 use std::{collections::BTreeMap, fs};
 use tar::Archive;
 
-#[derive(Debug, Deserialize)]
-struct Config {
-    dependencies: BTreeMap<String, String>,
+const DEPENDENCIES: &str = include_str!("../mass/server/pkg.toml");
+
+/// `mass/server/esbuild.lock`: sha256 of the esbuild binary per platform, recorded
+/// on first download and checked on every one after that.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct EsbuildLock {
+    version: String,
+    #[serde(default)]
+    sha256: BTreeMap<String, String>,
+}
+
+/// The `@esbuild/*` package for the machine running the build, or `None` when
+/// esbuild ships no native binary for it (or `MASS_ESBUILD_WASM` asks for the
+/// wasm build). Cross builds still run esbuild on the host, so this follows
+/// `HOST`, never `TARGET`.
+fn esbuild_platform() -> Option<&'static str> {
+    if std::env::var_os("MASS_ESBUILD_WASM").is_some_and(|v| v != "0") {
+        return None;
+    }
+
+    let host = std::env::var("HOST").unwrap();
+    let arch = host.split('-').next().unwrap_or_default();
+    let os = ["apple-darwin", "linux", "freebsd", "netbsd", "openbsd", "windows"]
+        .into_iter()
+        .find(|os| host.contains(os))?;
+
+    Some(match (os, arch) {
+        ("apple-darwin", "x86_64") => "darwin-x64",
+        ("apple-darwin", "aarch64") => "darwin-arm64",
+        ("linux", "x86_64") => "linux-x64",
+        ("linux", "aarch64") => "linux-arm64",
+        ("linux", "arm" | "armv7") => "linux-arm",
+        ("linux", "i586" | "i686") => "linux-ia32",
+        ("linux", "riscv64gc") => "linux-riscv64",
+        ("linux", "powerpc64le") => "linux-ppc64",
+        ("linux", "s390x") => "linux-s390x",
+        ("linux", "loongarch64") => "linux-loong64",
+        ("freebsd", "x86_64") => "freebsd-x64",
+        ("freebsd", "aarch64") => "freebsd-arm64",
+        ("netbsd", "x86_64") => "netbsd-x64",
+        ("openbsd", "x86_64") => "openbsd-x64",
+        ("windows", "x86_64") => "win32-x64",
+        ("windows", "aarch64") => "win32-arm64",
+        ("windows", "i686") => "win32-ia32",
+        _ => return None,
+    })
+}
+
+fn installed_esbuild_version(esbuild_path: &Path) -> Option<String> {
+    let output = std::process::Command::new(esbuild_path)
+        .arg("--version")
+        .output()
+        .ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Downloads an esbuild package tarball and checks it against the integrity the
+/// registry publishes, plus `MASS_ESBUILD_INTEGRITY` when that pins an SRI hash.
+async fn fetch_esbuild_package(
+    package: &str, registries: &crate::npm::Registries,
+) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let registry = registries.registry_for(package);
+
+    let meta_url = format!("{registry}{}/{ESBUILD_VERSION}", package.replace('/', "%2f"));
+    let client = Client::new();
+    let meta: serde_json::Value = registries
+        .authorize(client.get(&meta_url), &meta_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let tgz_url = meta["dist"]["tarball"]
+        .as_str()
+        .ok_or_else(|| format!("{meta_url} has no dist.tarball"))?
+        .to_string();
+    let integrity = meta["dist"]["integrity"]
+        .as_str()
+        .ok_or_else(|| format!("{meta_url} has no dist.integrity, refusing to run an unverified esbuild"))?;
+    println!("Downloading {}", tgz_url);
+
+    let bytes = registries
+        .authorize(client.get(&tgz_url), &tgz_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    crate::npm::verify_integrity(&tgz_url, &bytes, integrity)?;
+    if let Ok(pinned) = std::env::var("MASS_ESBUILD_INTEGRITY") {
+        crate::npm::verify_integrity(&tgz_url, &bytes, &pinned)?;
+    }
+
+    Ok((tgz_url, bytes.to_vec()))
 }
 
-const ESBUILD_URL: &'static str = "https://registry.npmjs.org/esbuild/latest";
-const DEPENDENCIES: &'static str = include_str!("../mass/server/pkg.toml");
+/// Checks `contents` against the checksum recorded for `key`, recording it if
+/// this is the first download for that key.
+fn check_recorded_checksum(lock_path: &Path, key: &str, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let checksum = hex::encode(Sha256::digest(contents));
+
+    let mut lock: EsbuildLock = match fs::read_to_string(lock_path) {
+        Ok(text) => toml::from_str(&text)?,
+        Err(_) => EsbuildLock::default(),
+    };
+    if lock.version != ESBUILD_VERSION {
+        lock = EsbuildLock {
+            version: ESBUILD_VERSION.to_string(),
+            ..Default::default()
+        };
+    }
+
+    match lock.sha256.get(key) {
+        Some(expected) if *expected != checksum => {
+            Err(format!("esbuild {ESBUILD_VERSION} for {key} has sha256 {checksum}, expected {expected}").into())
+        }
+        Some(_) => Ok(()),
+        None => {
+            println!(
+                "Recording esbuild {ESBUILD_VERSION} checksum for {key} in {}",
+                lock_path.display()
+            );
+            lock.sha256.insert(key.to_string(), checksum);
+            fs::write(lock_path, toml::to_string(&lock)?)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = fs::metadata(path)?.permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(path, perms)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), Box<dyn Error>> { Ok(()) }
+
+async fn install_esbuild(
+    esbuild_path: &Path, lock_path: &Path, registries: &crate::npm::Registries, platform: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (tgz_url, bytes) = fetch_esbuild_package(&format!("@esbuild/{platform}"), registries).await?;
+
+    let cursor = Cursor::new(bytes);
+    let tar = GzDecoder::new(cursor);
+    let mut archive = Archive::new(tar);
+    let mut binary = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
+
+        if path.to_string_lossy().ends_with("bin/esbuild") || path.to_string_lossy().ends_with("bin/esbuild.exe") {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            binary = Some(contents);
+        }
+    }
+
+    let binary = binary.ok_or_else(|| format!("{tgz_url} does not contain an esbuild binary"))?;
+    check_recorded_checksum(lock_path, platform, &binary)?;
+
+    let mut file = File::create(esbuild_path)?;
+    file.write_all(&binary)?;
+    drop(file);
+
+    make_executable(esbuild_path)
+}
+
+/// Installs `esbuild-wasm` into `dir` for hosts without a native binary. Its
+/// `bin/esbuild` runs the same esbuild compiled to wasm under node, so it speaks
+/// the protocol `esbuild_client` expects, only slower.
+async fn install_esbuild_wasm(
+    dir: &Path, lock_path: &Path, registries: &crate::npm::Registries,
+) -> Result<(), Box<dyn Error>> {
+    let node = std::process::Command::new("node").arg("--version").output();
+    if !node.is_ok_and(|output| output.status.success()) {
+        return Err("no native esbuild for this platform and no node on PATH to run esbuild-wasm with".into());
+    }
 
-pub async fn bundle_server() -> Result<(), Box<dyn Error>> {
-    let o = std::path::PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    let (tgz_url, bytes) = fetch_esbuild_package("esbuild-wasm", registries).await?;
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+
+    let mut archive = Archive::new(GzDecoder::new(Cursor::new(bytes)));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let rel: std::path::PathBuf = entry.path()?.components().skip(1).collect();
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+
+        let out = dir.join(rel);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(out)?;
+    }
+
+    let wasm = fs::read(dir.join("esbuild.wasm")).map_err(|err| format!("{tgz_url} has no esbuild.wasm: {err}"))?;
+    check_recorded_checksum(lock_path, "wasm", &wasm)?;
+
+    make_executable(&dir.join("bin/esbuild"))
+}
+
+/// Directories and files the bundle and snapshot are built from, relative to
+/// the manifest directory.
+const INPUTS: &[&str] = &[
+    "mass/server",
+    "mass/runtime",
+    "mass/worker",
+    "mass/modules",
+    "mass/modules.rs",
+];
+
+/// Hash of everything that goes into the bundle and the snapshot: the sources
+/// under [`INPUTS`], the esbuild version, the target, and the defines the
+/// bundle is built with. Installed packages and build outputs are left out.
+pub fn inputs_hash(manifest_dir: &Path) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    hasher.update(ESBUILD_VERSION);
+    hasher.update(std::env::var("TARGET").unwrap_or_default());
+
+    let pkg: PkgToml = toml::from_str(DEPENDENCIES)?;
+    for flag in bundler::define_flags(&pkg.build, manifest_dir) {
+        hasher.update(flag);
+        hasher.update([0]);
+    }
+
+    for input in INPUTS {
+        hash_tree(&mut hasher, manifest_dir, &manifest_dir.join(input))?;
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn hash_tree(hasher: &mut Sha256, root: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    if matches!(name, "node_modules" | "snapshot") {
+        return Ok(());
+    }
+
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        for entry in entries {
+            hash_tree(hasher, root, &entry)?;
+        }
+    } else if path.is_file() {
+        hasher.update(path.strip_prefix(root).unwrap_or(path).to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(path)?);
+    }
+
+    Ok(())
+}
+
+/// Installs this release of esbuild into [`bundler::esbuild_dir`] unless it's
+/// there already, returning the binary to run.
+pub async fn install() -> Result<std::path::PathBuf, Box<dyn Error>> {
     let m = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
 
-    let resp = reqwest::get(ESBUILD_URL).await?;
-    let body: serde_json::Value = resp.json().await?;
-
-    let version = body["version"].as_str().unwrap();
-    let esbuild_path = o.join(if cfg!(windows) { "esbuild.exe" } else { "esbuild" });
-
-    if !esbuild_path.exists() {
-        let platform = match (std::env::consts::OS, std::env::consts::ARCH) {
-            ("macos", "x86_64") => "darwin-x64",
-            ("macos", "aarch64") => "darwin-arm64",
-            ("linux", "x86_64") => "linux-x64",
-            ("linux", "aarch64") => "linux-arm64",
-            ("linux", "arm") => "linux-arm",
-            ("windows", "x86_64") => "win32-x64",
-            ("windows", "aarch64") => "win32-arm64",
-            ("windows", "x86") => "win32-ia32",
-            other => {
-                eprintln!("Unsupported platform: {:?}", other);
-                std::process::exit(1);
+    let version = ESBUILD_VERSION;
+    let lock_path = m.join("mass/server/esbuild.lock");
+    let registries = crate::npm::Registries::load(&m)?;
+
+    let esbuild_dir = bundler::esbuild_dir().ok_or("no cache directory to install esbuild into")?;
+    fs::create_dir_all(&esbuild_dir)?;
+    let esbuild_path = match esbuild_platform() {
+        Some(platform) => {
+            let path = esbuild_dir.join(if cfg!(windows) { "esbuild.exe" } else { "esbuild" });
+            if installed_esbuild_version(&path).as_deref() != Some(version) {
+                install_esbuild(&path, &lock_path, &registries, platform).await?;
             }
-        };
+            path
+        }
+        None => {
+            println!(
+                "cargo:warning=no native esbuild for {}-{}, bundling with esbuild-wasm",
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            );
 
-        let tgz_url = format!(
-            "https://registry.npmjs.org/@esbuild/{}/-/{}-{}.tgz",
-            platform, platform, version
-        );
-        println!("Downloading {}", tgz_url);
-
-        let client = Client::new();
-        let bytes = client.get(&tgz_url).send().await?.bytes().await?;
-        let cursor = Cursor::new(bytes);
-
-        let tar = GzDecoder::new(cursor);
-        let mut archive = Archive::new(tar);
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
-
-            if path.to_string_lossy().ends_with("bin/esbuild") || path.to_string_lossy().ends_with("bin/esbuild.exe") {
-                let mut file = File::create(&esbuild_path)?;
-                std::io::copy(&mut entry, &mut file)?;
-
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    let mut perms = file.metadata()?.permissions();
-                    perms.set_mode(0o755);
-                    std::fs::set_permissions(&esbuild_path, perms)?;
-                }
+            let dir = esbuild_dir.join("esbuild-wasm");
+            let path = dir.join("bin/esbuild");
+            if installed_esbuild_version(&path).as_deref() != Some(version) {
+                install_esbuild_wasm(&dir, &lock_path, &registries).await?;
             }
+            path
         }
-    }
+    };
+    Ok(esbuild_path)
+}
+
+pub async fn bundle_server(esbuild_path: &Path) -> Result<(), Box<dyn Error>> {
+    let m = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let registries = crate::npm::Registries::load(&m)?;
 
     let dist = m.join("mass/runtime/snapshot");
     let node_modules = m.join("mass/server/node_modules");
 
-    let cfg: Config = toml::from_str(DEPENDENCIES)?;
-    let roots = cfg.dependencies.into_iter().map(|(name, spec)| (name, spec));
-
-    crate::npm::install_all_packages(&reqwest::Client::new(), &node_modules, roots).await?;
-
-    let esbuild =
-        esbuild_client::EsbuildService::new(esbuild_path, version, None, EsbuildServiceOptions::default()).await?;
-    let flags = esbuild_client::EsbuildFlagsBuilder::default()
-        .bundle(true)
-        .minify(true)
-        .format(Format::Esm)
-        .build_with_defaults();
-
-    let response = esbuild
-        .client()
-        .send_build_request(esbuild_client::protocol::BuildRequest {
-            flags,
-            entries: vec![(
-                dist.join("server.min.js").to_string_lossy().into(),
-                "mass/server/index.ts".into(),
-            )],
-            ..Default::default()
-        })
-        .await?;
+    let cfg = crate::npm::Manifest::parse(DEPENDENCIES)?;
+    let members = cfg.members(&m.join("mass/server"))?;
+    let roots = cfg.dependencies;
+
+    let install = crate::npm::InstallOptions {
+        lockfile: m.join("mass/server/pkg.lock"),
+        frozen: std::env::var_os("MASS_FROZEN_LOCKFILE").is_some_and(|v| v != "0"),
+        store: crate::npm::Store::default_root(),
+        metadata_cache: crate::npm::InstallOptions::default_metadata_cache(),
+        registries: registries.clone(),
+        project: m.join("mass/server"),
+        import: ["package-lock.json", "yarn.lock"]
+            .into_iter()
+            .map(|name| m.join("mass/server").join(name))
+            .find(|path| path.exists()),
+        allow_scripts: cfg
+            .scripts
+            .allow
+            .into_iter()
+            .chain(
+                members
+                    .iter()
+                    .flat_map(|member| member.manifest.scripts.allow.iter().cloned()),
+            )
+            .collect(),
+        prune: std::env::var_os("MASS_NPM_PRUNE").is_some_and(|v| v != "0"),
+        verify: std::env::var_os("MASS_NPM_VERIFY").is_some_and(|v| v != "0"),
+        offline: std::env::var_os("MASS_NPM_OFFLINE").is_some_and(|v| v != "0"),
+        members: members
+            .iter()
+            .map(|member| (member.dir.clone(), member.manifest.dependencies.clone()))
+            .collect(),
+    };
 
-    let output_files = response.unwrap().output_files.unwrap();
-    let output_content = String::from_utf8(output_files[0].contents.clone())?;
+    crate::npm::install_all_packages(&reqwest::Client::new(), &node_modules, roots, &install).await?;
+
+    let mut flags = bundler::flags(&m.join("mass/server"), true)?;
+    // The map ships next to the bundle and is embedded in the binary, which maps
+    // stack frames itself, so the bundle carries no sourceMappingURL comment.
+    flags.push("--sourcemap=external".into());
+
+    // Every workspace member gets its own bundle next to server.min.js.
+    let mut entries = vec![(
+        dist.join("server.min.js").to_string_lossy().into(),
+        "mass/server/index.ts".into(),
+    )];
+    for member in &members {
+        entries.push((
+            dist.join(format!("{}.min.js", member.name.replace('/', "-")))
+                .to_string_lossy()
+                .into(),
+            member.dir.join("index.ts").to_string_lossy().into(),
+        ));
+    }
+
+    let output_files = bundler::bundle(esbuild_path, flags, entries).await?;
 
     fs::create_dir_all(&dist)?;
-    fs::write(dist.join("server.min.js"), output_content)?;
+    for output in &output_files {
+        let name = Path::new(&output.path)
+            .file_name()
+            .ok_or("esbuild output without a file name")?;
+        fs::write(dist.join(name), &output.contents)?;
+    }
 
     Ok(())
 }
@@ -1,16 +1,42 @@
+#[path = "npm/import.rs"]
+mod import;
+#[path = "npm/local.rs"]
+mod local;
+#[path = "npm/progress.rs"]
+mod progress;
+#[path = "npm/range.rs"]
+mod range;
+#[path = "npm/registry.rs"]
+mod registry;
+#[path = "npm/remote.rs"]
+mod remote;
+#[path = "npm/scripts.rs"]
+mod scripts;
+#[path = "npm/store.rs"]
+mod store;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use flate2::read::GzDecoder;
-use semver::{Version, VersionReq};
+use progress::Progress;
+use range::Range;
+pub use registry::Registries;
+use semver::Version;
 use serde::Deserialize;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use std::{
-    collections::{BTreeMap, HashSet},
-    io::Cursor,
-    path::Path,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Component, Path, PathBuf},
 };
+pub use store::{Store, cache_root};
 use tar::Archive;
 
 #[derive(Clone, Debug, Deserialize)]
 struct RegistryMeta {
     versions: BTreeMap<String, VersionMeta>,
+    #[serde(default, rename = "dist-tags")]
+    dist_tags: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -18,144 +44,1578 @@ struct VersionMeta {
     dist: Dist,
     #[serde(default)]
     dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "peerDependencies")]
+    peer_dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "peerDependenciesMeta")]
+    peer_dependencies_meta: BTreeMap<String, PeerMeta>,
+    #[serde(default, rename = "optionalDependencies")]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    os: Vec<String>,
+    #[serde(default)]
+    cpu: Vec<String>,
+}
+
+impl VersionMeta {
+    fn supports_host(&self) -> bool { platform_matches(&self.os, host_os()) && platform_matches(&self.cpu, host_cpu()) }
+
+    /// Regular and optional dependencies to walk next, requested by the package
+    /// installed at `parent`. npm lists optional dependencies in `dependencies`
+    /// as well, the optional entry wins.
+    fn children(&self, parent: &Path) -> Vec<Dependency> {
+        let required = self
+            .dependencies
+            .iter()
+            .filter(|(name, _)| !self.optional_dependencies.contains_key(*name))
+            .map(|(name, spec)| Dependency::new(name, spec, false));
+        let optional = self
+            .optional_dependencies
+            .iter()
+            .map(|(name, spec)| Dependency::new(name, spec, true));
+
+        // A local package declares its `file:` dependencies relative to its
+        // source, not to the copy installed from it.
+        let base = local::source(&self.dist.tarball).unwrap_or_else(|| parent.to_path_buf());
+        required
+            .chain(optional)
+            .map(|dep| Dependency {
+                base: Some(base.clone()),
+                ..dep.under(parent)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Dependency {
+    name: String,
+    spec: String,
+    optional: bool,
+    /// Install directory of the package that asked for this one, `None` for roots.
+    parent: Option<PathBuf>,
+    /// Directory a `file:` spec is relative to, that of the package declaring
+    /// it; `None` for the project's own.
+    base: Option<PathBuf>,
+}
+
+impl Dependency {
+    fn new(name: &str, spec: &str, optional: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            optional,
+            parent: None,
+            base: None,
+        }
+    }
+
+    fn under(self, parent: &Path) -> Self {
+        Self {
+            parent: Some(parent.to_path_buf()),
+            base: Some(parent.to_path_buf()),
+            ..self
+        }
+    }
+}
+
+/// Version of the package already extracted at `dir`, if any.
+fn installed_version(dir: &Path) -> Option<String> {
+    let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("package.json")).ok()?).ok()?;
+    manifest["version"].as_str().map(str::to_string)
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct PeerMeta {
+    #[serde(default)]
+    optional: bool,
+}
+
+/// A peer range one installed package expects its host project to provide.
+#[derive(Clone, Debug)]
+struct PeerRequirement {
+    dependent: String,
+    name: String,
+    range: String,
+    optional: bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 struct Dist {
     tarball: String,
+    #[serde(default)]
+    integrity: Option<String>,
+    #[serde(default)]
+    shasum: Option<String>,
 }
 
-async fn download_and_extract_tarball(
-    client: &reqwest::Client, tarball_url: &str, dest_dir: &Path,
+impl Dist {
+    /// The SRI string for this tarball, falling back to the legacy sha1 `shasum`.
+    fn sri(&self) -> Option<String> {
+        if let Some(integrity) = &self.integrity {
+            return Some(integrity.clone());
+        }
+
+        let bytes = hex::decode(self.shasum.as_ref()?).ok()?;
+        Some(format!("sha1-{}", BASE64.encode(bytes)))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct LockedPackage {
+    resolved: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    integrity: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    peer_dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    peer_dependencies_meta: BTreeMap<String, PeerMeta>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    os: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cpu: Vec<String>,
+}
+
+/// `pkg.lock`: which exact version every `name@spec` resolved to, and where that
+/// version's tarball lives, so repeated installs don't consult the registry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Lockfile {
+    #[serde(default)]
+    resolutions: BTreeMap<String, String>,
+    #[serde(default)]
+    packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Lockfile {
+    fn read(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Some(toml::from_str(&text)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let header = "# Generated by the mass npm installer. Do not edit by hand.\n\n";
+        std::fs::write(path, format!("{header}{}", toml::to_string(self)?))?;
+        Ok(())
+    }
+
+    fn locked(&self, name: &str, spec: &str) -> Option<(String, VersionMeta)> {
+        let version = self.resolutions.get(&format!("{name}@{spec}"))?;
+        let package = self.packages.get(&format!("{name}@{version}"))?;
+
+        let meta = VersionMeta {
+            dist: Dist {
+                tarball: package.resolved.clone(),
+                integrity: package.integrity.clone(),
+                shasum: None,
+            },
+            dependencies: package.dependencies.clone(),
+            peer_dependencies: package.peer_dependencies.clone(),
+            peer_dependencies_meta: package.peer_dependencies_meta.clone(),
+            optional_dependencies: package.optional_dependencies.clone(),
+            os: package.os.clone(),
+            cpu: package.cpu.clone(),
+        };
+
+        Some((version.clone(), meta))
+    }
+
+    fn insert(&mut self, name: &str, spec: &str, version: &str, meta: &VersionMeta) {
+        self.resolutions.insert(format!("{name}@{spec}"), version.to_string());
+        self.packages.insert(
+            format!("{name}@{version}"),
+            LockedPackage {
+                resolved: meta.dist.tarball.clone(),
+                integrity: meta.dist.sri(),
+                dependencies: meta.dependencies.clone(),
+                peer_dependencies: meta.peer_dependencies.clone(),
+                peer_dependencies_meta: meta.peer_dependencies_meta.clone(),
+                optional_dependencies: meta.optional_dependencies.clone(),
+                os: meta.os.clone(),
+                cpu: meta.cpu.clone(),
+            },
+        );
+    }
+}
+
+/// Install roots and settings from a project's `pkg.toml`, or the dependencies
+/// of its `package.json` when there's no `pkg.toml`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub scripts: ScriptPolicy,
+    #[serde(default)]
+    pub workspace: Workspace,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Workspace {
+    /// Member package directories, relative to the workspace root.
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+/// A workspace member: its directory and its own manifest.
+pub struct Member {
+    /// The member as the workspace lists it, which names its bundle.
+    #[cfg_attr(runtime, allow(dead_code))]
+    pub name: String,
+    pub dir: PathBuf,
+    pub manifest: Manifest,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ScriptPolicy {
+    /// Packages allowed to run preinstall/install/postinstall hooks, with the
+    /// same access to the machine as the install, see [`scripts::run`].
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl Manifest {
+    pub fn parse(pkg_toml: &str) -> Result<Self, Box<dyn std::error::Error>> { Ok(toml::from_str(pkg_toml)?) }
+
+    pub fn read(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Ok(contents) = std::fs::read_to_string(dir.join("pkg.toml")) {
+            return Self::parse(&contents);
+        }
+
+        let path = dir.join("package.json");
+        let contents = std::fs::read(&path).map_err(|err| format!("no pkg.toml or {}: {err}", path.display()))?;
+        let manifest: serde_json::Value = serde_json::from_slice(&contents)?;
+
+        Ok(Self {
+            dependencies: serde_json::from_value(manifest["dependencies"].clone()).unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    /// Reads the manifest of every workspace member under `dir`.
+    pub fn members(&self, dir: &Path) -> Result<Vec<Member>, Box<dyn std::error::Error>> {
+        self.workspace
+            .members
+            .iter()
+            .map(|member| {
+                let dir = dir.join(member);
+                let manifest = Self::read(&dir).map_err(|err| format!("workspace member {member}: {err}"))?;
+                if !manifest.workspace.members.is_empty() {
+                    return Err(format!("workspace member {member} can't declare a workspace of its own").into());
+                }
+
+                Ok(Member {
+                    name: member.clone(),
+                    dir,
+                    manifest,
+                })
+            })
+            .collect()
+    }
+}
+
+pub struct InstallOptions {
+    /// Path of the lockfile to read and (unless frozen) rewrite.
+    pub lockfile: PathBuf,
+    /// Fail instead of resolving anything the lockfile doesn't already pin.
+    pub frozen: bool,
+    /// Global package store to materialize packages from, `None` to always download.
+    pub store: Option<PathBuf>,
+    /// Directory for registry metadata revalidated with ETags, `None` to disable.
+    pub metadata_cache: Option<PathBuf>,
+    /// Registry URLs and auth tokens, see [`Registries::load`].
+    pub registries: Registries,
+    /// Directory the project's own `file:` dependency paths are relative to.
+    pub project: PathBuf,
+    /// `package-lock.json` or `yarn.lock` to seed resolutions from when there's no lockfile yet.
+    pub import: Option<PathBuf>,
+    /// Packages trusted to run their install scripts, which run with the
+    /// installer's own privileges. Everyone else's are skipped.
+    pub allow_scripts: HashSet<String>,
+    /// Remove packages that are no longer reachable from the roots.
+    pub prune: bool,
+    /// Check existing installs against their tarballs and repair any that differ.
+    pub verify: bool,
+    /// Never touch the network: packuments come from the metadata cache and
+    /// tarballs from the store, and anything missing from both fails the install.
+    pub offline: bool,
+    /// Dependencies of workspace members, resolved together with the roots. A
+    /// version that conflicts with the hoisted one nests under the member.
+    pub members: Vec<(PathBuf, BTreeMap<String, String>)>,
+}
+
+impl InstallOptions {
+    pub fn default_metadata_cache() -> Option<PathBuf> { Some(store::cache_root()?.join("metadata")) }
+}
+
+/// The host platform in npm's `process.platform` vocabulary.
+fn host_os() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "darwin",
+        "windows" => "win32",
+        other => other,
+    }
+}
+
+/// The host architecture in npm's `process.arch` vocabulary.
+fn host_cpu() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        "x86" => "ia32",
+        "powerpc64" => "ppc64",
+        other => other,
+    }
+}
+
+/// npm's `os`/`cpu` semantics: an empty list matches everything, `!name` excludes,
+/// and any positive entry means the host has to be one of them.
+fn platform_matches(list: &[String], host: &str) -> bool {
+    let mut allowed = None;
+
+    for entry in list {
+        match entry.strip_prefix('!') {
+            Some(excluded) if excluded == host => return false,
+            Some(_) => {}
+            None => *allowed.get_or_insert(false) |= entry == host,
+        }
+    }
+
+    allowed.unwrap_or(true)
+}
+
+/// Registry path for a package; scoped names keep their `@` but the separating
+/// slash is escaped, matching what the npm CLI requests.
+fn encode_package_name(name: &str) -> String { name.replace('/', "%2f") }
+
+/// Where a package lives under node_modules. Scoped packages nest under their
+/// `@scope` directory; anything that isn't a valid npm name is rejected so a
+/// malicious dependency name can't escape node_modules.
+fn package_dir(node_modules: &Path, name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let invalid = || format!("invalid package name {name:?}");
+    let valid_segment = |s: &str| !s.is_empty() && s != "." && s != ".." && !s.contains(['\\', ':']);
+
+    let (scope, package) = match name.strip_prefix('@') {
+        Some(scoped) => {
+            let (scope, package) = scoped.split_once('/').ok_or_else(invalid)?;
+            (Some(scope), package)
+        }
+        None => (None, name),
+    };
+
+    if package.contains('/') || !valid_segment(package) || scope.is_some_and(|scope| !valid_segment(scope)) {
+        return Err(invalid().into());
+    }
+
+    Ok(match scope {
+        Some(scope) => node_modules.join(format!("@{scope}")).join(package),
+        None => node_modules.join(package),
+    })
+}
+
+fn digest<D: Digest>(bytes: &[u8]) -> String { BASE64.encode(D::digest(bytes)) }
+
+/// Streams `reader` through `D`, for tarballs that live on disk rather than in memory.
+fn digest_reader<D: Digest>(mut reader: impl std::io::Read) -> std::io::Result<String> {
+    let mut hasher = D::new();
+    let mut buffer = [0; 64 * 1024];
+
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok(BASE64.encode(hasher.finalize())),
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Checks a subresource-integrity string using `hash`, which produces the base64
+/// digest for an algorithm. Any one matching hash is enough, as with npm;
+/// unknown algorithms are ignored.
+fn check_integrity(
+    tarball_url: &str, integrity: &str, mut hash: impl FnMut(&str) -> Option<std::io::Result<String>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = client.get(tarball_url).send().await?.bytes().await?;
-    let cursor = Cursor::new(bytes);
-    let gz = GzDecoder::new(cursor);
-    let mut ar = Archive::new(gz);
+    let mut checked = false;
+
+    for entry in integrity.split_whitespace() {
+        let Some((algorithm, expected)) = entry.split_once('-') else {
+            continue;
+        };
 
-    std::fs::create_dir_all(dest_dir)?;
-    for entry in ar.entries()? {
-        let mut e = entry?;
-        let path = e.path()?;
-        let rel = path.strip_prefix("package").unwrap_or(&path);
-        let out_path = dest_dir.join(rel);
+        let Some(actual) = hash(algorithm) else {
+            continue;
+        };
 
-        if let Some(parent) = out_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        if actual? == expected.split('?').next().unwrap_or_default() {
+            return Ok(());
         }
-        e.unpack(out_path)?;
+        checked = true;
     }
-    Ok(())
+
+    if checked {
+        Err(format!("integrity mismatch for {tarball_url}: expected {integrity}").into())
+    } else {
+        Err(format!("no supported integrity hash for {tarball_url}: {integrity}").into())
+    }
+}
+
+/// Checks `bytes` against a subresource-integrity string.
+#[cfg_attr(runtime, allow(dead_code))]
+pub(crate) fn verify_integrity(
+    tarball_url: &str, bytes: &[u8], integrity: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_integrity(tarball_url, integrity, |algorithm| {
+        Some(Ok(match algorithm {
+            "sha512" => digest::<Sha512>(bytes),
+            "sha384" => digest::<Sha384>(bytes),
+            "sha256" => digest::<Sha256>(bytes),
+            "sha1" => digest::<Sha1>(bytes),
+            _ => return None,
+        }))
+    })
+}
+
+/// Checks the file at `path` against a subresource-integrity string.
+fn verify_integrity_file(tarball_url: &str, path: &Path, integrity: &str) -> Result<(), Box<dyn std::error::Error>> {
+    check_integrity(tarball_url, integrity, |algorithm| {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => std::io::BufReader::new(file),
+            Err(err) => return Some(Err(err)),
+        };
+
+        Some(match algorithm {
+            "sha512" => digest_reader::<Sha512>(file),
+            "sha384" => digest_reader::<Sha384>(file),
+            "sha256" => digest_reader::<Sha256>(file),
+            "sha1" => digest_reader::<Sha1>(file),
+            _ => return None,
+        })
+    })
+}
+
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Runs `request` until it succeeds, fails for good or [`MAX_ATTEMPTS`] is
+/// reached, doubling the delay between attempts.
+async fn with_retries<T, F, Fut>(what: &str, mut request: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    let mut delay = RETRY_BACKOFF;
+
+    for attempt in 1.. {
+        match request().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < MAX_ATTEMPTS && transient(err.as_ref()) => {
+                eprintln!("{what} failed (attempt {attempt}/{MAX_ATTEMPTS}): {err}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(err) if attempt == 1 => return Err(format!("{what} failed: {err}").into()),
+            Err(err) => return Err(format!("{what} failed after {attempt} attempts: {err}").into()),
+        }
+    }
+
+    unreachable!()
+}
+
+/// Whether a request that failed with `err` may succeed if tried again: the
+/// connection failed, dropped or timed out, or the registry answered 429 or
+/// 5xx. Anything else, like a 404 for a misspelt package or a 401, fails the
+/// same way every time.
+fn transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    let Some(err) = err.downcast_ref::<reqwest::Error>() else {
+        return false;
+    };
+    match err.status() {
+        Some(status) => status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        None => err.is_connect() || err.is_timeout() || err.is_request() || err.is_body(),
+    }
+}
+
+/// Streams `url` into the file at `path`, resuming from the bytes already
+/// written with a range request when a retry happens partway through the body.
+async fn download_to(
+    client: &reqwest::Client, registries: &Registries, url: &str, path: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+    let file = tokio::fs::File::create(path).await?;
+    let file = &tokio::sync::Mutex::new(file);
+
+    with_retries(&format!("download of {url}"), move || async move {
+        let mut file = file.lock().await;
+        let written = file.stream_position().await?;
+
+        let _connection = registries.connect(url).await;
+        let mut request = registries.authorize(client.get(url), url);
+        if written > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={written}-"));
+        }
+
+        let mut response = request.send().await?.error_for_status()?;
+        if written > 0 && response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            file.set_len(0).await?;
+            file.rewind().await?;
+        }
+
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    })
+    .await?;
+
+    let mut file = file.lock().await;
+    Ok(file.stream_position().await?)
+}
+
+/// [`download_to`] for bodies small enough to keep in memory.
+async fn download(
+    client: &reqwest::Client, registries: &Registries, url: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join(format!(
+        "mass-download-{}-{}",
+        std::process::id(),
+        hex::encode(&Sha256::digest(url)[..8])
+    ));
+
+    let downloaded = download_to(client, registries, url, &path).await;
+    let bytes = downloaded.and_then(|_| Ok(std::fs::read(&path)?));
+    let _ = std::fs::remove_file(&path);
+    bytes
+}
+
+/// Extractions running at once. gzip and tar are synchronous, so they run on the
+/// blocking pool, bounded so large installs don't spawn a thread per package.
+static EXTRACT_PERMITS: std::sync::LazyLock<tokio::sync::Semaphore> = std::sync::LazyLock::new(|| {
+    tokio::sync::Semaphore::new(std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4))
+});
+
+/// Extracts into a sibling temp directory and renames it into place, so a failed
+/// install never leaves a half-written package at `dest_dir`. The tarball's top
+/// directory is stripped whatever it's called (`package/` on npm, `repo-ref/` on
+/// GitHub). The tarball is streamed to disk and never held in memory whole.
+/// Returns its size.
+async fn download_and_extract_tarball(
+    client: &reqwest::Client, registries: &Registries, tarball_url: &str, integrity: Option<&str>, dest_dir: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let name = dest_dir.file_name().unwrap_or_default().to_string_lossy();
+    let temp_dir = dest_dir.with_file_name(format!(".{name}.partial-{}", std::process::id()));
+    let archive = dest_dir.with_file_name(format!(".{name}.partial-{}.tgz", std::process::id()));
+
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+    if let Some(parent) = archive.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let size = match remote::fetch_to(client, registries, tarball_url, &archive).await {
+        Ok(size) => size,
+        Err(err) => {
+            let _ = std::fs::remove_file(&archive);
+            return Err(err);
+        }
+    };
+
+    let _permit = EXTRACT_PERMITS.acquire().await?;
+    let (tarball_url, integrity) = (tarball_url.to_string(), integrity.map(str::to_string));
+    let (archive_path, dest_dir) = (archive.clone(), dest_dir.to_path_buf());
+
+    let extracted = tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let extract = || -> Result<(), Box<dyn std::error::Error>> {
+            match &integrity {
+                Some(integrity) => verify_integrity_file(&tarball_url, &archive_path, integrity)?,
+                None => eprintln!("warning: {tarball_url} has no integrity hash, installing unverified"),
+            }
+
+            let gz = GzDecoder::new(std::io::BufReader::new(std::fs::File::open(&archive_path)?));
+            let mut ar = Archive::new(gz);
+
+            std::fs::create_dir_all(&temp_dir)?;
+            for entry in ar.entries()? {
+                let mut e = entry?;
+                // Links are skipped, as npm does; one could send the entries after it
+                // outside `temp_dir`.
+                let kind = e.header().entry_type();
+                if kind.is_symlink() || kind.is_hard_link() {
+                    continue;
+                }
+
+                let path = e.path()?;
+                let rel = entry_path(&path)
+                    .ok_or_else(|| format!("{tarball_url} has an entry outside its package: {}", path.display()))?;
+                if rel.as_os_str().is_empty() {
+                    continue;
+                }
+                let out_path = temp_dir.join(rel);
+
+                if let Some(parent) = out_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                e.unpack(out_path)?;
+            }
+            Ok(std::fs::rename(&temp_dir, &dest_dir)?)
+        };
+
+        let result = extract();
+        if result.is_err() {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+        }
+        result.map_err(|err| err.to_string())
+    })
+    .await;
+
+    let _ = std::fs::remove_file(&archive);
+    extracted?.map_err(|err| err.into()).map(|_| size)
+}
+
+/// Where a tarball entry goes under the package directory, with its top
+/// directory stripped. `None` for an entry with `..` in its path, one that
+/// would land outside the package.
+fn entry_path(path: &Path) -> Option<PathBuf> {
+    path.components()
+        .skip(1)
+        .map(|part| match part {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Abbreviated packument format, which only carries the fields installs need.
+const ABBREVIATED_METADATA: &str = "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+
+/// Fetches a package's metadata document, revalidating the copy under `cache`
+/// with its ETag so unchanged packuments aren't downloaded again.
+async fn fetch_packument(
+    client: &reqwest::Client, registries: &Registries, cache: Option<&Path>, name: &str, offline: bool,
+) -> Result<RegistryMeta, Box<dyn std::error::Error>> {
+    let registry = registries.registry_for(name);
+    let url = format!("{registry}{}", encode_package_name(name));
+
+    // Registries get their own cache directory so a mirror never serves another's documents.
+    let namespace = hex::encode(&Sha256::digest(registry)[..8]);
+    let cached = cache.map(|dir| dir.join(namespace).join(format!("{}.json", encode_package_name(name))));
+    let etag_path = cached.as_ref().map(|path| path.with_extension("etag"));
+
+    let etag = etag_path
+        .as_ref()
+        .filter(|_| cached.as_ref().is_some_and(|path| path.exists()))
+        .and_then(|path| std::fs::read_to_string(path).ok());
+
+    if offline {
+        let cached = cached
+            .filter(|path| path.exists())
+            .ok_or_else(|| format!("{name} is not in the metadata cache"))?;
+        return Ok(serde_json::from_str(&std::fs::read_to_string(cached)?)?);
+    }
+
+    let (url, etag) = (url.as_str(), etag.as_deref());
+    let response = with_retries(&format!("metadata request for {name}"), move || async move {
+        let _connection = registries.connect(url).await;
+        let mut request = registries
+            .authorize(client.get(url), url)
+            .header(reqwest::header::ACCEPT, ABBREVIATED_METADATA);
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let etag = response.headers().get(reqwest::header::ETAG).cloned();
+        Ok(Some((etag, response.text().await?)))
+    })
+    .await?;
+
+    let body = match (response, &cached) {
+        (None, Some(cached)) => std::fs::read_to_string(cached)?,
+        (None, None) => return Err(format!("registry returned 304 for {name} without a cached copy").into()),
+        (Some((etag, body)), cached) => {
+            if let (Some(cached), Some(etag_path)) = (cached, &etag_path) {
+                std::fs::create_dir_all(cached.parent().unwrap())?;
+                std::fs::write(cached, &body)?;
+
+                match etag.as_ref().and_then(|etag| etag.to_str().ok()) {
+                    Some(etag) => std::fs::write(etag_path, etag)?,
+                    None => _ = std::fs::remove_file(etag_path),
+                }
+            }
+            body
+        }
+    };
+
+    Ok(serde_json::from_str(&body)?)
 }
 
 async fn fetch_registry_meta(
-    client: &reqwest::Client, name: &str, spec: &str,
+    client: &reqwest::Client, registries: &Registries, cache: Option<&Path>, name: &str, spec: &str, offline: bool,
 ) -> Result<(String, VersionMeta), Box<dyn std::error::Error>> {
-    let url = format!("https://registry.npmjs.org/{name}");
-    let doc: RegistryMeta = client.get(&url).send().await?.json().await?;
+    let doc = fetch_packument(client, registries, cache, name, offline).await?;
+    choose_version(&doc, name, spec)
+}
 
+/// The version of `name` that `spec`, an exact version, dist-tag or range,
+/// picks out of its packument.
+fn choose_version(
+    doc: &RegistryMeta, name: &str, spec: &str,
+) -> Result<(String, VersionMeta), Box<dyn std::error::Error>> {
     if let Some(vmeta) = doc.versions.get(spec) {
         return Ok((spec.to_string(), vmeta.clone()));
     }
 
-    let req = VersionReq::parse(spec)?;
-    let mut best: Option<(Version, String)> = None;
-    for vstr in doc.versions.keys() {
-        if let Ok(v) = Version::parse(vstr) {
-            if req.matches(&v) {
-                if let Some((best_v, _)) = &best {
-                    if &v > best_v {
-                        best = Some((v, vstr.clone()));
-                    }
-                } else {
-                    best = Some((v, vstr.clone()));
-                }
+    let tag = if spec.is_empty() { "latest" } else { spec };
+    if let Some(version) = doc.dist_tags.get(tag) {
+        let vmeta = doc
+            .versions
+            .get(version)
+            .ok_or_else(|| format!("{name}@{tag} points at {version}, which the registry does not list"))?;
+        return Ok((version.clone(), vmeta.clone()));
+    }
+
+    // Like npm, prefer the `latest` tag whenever the range accepts it. A stale
+    // tag pointing at an unlisted version is passed over.
+    let range = Range::parse(spec)?;
+    let latest = doc.dist_tags.get("latest").filter(|latest| {
+        doc.versions.contains_key(latest.as_str())
+            && Version::parse(latest).is_ok_and(|version| range.matches(&version))
+    });
+    let chosen_str = latest
+        .map(String::as_str)
+        .or_else(|| range.max_satisfying(doc.versions.keys().map(String::as_str)))
+        .ok_or_else(|| format!("No version of {name} satisfies range {spec}"))?
+        .to_string();
+    let chosen_meta = doc
+        .versions
+        .get(&chosen_str)
+        .ok_or_else(|| format!("{name}@{chosen_str} is not listed by the registry"))?
+        .clone();
+    Ok((chosen_str, chosen_meta))
+}
+
+fn installed_versions(installed: &HashSet<String>) -> BTreeMap<String, Vec<Version>> {
+    let mut versions = BTreeMap::<String, Vec<Version>>::new();
+
+    for key in installed {
+        if let Some((name, version)) = key.rsplit_once('@')
+            && let Ok(version) = Version::parse(version)
+        {
+            versions.entry(name.to_string()).or_default().push(version);
+        }
+    }
+
+    versions
+}
+
+fn report_peers(peers: &[PeerRequirement], installed: &BTreeMap<String, Vec<Version>>) {
+    let mut unmet = 0;
+
+    for peer in peers {
+        let found = installed.get(&peer.name).map(Vec::as_slice).unwrap_or_default();
+        let Ok(range) = Range::parse(&peer.range) else {
+            continue;
+        };
+
+        if found.is_empty() {
+            if !peer.optional {
+                unmet += 1;
+                eprintln!(
+                    "warning: unmet peer {}@{} required by {}",
+                    peer.name, peer.range, peer.dependent
+                );
             }
+        } else if !found.iter().any(|version| range.matches(version)) {
+            unmet += 1;
+            let found = found.iter().map(Version::to_string).collect::<Vec<_>>().join(", ");
+            eprintln!(
+                "warning: conflicting peer {}@{} required by {}, installed {found}",
+                peer.name, peer.range, peer.dependent
+            );
         }
     }
 
-    let (_, chosen_str) = best.ok_or_else(|| format!("No version of {name} satisfies range {spec}"))?;
-    let chosen_meta = doc.versions.get(&chosen_str).unwrap().clone();
-    Ok((chosen_str, chosen_meta))
+    if unmet > 0 {
+        eprintln!("warning: {unmet} peer dependency problem(s), the bundle may fail to resolve them");
+    }
+}
+
+/// Removes every package directory under `node_modules` (nested ones included)
+/// that this install didn't place. Dot-directories like `.bin` are kept.
+fn prune(node_modules: &Path, placed: &HashSet<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(entries) = std::fs::read_dir(node_modules) else {
+        return Ok(());
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+        if name.starts_with('.') || !path.is_dir() {
+            continue;
+        }
+
+        let packages = match name.starts_with('@') {
+            true => std::fs::read_dir(&path)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()?,
+            false => vec![path.clone()],
+        };
+
+        for package in packages {
+            if placed.contains(&package) {
+                prune(&package.join("node_modules"), placed)?;
+            } else {
+                println!("Pruning {}", package.display());
+                std::fs::remove_dir_all(&package)?;
+            }
+        }
+
+        if name.starts_with('@') && std::fs::read_dir(&path)?.next().is_none() {
+            std::fs::remove_dir(&path)?;
+        }
+    }
+
+    Ok(())
 }
 
 pub async fn install_all_packages(
     client: &reqwest::Client, node_modules: &Path, roots: impl IntoIterator<Item = (String, String)>,
+    options: &InstallOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use futures::{StreamExt, stream::FuturesUnordered};
-    use std::{path::PathBuf, sync::Arc};
+    use std::sync::Arc;
     use tokio::sync::{Mutex, Semaphore};
 
     std::fs::create_dir_all(node_modules)?;
 
-    let client = Arc::new(client.clone());
-    let node_modules = Arc::new(PathBuf::from(node_modules));
-    let installed = Arc::new(Mutex::new(HashSet::<String>::new()));
+    let previous = match (Lockfile::read(&options.lockfile)?, &options.import) {
+        (None, Some(import)) => {
+            println!("Importing resolutions from {}", import.display());
+            Some(import::read(import)?)
+        }
+        (previous, _) => previous,
+    };
+    if options.frozen && previous.is_none() {
+        return Err(format!("{} is missing and the install is frozen", options.lockfile.display()).into());
+    }
 
     let max_concurrency = std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(8)
         .max(4);
 
-    let sem = Arc::new(Semaphore::new(max_concurrency));
+    struct Shared {
+        client: reqwest::Client,
+        node_modules: PathBuf,
+        installed: Mutex<HashSet<String>>,
+        hoisted: Mutex<BTreeMap<String, String>>,
+        placed: Mutex<HashSet<PathBuf>>,
+        resolved: Mutex<BTreeMap<String, (String, VersionMeta)>>,
+        sem: Arc<Semaphore>,
+        store: Option<Store>,
+        metadata_cache: Option<PathBuf>,
+        registries: Registries,
+        project: PathBuf,
+        allow_scripts: HashSet<String>,
+        scripted: Mutex<Vec<(String, PathBuf)>>,
+        unscripted: Mutex<Vec<String>>,
+        verify: bool,
+        locked: Lockfile,
+        lockfile: Mutex<Lockfile>,
+        peers: Mutex<Vec<PeerRequirement>>,
+        skipped: Mutex<Vec<String>>,
+        progress: Progress,
+        frozen: bool,
+        offline: bool,
+        missing: Mutex<BTreeSet<String>>,
+    }
 
-    async fn process_one(
-        client: Arc<reqwest::Client>, node_modules: Arc<PathBuf>, installed: Arc<Mutex<HashSet<String>>>,
-        sem: Arc<Semaphore>, name: String, spec: String,
-    ) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
-        let _permit = sem.clone().acquire_owned().await?;
+    /// Records something an offline install would have had to download.
+    async fn unavailable(shared: &Shared, what: String) -> Box<dyn std::error::Error> {
+        let err = format!("{what} is not available offline").into();
+        shared.missing.lock().await.insert(what);
+        err
+    }
 
-        let (version, vmeta) = fetch_registry_meta(&client, &name, &spec).await?;
-        let key = format!("{name}@{version}");
+    /// Installs one dependency. Optional dependencies that fail or don't support
+    /// this platform are recorded as skipped instead of failing the install.
+    async fn process_one(shared: Arc<Shared>, dep: Dependency) -> Result<Vec<Dependency>, Box<dyn std::error::Error>> {
+        match install_one(&shared, &dep).await {
+            Err(err) if dep.optional => {
+                shared
+                    .skipped
+                    .lock()
+                    .await
+                    .push(format!("{}@{} ({err})", dep.name, dep.spec));
+                Ok(vec![])
+            }
+            result => result,
+        }
+    }
 
-        {
-            let mut set = installed.lock().await;
-            if !set.insert(key.clone()) {
-                return Ok(vec![]);
+    /// Resolves `name@spec`, a `file:` spec relative to `base` when given.
+    async fn resolve(
+        shared: &Shared, name: &str, spec: &str, base: Option<&Path>,
+    ) -> Result<(String, VersionMeta), Box<dyn std::error::Error>> {
+        // Local packages change in place, so they're read fresh every install and
+        // kept out of the lockfile along with their machine-specific paths. The
+        // same spec means another package when another package declares it.
+        if local::is_local(spec) {
+            return local::resolve(base.unwrap_or(&shared.project), name, spec);
+        }
+
+        let request = format!("{name}@{spec}");
+        if let Some(resolved) = shared.resolved.lock().await.get(&request) {
+            return Ok(resolved.clone());
+        }
+
+        let resolved = match shared.locked.locked(name, spec) {
+            Some(locked) => locked,
+            None if shared.frozen => {
+                return Err(format!("{request} is not in the lockfile and the install is frozen").into());
+            }
+            None if shared.offline && remote::is_remote(spec) => return Err(unavailable(shared, request).await),
+            None if remote::is_remote(spec) => remote::resolve(&shared.client, &shared.registries, name, spec).await?,
+            None => {
+                let cache = shared.metadata_cache.as_deref();
+                match fetch_registry_meta(&shared.client, &shared.registries, cache, name, spec, shared.offline).await {
+                    Ok(resolved) => resolved,
+                    Err(err) if shared.offline => {
+                        shared.missing.lock().await.insert(request);
+                        return Err(err);
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+
+        shared.progress.resolved();
+        shared
+            .lockfile
+            .lock()
+            .await
+            .insert(name, spec, &resolved.0, &resolved.1);
+        shared.resolved.lock().await.insert(request, resolved.clone());
+        Ok(resolved)
+    }
+
+    /// Picks where a resolved package goes: the first version of a name is hoisted
+    /// to the top level, any other version nests under the package needing it.
+    async fn placement(
+        shared: &Shared, dep: &Dependency, version: &str,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let mut hoisted = shared.hoisted.lock().await;
+
+        match hoisted.get(&dep.name) {
+            None => {
+                hoisted.insert(dep.name.clone(), version.to_string());
+                package_dir(&shared.node_modules, &dep.name)
+            }
+            Some(top) if top == version => package_dir(&shared.node_modules, &dep.name),
+            Some(top) => {
+                let Some(parent) = dep.parent.as_deref() else {
+                    return Err(format!("{}@{version} conflicts with hoisted {top}", dep.name).into());
+                };
+
+                println!(
+                    "{}@{version} conflicts with hoisted {top}, nesting under {}",
+                    dep.name,
+                    parent.display()
+                );
+                package_dir(&parent.join("node_modules"), &dep.name)
             }
         }
+    }
+
+    /// Extracts the package into the store unless it's already there.
+    async fn store_entry(
+        shared: &Shared, store: &Store, name: &str, version: &str, vmeta: &VersionMeta,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let integrity = vmeta.dist.sri();
+        let entry = store.entry(name, version, integrity.as_deref());
+
+        if !store.contains(&entry) {
+            if shared.offline {
+                return Err(unavailable(shared, format!("{name}@{version}")).await);
+            }
+
+            let staging = store.staging(&entry);
+            if staging.exists() {
+                std::fs::remove_dir_all(&staging)?;
+            }
 
-        let dest = node_modules.join(&name);
-        if dest.exists() {
-            println!("{key} already exists, skipping download");
+            let size = download_and_extract_tarball(
+                &shared.client,
+                &shared.registries,
+                &vmeta.dist.tarball,
+                integrity.as_deref(),
+                &staging,
+            )
+            .await?;
+            store.commit(&staging, &entry)?;
+
+            shared.progress.downloaded(size);
+            shared.progress.extracted();
         } else {
-            println!("Installing {key}");
-            download_and_extract_tarball(&client, &vmeta.dist.tarball, &dest).await?;
-        }
-
-        Ok(vmeta.dependencies.into_iter().collect())
-    }
-
-    let mut tasks = FuturesUnordered::new();
-
-    for (name, spec) in roots {
-        tasks.push(process_one(
-            client.clone(),
-            node_modules.clone(),
-            installed.clone(),
-            sem.clone(),
-            name,
-            spec,
-        ));
-    }
-
-    while let Some(res) = tasks.next().await {
-        match res {
-            Ok(deps) => {
-                for (dep_name, dep_spec) in deps {
-                    tasks.push(process_one(
-                        client.clone(),
-                        node_modules.clone(),
-                        installed.clone(),
-                        sem.clone(),
-                        dep_name,
-                        dep_spec,
-                    ));
+            shared.progress.linked();
+        }
+
+        Ok(entry)
+    }
+
+    async fn fetch_package(
+        shared: &Shared, name: &str, version: &str, vmeta: &VersionMeta, dest: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if local::is_local(&vmeta.dist.tarball) {
+            return local::install(&vmeta.dist.tarball, dest);
+        }
+
+        let Some(store) = &shared.store else {
+            if shared.offline {
+                return Err(unavailable(shared, format!("{name}@{version}")).await);
+            }
+
+            let integrity = vmeta.dist.sri();
+            let size = download_and_extract_tarball(
+                &shared.client,
+                &shared.registries,
+                &vmeta.dist.tarball,
+                integrity.as_deref(),
+                dest,
+            )
+            .await?;
+
+            shared.progress.downloaded(size);
+            shared.progress.extracted();
+            return Ok(());
+        };
+
+        let entry = store_entry(shared, store, name, version, vmeta).await?;
+        Ok(store::materialize(&entry, dest, !shared.allow_scripts.contains(name))?)
+    }
+
+    /// Compares an existing install with a pristine copy of its tarball, taken
+    /// from the store or freshly downloaded (and integrity-checked) otherwise.
+    async fn verify_package(
+        shared: &Shared, name: &str, version: &str, vmeta: &VersionMeta, dest: &Path,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // Checked against a fresh copy of the tarball, verified against the
+        // lockfile's integrity, never the store entry: node_modules hardlinks
+        // the entry's files, so a file corrupted in one is corrupted in both.
+        if shared.offline {
+            return Err(unavailable(shared, format!("{name}@{version}")).await);
+        }
+
+        let file_name = dest.file_name().unwrap_or_default().to_string_lossy();
+        let pristine = dest.with_file_name(format!(".{file_name}.verify-{}", std::process::id()));
+        let integrity = vmeta.dist.sri();
+        let fetched = download_and_extract_tarball(
+            &shared.client,
+            &shared.registries,
+            &vmeta.dist.tarball,
+            integrity.as_deref(),
+            &pristine,
+        )
+        .await;
+
+        let same = fetched.and_then(|_| Ok(store::same_tree(&pristine, dest)?));
+
+        // A store entry sharing the corruption is dropped, so reinstalling
+        // extracts it again rather than linking the same files back.
+        if let (Ok(false), Some(store)) = (&same, &shared.store) {
+            let entry = store.entry(name, version, integrity.as_deref());
+            if store.contains(&entry) && !store::same_tree(&pristine, &entry).unwrap_or(false) {
+                std::fs::remove_dir_all(&entry)?;
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(&pristine);
+        same
+    }
+
+    async fn install_one(shared: &Shared, dep: &Dependency) -> Result<Vec<Dependency>, Box<dyn std::error::Error>> {
+        let Dependency { name, spec, base, .. } = dep;
+        let _permit = shared.sem.clone().acquire_owned().await?;
+
+        let (version, vmeta) = resolve(shared, name, spec, base.as_deref()).await?;
+        let key = format!("{name}@{version}");
+
+        if !vmeta.supports_host() {
+            return Err(format!("{key} does not support {}-{}", host_os(), host_cpu()).into());
+        }
+
+        let dest = placement(shared, dep, &version).await?;
+        if !shared.placed.lock().await.insert(dest.clone()) {
+            return Ok(vec![]);
+        }
+
+        match installed_version(&dest) {
+            Some(existing) if existing == version && !local::is_local(&vmeta.dist.tarball) => {
+                // Directories install scripts ran in legitimately differ from the tarball.
+                shared.progress.up_to_date();
+                if !shared.verify || scripts::ran(&dest) {
+                    println!("{key} already exists, skipping download");
+                } else if verify_package(shared, name, &version, &vmeta, &dest).await? {
+                    println!("{key} verified");
+                } else {
+                    println!("{key} differs from its tarball, reinstalling");
+                    std::fs::remove_dir_all(&dest)?;
+                    fetch_package(shared, name, &version, &vmeta, &dest).await?;
                 }
             }
-            Err(err) => eprintln!("Install task failed: {err}"),
+            existing => {
+                if existing.is_some() {
+                    std::fs::remove_dir_all(&dest)?;
+                }
+
+                println!("Installing {key}");
+                fetch_package(shared, name, &version, &vmeta, &dest).await?;
+            }
+        }
+
+        if !scripts::hooks(&dest).is_empty() && !scripts::ran(&dest) {
+            match shared.allow_scripts.contains(name) {
+                true => shared.scripted.lock().await.push((key.clone(), dest.clone())),
+                false => shared.unscripted.lock().await.push(key.clone()),
+            }
+        }
+
+        if shared.installed.lock().await.insert(key.clone()) {
+            shared
+                .peers
+                .lock()
+                .await
+                .extend(vmeta.peer_dependencies.iter().map(|(peer, range)| PeerRequirement {
+                    dependent: key.clone(),
+                    name: peer.clone(),
+                    range: range.clone(),
+                    optional: vmeta.peer_dependencies_meta.get(peer).is_some_and(|meta| meta.optional),
+                }));
+        }
+
+        Ok(vmeta.children(&dest))
+    }
+
+    let frozen = options.frozen;
+    let shared = Arc::new(Shared {
+        client: client.clone(),
+        node_modules: node_modules.to_path_buf(),
+        installed: Mutex::new(HashSet::new()),
+        hoisted: Mutex::new(BTreeMap::new()),
+        placed: Mutex::new(HashSet::new()),
+        resolved: Mutex::new(BTreeMap::new()),
+        sem: Arc::new(Semaphore::new(max_concurrency)),
+        store: options.store.clone().map(Store::new),
+        metadata_cache: options.metadata_cache.clone(),
+        registries: options.registries.clone(),
+        project: options.project.clone(),
+        allow_scripts: options.allow_scripts.clone(),
+        scripted: Mutex::new(Vec::new()),
+        unscripted: Mutex::new(Vec::new()),
+        verify: options.verify,
+        locked: previous.clone().unwrap_or_default(),
+        lockfile: Mutex::new(Lockfile::default()),
+        peers: Mutex::new(Vec::new()),
+        skipped: Mutex::new(Vec::new()),
+        progress: Progress::new(),
+        frozen,
+        offline: options.offline,
+        missing: Mutex::new(BTreeSet::new()),
+    });
+
+    let reporter = tokio::spawn({
+        let shared = shared.clone();
+        async move {
+            loop {
+                tokio::time::sleep(progress::REPORT_INTERVAL).await;
+                println!("Progress: {}", shared.progress.line());
+            }
+        }
+    });
+
+    let mut pending: Vec<Dependency> = roots
+        .into_iter()
+        .map(|(name, spec)| Dependency::new(&name, &spec, false))
+        .collect();
+    let mut requested_peers = HashSet::new();
+    let mut failed = false;
+
+    // Roots claim their top-level slot before any transitive dependency can.
+    for root in &pending {
+        match resolve(&shared, &root.name, &root.spec, None).await {
+            Ok((version, _)) => {
+                shared.hoisted.lock().await.insert(root.name.clone(), version);
+            }
+            Err(err) => eprintln!("Failed to resolve {}@{}: {err}", root.name, root.spec),
+        }
+    }
+
+    // Members come next, so a member only nests what the root already claimed.
+    for (dir, dependencies) in &options.members {
+        for (name, spec) in dependencies {
+            let dep = Dependency::new(name, spec, false).under(dir);
+            match resolve(&shared, name, spec, Some(dir)).await {
+                Ok((version, _)) => {
+                    shared.hoisted.lock().await.entry(name.clone()).or_insert(version);
+                }
+                Err(err) => eprintln!("Failed to resolve {name}@{spec} for {}: {err}", dir.display()),
+            }
+            pending.push(dep);
         }
     }
 
+    while !pending.is_empty() {
+        let mut tasks: FuturesUnordered<_> = pending.drain(..).map(|dep| process_one(shared.clone(), dep)).collect();
+
+        while let Some(res) = tasks.next().await {
+            match res {
+                Ok(deps) => {
+                    for dep in deps {
+                        tasks.push(process_one(shared.clone(), dep));
+                    }
+                }
+                Err(err) => {
+                    failed = true;
+                    eprintln!("Install task failed: {err}");
+                }
+            }
+        }
+
+        // Required peers nothing else pulled in get installed like regular
+        // dependencies, after which their own dependencies are walked as well.
+        let installed = installed_versions(&*shared.installed.lock().await);
+        for peer in shared.peers.lock().await.iter() {
+            if !peer.optional && !installed.contains_key(&peer.name) && requested_peers.insert(peer.name.clone()) {
+                println!(
+                    "Installing peer {}@{} required by {}",
+                    peer.name, peer.range, peer.dependent
+                );
+                pending.push(Dependency::new(&peer.name, &peer.range, false));
+            }
+        }
+    }
+
+    report_peers(
+        &shared.peers.lock().await,
+        &installed_versions(&*shared.installed.lock().await),
+    );
+
+    // Scripts run once everything is in place, since they usually need their
+    // dependencies (node-gyp, prebuild-install) to be installed already.
+    let log_dir = scripts::log_dir(node_modules);
+    for (key, dir) in shared.scripted.lock().await.iter() {
+        if let Err(err) = scripts::run(key, dir, node_modules, &log_dir).await {
+            failed = true;
+            eprintln!("Install script failed: {err}");
+        }
+    }
+
+    for key in shared.unscripted.lock().await.iter() {
+        println!("Skipped install scripts for {key}, add it to [scripts] allow in pkg.toml to run them");
+    }
+
+    reporter.abort();
+    shared.progress.summary(&shared.skipped.lock().await, failed);
+
+    let missing = shared.missing.lock().await;
+    if !missing.is_empty() {
+        let list = missing.iter().map(|what| format!("\n  {what}")).collect::<String>();
+        return Err(format!("offline install is missing {} package(s):{list}", missing.len()).into());
+    }
+
+    if options.prune && !failed {
+        prune(node_modules, &*shared.placed.lock().await)?;
+    }
+
+    if failed {
+        return Err("install failed, see errors above".into());
+    }
+
+    let lockfile = shared.lockfile.lock().await;
+    let changed = previous.as_ref().is_none_or(|previous| {
+        previous.resolutions != lockfile.resolutions || previous.packages.keys().ne(lockfile.packages.keys())
+    });
+
+    if !frozen && changed {
+        println!("Writing {}", options.lockfile.display());
+        lockfile.write(&options.lockfile)?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        InstallOptions, Lockfile, Registries, RegistryMeta, choose_version, digest, encode_package_name, entry_path,
+        install_all_packages, installed_version, package_dir,
+    };
+    use sha2::Sha512;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn packument(versions: &[&str], latest: &str) -> RegistryMeta {
+        let versions: serde_json::Map<_, _> = versions
+            .iter()
+            .map(|version| {
+                let dist =
+                    serde_json::json!({ "dist": { "tarball": format!("https://registry.test/pkg-{version}.tgz") } });
+                (version.to_string(), dist)
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "versions": versions, "dist-tags": { "latest": latest } })).unwrap()
+    }
+
+    #[test]
+    fn ranges_prefer_latest_when_it_satisfies() {
+        let doc = packument(&["1.0.0", "1.1.0", "1.2.0"], "1.1.0");
+        assert_eq!(choose_version(&doc, "pkg", "^1.0.0").unwrap().0, "1.1.0");
+        assert_eq!(choose_version(&doc, "pkg", ">=1.2.0").unwrap().0, "1.2.0");
+    }
+
+    #[test]
+    fn ranges_skip_a_latest_tag_missing_from_versions() {
+        let doc = packument(&["1.0.0", "1.1.0"], "1.5.0");
+        assert_eq!(choose_version(&doc, "pkg", "^1.0.0").unwrap().0, "1.1.0");
+        assert!(choose_version(&doc, "pkg", "latest").is_err());
+        assert!(choose_version(&doc, "pkg", "^2.0.0").is_err());
+    }
+
+    #[test]
+    fn package_dir_nests_scoped_names() {
+        let root = Path::new("node_modules");
+        assert_eq!(package_dir(root, "left-pad").unwrap(), root.join("left-pad"));
+        assert_eq!(
+            package_dir(root, "@types/node").unwrap(),
+            root.join("@types").join("node")
+        );
+    }
+
+    #[test]
+    fn package_dir_nests_under_a_dependency() {
+        let nested = Path::new("node_modules/@scope/parent/node_modules");
+        assert_eq!(package_dir(nested, "child").unwrap(), nested.join("child"));
+        assert_eq!(
+            package_dir(nested, "@scope/child").unwrap(),
+            nested.join("@scope").join("child")
+        );
+    }
+
+    #[test]
+    fn package_dir_rejects_names_leaving_node_modules() {
+        let root = Path::new("node_modules");
+        for name in [
+            "",
+            ".",
+            "..",
+            "a/b",
+            "@scope",
+            "@scope/",
+            "@/pkg",
+            "@../pkg",
+            "@scope/..",
+            "@scope/a/b",
+            "a\\b",
+            "c:pkg",
+        ] {
+            assert!(package_dir(root, name).is_err(), "{name:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn tarball_entries_stay_inside_the_package() {
+        assert_eq!(
+            entry_path(Path::new("package/lib/index.js")),
+            Some("lib/index.js".into())
+        );
+        assert_eq!(entry_path(Path::new("repo-main/./README.md")), Some("README.md".into()));
+        assert_eq!(entry_path(Path::new("package")), Some("".into()));
+        assert_eq!(entry_path(Path::new("package/../../x")), None);
+        assert_eq!(entry_path(Path::new("package/lib/../../x")), None);
+    }
+
+    #[test]
+    fn encoded_names_are_one_path_segment() {
+        for name in ["left-pad", "@types/node", "@scope/pkg.name"] {
+            let encoded = encode_package_name(name);
+            let url = url::Url::parse(&format!("https://registry.npmjs.org/{encoded}")).unwrap();
+            let segments = url.path_segments().unwrap().collect::<Vec<_>>();
+
+            assert_eq!(segments, [encoded.as_str()]);
+            assert_eq!(segments[0].replace("%2f", "/"), name);
+        }
+    }
+
+    type Routes = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// A registry serving `routes` by request path over plain HTTP, one
+    /// connection at a time. Returns its URL.
+    fn registry(routes: Routes) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(Result::ok) {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap_or(0) > 0 && !header.trim().is_empty() {
+                    header.clear();
+                }
+
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let (status, body) = match routes.lock().unwrap().get(path) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).and_then(|()| stream.write_all(&body));
+            }
+        });
+        url
+    }
+
+    /// A gzipped npm tarball holding only `package/package.json`.
+    fn tarball(manifest: &serde_json::Value) -> Vec<u8> {
+        let manifest = serde_json::to_vec(manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        builder
+            .append_data(&mut header, "package/package.json", manifest.as_slice())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Publishes `versions` of `name`, each with its dependencies, to `routes`
+    /// the way npm lays out a scoped package: the packument at `@scope%2fname`
+    /// and tarballs under `@scope/name/-/`.
+    fn publish(routes: &Routes, registry: &str, name: &str, versions: &[(&str, serde_json::Value)]) {
+        let basename = name.rsplit('/').next().unwrap();
+        let mut routes = routes.lock().unwrap();
+        let mut packument = serde_json::Map::new();
+
+        for (version, dependencies) in versions {
+            let manifest = serde_json::json!({ "name": name, "version": version, "dependencies": dependencies });
+            let bytes = tarball(&manifest);
+            let path = format!("{name}/-/{basename}-{version}.tgz");
+
+            let mut meta = manifest;
+            meta["dist"] = serde_json::json!({
+                "tarball": format!("{registry}{path}"),
+                "integrity": format!("sha512-{}", digest::<Sha512>(&bytes)),
+            });
+            packument.insert(version.to_string(), meta);
+            routes.insert(format!("/{path}"), bytes);
+        }
+
+        let packument = serde_json::json!({ "name": name, "versions": packument });
+        routes.insert(
+            format!("/{}", encode_package_name(name)),
+            serde_json::to_vec(&packument).unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn scoped_packages_install_nest_and_lock() {
+        let dir = std::env::temp_dir().join(format!("mass-npm-scoped-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let routes = Routes::default();
+        let url = registry(routes.clone());
+        std::fs::write(dir.join(".npmrc"), format!("@scope:registry={url}\n")).unwrap();
+
+        let none = serde_json::json!({});
+        publish(
+            &routes,
+            &url,
+            "@scope/child",
+            &[("1.0.0", none.clone()), ("2.0.0", none)],
+        );
+        publish(
+            &routes,
+            &url,
+            "@scope/parent",
+            &[("1.0.0", serde_json::json!({ "@scope/child": "^2.0.0" }))],
+        );
+
+        let roots = [("@scope/child", "1.0.0"), ("@scope/parent", "^1.0.0")];
+        let roots = || roots.map(|(name, spec)| (name.to_string(), spec.to_string()));
+        let mut options = InstallOptions {
+            lockfile: dir.join("pkg.lock"),
+            frozen: false,
+            store: None,
+            metadata_cache: None,
+            registries: Registries::load(&dir).unwrap(),
+            project: dir.clone(),
+            import: None,
+            allow_scripts: Default::default(),
+            prune: false,
+            verify: false,
+            offline: false,
+            members: vec![],
+        };
+
+        let client = reqwest::Client::new();
+        let node_modules = dir.join("node_modules");
+        install_all_packages(&client, &node_modules, roots(), &options)
+            .await
+            .unwrap();
+
+        let nested = node_modules.join("@scope/parent/node_modules/@scope/child");
+        assert_eq!(
+            installed_version(&node_modules.join("@scope/child")).as_deref(),
+            Some("1.0.0")
+        );
+        assert_eq!(
+            installed_version(&node_modules.join("@scope/parent")).as_deref(),
+            Some("1.0.0")
+        );
+        assert_eq!(installed_version(&nested).as_deref(), Some("2.0.0"));
+
+        let lockfile = Lockfile::read(&options.lockfile).unwrap().unwrap();
+        let (version, meta) = lockfile.locked("@scope/child", "^2.0.0").unwrap();
+        assert_eq!(version, "2.0.0");
+        assert_eq!(meta.dist.tarball, format!("{url}@scope/child/-/child-2.0.0.tgz"));
+        assert!(meta.dist.sri().is_some_and(|sri| sri.starts_with("sha512-")));
+
+        // Without the packuments, a frozen install has only the lockfile to go by.
+        routes.lock().unwrap().retain(|path, _| path.ends_with(".tgz"));
+        options.frozen = true;
+        let node_modules = dir.join("node_modules-frozen");
+        install_all_packages(&client, &node_modules, roots(), &options)
+            .await
+            .unwrap();
+
+        let nested = node_modules.join("@scope/parent/node_modules/@scope/child");
+        assert_eq!(
+            installed_version(&node_modules.join("@scope/child")).as_deref(),
+            Some("1.0.0")
+        );
+        assert_eq!(installed_version(&nested).as_deref(), Some("2.0.0"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
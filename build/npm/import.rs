@@ -0,0 +1,251 @@
+use super::{BASE64, Engine, LockedPackage, Lockfile, PeerMeta};
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Converts a `package-lock.json` (v2/v3) or classic `yarn.lock` into a
+/// [`Lockfile`], so an install pins the same versions and integrity hashes the
+/// project used before moving to `pkg.toml`.
+pub fn read(path: &Path) -> Result<Lockfile, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some("package-lock.json" | "npm-shrinkwrap.json") => package_lock(&contents),
+        Some("yarn.lock") => yarn_lock(&contents),
+        _ => Err(format!("{}: expected package-lock.json or yarn.lock", path.display()).into()),
+    }
+}
+
+#[derive(Deserialize)]
+struct PackageLock {
+    #[serde(default, rename = "lockfileVersion")]
+    lockfile_version: u32,
+    #[serde(default)]
+    packages: BTreeMap<String, PackageLockEntry>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageLockEntry {
+    version: Option<String>,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    link: bool,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies_meta: BTreeMap<String, PeerMeta>,
+    #[serde(default)]
+    os: Vec<String>,
+    #[serde(default)]
+    cpu: Vec<String>,
+}
+
+fn package_lock(contents: &str) -> Result<Lockfile, Box<dyn std::error::Error>> {
+    let lock: PackageLock = serde_json::from_str(contents)?;
+    if lock.lockfile_version < 2 {
+        return Err("package-lock.json v1 isn't supported, regenerate it with npm 7 or newer".into());
+    }
+
+    let mut lockfile = Lockfile::default();
+
+    for (location, entry) in &lock.packages {
+        let (Some(name), Some(version), Some(resolved)) = (package_name(location), &entry.version, &entry.resolved)
+        else {
+            continue;
+        };
+
+        if entry.link {
+            continue;
+        }
+
+        lockfile.packages.insert(
+            format!("{name}@{version}"),
+            LockedPackage {
+                resolved: resolved.clone(),
+                integrity: entry.integrity.clone(),
+                dependencies: entry.dependencies.clone(),
+                peer_dependencies: entry.peer_dependencies.clone(),
+                peer_dependencies_meta: entry.peer_dependencies_meta.clone(),
+                optional_dependencies: entry.optional_dependencies.clone(),
+                os: entry.os.clone(),
+                cpu: entry.cpu.clone(),
+            },
+        );
+    }
+
+    // Each dependency edge resolves the way node does: the closest
+    // `node_modules/<name>` walking up from the dependent's location.
+    for (location, entry) in &lock.packages {
+        let edges = entry
+            .dependencies
+            .iter()
+            .chain(&entry.optional_dependencies)
+            .chain(&entry.peer_dependencies);
+
+        for (name, spec) in edges {
+            let Some(version) = nearest(&lock.packages, location, name).and_then(|found| found.version.as_ref()) else {
+                continue;
+            };
+
+            lockfile
+                .resolutions
+                .entry(format!("{name}@{spec}"))
+                .or_insert_with(|| version.clone());
+        }
+    }
+
+    Ok(lockfile)
+}
+
+/// `node_modules/a/node_modules/@s/b` → `@s/b`; `None` for the root entry.
+fn package_name(location: &str) -> Option<&str> { location.rsplit_once("node_modules/").map(|(_, name)| name) }
+
+fn nearest<'a>(
+    packages: &'a BTreeMap<String, PackageLockEntry>, location: &str, name: &str,
+) -> Option<&'a PackageLockEntry> {
+    let mut scope = location;
+
+    loop {
+        let candidate = match scope {
+            "" => format!("node_modules/{name}"),
+            scope => format!("{scope}/node_modules/{name}"),
+        };
+
+        if let Some(entry) = packages.get(&candidate) {
+            return Some(entry);
+        }
+
+        if scope.is_empty() {
+            return None;
+        }
+
+        scope = scope.rsplit_once("/node_modules/").map_or("", |(parent, _)| parent);
+    }
+}
+
+#[derive(Default)]
+struct YarnEntry {
+    specs: Vec<(String, String)>,
+    version: Option<String>,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    dependencies: BTreeMap<String, String>,
+    optional_dependencies: BTreeMap<String, String>,
+}
+
+fn yarn_lock(contents: &str) -> Result<Lockfile, Box<dyn std::error::Error>> {
+    if contents.contains("__metadata:") {
+        return Err("yarn.lock from Yarn 2+ isn't supported, only the classic v1 format".into());
+    }
+
+    let mut entries = Vec::<YarnEntry>::new();
+    let mut section = None;
+
+    for line in contents.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let line = line.trim();
+
+        if indent == 0 {
+            let specs = line
+                .trim_end_matches(':')
+                .split(", ")
+                .filter_map(|spec| split_spec(unquote(spec)))
+                .collect();
+            entries.push(YarnEntry {
+                specs,
+                ..Default::default()
+            });
+            section = None;
+            continue;
+        }
+
+        let Some(entry) = entries.last_mut() else {
+            continue;
+        };
+
+        if indent == 2 {
+            section = None;
+            if let Some(name) = line.strip_suffix(':') {
+                section = Some(name.to_string());
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = unquote(value).to_string();
+            match key {
+                "version" => entry.version = Some(value),
+                "resolved" => entry.resolved = Some(value),
+                "integrity" => entry.integrity = Some(value),
+                _ => {}
+            }
+        } else if let Some(section) = &section {
+            let (name, spec) = line.split_once(' ').unwrap_or((line, ""));
+            let (name, spec) = (unquote(name).to_string(), unquote(spec).to_string());
+
+            match section.as_str() {
+                "dependencies" => entry.dependencies.insert(name, spec),
+                "optionalDependencies" => entry.optional_dependencies.insert(name, spec),
+                _ => None,
+            };
+        }
+    }
+
+    let mut lockfile = Lockfile::default();
+
+    for entry in entries {
+        let (Some(version), Some(resolved)) = (entry.version, entry.resolved) else {
+            continue;
+        };
+
+        // Yarn appends the legacy sha1 shasum to the URL when there's no SRI.
+        let (resolved, shasum) = match resolved.split_once('#') {
+            Some((url, shasum)) => (url.to_string(), Some(shasum.to_string())),
+            None => (resolved, None),
+        };
+        let integrity = entry.integrity.or_else(|| {
+            let bytes = hex::decode(shasum?).ok()?;
+            Some(format!("sha1-{}", BASE64.encode(bytes)))
+        });
+
+        for (name, spec) in &entry.specs {
+            lockfile.resolutions.insert(format!("{name}@{spec}"), version.clone());
+        }
+
+        let Some((name, _)) = entry.specs.first() else {
+            continue;
+        };
+
+        lockfile.packages.insert(
+            format!("{name}@{version}"),
+            LockedPackage {
+                resolved,
+                integrity,
+                dependencies: entry.dependencies,
+                peer_dependencies: BTreeMap::new(),
+                peer_dependencies_meta: BTreeMap::new(),
+                optional_dependencies: entry.optional_dependencies,
+                os: vec![],
+                cpu: vec![],
+            },
+        );
+    }
+
+    Ok(lockfile)
+}
+
+fn unquote(value: &str) -> &str { value.trim().trim_matches('"') }
+
+/// `@scope/name@^1.0.0` → (`@scope/name`, `^1.0.0`).
+fn split_spec(spec: &str) -> Option<(String, String)> {
+    let at = spec[1..].find('@')? + 1;
+    Some((spec[..at].to_string(), spec[at + 1..].to_string()))
+}
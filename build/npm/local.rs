@@ -0,0 +1,77 @@
+use super::VersionMeta;
+use std::path::{Path, PathBuf};
+
+/// Whether the dist URL or spec points at a package directory on disk.
+pub fn is_local(spec: &str) -> bool { spec.starts_with("file:") }
+
+/// The directory a local package's dist URL points at.
+pub fn source(tarball: &str) -> Option<PathBuf> { tarball.strip_prefix("file:").map(PathBuf::from) }
+
+/// Resolves `file:<path>` against `base`, the directory of the package that
+/// declares it, reading the version and dependencies from the package's own
+/// `package.json`.
+pub fn resolve(base: &Path, name: &str, spec: &str) -> Result<(String, VersionMeta), Box<dyn std::error::Error>> {
+    let path = spec.strip_prefix("file:").unwrap_or(spec);
+    let dir = base
+        .join(path)
+        .canonicalize()
+        .map_err(|err| format!("{name}@{spec}: {err}"))?;
+
+    let manifest = std::fs::read(dir.join("package.json")).map_err(|err| format!("{}: {err}", dir.display()))?;
+    let mut manifest: serde_json::Value = serde_json::from_slice(&manifest)?;
+    let version = manifest["version"].as_str().unwrap_or("0.0.0").to_string();
+
+    manifest["dist"] = serde_json::json!({ "tarball": format!("file:{}", dir.display()) });
+    Ok((version, serde_json::from_value(manifest)?))
+}
+
+/// Copies a local package into `dest`. Copies rather than links so the bundler
+/// resolves the package's own dependencies from the install tree, and always
+/// fresh because local sources change without a version bump.
+pub fn install(source: &str, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let source = self::source(source).unwrap_or_else(|| PathBuf::from(source));
+
+    if dest.exists() {
+        std::fs::remove_dir_all(dest)?;
+    }
+    Ok(copy_dir(&source, dest)?)
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "node_modules" || name == ".git" {
+            continue;
+        }
+
+        // Links are copied as links: followed, they could loop or pull in
+        // files from outside the package.
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            copy_link(&entry.path(), &dest.join(&name))?;
+        } else if file_type.is_dir() {
+            copy_dir(&entry.path(), &dest.join(&name))?;
+        } else {
+            std::fs::copy(entry.path(), dest.join(&name))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_link(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(src)?, dest)
+}
+
+#[cfg(windows)]
+fn copy_link(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let target = std::fs::read_link(src)?;
+    match std::fs::metadata(src).is_ok_and(|meta| meta.is_dir()) {
+        true => std::os::windows::fs::symlink_dir(target, dest),
+        false => std::os::windows::fs::symlink_file(target, dest),
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the reporter prints a progress line during an install.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Install counters, printed periodically and as a summary at the end. Lines
+/// are plain text with no carriage returns so they read well in CI logs.
+pub struct Progress {
+    started: Instant,
+    resolved: AtomicUsize,
+    downloaded: AtomicUsize,
+    extracted: AtomicUsize,
+    linked: AtomicUsize,
+    up_to_date: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            resolved: AtomicUsize::new(0),
+            downloaded: AtomicUsize::new(0),
+            extracted: AtomicUsize::new(0),
+            linked: AtomicUsize::new(0),
+            up_to_date: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn resolved(&self) { self.resolved.fetch_add(1, Ordering::Relaxed); }
+
+    pub fn downloaded(&self, bytes: u64) {
+        self.downloaded.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn extracted(&self) { self.extracted.fetch_add(1, Ordering::Relaxed); }
+
+    /// Materialized from the store without downloading.
+    pub fn linked(&self) { self.linked.fetch_add(1, Ordering::Relaxed); }
+
+    pub fn up_to_date(&self) { self.up_to_date.fetch_add(1, Ordering::Relaxed); }
+
+    pub fn line(&self) -> String {
+        format!(
+            "resolved {}, downloaded {} ({}), extracted {}, from store {}, up to date {} [{:.1}s]",
+            self.resolved.load(Ordering::Relaxed),
+            self.downloaded.load(Ordering::Relaxed),
+            format_bytes(self.bytes.load(Ordering::Relaxed)),
+            self.extracted.load(Ordering::Relaxed),
+            self.linked.load(Ordering::Relaxed),
+            self.up_to_date.load(Ordering::Relaxed),
+            self.started.elapsed().as_secs_f64(),
+        )
+    }
+
+    pub fn summary(&self, skipped: &[String], failed: bool) {
+        let installed = self.extracted.load(Ordering::Relaxed) + self.linked.load(Ordering::Relaxed);
+        let outcome = if failed { "Install failed" } else { "Install finished" };
+
+        println!("{outcome}: {installed} installed, {}", self.line());
+        if !skipped.is_empty() {
+            println!(
+                "Skipped {} optional dependenc{}:",
+                skipped.len(),
+                if skipped.len() == 1 { "y" } else { "ies" }
+            );
+            for skipped in skipped {
+                println!("  {skipped}");
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1_024 => format!("{bytes} B"),
+        1_024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1_024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
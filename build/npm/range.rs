@@ -0,0 +1,239 @@
+use semver::{BuildMetadata, Version, VersionReq};
+
+/// An npm version range: comparator sets joined by `||`, each a conjunction
+/// of comparators. Every set is lowered to a Cargo [`VersionReq`], whose
+/// prerelease rule is npm's: a prerelease only matches a set with a comparator
+/// on the same `major.minor.patch` that carries a prerelease itself, so
+/// `^1.0.0-beta.2` takes `1.0.0-beta.3` and `1.4.0` but not `1.4.0-rc.1`.
+///
+/// What differs is the syntax, which is rewritten: a bare version is exact
+/// (`=`, not Cargo's caret), comparators are separated by spaces, `a - b` is
+/// an inclusive hyphen range, `x`/`X`/`*` are wildcards, `~>` means `~`, and
+/// `v` prefixes and build metadata are ignored.
+#[derive(Debug)]
+pub struct Range(Vec<VersionReq>);
+
+impl Range {
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        spec.split("||")
+            .map(|set| {
+                let comparators = comparator_set(set.trim());
+                VersionReq::parse(&comparators).map_err(|err| format!("invalid range {spec}: {err}").into())
+            })
+            .collect::<Result<_, Box<dyn std::error::Error>>>()
+            .map(Self)
+    }
+
+    /// Whether `version` satisfies any comparator set. Build metadata never
+    /// takes part in matching.
+    pub fn matches(&self, version: &Version) -> bool {
+        let version = without_build(version);
+        self.0.iter().any(|req| req.matches(&version))
+    }
+
+    /// The highest of `versions` the range accepts. Versions differing only in
+    /// build metadata rank equal, the first listed wins.
+    pub fn max_satisfying<'a>(&self, versions: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        let mut best: Option<(Version, &str)> = None;
+
+        for candidate in versions {
+            let Ok(version) = Version::parse(candidate.trim_start_matches('v')) else {
+                continue;
+            };
+            if !self.matches(&version) {
+                continue;
+            }
+
+            let version = without_build(&version);
+            if best.as_ref().is_none_or(|(top, _)| version > *top) {
+                best = Some((version, candidate));
+            }
+        }
+
+        best.map(|(_, candidate)| candidate)
+    }
+}
+
+fn without_build(version: &Version) -> Version {
+    Version {
+        build: BuildMetadata::EMPTY,
+        ..version.clone()
+    }
+}
+
+/// Rewrites one npm comparator set into Cargo's comma-separated syntax.
+fn comparator_set(set: &str) -> String {
+    if let Some((low, high)) = set.split_once(" - ") {
+        return match (partial(low.trim()), partial(high.trim())) {
+            (None, None) => "*".to_string(),
+            (Some(low), None) => format!(">={low}"),
+            (None, Some(high)) => format!("<={high}"),
+            (Some(low), Some(high)) => format!(">={low}, <={high}"),
+        };
+    }
+
+    // npm allows whitespace between an operator and its version: `>= 1.2.3`.
+    let mut comparators = Vec::new();
+    let mut pending_op = String::new();
+    for token in set.split_whitespace() {
+        if token.chars().all(|c| matches!(c, '<' | '>' | '=' | '~' | '^')) {
+            pending_op.push_str(token);
+            continue;
+        }
+        comparators.push(comparator(&format!("{pending_op}{token}")));
+        pending_op.clear();
+    }
+
+    if comparators.is_empty() {
+        "*".to_string()
+    } else {
+        comparators.join(", ")
+    }
+}
+
+fn comparator(token: &str) -> String {
+    let (op, version) = ["~>", ">=", "<=", ">", "<", "=", "^", "~"]
+        .into_iter()
+        .find_map(|op| token.strip_prefix(op).map(|rest| (op, rest)))
+        .unwrap_or(("", token));
+
+    match (op, partial(version)) {
+        // `<*` and `>*` can't be satisfied; `0.0.0-0` sorts below every version.
+        ("<" | ">", None) => "<0.0.0-0".to_string(),
+        (_, None) => "*".to_string(),
+        ("" | "=", Some(version)) => format!("={version}"),
+        ("~>", Some(version)) => format!("~{version}"),
+        (op, Some(version)) => format!("{op}{version}"),
+    }
+}
+
+/// A version with its `v`/`=` prefix and build metadata dropped and cut off at
+/// the first wildcard component, `None` when nothing is left (`*`, `x.x`).
+fn partial(version: &str) -> Option<String> {
+    let version = version.trim_start_matches(['v', '=']);
+    let version = version.split_once('+').map_or(version, |(version, _)| version);
+
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (version, None),
+    };
+
+    let parts = core
+        .split('.')
+        .take_while(|part| !matches!(*part, "x" | "X" | "*" | ""))
+        .collect::<Vec<_>>();
+
+    match (parts.len(), pre) {
+        (0, _) => None,
+        (3, Some(pre)) => Some(format!("{}-{pre}", parts.join("."))),
+        _ => Some(parts.join(".")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Range;
+    use semver::Version;
+
+    fn matches(range: &str, version: &str) -> bool {
+        Range::parse(range).unwrap().matches(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn caret_and_tilde() {
+        assert!(matches("^1.2.3", "1.9.0"));
+        assert!(!matches("^1.2.3", "2.0.0"));
+        assert!(!matches("^1.2.3", "1.2.2"));
+        assert!(matches("^0.2.3", "0.2.9"));
+        assert!(!matches("^0.2.3", "0.3.0"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(matches("~>1.2", "1.2.0"));
+    }
+
+    #[test]
+    fn bare_versions_are_exact() {
+        assert!(matches("1.2.3", "1.2.3"));
+        assert!(!matches("1.2.3", "1.2.4"));
+        assert!(matches("v1.2.3", "1.2.3"));
+        assert!(matches("=1.2.3+build", "1.2.3"));
+    }
+
+    #[test]
+    fn wildcards() {
+        for range in ["", "*", "x", "X.x.x"] {
+            assert!(matches(range, "3.1.4"), "{range:?}");
+        }
+        assert!(matches("1.x", "1.8.0"));
+        assert!(!matches("1.x", "2.0.0"));
+        assert!(matches("1.2.x", "1.2.7"));
+        assert!(!matches("1.2.x", "1.3.0"));
+        assert!(!matches("<*", "0.0.1"));
+    }
+
+    #[test]
+    fn comparators_and_alternatives() {
+        assert!(matches(">=1.2.3 <2", "1.5.0"));
+        assert!(!matches(">=1.2.3 <2", "2.0.0"));
+        assert!(matches(">= 1.2.3 < 2", "1.2.3"));
+        assert!(matches("^1.0.0 || ^3.0.0", "3.4.0"));
+        assert!(!matches("^1.0.0 || ^3.0.0", "2.0.0"));
+        assert!(Range::parse("^1.0.0 || not-a-version").is_err());
+    }
+
+    #[test]
+    fn hyphen_ranges_are_inclusive() {
+        assert!(matches("1.2.3 - 2.3.4", "1.2.3"));
+        assert!(matches("1.2.3 - 2.3.4", "2.3.4"));
+        assert!(!matches("1.2.3 - 2.3.4", "2.3.5"));
+        assert!(matches("1.2 - 2.3", "2.3.9"));
+        assert!(!matches("1.2 - 2.3", "2.4.0"));
+        assert!(!matches("1.2 - 2.3", "1.1.9"));
+    }
+
+    #[test]
+    fn prereleases_need_a_prerelease_on_their_version() {
+        assert!(!matches("^1.0.0", "1.4.0-rc.1"));
+        assert!(!matches("*", "1.0.0-beta"));
+        assert!(matches("^1.0.0-beta.2", "1.0.0-beta.3"));
+        assert!(!matches("^1.0.0-beta.2", "1.0.0-beta.1"));
+        assert!(matches("^1.0.0-beta.2", "1.4.0"));
+        assert!(!matches("^1.0.0-beta.2", "1.4.0-rc.1"));
+        assert!(!matches("1.2.3 - 2.3.4", "2.0.0-alpha"));
+    }
+
+    #[test]
+    fn max_satisfying_picks_the_highest_match() {
+        let versions = ["1.0.0", "1.2.0", "1.10.0", "2.0.0", "1.11.0-rc.1", "v1.9.0"];
+        let range = Range::parse("^1.0.0").unwrap();
+        assert_eq!(range.max_satisfying(versions), Some("1.10.0"));
+        assert_eq!(Range::parse("~1.9").unwrap().max_satisfying(versions), Some("v1.9.0"));
+        assert_eq!(Range::parse("^3").unwrap().max_satisfying(versions), None);
+    }
+
+    #[test]
+    fn max_satisfying_only_takes_prereleases_it_allows() {
+        let versions = ["1.0.0-beta.1", "1.0.0-beta.4", "1.0.0", "1.1.0-rc.1"];
+        assert_eq!(
+            Range::parse("^1.0.0-beta.1").unwrap().max_satisfying(versions),
+            Some("1.0.0")
+        );
+        assert_eq!(
+            Range::parse("1.0.0-beta.1 - 1.0.0-beta.9")
+                .unwrap()
+                .max_satisfying(versions),
+            Some("1.0.0-beta.4")
+        );
+        assert_eq!(
+            Range::parse(">=1.1.0-rc.0").unwrap().max_satisfying(versions),
+            Some("1.1.0-rc.1")
+        );
+        assert_eq!(Range::parse("1.1.x").unwrap().max_satisfying(versions), None);
+    }
+
+    #[test]
+    fn build_metadata_is_ignored() {
+        let versions = ["1.0.0+a", "1.0.0+b"];
+        assert_eq!(Range::parse("1.0.0").unwrap().max_satisfying(versions), Some("1.0.0+a"));
+    }
+}
@@ -0,0 +1,174 @@
+use futures::future::BoxFuture;
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+type Connect = dyn Fn(reqwest::Url) -> BoxFuture<'static, Box<dyn Send>> + Send + Sync;
+
+/// Asked before every registry request with its URL; resolves once the
+/// request may go out, to what has to be held until its response is read.
+#[derive(Clone, Default)]
+struct Connector(Option<Arc<Connect>>);
+
+impl std::fmt::Debug for Connector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Connector").field(&self.0.is_some()).finish()
+    }
+}
+
+/// Registry configuration: a default registry, per-scope overrides and auth
+/// tokens keyed by `//host/path/` the way `.npmrc` does.
+#[derive(Clone, Debug)]
+pub struct Registries {
+    default: String,
+    scopes: BTreeMap<String, String>,
+    tokens: BTreeMap<String, String>,
+    connector: Connector,
+}
+
+impl Default for Registries {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_REGISTRY.to_string(),
+            scopes: BTreeMap::new(),
+            tokens: BTreeMap::new(),
+            connector: Connector::default(),
+        }
+    }
+}
+
+impl Registries {
+    /// Reads `.npmrc` from `dir` and then the home directory (the first setting
+    /// wins), after which `MASS_NPM_REGISTRY` and `MASS_NPM_TOKEN` override the
+    /// default registry and its token.
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut registries = Self::default();
+        let mut files = vec![dir.join(".npmrc")];
+        if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+            files.push(Path::new(&home).join(".npmrc"));
+        }
+
+        let mut settings = BTreeMap::new();
+        for file in files.iter().rev() {
+            if let Ok(contents) = std::fs::read_to_string(file) {
+                settings.extend(parse_npmrc(&contents));
+            }
+        }
+
+        for (key, value) in settings {
+            if key == "registry" {
+                registries.default = with_trailing_slash(value);
+            } else if let Some(scope) = key.strip_suffix(":registry") {
+                registries.scopes.insert(scope.to_string(), with_trailing_slash(value));
+            } else if let Some(prefix) = key.strip_suffix(":_authToken") {
+                registries.tokens.insert(with_trailing_slash(prefix.to_string()), value);
+            }
+        }
+
+        if let Ok(url) = std::env::var("MASS_NPM_REGISTRY") {
+            registries.default = with_trailing_slash(url);
+        }
+
+        if let Ok(token) = std::env::var("MASS_NPM_TOKEN") {
+            let prefix = auth_prefix(&registries.default).to_string();
+            registries.tokens.insert(prefix, token);
+        }
+
+        Ok(registries)
+    }
+
+    /// Registry serving `name`, picked by its scope if it has one.
+    pub fn registry_for(&self, name: &str) -> &str {
+        name.split_once('/')
+            .filter(|(scope, _)| scope.starts_with('@'))
+            .and_then(|(scope, _)| self.scopes.get(scope))
+            .unwrap_or(&self.default)
+    }
+
+    /// The default registry and then each scope's, without repeats.
+    #[cfg(runtime)]
+    pub fn urls(&self) -> Vec<&str> {
+        let mut urls = vec![self.default.as_str()];
+        for url in self.scopes.values() {
+            if !urls.contains(&url.as_str()) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// Token for `url`, from the longest configured `//host/path/` prefix it falls under.
+    pub fn token_for(&self, url: &str) -> Option<&str> {
+        let target = auth_prefix(url);
+
+        self.tokens
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, token)| token.as_str())
+    }
+
+    /// Sends each request once `connect`, called with its URL, resolves, and
+    /// holds on to what it resolved to until the response has been read.
+    #[cfg(runtime)]
+    pub fn with_connect(
+        mut self, connect: impl Fn(reqwest::Url) -> BoxFuture<'static, Box<dyn Send>> + Send + Sync + 'static,
+    ) -> Self {
+        self.connector = Connector(Some(Arc::new(connect)));
+        self
+    }
+
+    /// Waits until a request to `url` is allowed out, returning what to keep
+    /// until its response has been read.
+    pub async fn connect(&self, url: &str) -> Option<Box<dyn Send>> {
+        let connect = self.connector.0.as_ref()?;
+        let url = reqwest::Url::parse(url).ok()?;
+        Some(connect(url).await)
+    }
+
+    pub fn authorize(&self, request: reqwest::RequestBuilder, url: &str) -> reqwest::RequestBuilder {
+        match self.token_for(url) {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// `https://host/path` → `//host/path`, the form `.npmrc` keys tokens by.
+fn auth_prefix(url: &str) -> &str { url.find("//").map_or(url, |start| &url[start..]) }
+
+fn with_trailing_slash(mut url: String) -> String {
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    url
+}
+
+/// Parses `key=value` lines, skipping comments and expanding `${VAR}`.
+fn parse_npmrc(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), expand_env(value.trim().trim_matches('"'))))
+        .collect()
+}
+
+fn expand_env(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&std::env::var(&rest[start + 2..start + end]).unwrap_or_default());
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
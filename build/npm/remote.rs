@@ -0,0 +1,198 @@
+use super::{Registries, VersionMeta, digest, download, download_to};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+use tar::Archive;
+
+/// Whether `spec` points at a git repository or tarball instead of a registry range.
+pub fn is_remote(spec: &str) -> bool { tarball_url(spec).is_some() }
+
+/// Where to fetch a remote spec from. `github:user/repo#ref` becomes a codeload
+/// tarball, `git+…` URLs are kept and fetched with git, and plain http(s)
+/// URLs are used as they are.
+pub fn tarball_url(spec: &str) -> Option<String> {
+    if let Some(repo) = spec.strip_prefix("github:") {
+        let (repo, reference) = repo.split_once('#').unwrap_or((repo, "HEAD"));
+        return Some(format!("https://codeload.github.com/{repo}/tar.gz/{reference}"));
+    }
+
+    if spec.starts_with("git+") || spec.starts_with("https://") || spec.starts_with("http://") {
+        return Some(spec.to_string());
+    }
+
+    None
+}
+
+/// `git archive`s made while resolving, by the pinned `git+…#<sha>` URL they
+/// resolved to, so installing them doesn't clone the repository again.
+static ARCHIVES: LazyLock<Mutex<HashMap<String, Vec<u8>>>> = LazyLock::new(Default::default);
+
+/// Fetches a gzipped package tarball straight into the file at `path`,
+/// producing one with `git archive` for git URLs. Returns its size.
+pub async fn fetch_to(
+    client: &reqwest::Client, registries: &Registries, url: &str, path: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    match url.strip_prefix("git+") {
+        Some(repo) => {
+            let archived = ARCHIVES.lock().unwrap().remove(url);
+            let archive = match archived {
+                Some(archive) => archive,
+                None => archive_git(repo).await?.1,
+            };
+            tokio::fs::write(path, &archive).await?;
+            Ok(archive.len() as u64)
+        }
+        None => download_to(client, registries, url, path).await,
+    }
+}
+
+/// Runs git in `dir`, failing with what it printed to stderr.
+async fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+/// Checks `repo` out at `#<reference>`, a branch, tag or commit, or at its
+/// default branch without one, and archives it. Returns the commit it was
+/// archived at along with the archive.
+async fn archive_git(repo: &str) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
+    let (repo, reference) = match repo.split_once('#') {
+        Some((repo, reference)) => (repo, Some(reference)),
+        None => (repo, None),
+    };
+
+    let id = hex::encode(&Sha256::digest(repo)[..8]);
+    let checkout = std::env::temp_dir().join(format!("mass-git-{}-{id}", std::process::id()));
+    if checkout.exists() {
+        std::fs::remove_dir_all(&checkout)?;
+    }
+    std::fs::create_dir_all(&checkout)?;
+
+    let archived = async {
+        // `--` keeps a repository such as `--upload-pack=…` from being taken
+        // for an option.
+        git(
+            &checkout,
+            &["clone", "--quiet", "--no-checkout", "--depth", "1", "--", repo, "."],
+        )
+        .await?;
+
+        // Fetched rather than cloned with `--branch`, which takes branches and
+        // tags but not commits.
+        if let Some(reference) = reference {
+            git(
+                &checkout,
+                &["fetch", "--quiet", "--depth", "1", "origin", "--", reference],
+            )
+            .await?;
+            git(&checkout, &["checkout", "--quiet", "FETCH_HEAD"]).await?;
+        }
+
+        let sha = String::from_utf8(git(&checkout, &["rev-parse", "HEAD"]).await?)?;
+        let archive = git(&checkout, &["archive", "--format=tar.gz", "--prefix=package/", "HEAD"]).await?;
+        Ok::<_, Box<dyn std::error::Error>>((sha.trim().to_string(), archive))
+    }
+    .await;
+
+    let _ = std::fs::remove_dir_all(&checkout);
+    archived.map_err(|err| format!("{repo}: {err}").into())
+}
+
+/// `github:user/repo#ref` as the codeload tarball of the commit `ref` points at
+/// now, so a locked install keeps fetching that commit after the branch moves.
+async fn pin_github(
+    client: &reqwest::Client, registries: &Registries, repo: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    use reqwest::header::{ACCEPT, USER_AGENT};
+
+    let (repo, reference) = repo.split_once('#').unwrap_or((repo, "HEAD"));
+    let url = format!("https://api.github.com/repos/{repo}/commits/{reference}");
+
+    let _connection = registries.connect(&url).await;
+    let sha = client
+        .get(&url)
+        .header(USER_AGENT, concat!("mass/", env!("CARGO_PKG_VERSION")))
+        .header(ACCEPT, "application/vnd.github.sha")
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|err| format!("github:{repo}#{reference}: {err}"))?
+        .text()
+        .await?;
+
+    let sha = sha.trim();
+    if sha.len() != 40 || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("github:{repo}#{reference} resolved to {sha:?}, not a commit").into());
+    }
+    Ok(format!("https://codeload.github.com/{repo}/tar.gz/{sha}"))
+}
+
+/// Resolves a remote spec by fetching it and reading the `package.json` inside.
+/// A downloaded tarball's sha512 becomes its integrity, so the lockfile pins the
+/// exact contents even when the spec names a moving branch; a `github:` spec is
+/// also locked to the tarball of the commit its ref resolved to. `git archive`
+/// output isn't byte-stable across git versions, so a git source is pinned to
+/// the commit it resolved to instead, as `git+<repo>#<sha>`.
+pub async fn resolve(
+    client: &reqwest::Client, registries: &Registries, name: &str, spec: &str,
+) -> Result<(String, VersionMeta), Box<dyn std::error::Error>> {
+    let url = tarball_url(spec).ok_or_else(|| format!("{name}@{spec} is not a git or tarball dependency"))?;
+    let (url, bytes) = match url.strip_prefix("git+") {
+        Some(repo) => {
+            let (sha, bytes) = archive_git(repo).await?;
+            let repo = repo.split_once('#').map_or(repo, |(repo, _)| repo);
+            (format!("git+{repo}#{sha}"), bytes)
+        }
+        None => {
+            let url = match spec.strip_prefix("github:") {
+                Some(repo) => pin_github(client, registries, repo).await?,
+                None => url,
+            };
+            let bytes = download(client, registries, &url).await?;
+            (url, bytes)
+        }
+    };
+
+    let mut archive = Archive::new(GzDecoder::new(bytes.as_slice()));
+    let mut manifest = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.components().count() == 2 && entry.path()?.ends_with("package.json") {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            manifest = Some(contents);
+            break;
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| format!("{url} has no package.json"))?;
+    let mut manifest: serde_json::Value = serde_json::from_str(&manifest)?;
+    let version = manifest["version"].as_str().unwrap_or("0.0.0").to_string();
+
+    manifest["dist"] = match url.starts_with("git+") {
+        true => serde_json::json!({ "tarball": url }),
+        false => serde_json::json!({ "tarball": url, "integrity": format!("sha512-{}", digest::<Sha512>(&bytes)) }),
+    };
+    let meta = serde_json::from_value(manifest)?;
+
+    if url.starts_with("git+") {
+        ARCHIVES.lock().unwrap().insert(url, bytes);
+    }
+    Ok((version, meta))
+}
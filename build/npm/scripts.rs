@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Lifecycle hooks npm runs on install, in order.
+const HOOKS: [&str; 3] = ["preinstall", "install", "postinstall"];
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(300);
+const MARKER: &str = ".mass-scripts";
+
+/// Install hooks the package at `dir` declares, in the order they run.
+pub fn hooks(dir: &Path) -> Vec<(&'static str, String)> {
+    let Ok(manifest) = std::fs::read(dir.join("package.json")) else {
+        return vec![];
+    };
+    let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(&manifest) else {
+        return vec![];
+    };
+
+    HOOKS
+        .iter()
+        .filter_map(|hook| Some((*hook, manifest["scripts"][hook].as_str()?.to_string())))
+        .collect()
+}
+
+/// Whether the hooks already ran successfully for the copy at `dir`.
+pub fn ran(dir: &Path) -> bool { dir.join(MARKER).exists() }
+
+/// Runs the install hooks of an allowlisted package. They are not sandboxed:
+/// scripts run with the privileges of the install itself and can read and
+/// write whatever it can and reach the network, which is why only packages in
+/// the allowlist get to run them. What they do get is an environment cleared
+/// down to `PATH` and the npm lifecycle variables, so tokens in the installer's
+/// own environment don't leak into them, a temporary `HOME`, removed again
+/// afterwards, no stdin and a timeout. Their output goes to
+/// `<log_dir>/<name>.log`.
+pub async fn run(key: &str, dir: &Path, node_modules: &Path, log_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let (name, _) = key.rsplit_once('@').unwrap_or((key, ""));
+    let home = std::env::temp_dir().join(format!(
+        "mass-scripts-{}-{}",
+        std::process::id(),
+        super::encode_package_name(name)
+    ));
+
+    std::fs::create_dir_all(&home)?;
+    let result = run_hooks(key, dir, node_modules, log_dir, &home).await;
+    let _ = std::fs::remove_dir_all(&home);
+    result?;
+
+    std::fs::write(dir.join(MARKER), b"")?;
+    Ok(())
+}
+
+async fn run_hooks(
+    key: &str, dir: &Path, node_modules: &Path, log_dir: &Path, home: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (name, version) = key.rsplit_once('@').unwrap_or((key, ""));
+    let log_path = log_dir.join(format!("{}.log", super::encode_package_name(name)));
+    std::fs::create_dir_all(log_dir)?;
+
+    let bin = node_modules.join(".bin");
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::var_os("PATH").iter().flat_map(std::env::split_paths)),
+    )?;
+
+    for (hook, script) in hooks(dir) {
+        println!("Running {hook} script for {key}");
+        let log = std::fs::OpenOptions::new().create(true).append(true).open(&log_path)?;
+
+        let mut command = shell(&script);
+        command
+            .current_dir(dir)
+            .env_clear()
+            .env("PATH", &path)
+            .env("HOME", home)
+            .env("npm_lifecycle_event", hook)
+            .env("npm_package_name", name)
+            .env("npm_package_version", version)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .kill_on_drop(true);
+
+        let status = match tokio::time::timeout(SCRIPT_TIMEOUT, command.status()).await {
+            Ok(status) => status?,
+            Err(_) => return Err(format!("{hook} script for {key} timed out after {SCRIPT_TIMEOUT:?}").into()),
+        };
+
+        if !status.success() {
+            return Err(format!(
+                "{hook} script for {key} failed with {status}, see {}",
+                log_path.display()
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+fn shell(script: &str) -> tokio::process::Command {
+    let mut command = if cfg!(windows) {
+        let mut command = tokio::process::Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = tokio::process::Command::new("sh");
+        command.arg("-c");
+        command
+    };
+
+    command.arg(script);
+    command
+}
+
+pub fn log_dir(node_modules: &Path) -> PathBuf { node_modules.join(".mass").join("scripts") }
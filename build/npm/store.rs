@@ -0,0 +1,138 @@
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Global content-addressable package store. Each entry is an extracted package
+/// keyed by name, version and integrity, materialized into node_modules with
+/// hardlinks so clean builds don't download or extract anything twice.
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn new(root: PathBuf) -> Self { Self { root } }
+
+    /// `$MASS_STORE_DIR`, or `mass/store` under the platform cache directory.
+    pub fn default_root() -> Option<PathBuf> {
+        match std::env::var_os("MASS_STORE_DIR") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => Some(cache_root()?.join("store")),
+        }
+    }
+
+    pub fn entry(&self, name: &str, version: &str, integrity: Option<&str>) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{name}@{version}\0{}", integrity.unwrap_or_default()));
+        self.root.join(hex::encode(hasher.finalize()))
+    }
+
+    pub fn contains(&self, entry: &Path) -> bool { entry.join(".mass-complete").exists() }
+
+    /// Directory to extract a new entry into before [`Store::commit`] moves it
+    /// into place, so an interrupted extraction never looks complete.
+    pub fn staging(&self, entry: &Path) -> PathBuf { entry.with_extension(format!("tmp-{}", std::process::id())) }
+
+    pub fn commit(&self, staging: &Path, entry: &Path) -> std::io::Result<()> {
+        std::fs::write(staging.join(".mass-complete"), b"")?;
+
+        match std::fs::rename(staging, entry) {
+            Ok(()) => Ok(()),
+            // Another build committed the same entry first; theirs is just as good.
+            Err(_) if self.contains(entry) => std::fs::remove_dir_all(staging),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// `mass` under the platform cache directory, shared by the store and the
+/// registry metadata cache.
+pub fn cache_root() -> Option<PathBuf> {
+    let cache = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("LOCALAPPDATA")?),
+        None if cfg!(target_os = "macos") => PathBuf::from(std::env::var_os("HOME")?).join("Library/Caches"),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(cache.join("mass"))
+}
+
+/// Recreates `src` at `dest`, hardlinking files and falling back to a copy when
+/// linking isn't possible (different filesystem, unsupported platform). Pass
+/// `link: false` for packages whose install scripts may modify their files, so
+/// the store entry stays pristine.
+pub fn materialize(src: &Path, dest: &Path, link: bool) -> std::io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if entry.file_name() == ".mass-complete" {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            materialize(&from, &to, link)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(std::fs::read_link(&from)?, &to)?;
+            #[cfg(not(unix))]
+            std::fs::copy(&from, &to).map(|_| ())?;
+        } else if !link || std::fs::hard_link(&from, &to).is_err() {
+            std::fs::copy(&from, &to)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `installed` holds exactly the files of `pristine`, with the same
+/// contents and symlinks pointing at the same targets. A file added to
+/// `installed` counts as a difference too, except its top `node_modules`,
+/// where the dependencies nested under a package are placed.
+pub fn same_tree(pristine: &Path, installed: &Path) -> std::io::Result<bool> { same_dir(pristine, installed, true) }
+
+fn same_dir(pristine: &Path, installed: &Path, top: bool) -> std::io::Result<bool> {
+    let mut expected = std::collections::HashSet::new();
+
+    for entry in std::fs::read_dir(pristine)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let file_type = entry.file_type()?;
+        let target = installed.join(&name);
+
+        if name == ".mass-complete" {
+            continue;
+        }
+
+        let same = if file_type.is_symlink() {
+            std::fs::read_link(&target)
+                .is_ok_and(|link| std::fs::read_link(entry.path()).is_ok_and(|want| link == want))
+        } else if file_type.is_dir() {
+            target.is_dir() && !target.is_symlink() && same_dir(&entry.path(), &target, false)?
+        } else {
+            !target.is_symlink()
+                && match std::fs::read(&target) {
+                    Ok(contents) => contents == std::fs::read(entry.path())?,
+                    Err(_) => false,
+                }
+        };
+
+        if !same {
+            return Ok(false);
+        }
+        expected.insert(name);
+    }
+
+    for entry in std::fs::read_dir(installed)? {
+        let name = entry?.file_name();
+        let ours = name == ".mass-complete" || (top && name == "node_modules");
+        if !expected.contains(&name) && !ours {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,911 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use syn::{
+    Attribute, Expr, Fields, FnArg, GenericArgument, Item, Lit, Meta, Pat, PathArguments, ReturnType, Type, UseTree,
+};
+
+/// Typings for every `#[op2]` in mass/modules.rs, as `ext:core/ops` exports,
+/// for the serde types they take and return, under `MassOps`, and for the
+/// members of `MASS` in mass/runtime/entry.js that are ops.
+pub fn generate(root: &Path) -> Result<(), Box<dyn Error>> {
+    let mut types = Types::new(root.join("mass/modules"));
+    let file = syn::parse_file(&std::fs::read_to_string(root.join("mass/modules.rs"))?)?;
+    types.files.insert(String::new(), Module::new(&file));
+
+    let mut ops = HashMap::new();
+    let mut exports = String::new();
+    for item in &file.items {
+        let Item::Fn(function) = item else { continue };
+        if !function.attrs.iter().any(|attr| attr.path().is_ident("op2")) {
+            continue;
+        }
+        let op = types.op(function);
+        exports.push_str(&docs(&op.docs, "  "));
+        writeln!(
+            exports,
+            "  export function {}({}): {};",
+            op.name,
+            op.params.join(", "),
+            op.returns
+        )?;
+        ops.insert(op.name.clone(), op);
+    }
+
+    let entry = std::fs::read_to_string(root.join("mass/runtime/entry.js"))?;
+    let mass = entry
+        .find("globalThis.MASS =")
+        .and_then(|at| {
+            let mut scanner = Scanner {
+                source: &entry,
+                at: at + "globalThis.MASS =".len(),
+            };
+            scanner.object()
+        })
+        .ok_or("mass/runtime/entry.js has no `globalThis.MASS = { ... }` to type")?;
+    let mut interfaces = Vec::new();
+    declare_mass("OPS_MASS", &mass, &ops, &mut interfaces);
+
+    let mut output = String::from(
+        "// Generated from the #[op2] functions in mass/modules.rs and the `MASS` object in\n\
+         // mass/runtime/entry.js by build/typings.rs, do not edit.\n\
+         /// <reference path=\"../../runtime.d.ts\" />\n\n",
+    );
+    writeln!(output, "declare module 'ext:core/ops' {{\n{exports}}}\n")?;
+    output.push_str(&types.declarations());
+    // runtime.d.ts declares the members that aren't simply ops into the same
+    // interfaces.
+    for interface in interfaces {
+        write!(output, "\n{interface}")?;
+    }
+
+    // Written only when it changed, so it doesn't rerun the build script.
+    let path = root.join("mass/runtime/ops.d.ts");
+    if std::fs::read_to_string(&path).ok().as_deref() != Some(output.as_str()) {
+        std::fs::write(path, output)?;
+    }
+    Ok(())
+}
+
+/// An `#[op2]` as JS calls it.
+struct Op {
+    name: String,
+    docs: Vec<Attribute>,
+    /// `name: type`, for what JS passes.
+    params: Vec<String>,
+    returns: String,
+}
+
+impl Op {
+    /// The method `member` of `MASS` is, passing its own parameters on to
+    /// this op as `forwarded` says, or being the op itself when it's `None`.
+    fn method(&self, member: &str, forwarded: Option<&[Forwarded]>) -> Option<String> {
+        let params = match forwarded {
+            None => self.params.clone(),
+            Some(forwarded) if forwarded.len() == self.params.len() => forwarded
+                .iter()
+                .map(|param| {
+                    let (_, ty) = self.params[param.index].split_once(": ")?;
+                    Some(match param.optional {
+                        true => format!("{}?: {}", param.name, ty.strip_suffix(" | null").unwrap_or(ty)),
+                        false => format!("{}: {ty}", param.name),
+                    })
+                })
+                .collect::<Option<_>>()?,
+            Some(_) => return None,
+        };
+        Some(format!("  {member}({}): {};\n", params.join(", "), self.returns))
+    }
+}
+
+/// A member of the `MASS` object literal in mass/runtime/entry.js.
+enum Member {
+    Object(Vec<(String, Member)>),
+    /// An op, or an arrow function passing its parameters straight on to one.
+    Op {
+        op: String,
+        forwarded: Option<Vec<Forwarded>>,
+    },
+    /// Anything else, which runtime.d.ts declares by hand.
+    Other,
+}
+
+/// A parameter of a `MASS` member that it passes on to its op.
+struct Forwarded {
+    name: String,
+    /// Which of the op's parameters it's passed as.
+    index: usize,
+    /// Passed as `name ?? null`, so it may be left out.
+    optional: bool,
+}
+
+fn is_ident(text: &str) -> bool {
+    !text.is_empty()
+        && !text.starts_with(|char: char| char.is_ascii_digit())
+        && text
+            .chars()
+            .all(|char| char.is_alphanumeric() || char == '_' || char == '$')
+}
+
+fn is_op(text: &str) -> bool { text.starts_with("op_") && is_ident(text) }
+
+/// What a `MASS` member is, by the expression it's set to.
+fn member(value: &str) -> Member {
+    if is_op(value) {
+        return Member::Op {
+            op: value.to_string(),
+            forwarded: None,
+        };
+    }
+
+    let Some((params, body)) = value.split_once("=>") else {
+        return Member::Other;
+    };
+    let params = params.trim();
+    let params: Vec<&str> = params
+        .strip_prefix('(')
+        .and_then(|params| params.strip_suffix(')'))
+        .unwrap_or(params)
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .collect();
+    let Some((op, args)) = body.trim().strip_suffix(')').and_then(|body| body.split_once('(')) else {
+        return Member::Other;
+    };
+
+    let args: Vec<(&str, bool)> = args
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| match arg.strip_suffix("?? null") {
+            Some(arg) => (arg.trim(), true),
+            None => (arg, false),
+        })
+        .collect();
+    let plain = is_op(op.trim())
+        && params.iter().all(|param| is_ident(param))
+        && args.len() == params.len()
+        && args.iter().all(|(arg, _)| params.contains(arg));
+    if !plain {
+        return Member::Other;
+    }
+
+    let forwarded = params
+        .iter()
+        .filter_map(|param| {
+            let index = args.iter().position(|(arg, _)| arg == param)?;
+            Some(Forwarded {
+                name: param.to_string(),
+                index,
+                optional: args[index].1,
+            })
+        })
+        .collect();
+    Member::Op {
+        op: op.trim().to_string(),
+        forwarded: Some(forwarded),
+    }
+}
+
+/// Just enough of a JS scanner to read the `MASS` object literal with.
+struct Scanner<'a> {
+    source: &'a str,
+    at: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn rest(&self) -> &'a str { &self.source[self.at..] }
+
+    fn peek(&self) -> Option<u8> { self.source.as_bytes().get(self.at).copied() }
+
+    /// Skips whitespace and comments.
+    fn trivia(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.at += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.at += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.at += trimmed.find("*/").map_or(trimmed.len(), |end| end + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.trivia();
+        let eaten = self.rest().starts_with(token);
+        if eaten {
+            self.at += token.len();
+        }
+        eaten
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.trivia();
+        let rest = self.rest();
+        let len = rest
+            .find(|char: char| !(char.is_alphanumeric() || char == '_' || char == '$'))
+            .unwrap_or(rest.len());
+        self.at += len;
+        is_ident(&rest[..len]).then(|| &rest[..len])
+    }
+
+    fn string(&mut self, quote: u8) {
+        self.at += 1;
+        while let Some(byte) = self.peek() {
+            self.at += 1;
+            match byte {
+                b'\\' => self.at += 1,
+                byte if byte == quote => break,
+                _ => {}
+            }
+        }
+        self.at = self.at.min(self.source.len());
+    }
+
+    /// Skips to the `,` or closing bracket after an expression, returning it.
+    fn expression(&mut self) -> &'a str {
+        self.trivia();
+        let start = self.at;
+        let mut depth = 0usize;
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'(' | b'[' | b'{' => depth += 1,
+                b')' | b']' | b'}' | b',' if depth == 0 => break,
+                b')' | b']' | b'}' => depth -= 1,
+                b'\'' | b'"' | b'`' => {
+                    self.string(byte);
+                    continue;
+                }
+                b'/' if self.rest().starts_with("//") || self.rest().starts_with("/*") => {
+                    self.trivia();
+                    continue;
+                }
+                _ => {}
+            }
+            self.at += 1;
+        }
+        self.source[start..self.at].trim()
+    }
+
+    /// An object literal's members, `scoped({ ... })` counting as one.
+    fn object(&mut self) -> Option<Vec<(String, Member)>> {
+        if !self.eat("{") {
+            return None;
+        }
+
+        let mut members = Vec::new();
+        while !self.eat("}") {
+            let key = self.ident()?.to_string();
+            let value = if self.eat(":") {
+                self.trivia();
+                if self.rest().starts_with('{') {
+                    Member::Object(self.object()?)
+                } else if self.eat("scoped(") {
+                    let object = self.object()?;
+                    if !self.eat(")") {
+                        return None;
+                    }
+                    Member::Object(object)
+                } else {
+                    member(self.expression())
+                }
+            } else if self.rest().starts_with([',', '}']) {
+                member(&key)
+            } else {
+                // A method, whose body there's no telling the type of.
+                self.expression();
+                Member::Other
+            };
+            members.push((key, value));
+            self.eat(",");
+        }
+        Some(members)
+    }
+}
+
+/// `interface <name>` for an object in `MASS`, with the members that are ops,
+/// then those of the objects in it, named after their path.
+fn declare_mass(name: &str, members: &[(String, Member)], ops: &HashMap<String, Op>, interfaces: &mut Vec<String>) {
+    let mut body = String::new();
+    let mut nested = Vec::new();
+    for (key, member) in members {
+        match member {
+            Member::Object(members) => {
+                let interface = format!("{name}_{}", rename(key, Some("SCREAMING_SNAKE_CASE")));
+                let _ = writeln!(body, "  {key}: {interface};");
+                nested.push((interface, members));
+            }
+            Member::Op { op, forwarded } => {
+                if let Some(op) = ops.get(op)
+                    && let Some(method) = op.method(key, forwarded.as_deref())
+                {
+                    body.push_str(&docs(&op.docs, "  "));
+                    body.push_str(&method);
+                }
+            }
+            Member::Other => {}
+        }
+    }
+
+    interfaces.push(match body.is_empty() {
+        true => format!("interface {name} {{}}\n"),
+        false => format!("interface {name} {{\n{body}}}\n"),
+    });
+    for (interface, members) in nested {
+        declare_mass(&interface, members, ops, interfaces);
+    }
+}
+
+/// The items of one source file and where its `use`d names come from.
+struct Module {
+    items: Vec<Item>,
+    imports: HashMap<String, String>,
+}
+
+impl Module {
+    fn new(file: &syn::File) -> Self {
+        let mut imports = HashMap::new();
+        for item in &file.items {
+            if let Item::Use(item) = item {
+                collect_imports(&item.tree, None, &mut imports);
+            }
+        }
+        Self {
+            items: file.items.clone(),
+            imports,
+        }
+    }
+}
+
+/// Maps each name a `use` brings in to the module before it, e.g. `Analysis`
+/// to `analysis` for `use crate::modules::analysis::Analysis`.
+fn collect_imports(tree: &UseTree, parent: Option<String>, imports: &mut HashMap<String, String>) {
+    match tree {
+        UseTree::Path(path) => collect_imports(&path.tree, Some(path.ident.to_string()), imports),
+        UseTree::Name(name) => {
+            if let Some(parent) = parent {
+                imports.insert(name.ident.to_string(), parent);
+            }
+        }
+        UseTree::Rename(rename) => {
+            if let Some(parent) = parent {
+                imports.insert(rename.rename.to_string(), parent);
+            }
+        }
+        UseTree::Group(group) => group
+            .items
+            .iter()
+            .for_each(|tree| collect_imports(tree, parent.clone(), imports)),
+        UseTree::Glob(_) => {}
+    }
+}
+
+struct Types {
+    dir: PathBuf,
+    /// Parsed modules by name, the empty name for mass/modules.rs itself.
+    files: HashMap<String, Module>,
+    /// Declarations by module and type name.
+    declared: BTreeMap<String, BTreeMap<String, String>>,
+    pending: VecDeque<(String, String)>,
+}
+
+/// The serde attributes of a container, field or variant that change how it
+/// looks in JS.
+#[derive(Default)]
+struct Serde {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    default: bool,
+    skip_serializing_if: bool,
+    flatten: bool,
+    skip: bool,
+    transparent: bool,
+}
+
+fn serde(attrs: &[Attribute]) -> Serde {
+    let mut serde = Serde::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            let key = meta.path.get_ident().map(ToString::to_string).unwrap_or_default();
+            let value = match meta.value() {
+                Ok(value) => match value.parse::<Lit>() {
+                    Ok(Lit::Str(value)) => Some(value.value()),
+                    _ => None,
+                },
+                Err(_) => None,
+            };
+            match key.as_str() {
+                "rename" => serde.rename = value,
+                "rename_all" => serde.rename_all = value,
+                "tag" => serde.tag = value,
+                "content" => serde.content = value,
+                "untagged" => serde.untagged = true,
+                "default" => serde.default = true,
+                "flatten" => serde.flatten = true,
+                "skip" | "skip_serializing" => serde.skip = true,
+                "skip_serializing_if" => serde.skip_serializing_if = true,
+                "transparent" => serde.transparent = true,
+                _ => {}
+            }
+            Ok(())
+        });
+    }
+    serde
+}
+
+/// Whether a type only ever comes from JS, so its `Option`s may be left out.
+fn deserialize_only(attrs: &[Attribute]) -> bool {
+    let derives: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .filter_map(|attr| attr.meta.require_list().ok())
+        .map(|list| list.tokens.to_string())
+        .collect();
+    derives.iter().any(|derive| derive.contains("Deserialize"))
+        && !derives.iter().any(|derive| derive.contains("Serialize"))
+}
+
+fn docs(attrs: &[Attribute], indent: &str) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(expr) => match &expr.lit {
+                    Lit::Str(doc) => Some(doc.value().trim().to_string()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    match lines.as_slice() {
+        [] => String::new(),
+        [line] => format!("{indent}/** {line} */\n"),
+        lines => {
+            let mut docs = format!("{indent}/**\n");
+            for line in lines {
+                let line = format!("{indent} * {line}");
+                let _ = writeln!(docs, "{}", line.trim_end());
+            }
+            format!("{docs}{indent} */\n")
+        }
+    }
+}
+
+/// An identifier's words, from either `snake_case` or `PascalCase`.
+fn words(ident: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in ident.split('_').filter(|part| !part.is_empty()) {
+        let mut word = String::new();
+        for char in part.chars() {
+            if char.is_uppercase() && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.extend(char.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// `ident` the way serde's `rename_all = "<style>"` spells it.
+fn rename(ident: &str, style: Option<&str>) -> String {
+    let words = words(ident);
+    match style {
+        Some("lowercase") => words.concat(),
+        Some("UPPERCASE") => words.concat().to_uppercase(),
+        Some("snake_case") => words.join("_"),
+        Some("SCREAMING_SNAKE_CASE") => words.join("_").to_uppercase(),
+        Some("kebab-case") => words.join("-"),
+        Some("SCREAMING-KEBAB-CASE") => words.join("-").to_uppercase(),
+        Some("PascalCase") => words.iter().map(|word| capitalize(word)).collect(),
+        Some("camelCase") => words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| if index == 0 { word.clone() } else { capitalize(word) })
+            .collect(),
+        _ => ident.to_string(),
+    }
+}
+
+fn generic(segment: &syn::PathSegment, index: usize) -> Option<&Type> {
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    arguments
+        .args
+        .iter()
+        .filter_map(|argument| match argument {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        })
+        .nth(index)
+}
+
+fn parenthesize(ty: String) -> String { if ty.contains(' ') { format!("({ty})") } else { ty } }
+
+/// Whether an op parameter is filled in by deno_core rather than passed from JS.
+fn injected(ty: &Type) -> bool {
+    let text = quote_type(ty);
+    ["OpState", "HandleScope", "PinScope"]
+        .iter()
+        .any(|injected| text.contains(injected))
+}
+
+fn quote_type(ty: &Type) -> String {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .iter()
+            .map(|segment| {
+                let inner = match &segment.arguments {
+                    PathArguments::AngleBracketed(arguments) => arguments
+                        .args
+                        .iter()
+                        .filter_map(|argument| match argument {
+                            GenericArgument::Type(ty) => Some(quote_type(ty)),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    _ => String::new(),
+                };
+                format!("{}<{inner}>", segment.ident)
+            })
+            .collect::<Vec<_>>()
+            .join("::"),
+        Type::Reference(reference) => quote_type(&reference.elem),
+        _ => String::new(),
+    }
+}
+
+impl Types {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            files: HashMap::new(),
+            declared: BTreeMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn op(&mut self, function: &syn::ItemFn) -> Op {
+        let attrs = |attrs: &[Attribute]| -> Vec<String> {
+            attrs
+                .iter()
+                .filter_map(|attr| attr.path().get_ident().map(ToString::to_string))
+                .collect()
+        };
+
+        let mut params = Vec::new();
+        for input in &function.sig.inputs {
+            let FnArg::Typed(input) = input else { continue };
+            if injected(&input.ty) {
+                continue;
+            }
+            let name = match &*input.pat {
+                Pat::Ident(pat) => rename(pat.ident.to_string().trim_start_matches('_'), Some("camelCase")),
+                _ => "arg".into(),
+            };
+            let ty = self.arg(&attrs(&input.attrs), &input.ty);
+            params.push(format!("{name}: {ty}"));
+        }
+
+        let returns = match &function.sig.output {
+            ReturnType::Default => "void".to_string(),
+            ReturnType::Type(_, ty) => self.arg(&attrs(&function.attrs), ty),
+        };
+        let returns = if function.sig.asyncness.is_some() {
+            format!("Promise<{returns}>")
+        } else {
+            returns
+        };
+
+        Op {
+            name: function.sig.ident.to_string(),
+            docs: function.attrs.clone(),
+            params,
+            returns,
+        }
+    }
+
+    /// An op parameter or return value, by its `#[op2]` marker and type.
+    fn arg(&mut self, markers: &[String], ty: &Type) -> String {
+        if markers
+            .iter()
+            .any(|marker| marker == "buffer" || marker == "arraybuffer")
+        {
+            return "Uint8Array".into();
+        }
+        if markers.iter().any(|marker| marker == "bigint") {
+            return "bigint".into();
+        }
+        self.ty(ty, "")
+    }
+
+    fn ty(&mut self, ty: &Type, module: &str) -> String {
+        match ty {
+            Type::Reference(reference) => self.ty(&reference.elem, module),
+            Type::Slice(slice) => format!("{}[]", parenthesize(self.ty(&slice.elem, module))),
+            Type::Array(array) => format!("{}[]", parenthesize(self.ty(&array.elem, module))),
+            Type::Tuple(tuple) if tuple.elems.is_empty() => "void".into(),
+            Type::Tuple(tuple) => format!(
+                "[{}]",
+                tuple
+                    .elems
+                    .iter()
+                    .map(|elem| self.ty(elem, module))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Type::Path(path) => {
+                let Some(last) = path.path.segments.last() else {
+                    return "unknown".into();
+                };
+                let inner = |types: &mut Self, index| match generic(last, index) {
+                    Some(ty) => types.ty(ty, module),
+                    None => "unknown".into(),
+                };
+
+                match last.ident.to_string().as_str() {
+                    "String" | "str" | "PathBuf" | "Path" | "char" | "ModuleSpecifier" | "Url" => "string".into(),
+                    "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize" | "f32" | "f64"
+                    | "ResourceId" => "number".into(),
+                    "bool" => "boolean".into(),
+                    "Value" => "unknown".into(),
+                    "Option" => format!("{} | null", inner(self, 0)),
+                    "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => format!("{}[]", parenthesize(inner(self, 0))),
+                    "HashMap" | "BTreeMap" => format!("Record<string, {}>", inner(self, 1)),
+                    "Box" | "Rc" | "Arc" | "Cow" | "Result" => inner(self, 0),
+                    name => {
+                        let owner = match path.path.segments.len() {
+                            1 => self.owner(module, name),
+                            _ => path
+                                .path
+                                .segments
+                                .iter()
+                                .rev()
+                                .nth(1)
+                                .map(|segment| segment.ident.to_string()),
+                        };
+                        match owner {
+                            Some(owner) if self.module(&owner).is_some() => {
+                                self.pending.push_back((owner.clone(), name.to_string()));
+                                match owner.as_str() {
+                                    "" => format!("MassOps.{name}"),
+                                    owner => format!("MassOps.{owner}.{name}"),
+                                }
+                            }
+                            _ => "unknown".into(),
+                        }
+                    }
+                }
+            }
+            _ => "unknown".into(),
+        }
+    }
+
+    /// The module a bare type name used in `module` is declared in.
+    fn owner(&mut self, module: &str, name: &str) -> Option<String> {
+        let file = self.module(module)?;
+        let declared = file.items.iter().any(|item| match item {
+            Item::Struct(item) => item.ident == name,
+            Item::Enum(item) => item.ident == name,
+            Item::Type(item) => item.ident == name,
+            _ => false,
+        });
+        if declared {
+            return Some(module.to_string());
+        }
+        file.imports.get(name).cloned()
+    }
+
+    fn module(&mut self, name: &str) -> Option<&Module> {
+        if !self.files.contains_key(name) {
+            let source = std::fs::read_to_string(self.dir.join(format!("{name}.rs"))).ok()?;
+            let file = syn::parse_file(&source).ok()?;
+            self.files.insert(name.to_string(), Module::new(&file));
+        }
+        self.files.get(name)
+    }
+
+    fn fields(
+        &mut self, fields: &syn::FieldsNamed, container: &Serde, module: &str, input: bool, indent: &str,
+    ) -> (String, Vec<String>) {
+        let mut body = String::new();
+        let mut flattened = Vec::new();
+
+        for field in &fields.named {
+            let attrs = serde(&field.attrs);
+            if attrs.skip {
+                continue;
+            }
+            let ty = self.ty(&field.ty, module);
+            if attrs.flatten {
+                flattened.push(ty);
+                continue;
+            }
+
+            let ident = field.ident.as_ref().map(ToString::to_string).unwrap_or_default();
+            let name = attrs
+                .rename
+                .unwrap_or_else(|| rename(&ident, container.rename_all.as_deref()));
+            // Defaults only apply to what JS passes in.
+            let defaulted = container.default || attrs.default || ty.ends_with(" | null");
+            let optional = attrs.skip_serializing_if || (input && defaulted);
+            body.push_str(&docs(&field.attrs, indent));
+            let _ = writeln!(body, "{indent}{name}{}: {ty};", if optional { "?" } else { "" });
+        }
+
+        (body, flattened)
+    }
+
+    fn declare(&mut self, module: &str, name: &str) -> Option<String> {
+        let item = self
+            .module(module)?
+            .items
+            .iter()
+            .find(|item| match item {
+                Item::Struct(item) => item.ident == name,
+                Item::Enum(item) => item.ident == name,
+                Item::Type(item) => item.ident == name,
+                _ => false,
+            })?
+            .clone();
+
+        let declaration = match item {
+            Item::Type(alias) => {
+                format!("type {name} = {};\n", self.ty(&alias.ty, module))
+            }
+            Item::Struct(item) => {
+                let container = serde(&item.attrs);
+                let input = deserialize_only(&item.attrs);
+                let docs = docs(&item.attrs, "");
+                match &item.fields {
+                    Fields::Named(fields) if !container.transparent => {
+                        let (body, flattened) = self.fields(fields, &container, module, input, "  ");
+                        if flattened.is_empty() {
+                            format!("{docs}interface {name} {{\n{body}}}\n")
+                        } else {
+                            format!("{docs}type {name} = {{\n{body}}} & {};\n", flattened.join(" & "))
+                        }
+                    }
+                    Fields::Named(fields) => {
+                        let ty = fields.named.first().map(|field| self.ty(&field.ty, module));
+                        format!("{docs}type {name} = {};\n", ty.unwrap_or_else(|| "unknown".into()))
+                    }
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                        format!("{docs}type {name} = {};\n", self.ty(&fields.unnamed[0].ty, module))
+                    }
+                    Fields::Unnamed(fields) => {
+                        let types: Vec<_> = fields.unnamed.iter().map(|field| self.ty(&field.ty, module)).collect();
+                        format!("{docs}type {name} = [{}];\n", types.join(", "))
+                    }
+                    Fields::Unit => format!("{docs}type {name} = null;\n"),
+                }
+            }
+            Item::Enum(item) => {
+                let container = serde(&item.attrs);
+                let input = deserialize_only(&item.attrs);
+                let mut variants = Vec::new();
+
+                for variant in &item.variants {
+                    let attrs = serde(&variant.attrs);
+                    if attrs.skip {
+                        continue;
+                    }
+                    let tag = attrs
+                        .rename
+                        .clone()
+                        .unwrap_or_else(|| rename(&variant.ident.to_string(), container.rename_all.as_deref()));
+                    let value = match &variant.fields {
+                        Fields::Unit => None,
+                        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                            Some(self.ty(&fields.unnamed[0].ty, module))
+                        }
+                        Fields::Unnamed(_) => Some("unknown[]".into()),
+                        Fields::Named(fields) => {
+                            let variant = Serde {
+                                rename_all: attrs.rename_all.clone(),
+                                ..Default::default()
+                            };
+                            let (body, _) = self.fields(fields, &variant, module, input, "");
+                            Some(format!("{{ {}}}", body.replace('\n', " ")))
+                        }
+                    };
+
+                    variants.push(match (&container.tag, &container.content, container.untagged, value) {
+                        (_, _, true, value) => value.unwrap_or_else(|| "null".into()),
+                        (Some(tag_key), Some(content), _, Some(value)) => {
+                            format!("{{ {tag_key}: '{tag}'; {content}: {value} }}")
+                        }
+                        (Some(tag_key), None, _, Some(value)) if value.starts_with('{') => {
+                            format!("{{ {tag_key}: '{tag}'; {}", value.trim_start_matches('{').trim_start())
+                        }
+                        (Some(tag_key), None, _, Some(value)) => format!("({{ {tag_key}: '{tag}' }} & {value})"),
+                        (Some(tag_key), _, _, None) => format!("{{ {tag_key}: '{tag}' }}"),
+                        (None, _, _, Some(value)) => format!("{{ {tag}: {value} }}"),
+                        (None, _, _, None) => format!("'{tag}'"),
+                    });
+                }
+
+                let docs = docs(&item.attrs, "");
+                if variants.is_empty() {
+                    format!("{docs}type {name} = never;\n")
+                } else {
+                    format!("{docs}type {name} = {};\n", variants.join(" | "))
+                }
+            }
+            _ => return None,
+        };
+        Some(declaration)
+    }
+
+    /// Declarations for every type the ops reached, declaring the types those
+    /// refer to in turn.
+    fn declarations(&mut self) -> String {
+        while let Some((module, name)) = self.pending.pop_front() {
+            if self
+                .declared
+                .get(&module)
+                .is_some_and(|types| types.contains_key(&name))
+            {
+                continue;
+            }
+            // Declared before it's filled in, so recursive types terminate.
+            self.declared
+                .entry(module.clone())
+                .or_default()
+                .insert(name.clone(), String::new());
+            let declaration = self
+                .declare(&module, &name)
+                .unwrap_or_else(|| format!("type {name} = unknown;\n"));
+            self.declared.entry(module).or_default().insert(name, declaration);
+        }
+
+        let indent = |text: &str, by: &str| {
+            text.lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{by}{line}\n")
+                    }
+                })
+                .collect::<String>()
+        };
+
+        let mut output = String::from("declare namespace MassOps {\n");
+        for (module, types) in &self.declared {
+            let body: String = types.values().map(|declaration| indent(declaration, "  ")).collect();
+            match module.as_str() {
+                "" => output.push_str(&body),
+                module => {
+                    let _ = write!(output, "  namespace {module} {{\n{}  }}\n", indent(&body, "  "));
+                }
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+}
@@ -0,0 +1,82 @@
+use deno_runtime::deno_napi::DenoRtNativeAddonLoader;
+use denort_helper::LoadError;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Finds native addons for a server bundled into the binary. A bundled
+/// `require('./build/Release/addon.node')` points next to a bundle that isn't
+/// on disk, so the `.node` file is looked up under `--allow-addons` directories
+/// instead, preferring the candidate whose path shares the longest tail with
+/// the one asked for.
+pub struct AddonLoader {
+    dirs: Vec<PathBuf>,
+    index: OnceLock<HashMap<OsString, Vec<PathBuf>>>,
+}
+
+impl AddonLoader {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            index: OnceLock::new(),
+        }
+    }
+
+    fn index(&self) -> &HashMap<OsString, Vec<PathBuf>> {
+        self.index.get_or_init(|| {
+            let mut index: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+            let mut pending = self.dirs.clone();
+            // Package stores link the same directory in from many places.
+            let mut seen = HashSet::new();
+
+            while let Some(dir) = pending.pop() {
+                if !std::fs::canonicalize(&dir).is_ok_and(|dir| seen.insert(dir)) {
+                    continue;
+                }
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let Ok(meta) = std::fs::metadata(&path) else {
+                        continue;
+                    };
+                    if meta.is_dir() {
+                        pending.push(path);
+                    } else if path.extension().is_some_and(|ext| ext == "node") {
+                        index.entry(entry.file_name()).or_default().push(path);
+                    }
+                }
+            }
+
+            index
+        })
+    }
+
+    fn find(&self, path: &Path) -> Option<PathBuf> {
+        let candidates = self.index().get(path.file_name()?)?;
+        let shared = |candidate: &PathBuf| {
+            candidate
+                .components()
+                .rev()
+                .zip(path.components().rev())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+
+        candidates.iter().max_by_key(|candidate| shared(candidate)).cloned()
+    }
+}
+
+impl DenoRtNativeAddonLoader for AddonLoader {
+    fn load_if_in_vfs(&self, _path: &Path) -> Option<Cow<'static, [u8]>> { None }
+
+    fn load_and_resolve_path<'a>(&self, path: &'a Path) -> Result<Cow<'a, Path>, LoadError> {
+        if path.exists() {
+            return Ok(Cow::Borrowed(path));
+        }
+        Ok(self.find(path).map_or(Cow::Borrowed(path), Cow::Owned))
+    }
+}
@@ -0,0 +1,85 @@
+use crate::modules::redis::Redis;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use deno_runtime::deno_broadcast_channel::{BroadcastChannel, InMemoryBroadcastChannel};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Redis channel every process relays `BroadcastChannel` messages through.
+const CHANNEL: &str = "mass:broadcast";
+
+#[derive(Serialize, Deserialize)]
+struct Relayed {
+    origin: String,
+    name: String,
+    /// The structured-clone bytes of the message, base64 encoded.
+    data: String,
+}
+
+/// Relays `BroadcastChannel` messages between this process and every other one
+/// on the same Redis server, on a thread of its own. Isolates in one process
+/// already share `channel`; the relay joins it like another isolate would.
+pub fn relay(channel: InMemoryBroadcastChannel, url: String) {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let origin = format!("{}-{nanos:x}", std::process::id());
+
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(error) => return eprintln!("MASS failed to start the broadcast relay: {error}"),
+        };
+
+        runtime.block_on(async {
+            loop {
+                if let Err(error) = run(&channel, &url, &origin).await {
+                    eprintln!("MASS broadcast relay disconnected, retrying: {error}");
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    });
+}
+
+async fn run(channel: &InMemoryBroadcastChannel, url: &str, origin: &str) -> std::io::Result<()> {
+    let redis = Redis::new(Some(url.to_string()));
+    let subscription = redis.subscribe(&[CHANNEL.to_string()]).await?;
+    let local = channel.subscribe().map_err(Error::other)?;
+
+    let outgoing = async {
+        while let Some((name, data)) = channel.recv(&local).await.map_err(Error::other)? {
+            let relayed = Relayed {
+                origin: origin.to_string(),
+                name,
+                data: BASE64.encode(data),
+            };
+            let payload = serde_json::to_string(&relayed).map_err(Error::other)?;
+            redis.command(&["PUBLISH", CHANNEL, &payload]).await?;
+        }
+        Ok::<_, Error>(())
+    };
+
+    let incoming = async {
+        while let Some(message) = subscription.next().await? {
+            let Ok(relayed) = serde_json::from_str::<Relayed>(&message.message) else {
+                continue;
+            };
+            if relayed.origin == origin {
+                continue;
+            }
+            let Ok(data) = BASE64.decode(relayed.data) else {
+                continue;
+            };
+            channel.send(&local, relayed.name, data).await.map_err(Error::other)?;
+        }
+        Err::<(), _>(Error::new(ErrorKind::UnexpectedEof, "redis closed the subscription"))
+    };
+
+    let result = tokio::try_join!(outgoing, incoming).map(|_| ());
+    let _ = channel.unsubscribe(&local);
+    result
+}
@@ -0,0 +1,43 @@
+use crate::bundler;
+use crate::cli::{BundleArgs, Cli, InstallArgs};
+use crate::install;
+
+/// `mass bundle`: what a release build does for `mass/server`, for any entry
+/// point. The project's npm dependencies are installed the way `mass install`
+/// would, then the entry is bundled to ESM with its pkg.toml `[build]` settings.
+pub async fn run(cli: &Cli, args: &BundleArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let esbuild = bundler::esbuild().ok_or("esbuild not found, see `mass doctor`")?;
+
+    let has_manifest = ["pkg.toml", "package.json"]
+        .iter()
+        .any(|name| args.dir.join(name).is_file());
+    if has_manifest && !args.no_install {
+        let install = InstallArgs {
+            dir: args.dir.clone(),
+            frozen: args.frozen,
+            prune: false,
+            verify: false,
+            offline: false,
+            allow_scripts: Vec::new(),
+        };
+        install::run(&install, &cli.config).await?;
+    }
+
+    let mut flags = bundler::flags(&args.dir, !args.no_minify)?;
+    flags.push("--sourcemap=linked".into());
+
+    let entries = vec![(
+        args.out.to_string_lossy().into_owned(),
+        args.entry.to_string_lossy().into_owned(),
+    )];
+    for output in bundler::bundle(&esbuild, flags, entries).await? {
+        let path = std::path::Path::new(&output.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &output.contents)?;
+        println!("wrote {} ({} bytes)", path.display(), output.contents.len());
+    }
+
+    Ok(())
+}
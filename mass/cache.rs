@@ -0,0 +1,52 @@
+use crate::cli::{CacheCommand, Cli, WarmArgs};
+use crate::config::Config;
+use crate::modules::audit::AuditLog;
+use crate::modules::cache::{self, WarmManifest, WarmOptions};
+use crate::modules::quota::RateLimiter;
+
+/// `mass cache`: maintenance of the remote module cache outside a running server.
+pub async fn run(cli: &Cli, command: &CacheCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        CacheCommand::Warm(args) => warm(cli, args).await,
+        CacheCommand::Export(args) => {
+            let count = cache::export(&args.out, &args.host)?;
+            println!("exported {count} modules to {}", args.out.display());
+            Ok(())
+        }
+        CacheCommand::Import(args) => {
+            let count = cache::import(&args.archive)?;
+            println!("imported {count} modules from {}", args.archive.display());
+            Ok(())
+        }
+    }
+}
+
+async fn warm(cli: &Cli, args: &WarmArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let urls = WarmManifest::read(&args.manifest)?;
+    let quota = RateLimiter::new(Config::load(&cli.config)?.rate_limit);
+    let audit = match &cli.audit_permissions {
+        Some(path) => AuditLog::open(path)?,
+        None => AuditLog::default(),
+    };
+
+    let options = WarmOptions {
+        concurrency: args.concurrency,
+    };
+    let report = cache::warm(urls, options, &quota, &audit).await;
+
+    for failure in &report.failed {
+        eprintln!("failed {}: {}", failure.url, failure.error);
+    }
+    println!(
+        "fetched {}, already cached {}, failed {}",
+        report.fetched.len(),
+        report.cached.len(),
+        report.failed.len()
+    );
+
+    if report.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} modules could not be cached", report.failed.len()).into())
+    }
+}
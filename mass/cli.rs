@@ -0,0 +1,296 @@
+use crate::modules::listen::Listen;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Check the environment mass needs and print what to fix
+    Doctor,
+    /// Install the npm dependencies of a server project into its node_modules
+    Install(InstallArgs),
+    /// Run the server from source, rebuilding and restarting the worker on change
+    Dev(DevArgs),
+    /// Bundle a server entry and its npm dependencies into one deployable file
+    Bundle(BundleArgs),
+    /// Manage the remote module cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Print the dependency tree of a module with sizes, types and cache status
+    Info(InfoArgs),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// Fetch every module a manifest lists into the cache, e.g. while building an image
+    Warm(WarmArgs),
+    /// Write cached modules and their metadata to a tarball for another machine
+    Export(ExportArgs),
+    /// Load a tarball written by `mass cache export` into the cache
+    Import(ImportArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct WarmArgs {
+    /// JSON array of module URLs, or an object listing them under `modules`
+    pub manifest: PathBuf,
+
+    /// How many modules to fetch at once
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pub concurrency: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// Tarball to write
+    #[arg(default_value = "mass-cache.tar.gz")]
+    pub out: PathBuf,
+
+    /// Only export modules served from these hosts
+    #[arg(long, value_name = "HOSTS", value_delimiter = ',')]
+    pub host: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportArgs {
+    /// Tarball written by `mass cache export`
+    pub archive: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InfoArgs {
+    /// Module to inspect, as a path or URL
+    pub specifier: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DevArgs {
+    /// Server entry point to bundle
+    #[arg(default_value = "mass/server/index.ts")]
+    pub entry: PathBuf,
+
+    /// Where the development bundle is written
+    #[arg(long, value_name = "FILE", default_value = ".mass/dev/server.js")]
+    pub out: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BundleArgs {
+    /// Entry point to bundle
+    pub entry: PathBuf,
+
+    /// Where the bundle is written, with its source map next to it
+    #[arg(long, value_name = "FILE", default_value = "dist/server.js")]
+    pub out: PathBuf,
+
+    /// Project directory holding pkg.toml or package.json
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Leave the bundle unminified
+    #[arg(long)]
+    pub no_minify: bool,
+
+    /// Bundle against node_modules as it is instead of installing first
+    #[arg(long)]
+    pub no_install: bool,
+
+    /// Fail instead of resolving anything pkg.lock doesn't already pin
+    #[arg(long, env = "MASS_FROZEN_LOCKFILE")]
+    pub frozen: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InstallArgs {
+    /// Project directory holding pkg.toml or package.json
+    #[arg(default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Fail instead of resolving anything pkg.lock doesn't already pin
+    #[arg(long, env = "MASS_FROZEN_LOCKFILE")]
+    pub frozen: bool,
+
+    /// Remove packages that are no longer reachable from the dependencies
+    #[arg(long)]
+    pub prune: bool,
+
+    /// Check installed packages against their tarballs and repair any that differ
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Install from pkg.lock, the metadata cache and the package store only,
+    /// listing whatever is missing instead of using the network
+    #[arg(long, env = "MASS_NPM_OFFLINE")]
+    pub offline: bool,
+
+    /// Packages allowed to run install scripts, in addition to pkg.toml's allowlist
+    #[arg(long, value_name = "PACKAGES", value_delimiter = ',')]
+    pub allow_scripts: Vec<String>,
+}
+
+#[derive(Parser, Debug, Clone, Default)]
+#[command(name = "mass", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Confine all file-based ops and `file:` module loads to this directory
+    #[arg(long, value_name = "DIR", env = "MASS_FS_ROOT", global = true)]
+    pub fs_root: Option<PathBuf>,
+
+    /// Load this module as the worker bootstrap instead of the built-in one
+    #[arg(long, value_name = "FILE", env = "MASS_BOOTSTRAP", global = true)]
+    pub bootstrap: Option<PathBuf>,
+
+    /// Fail if the main module's top-level evaluation takes longer than this many seconds
+    #[arg(long, value_name = "SECS", env = "MASS_INIT_DEADLINE", global = true)]
+    pub init_deadline: Option<u64>,
+
+    /// Restrict outbound network access from JS to these hosts
+    #[arg(
+        long,
+        value_name = "HOSTS",
+        value_delimiter = ',',
+        env = "MASS_ALLOW_NET",
+        global = true
+    )]
+    pub allow_net: Option<Vec<String>>,
+
+    /// Enable `Deno.dlopen` for native libraries, limited to these paths when any are given
+    #[arg(
+        long,
+        value_name = "PATHS",
+        value_delimiter = ',',
+        num_args = 0..,
+        require_equals = true,
+        env = "MASS_ALLOW_FFI",
+        global = true
+    )]
+    pub allow_ffi: Option<Vec<String>>,
+
+    /// Let npm packages load native `.node` addons, looked up under these
+    /// directories when the bundled server asks for one that isn't on disk
+    /// [default: node_modules]
+    #[arg(
+        long,
+        value_name = "DIRS",
+        value_delimiter = ',',
+        num_args = 0..,
+        require_equals = true,
+        env = "MASS_ALLOW_ADDONS",
+        global = true
+    )]
+    pub allow_addons: Option<Vec<PathBuf>>,
+
+    /// Ask on the terminal before granting read, net or run access
+    #[arg(long, env = "MASS_PROMPT", global = true)]
+    pub prompt: bool,
+
+    /// Record permission-relevant access as JSONL in this file (Deno APIs only on first access to each resource)
+    #[arg(long, value_name = "FILE", env = "MASS_AUDIT_PERMISSIONS", global = true)]
+    pub audit_permissions: Option<PathBuf>,
+
+    /// Format for console output from JavaScript
+    #[arg(long, value_enum, default_value_t, env = "MASS_LOG_FORMAT", global = true)]
+    pub log_format: LogFormat,
+
+    /// Print diagnostics without ANSI colors, as does setting NO_COLOR
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Write structured console records here instead of stdout
+    #[arg(long, value_name = "FILE", env = "MASS_LOG_FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Settings file holding per-host rate limits and the like
+    #[arg(
+        long,
+        value_name = "FILE",
+        default_value = "mass.toml",
+        env = "MASS_CONFIG",
+        global = true
+    )]
+    pub config: PathBuf,
+
+    /// Where the server listens: `[hostname:]port`, `unix:PATH`, or `systemd` for
+    /// the socket systemd passes in [default: 0.0.0.0:8080]
+    #[arg(long, value_name = "ADDR", env = "MASS_LISTEN", global = true)]
+    pub listen: Option<Listen>,
+
+    /// Serve HTTPS with this PEM certificate chain, instead of `[tls]` in the config file
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "MASS_TLS_CERT",
+        requires = "tls_key",
+        global = true
+    )]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "MASS_TLS_KEY",
+        requires = "tls_cert",
+        global = true
+    )]
+    pub tls_key: Option<PathBuf>,
+
+    /// Redis server the `op_redis_*` ops talk to, as `redis://[user:password@]host[:port][/db]`
+    #[arg(long, value_name = "URL", env = "MASS_REDIS_URL", global = true)]
+    pub redis_url: Option<String>,
+
+    /// Relay `BroadcastChannel` messages to every mass process on the `--redis-url` server
+    #[arg(long, requires = "redis_url", env = "MASS_BROADCAST_REDIS", global = true)]
+    pub broadcast_redis: bool,
+
+    /// Directory ops keep persistent state in, such as vector indexes
+    /// [default: the platform data directory]
+    #[arg(long, value_name = "DIR", env = "MASS_DATA_DIR", global = true)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Directory crash reports are written to when the host panics
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = "./crashes",
+        env = "MASS_CRASH_DIR",
+        global = true
+    )]
+    pub crash_dir: PathBuf,
+
+    /// Cap outbound request body bandwidth from JS `fetch()` in bytes per second
+    #[arg(long, value_name = "BYTES", env = "MASS_MAX_EGRESS", global = true)]
+    pub max_egress: Option<u64>,
+
+    /// Give up after this many consecutive worker restarts
+    #[arg(long, value_name = "N", env = "MASS_MAX_RESTARTS", global = true)]
+    pub max_restarts: Option<u32>,
+
+    /// Drain and replace the isolate after it has completed this many jobs
+    #[arg(long, value_name = "N", env = "MASS_RECYCLE_JOBS", global = true)]
+    pub recycle_jobs: Option<u64>,
+
+    /// Drain and replace the isolate once its heap grew by this many megabytes
+    #[arg(long, value_name = "MB", env = "MASS_RECYCLE_HEAP_GROWTH", global = true)]
+    pub recycle_heap_growth: Option<u64>,
+
+    /// Report event-loop stalls longer than this many milliseconds
+    #[arg(long, value_name = "MS", env = "MASS_STALL_THRESHOLD", global = true)]
+    pub stall_threshold: Option<u64>,
+
+    /// Print the JavaScript stack when a stall is reported
+    #[arg(long, requires = "stall_threshold", global = true)]
+    pub stall_stack: bool,
+
+    /// Serve this IIFE bundle instead of the snapshotted server (set by `mass dev`)
+    #[arg(long, value_name = "FILE", env = "MASS_DEV_SERVER", hide = true, global = true)]
+    pub dev_server: Option<PathBuf>,
+}
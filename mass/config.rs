@@ -0,0 +1,47 @@
+use crate::front::ServerConfig;
+use crate::modules::listen::TlsConfig;
+use crate::modules::plugins::PluginManifest;
+use crate::modules::quota::QuotaConfig;
+use crate::modules::walk::WalkPolicy;
+use crate::telemetry::TelemetryConfig;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// `mass.toml`: settings too structured for flags. A missing file is the same
+/// as an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Outbound requests per host, see [`QuotaConfig`].
+    #[serde(default)]
+    pub rate_limit: QuotaConfig,
+    /// Certificate and key to serve HTTPS with, see [`TlsConfig`].
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// HTTP tuning for the server, see [`ServerConfig`].
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Metrics, traces and crash reports, see [`TelemetryConfig`].
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    /// What the repository ops walk, see [`WalkPolicy`].
+    #[serde(default)]
+    pub walk: WalkPolicy,
+    /// Extra ES modules for every isolate, see [`PluginManifest`].
+    #[serde(default)]
+    pub plugins: Vec<PluginManifest>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&contents)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {err}", path.display())))
+    }
+}
@@ -0,0 +1,116 @@
+use crate::modules::metrics::{OpCall, OpTimings};
+use crate::telemetry::TelemetryConfig;
+
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Exit code used when the host panics, distinct from JS errors (1) and from
+/// Rust's own panic exit (101): `EX_SOFTWARE`, an internal error.
+pub const CRASH_EXIT_CODE: i32 = 70;
+
+struct CrashContext {
+    module: Option<String>,
+    timings: Option<OpTimings>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    module: None,
+    timings: None,
+});
+
+#[derive(Serialize)]
+struct CrashReport {
+    timestamp: u128,
+    pid: u32,
+    version: &'static str,
+    service: String,
+    labels: BTreeMap<String, String>,
+    message: String,
+    location: Option<String>,
+    module: Option<String>,
+    current_op: Option<&'static str>,
+    recent_ops: Vec<OpCall>,
+    backtrace: String,
+}
+
+pub fn set_worker(module: &str, timings: &OpTimings) {
+    if let Ok(mut ctx) = CONTEXT.lock() {
+        ctx.module = Some(module.to_string());
+        ctx.timings = Some(timings.clone());
+    }
+}
+
+/// Installs a panic hook that writes a JSON crash report into `dir`, unless
+/// telemetry turned crash reports off. A panic on the calling thread exits with
+/// [`CRASH_EXIT_CODE`] instead of unwinding through the runtime; one on any
+/// other thread unwinds as usual, so the supervisor sees its worker fail.
+pub fn install(dir: PathBuf, telemetry: &TelemetryConfig) {
+    let main = std::thread::current().id();
+    let enabled = telemetry.crash_reports();
+    let service = telemetry.service_name.clone();
+    let labels = telemetry.labels.clone();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => info
+                .payload()
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "Box<dyn Any>".into()),
+        };
+
+        let (module, timings) = match CONTEXT.try_lock() {
+            Ok(ctx) => (ctx.module.clone(), ctx.timings.clone()),
+            Err(_) => (None, None),
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let report = CrashReport {
+            timestamp,
+            pid: std::process::id(),
+            version: env!("CARGO_PKG_VERSION"),
+            service: service.clone(),
+            labels: labels.clone(),
+            location: info.location().map(|l| l.to_string()),
+            current_op: timings.as_ref().and_then(|t| t.try_current()).map(|(name, _)| name),
+            recent_ops: timings.map(|t| t.history()).unwrap_or_default(),
+            backtrace: Backtrace::force_capture().to_string(),
+            message,
+            module,
+        };
+
+        eprintln!(
+            "MASS panicked: {} at {}",
+            report.message,
+            report.location.as_deref().unwrap_or("<unknown>")
+        );
+
+        let exit = || {
+            if std::thread::current().id() == main {
+                std::process::exit(CRASH_EXIT_CODE);
+            }
+        };
+        if !enabled {
+            return exit();
+        }
+
+        let path = dir.join(format!("mass-crash-{}-{timestamp}.json", report.pid));
+        let written = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&path, serde_json::to_vec_pretty(&report).unwrap_or_default()));
+
+        match written {
+            Ok(()) => eprintln!("crash report written to {}", path.display()),
+            Err(err) => eprintln!("failed to write crash report to {}: {err}", path.display()),
+        }
+
+        exit();
+    }));
+}
@@ -0,0 +1,59 @@
+use crate::bundler;
+use crate::cli::{Cli, DevArgs};
+use crate::supervisor;
+
+use deno_core::error::CoreError;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// The flags a release bundles with, under which the dev bundle is a script
+/// the worker evaluates rather than a module.
+fn dev_flags(args: &DevArgs) -> Result<Vec<String>, CoreError> {
+    let dir = args.entry.parent().unwrap_or(Path::new("."));
+    let mut flags = bundler::flags(dir, false).map_err(|err| std::io::Error::other(err.to_string()))?;
+    flags.retain(|flag| !flag.starts_with("--format="));
+    flags.extend([
+        "--format=iife".into(),
+        "--global-name=__massDevServer".into(),
+        "--sourcemap=inline".into(),
+    ]);
+    Ok(flags)
+}
+
+fn esbuild_command(esbuild: &PathBuf, args: &DevArgs, flags: &[String]) -> Command {
+    let mut command = Command::new(esbuild);
+    command
+        .arg(&args.entry)
+        .args(flags)
+        .arg(format!("--outfile={}", args.out.display()));
+    command
+}
+
+/// `mass dev`: bundles the server with esbuild in watch mode and supervises a
+/// worker serving that bundle, booting a standby whenever esbuild rewrites it.
+pub async fn run(mut cli: Cli, args: DevArgs) -> Result<(), CoreError> {
+    let esbuild = bundler::esbuild().ok_or_else(|| std::io::Error::other("esbuild not found, see `mass doctor`"))?;
+
+    if let Some(parent) = args.out.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let flags = dev_flags(&args)?;
+    let status = esbuild_command(&esbuild, &args, &flags).status().await?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("initial build of {} failed", args.entry.display())).into());
+    }
+
+    let mut watcher = esbuild_command(&esbuild, &args, &flags)
+        .arg("--watch=forever")
+        .kill_on_drop(true)
+        .spawn()?;
+
+    println!("MASS dev watching {}", args.entry.display());
+    cli.dev_server = Some(args.out.clone());
+
+    tokio::select! {
+        result = supervisor::supervise(cli) => result,
+        status = watcher.wait() => Err(std::io::Error::other(format!("esbuild watcher exited with {}", status?)).into()),
+    }
+}
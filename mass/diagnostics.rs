@@ -0,0 +1,108 @@
+use crate::cli::Cli;
+use crate::loader;
+use crate::stack;
+
+use deno_core::error::{CoreError, CoreErrorKind, JsError};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Colors diagnostics when stderr is a terminal, unless `--no-color` is
+/// passed or `NO_COLOR` is set.
+pub fn init(cli: &Cli) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    COLOR.store(
+        !cli.no_color && !no_color && std::io::stderr().is_terminal(),
+        Ordering::Relaxed,
+    );
+}
+
+fn paint(code: &str, text: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+fn red(text: &str) -> String { paint("1;31", text) }
+
+fn yellow(text: &str) -> String { paint("1;33", text) }
+
+fn cyan(text: &str) -> String { paint("36", text) }
+
+fn dim(text: &str) -> String { paint("2", text) }
+
+/// What a Deno permission error (`Requires net access to "host"`) or a
+/// `--fs-root` refusal is about, as a hint naming the mass flag to change.
+fn hint(message: &str) -> Option<String> {
+    if message.contains("escapes the filesystem root") {
+        return Some("keep the path under --fs-root, or point --fs-root at a directory containing it".into());
+    }
+
+    let (kind, rest) = message.split_once("Requires ")?.1.split_once(" access")?;
+    let target = rest
+        .strip_prefix(" to ")
+        .and_then(|rest| rest.split('"').nth(1))
+        .filter(|target| !target.is_empty());
+
+    Some(match kind {
+        "net" => {
+            let host = target.map_or("HOST", |target| target.split(':').next().unwrap_or(target));
+            format!("allow it with --allow-net={host} (or add it to MASS_ALLOW_NET)")
+        }
+        "ffi" => "enable native libraries with --allow-ffi, or --allow-ffi=PATH for just this one".into(),
+        "read" | "run" | "write" => "grant it when --prompt asks, or start mass without --prompt".into(),
+        kind => format!("{kind} access is not granted to servers run by mass"),
+    })
+}
+
+fn frames(out: &mut String, error: &JsError) {
+    for frame in &error.frames {
+        let location = format!(
+            "{}:{}:{}",
+            frame.file_name.as_deref().unwrap_or("<anonymous>"),
+            frame.line_number.unwrap_or_default(),
+            frame.column_number.unwrap_or_default()
+        );
+        let line = match &frame.function_name {
+            Some(name) => format!("at {name} ({})", cyan(&location)),
+            None => format!("at {}", cyan(&location)),
+        };
+        out.push_str(&format!("\n    {}", dim(&line)));
+    }
+
+    if let Some(cause) = &error.cause {
+        out.push_str(&format!("\n{} {}", dim("caused by:"), cause.exception_message));
+        frames(out, cause);
+    }
+}
+
+/// `error` as printed when a worker or command fails: the message, stack
+/// frames mapped back to source, the imports that led to a module that failed
+/// to load, and a hint when a permission was denied.
+pub fn format(error: &CoreError) -> String {
+    let (message, error) = match error.0.as_ref() {
+        CoreErrorKind::Js(error) => (error.exception_message.clone(), Some(stack::remapped(error))),
+        error => (error.to_string(), None),
+    };
+
+    let mut out = format!("{} {message}", red("error:"));
+    if let Some(error) = &error {
+        frames(&mut out, error);
+    }
+
+    if let Some(chain) = loader::failed_import_chain()
+        && message.contains(chain[0].as_str())
+    {
+        for importer in &chain[1..] {
+            out.push_str(&format!("\n    {} {}", dim("imported from"), cyan(importer.as_str())));
+        }
+    }
+
+    if let Some(hint) = hint(&message) {
+        out.push_str(&format!("\n{} {hint}", yellow("hint:")));
+    }
+    out
+}
@@ -0,0 +1,132 @@
+use crate::bundler;
+use crate::modules::cache;
+use crate::npm::Registries;
+use crate::snapshot;
+
+use deno_core::{JsRuntime, v8};
+use std::time::Duration;
+
+struct Check {
+    name: &'static str,
+    result: Result<String, String>,
+    hint: &'static str,
+}
+
+fn check_cache() -> Check {
+    let dir = cache::cache_dir();
+    let probe = dir.join(".doctor");
+
+    let result = std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map(|_| format!("{} is writable", dir.display()))
+        .map_err(|err| format!("{}: {err}", dir.display()));
+
+    Check {
+        name: "module cache",
+        result,
+        hint: "run mass from a directory it can write to, or fix permissions on ./cache",
+    }
+}
+
+async fn check_registry(client: &reqwest::Client, url: &str) -> Check {
+    let result = match client.head(url).send().await {
+        Ok(res) if res.status().is_server_error() => Err(format!("{url} answered {}", res.status())),
+        Ok(res) => Ok(format!("{url} reachable ({})", res.status())),
+        Err(err) => Err(format!("{url}: {err}")),
+    };
+
+    Check {
+        name: "registry",
+        result,
+        hint: "check DNS, proxy settings and --allow-net for this host",
+    }
+}
+
+fn check_snapshot() -> Check {
+    Check {
+        name: "snapshot",
+        result: snapshot::validate().map(|_| "embedded RUNTIME.bin matches this binary".into()),
+        hint: "rebuild with `cargo build --release` so the snapshot is regenerated",
+    }
+}
+
+/// Starts an isolate and evaluates a script in it.
+fn check_v8() -> Check {
+    let mut runtime = JsRuntime::new(Default::default());
+    let result = match runtime.execute_script("doctor", "[1, 2, 3].reduce((sum, n) => sum + n, 0)") {
+        Ok(value) => {
+            let scope = &mut runtime.handle_scope();
+            let value = v8::Local::new(scope, value);
+            match value.integer_value(scope) {
+                Some(6) => Ok(format!("V8 {} evaluates scripts", v8::VERSION_STRING)),
+                _ => Err(format!(
+                    "V8 {} evaluated a script to {}",
+                    v8::VERSION_STRING,
+                    value.to_rust_string_lossy(scope)
+                )),
+            }
+        }
+        Err(err) => Err(format!("V8 {}: {err}", v8::VERSION_STRING)),
+    };
+
+    Check {
+        name: "v8",
+        result,
+        hint: "rebuild mass; the V8 it was linked with doesn't run on this machine",
+    }
+}
+
+fn check_esbuild() -> Check {
+    let result = match bundler::esbuild() {
+        Some(path) => Ok(format!("found {}", path.display())),
+        None => Err("no esbuild in the mass cache directory or on PATH".into()),
+    };
+
+    Check {
+        name: "esbuild",
+        result,
+        hint: "build mass once on this machine to install esbuild, or install it on PATH",
+    }
+}
+
+pub async fn run() {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default();
+    let mut checks = vec![check_cache(), check_snapshot(), check_v8(), check_esbuild()];
+
+    // The registries installs use, from .npmrc and MASS_NPM_REGISTRY.
+    match Registries::load(std::path::Path::new(".")) {
+        Ok(registries) => {
+            for url in registries.urls() {
+                checks.push(check_registry(&client, url).await);
+            }
+        }
+        Err(err) => checks.push(Check {
+            name: "registry",
+            result: Err(format!("reading .npmrc: {err}")),
+            hint: "fix the registry settings in .npmrc",
+        }),
+    }
+
+    let mut failed = 0;
+    for check in &checks {
+        match &check.result {
+            Ok(detail) => println!("  ok    {:<14} {detail}", check.name),
+            Err(detail) => {
+                failed += 1;
+                println!("  fail  {:<14} {detail}", check.name);
+                println!("        {:<14} hint: {}", "", check.hint);
+            }
+        }
+    }
+
+    if failed > 0 {
+        println!("\n{failed} check(s) failed");
+        std::process::exit(1);
+    }
+
+    println!("\nall checks passed");
+}
@@ -0,0 +1,379 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::stardust;
+
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, RwLock};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+
+/// `[server]` in mass.toml: HTTP settings `Deno.serve` doesn't expose. Setting
+/// any of them puts the host's HTTP server in front of the worker, which then
+/// serves on a private socket.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Accept HTTP/2, over ALPN with TLS or by prior knowledge without. On by
+    /// default.
+    pub http2: Option<bool>,
+    /// Streams one HTTP/2 connection may have open at once.
+    pub max_concurrent_streams: Option<u32>,
+    /// Reuse HTTP/1.1 connections for more than one request. On by default.
+    pub keep_alive: Option<bool>,
+    /// Ping idle HTTP/2 connections this often.
+    pub keep_alive_interval_secs: Option<u64>,
+    /// Drop an HTTP/2 connection whose ping goes unanswered this long.
+    pub keep_alive_timeout_secs: Option<u64>,
+    /// Largest request head accepted, in bytes. HTTP/1.1 never goes below 8 KiB.
+    pub max_header_bytes: Option<usize>,
+    /// Largest request body passed on to JS, in bytes. Larger declared bodies
+    /// are refused with 413 before the worker sees them; streamed ones are cut
+    /// off at the limit.
+    pub max_body_bytes: Option<usize>,
+    /// Time a client gets to finish the TLS handshake and send a request head,
+    /// including between requests on a kept-alive HTTP/1.1 connection.
+    pub header_timeout_secs: Option<u64>,
+    /// Time the worker gets to start responding, request body included, before
+    /// the client is sent 504. Responses already streaming aren't cut short.
+    pub request_timeout_secs: Option<u64>,
+    /// Tenant every request is handled for, passed on in `x-tenant-id`. What
+    /// clients send in that header is always dropped.
+    pub tenant_id: Option<String>,
+}
+
+impl ServerConfig {
+    pub fn is_tuned(&self) -> bool {
+        self.http2.is_some()
+            || self.max_concurrent_streams.is_some()
+            || self.keep_alive.is_some()
+            || self.keep_alive_interval_secs.is_some()
+            || self.keep_alive_timeout_secs.is_some()
+            || self.max_header_bytes.is_some()
+            || self.max_body_bytes.is_some()
+            || self.header_timeout_secs.is_some()
+            || self.request_timeout_secs.is_some()
+            || self.tenant_id.is_some()
+    }
+
+    fn http2(&self) -> bool { self.http2.unwrap_or(true) }
+}
+
+fn acceptor(cli: &Cli, http2: bool) -> std::io::Result<Option<TlsAcceptor>> {
+    let Some(tls) = stardust::tls_config(cli, Config::load(&cli.config)?.tls).load()? else {
+        return Ok(None);
+    };
+
+    let certs = rustls_pemfile::certs(&mut tls.cert.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut tls.key.as_bytes())?
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "tls key: no private key in the PEM"))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, format!("tls: {err}")))?;
+
+    config.alpn_protocols = if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// The host's HTTP server, terminating TLS and HTTP/2 and forwarding every
+/// request to the current worker over HTTP/1.1.
+#[derive(Clone)]
+pub struct Front {
+    config: Arc<ServerConfig>,
+    tls: Arc<RwLock<Option<TlsAcceptor>>>,
+}
+
+impl Front {
+    pub fn new(cli: &Cli, config: ServerConfig) -> std::io::Result<Self> {
+        Ok(Self {
+            tls: Arc::new(RwLock::new(acceptor(cli, config.http2())?)),
+            config: Arc::new(config),
+        })
+    }
+
+    /// Re-reads the certificate and key for connections from now on. The old
+    /// ones stay in use when the new ones don't load.
+    pub fn reload_tls(&self, cli: &Cli) -> std::io::Result<()> {
+        let acceptor = acceptor(cli, self.config.http2())?;
+        *self.tls.write().unwrap() = acceptor;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod serve {
+    use super::{Front, ServerConfig};
+    use crate::listener::{self, Accepted, Public};
+    use crate::modules::context::{DEADLINE_HEADER, REQUEST_ID_HEADER, TENANT_ID_HEADER};
+
+    use bytes::Bytes;
+    use http_body_util::combinators::BoxBody;
+    use http_body_util::{BodyExt, Full, Limited};
+    use hyper::body::Incoming;
+    use hyper::header::{self, HeaderValue};
+    use hyper::http::uri::PathAndQuery;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response, StatusCode, Uri, Version};
+    use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+    use hyper_util::server::conn::auto;
+    use std::convert::Infallible;
+    use std::hash::{BuildHasher, RandomState};
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio::sync::watch;
+
+    type Body = BoxBody<Bytes, hyper::Error>;
+
+    fn respond(status: StatusCode, message: &'static str) -> Response<Body> {
+        let body = Full::new(Bytes::from_static(message.as_bytes())).map_err(|never| match never {});
+        let mut response = Response::new(body.boxed());
+        *response.status_mut() = status;
+        response
+    }
+
+    /// A fresh id for a request that came without one.
+    fn request_id() -> String {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!("{:016x}", RandomState::new().hash_one(time))
+    }
+
+    /// Sends `request` on to the worker as HTTP/1.1 within the configured
+    /// limits, carrying over upgraded connections such as WebSockets.
+    async fn forward(
+        mut request: Request<Incoming>, config: Arc<ServerConfig>, backend: watch::Receiver<PathBuf>,
+        peer: Option<IpAddr>, https: bool,
+    ) -> Result<Response<Body>, Infallible> {
+        let upgrade = request
+            .headers()
+            .contains_key(header::UPGRADE)
+            .then(|| hyper::upgrade::on(&mut request));
+
+        if let Some(authority) = request.uri().authority().cloned()
+            && !request.headers().contains_key(header::HOST)
+            && let Ok(host) = HeaderValue::from_str(authority.as_str())
+        {
+            request.headers_mut().insert(header::HOST, host);
+        }
+        let path = request
+            .uri()
+            .path_and_query()
+            .cloned()
+            .unwrap_or_else(|| PathAndQuery::from_static("/"));
+        *request.uri_mut() = Uri::from(path);
+        *request.version_mut() = Version::HTTP_11;
+
+        // Only what the front itself sets is passed on; the client's own are
+        // whatever it wanted the worker to believe.
+        let headers = request.headers_mut();
+        headers.remove("x-forwarded-for");
+        headers.remove(TENANT_ID_HEADER);
+        if let Some(peer) = peer
+            && let Ok(peer) = HeaderValue::from_str(&peer.to_string())
+        {
+            headers.insert("x-forwarded-for", peer);
+        }
+        if let Some(tenant) = &config.tenant_id
+            && let Ok(tenant) = HeaderValue::from_str(tenant)
+        {
+            headers.insert(TENANT_ID_HEADER, tenant);
+        }
+        headers.insert(
+            "x-forwarded-proto",
+            HeaderValue::from_static(if https { "https" } else { "http" }),
+        );
+
+        // The worker scopes its handling of the request to these, see
+        // `MASS.context`. A deadline the client sent is kept when it's sooner.
+        if !headers.contains_key(REQUEST_ID_HEADER)
+            && let Ok(id) = HeaderValue::from_str(&request_id())
+        {
+            headers.insert(REQUEST_ID_HEADER, id);
+        }
+        if let Some(secs) = config.request_timeout_secs {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            let deadline = (now + Duration::from_secs(secs)).as_millis() as u64;
+            let sent = headers
+                .get(DEADLINE_HEADER)
+                .and_then(|deadline| deadline.to_str().ok()?.parse::<u64>().ok());
+            headers.insert(
+                DEADLINE_HEADER,
+                HeaderValue::from(sent.map_or(deadline, |sent| sent.min(deadline))),
+            );
+        }
+
+        if let Some(max) = config.max_body_bytes {
+            let declared = request
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok()?.parse::<u64>().ok());
+            if declared.is_some_and(|len| len > max as u64) {
+                return Ok(respond(StatusCode::PAYLOAD_TOO_LARGE, "request body too large"));
+            }
+        }
+        let request = request.map(|body| match config.max_body_bytes {
+            Some(max) => Limited::new(body, max).boxed(),
+            None => body.map_err(Into::into).boxed(),
+        });
+
+        let upstream = match listener::connect(backend).await {
+            Ok(upstream) => upstream,
+            Err(error) => {
+                eprintln!("MASS no worker to forward a request to: {error}");
+                return Ok(respond(StatusCode::BAD_GATEWAY, "worker unavailable"));
+            }
+        };
+
+        let (mut sender, connection) = match hyper::client::conn::http1::handshake(TokioIo::new(upstream)).await {
+            Ok(handshake) => handshake,
+            Err(error) => {
+                eprintln!("MASS failed to reach the worker: {error}");
+                return Ok(respond(StatusCode::BAD_GATEWAY, "worker unavailable"));
+            }
+        };
+        tokio::spawn(connection.with_upgrades());
+
+        let response = match config.request_timeout_secs {
+            Some(secs) => tokio::time::timeout(Duration::from_secs(secs), sender.send_request(request)).await,
+            None => Ok(sender.send_request(request).await),
+        };
+        let mut response = match response {
+            Ok(Ok(response)) => response,
+            Err(_) => return Ok(respond(StatusCode::GATEWAY_TIMEOUT, "request timed out")),
+            // The body erroring on the way out is the limit above cutting it off.
+            Ok(Err(error)) if error.is_user() && config.max_body_bytes.is_some() => {
+                return Ok(respond(StatusCode::PAYLOAD_TOO_LARGE, "request body too large"));
+            }
+            Ok(Err(error)) => {
+                eprintln!("MASS worker failed to answer a request: {error}");
+                return Ok(respond(StatusCode::BAD_GATEWAY, "worker failed to respond"));
+            }
+        };
+
+        if let Some(upgrade) = upgrade
+            && response.status() == StatusCode::SWITCHING_PROTOCOLS
+        {
+            let upstream = hyper::upgrade::on(&mut response);
+            tokio::spawn(async move {
+                if let (Ok(client), Ok(upstream)) = (upgrade.await, upstream.await) {
+                    let _ = tokio::io::copy_bidirectional(&mut TokioIo::new(client), &mut TokioIo::new(upstream)).await;
+                }
+            });
+        }
+
+        Ok(response.map(BodyExt::boxed))
+    }
+
+    impl Front {
+        fn builder(&self) -> auto::Builder<TokioExecutor> {
+            let config = &self.config;
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            if !config.http2() {
+                builder = builder.http1_only();
+            }
+
+            {
+                let mut http1 = builder.http1();
+                http1
+                    .timer(TokioTimer::new())
+                    .keep_alive(config.keep_alive.unwrap_or(true));
+                if let Some(bytes) = config.max_header_bytes {
+                    http1.max_buf_size(bytes.max(8192));
+                }
+                if let Some(secs) = config.header_timeout_secs {
+                    http1.header_read_timeout(Duration::from_secs(secs));
+                }
+            }
+
+            {
+                let mut http2 = builder.http2();
+                http2.timer(TokioTimer::new());
+                if let Some(streams) = config.max_concurrent_streams {
+                    http2.max_concurrent_streams(streams);
+                }
+                if let Some(secs) = config.keep_alive_interval_secs {
+                    http2.keep_alive_interval(Duration::from_secs(secs));
+                }
+                if let Some(secs) = config.keep_alive_timeout_secs {
+                    http2.keep_alive_timeout(Duration::from_secs(secs));
+                }
+                if let Some(bytes) = config.max_header_bytes {
+                    http2.max_header_list_size(u32::try_from(bytes).unwrap_or(u32::MAX));
+                }
+            }
+
+            builder
+        }
+
+        async fn connection<S>(self, stream: S, peer: Option<IpAddr>, backend: watch::Receiver<PathBuf>)
+        where
+            S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        {
+            let tls = self.tls.read().unwrap().clone();
+            let https = tls.is_some();
+            let config = self.config.clone();
+            let service = move |request| forward(request, config.clone(), backend.clone(), peer, https);
+            let handshake = Duration::from_secs(self.config.header_timeout_secs.unwrap_or(30));
+
+            let result = match tls {
+                Some(tls) => match tokio::time::timeout(handshake, tls.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        self.builder()
+                            .serve_connection_with_upgrades(TokioIo::new(stream), service_fn(service))
+                            .await
+                    }
+                    Ok(Err(_)) | Err(_) => return,
+                },
+                None => {
+                    self.builder()
+                        .serve_connection_with_upgrades(TokioIo::new(stream), service_fn(service))
+                        .await
+                }
+            };
+
+            if let Err(error) = result {
+                eprintln!("MASS connection closed with an error: {error}");
+            }
+        }
+
+        /// Accepts forever, serving every connection with `[server]` settings.
+        pub async fn serve(self, public: Public, backend: watch::Receiver<PathBuf>) {
+            loop {
+                let (stream, peer) = match public.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(error) => {
+                        eprintln!("MASS failed to accept a connection: {error}");
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        continue;
+                    }
+                };
+
+                let front = self.clone();
+                let backend = backend.clone();
+                match stream {
+                    Accepted::Tcp(stream) => tokio::spawn(front.connection(stream, peer, backend)),
+                    Accepted::Unix(stream) => tokio::spawn(front.connection(stream, peer, backend)),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Front {
+    pub async fn serve(self, public: crate::listener::Public, _: tokio::sync::watch::Receiver<std::path::PathBuf>) {
+        match public {}
+    }
+}
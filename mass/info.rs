@@ -0,0 +1,241 @@
+use crate::cli::{Cli, InfoArgs};
+use crate::config::Config;
+use crate::loader::{ExtendedModuleLoader, ModuleCache};
+use crate::modules::audit::AuditLog;
+use crate::modules::cache;
+use crate::modules::quota::RateLimiter;
+use crate::modules::sandbox::FsRoot;
+
+use deno_core::futures::FutureExt;
+use deno_core::{
+    JsRuntime, ModuleCodeBytes, ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode, ModuleSpecifier,
+    ModuleType, RequestedModuleType, ResolutionKind, RuntimeOptions,
+};
+use deno_error::JsErrorBox;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// A module as the loader handed it over.
+struct Module {
+    module_type: String,
+    size: usize,
+    /// Whether a remote module was already in the disk cache, `None` for local
+    /// and data modules.
+    cached: Option<bool>,
+    redirect: Option<ModuleSpecifier>,
+}
+
+#[derive(Default)]
+struct Graph {
+    /// The modules each one imports, in the order they were resolved.
+    imports: HashMap<ModuleSpecifier, Vec<ModuleSpecifier>>,
+    modules: HashMap<ModuleSpecifier, Result<Module, String>>,
+}
+
+/// Loads through the module loader a server would use, noting every module
+/// and import on the way. A module that fails to load is recorded and stood in
+/// for by an empty one, so the rest of the graph is still walked.
+struct Recorder {
+    loader: ExtendedModuleLoader,
+    /// The loader's copy of remote modules, which knows where each one was
+    /// redirected to.
+    modules: ModuleCache,
+    graph: Rc<RefCell<Graph>>,
+}
+
+impl ModuleLoader for Recorder {
+    fn resolve(&self, specifier: &str, referrer: &str, kind: ResolutionKind) -> Result<ModuleSpecifier, JsErrorBox> {
+        let resolved = self.loader.resolve(specifier, referrer, kind)?;
+        if let Ok(referrer) = ModuleSpecifier::parse(referrer) {
+            let mut graph = self.graph.borrow_mut();
+            let imports = graph.imports.entry(referrer).or_default();
+            if !imports.contains(&resolved) {
+                imports.push(resolved.clone());
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn load(
+        &self, specifier: &ModuleSpecifier, referrer: Option<&ModuleSpecifier>, is_dynamic: bool,
+        requested_module_type: RequestedModuleType,
+    ) -> ModuleLoadResponse {
+        let cached = matches!(specifier.scheme(), "http" | "https").then(|| cache::locate(specifier).is_some());
+        let response = self
+            .loader
+            .load(specifier, referrer, is_dynamic, requested_module_type.clone());
+        let graph = self.graph.clone();
+        let modules = self.modules.clone();
+        let specifier = specifier.clone();
+
+        let future = async move {
+            let result = match response {
+                ModuleLoadResponse::Sync(result) => result,
+                ModuleLoadResponse::Async(future) => future.await,
+            };
+
+            let source = match result {
+                Ok(source) => source,
+                Err(err) => {
+                    graph
+                        .borrow_mut()
+                        .modules
+                        .insert(specifier.clone(), Err(err.to_string()));
+                    let (module_type, code) = match requested_module_type {
+                        RequestedModuleType::Json => (ModuleType::Json, "null"),
+                        RequestedModuleType::Text => (ModuleType::Text, ""),
+                        RequestedModuleType::Bytes => (ModuleType::Bytes, ""),
+                        RequestedModuleType::Other(other) => (ModuleType::Other(other), ""),
+                        RequestedModuleType::None => (ModuleType::JavaScript, ""),
+                    };
+                    let code = ModuleSourceCode::Bytes(ModuleCodeBytes::Static(code.as_bytes()));
+                    return Ok(ModuleSource::new(module_type, code, &specifier, None));
+                }
+            };
+
+            let redirect = modules.redirect(&specifier);
+            let module = Module {
+                module_type: source.module_type.to_string(),
+                size: source.code.as_bytes().len(),
+                cached,
+                redirect,
+            };
+            graph.borrow_mut().modules.insert(specifier, Ok(module));
+            Ok(source)
+        };
+
+        ModuleLoadResponse::Async(future.boxed_local())
+    }
+}
+
+fn size(bytes: usize) -> String {
+    match bytes {
+        bytes if bytes >= 1 << 20 => format!("{:.2}MB", bytes as f64 / (1 << 20) as f64),
+        bytes if bytes >= 1 << 10 => format!("{:.2}KB", bytes as f64 / (1 << 10) as f64),
+        bytes => format!("{bytes}B"),
+    }
+}
+
+impl Graph {
+    fn describe(&self, specifier: &ModuleSpecifier) -> String {
+        match self.modules.get(specifier) {
+            None => format!("{specifier} (not loaded)"),
+            Some(Err(err)) => format!("{specifier} (error: {err})"),
+            Some(Ok(module)) => {
+                let mut details = vec![module.module_type.clone(), size(module.size)];
+                match module.cached {
+                    Some(true) => details.push("cached".into()),
+                    Some(false) => details.push("fetched".into()),
+                    None => {}
+                }
+                if let Some(redirect) = &module.redirect {
+                    details.push(format!("redirected to {redirect}"));
+                }
+                format!("{specifier} ({})", details.join(", "))
+            }
+        }
+    }
+
+    /// A redirected module's imports are resolved against where it ended up.
+    fn imports_of(&self, specifier: &ModuleSpecifier) -> &[ModuleSpecifier] {
+        let found = match self.modules.get(specifier) {
+            Some(Ok(Module {
+                redirect: Some(redirect),
+                ..
+            })) => redirect,
+            _ => specifier,
+        };
+        self.imports.get(found).map_or(&[], Vec::as_slice)
+    }
+
+    /// `specifier`'s imports as a tree under `prefix`. A module already shown
+    /// is marked with `*` instead of being expanded again.
+    fn print(&self, specifier: &ModuleSpecifier, prefix: &str, seen: &mut HashSet<ModuleSpecifier>) {
+        let imports = self.imports_of(specifier);
+        for (index, import) in imports.iter().enumerate() {
+            let last = index == imports.len() - 1;
+            let expand = seen.insert(import.clone()) && !self.imports_of(import).is_empty();
+
+            let branch = match (last, expand) {
+                (true, true) => "└─┬",
+                (true, false) => "└──",
+                (false, true) => "├─┬",
+                (false, false) => "├──",
+            };
+            let repeated = if expand || self.imports_of(import).is_empty() {
+                ""
+            } else {
+                " *"
+            };
+            println!("{prefix}{branch} {}{repeated}", self.describe(import));
+
+            if expand {
+                self.print(import, &format!("{prefix}{}", if last { "  " } else { "│ " }), seen);
+            }
+        }
+    }
+}
+
+/// `mass info`: the dependency tree of a module, resolved and loaded the way
+/// a server would, fetching whatever the cache doesn't have yet.
+pub async fn run(cli: &Cli, args: &InfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let root = deno_core::resolve_url_or_path(&args.specifier, &std::env::current_dir()?)?;
+    let audit = match &cli.audit_permissions {
+        Some(path) => AuditLog::open(path)?,
+        None => AuditLog::default(),
+    };
+    let modules = ModuleCache::default();
+    let loader = ExtendedModuleLoader::new(
+        FsRoot::new(cli.fs_root.clone())?,
+        modules.clone(),
+        audit,
+        RateLimiter::new(Config::load(&cli.config)?.rate_limit),
+    );
+
+    let graph = Rc::new(RefCell::new(Graph::default()));
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        module_loader: Some(Rc::new(Recorder {
+            loader,
+            modules,
+            graph: graph.clone(),
+        })),
+        ..Default::default()
+    });
+
+    // Failing to link, e.g. on an import an errored module couldn't provide,
+    // comes after every module was loaded and doesn't change the graph.
+    let linked = runtime.load_main_es_module(&root).await;
+    let graph = graph.borrow();
+    if let Err(err) = linked
+        && !graph.modules.contains_key(&root)
+    {
+        return Err(err.into());
+    }
+
+    let modules: Vec<_> = graph
+        .modules
+        .values()
+        .filter_map(|module| module.as_ref().ok())
+        .collect();
+    println!(
+        "{} {}",
+        if root.scheme() == "file" { "local:" } else { "remote:" },
+        root
+    );
+    println!(
+        "dependencies: {} unique, {} total",
+        graph.modules.len().saturating_sub(1),
+        size(modules.iter().map(|module| module.size).sum())
+    );
+    println!();
+    println!("{}", graph.describe(&root));
+    graph.print(&root, "", &mut HashSet::from([root.clone()]));
+
+    let failed = graph.modules.values().filter(|module| module.is_err()).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!("{failed} modules failed to load").into())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::cli::InstallArgs;
+use crate::config::Config;
+use crate::modules::quota::RateLimiter;
+use crate::npm::{InstallOptions, Manifest, Registries, Store};
+use std::path::Path;
+
+/// `mass install`: the installer release builds use for `mass/server`, run
+/// against any project directory.
+pub async fn run(args: &InstallArgs, config: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = &args.dir;
+    let manifest = Manifest::read(dir)?;
+    let members = manifest.members(dir)?;
+    // Registry requests share the limiter's client and connection caps with
+    // everything else going out.
+    let limiter = RateLimiter::new(Config::load(config)?.rate_limit);
+    let client = limiter.client().clone();
+
+    let options = InstallOptions {
+        lockfile: dir.join("pkg.lock"),
+        frozen: args.frozen,
+        store: Store::default_root(),
+        metadata_cache: InstallOptions::default_metadata_cache(),
+        registries: Registries::load(dir)?.with_connect(move |url| {
+            let limiter = limiter.clone();
+            Box::pin(async move { Box::new(limiter.connect(&url).await) as Box<dyn Send> })
+        }),
+        project: dir.clone(),
+        import: ["package-lock.json", "yarn.lock"]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists()),
+        allow_scripts: manifest
+            .scripts
+            .allow
+            .into_iter()
+            .chain(
+                members
+                    .iter()
+                    .flat_map(|member| member.manifest.scripts.allow.iter().cloned()),
+            )
+            .chain(args.allow_scripts.iter().cloned())
+            .collect(),
+        prune: args.prune,
+        verify: args.verify,
+        offline: args.offline,
+        members: members
+            .iter()
+            .map(|member| (member.dir.clone(), member.manifest.dependencies.clone()))
+            .collect(),
+    };
+
+    crate::npm::install_all_packages(&client, &dir.join("node_modules"), manifest.dependencies, &options).await
+}
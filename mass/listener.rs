@@ -0,0 +1,179 @@
+use crate::modules::listen::Listen;
+
+use std::io::{Error, ErrorKind};
+#[cfg(unix)]
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// How long a connection waits for a booting worker to bind its socket.
+#[cfg(unix)]
+const CONNECT_DEADLINE: std::time::Duration = std::time::Duration::from_secs(10);
+
+static NEXT_SOCKET: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh socket path for one worker to serve on, behind the public listener.
+pub fn private_socket() -> PathBuf {
+    let n = NEXT_SOCKET.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("mass-{}-{n}.sock", std::process::id()))
+}
+
+/// The socket the host accepts on when it sits in front of the worker. It stays
+/// bound for the life of the process, so restarts and reloads never refuse
+/// connections or need the socket file recreated.
+#[cfg(unix)]
+pub enum Public {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener),
+}
+
+#[cfg(unix)]
+impl Public {
+    pub fn bind(listen: &Listen) -> std::io::Result<Self> {
+        match listen {
+            Listen::Unix(path) => {
+                remove_stale(path)?;
+                Ok(Self::Unix(tokio::net::UnixListener::bind(path)?))
+            }
+            Listen::Systemd => Self::inherit(),
+            Listen::Tcp { hostname, port } => {
+                let listener = std::net::TcpListener::bind((hostname.as_str(), *port))?;
+                listener.set_nonblocking(true)?;
+                Ok(Self::Tcp(tokio::net::TcpListener::from_std(listener)?))
+            }
+        }
+    }
+
+    /// The first socket passed by systemd socket activation (`sd_listen_fds`).
+    fn inherit() -> std::io::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        const SD_LISTEN_FDS_START: i32 = 3;
+
+        let ours = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+        let fds = std::env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|fds| fds.parse::<u32>().ok())
+            .unwrap_or(0);
+        if !ours || fds == 0 {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "--listen systemd needs a socket passed in by systemd socket activation",
+            ));
+        }
+
+        // SAFETY: systemd hands the process ownership of the fds from 3 on, and
+        // nothing else in the process claims them.
+        let tcp = unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+        // getsockname only yields an address std understands for inet sockets.
+        if tcp.local_addr().is_ok() {
+            tcp.set_nonblocking(true)?;
+            return Ok(Self::Tcp(tokio::net::TcpListener::from_std(tcp)?));
+        }
+
+        let unix = std::os::unix::net::UnixListener::from(std::os::fd::OwnedFd::from(tcp));
+        unix.set_nonblocking(true)?;
+        Ok(Self::Unix(tokio::net::UnixListener::from_std(unix)?))
+    }
+
+    /// The next connection, with the client's address when it came over TCP.
+    pub async fn accept(&self) -> std::io::Result<(Accepted, Option<IpAddr>)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Ok((Accepted::Tcp(stream), Some(peer.ip())))
+            }
+            Self::Unix(listener) => Ok((Accepted::Unix(listener.accept().await?.0), None)),
+        }
+    }
+
+    /// Accepts forever, forwarding the bytes of every connection to the socket
+    /// `backend` currently points at.
+    pub async fn proxy(self, backend: watch::Receiver<PathBuf>) {
+        loop {
+            let client = match self.accept().await {
+                Ok((client, _)) => client,
+                Err(error) => {
+                    eprintln!("MASS failed to accept a connection: {error}");
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    continue;
+                }
+            };
+
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                if let Err(error) = forward(client, backend).await {
+                    eprintln!("MASS failed to forward a connection: {error}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub enum Public {}
+
+#[cfg(not(unix))]
+impl Public {
+    pub fn bind(_: &Listen) -> std::io::Result<Self> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "unix sockets and systemd activation need a unix platform",
+        ))
+    }
+
+    pub async fn proxy(self, _: watch::Receiver<PathBuf>) { match self {} }
+}
+
+#[cfg(unix)]
+pub enum Accepted {
+    Tcp(tokio::net::TcpStream),
+    Unix(tokio::net::UnixStream),
+}
+
+/// Connects to the current worker. A worker that's still booting, or restarting
+/// after a crash, hasn't bound its socket yet; this holds on until it has.
+#[cfg(unix)]
+pub async fn connect(mut backend: watch::Receiver<PathBuf>) -> std::io::Result<tokio::net::UnixStream> {
+    let deadline = tokio::time::Instant::now() + CONNECT_DEADLINE;
+
+    loop {
+        let path = backend.borrow_and_update().clone();
+        match tokio::net::UnixStream::connect(&path).await {
+            Ok(upstream) => return Ok(upstream),
+            Err(error) if tokio::time::Instant::now() >= deadline => return Err(error),
+            Err(_) => {
+                let wait = std::time::Duration::from_millis(50);
+                let _ = tokio::time::timeout(wait, backend.changed()).await;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn forward(client: Accepted, backend: watch::Receiver<PathBuf>) -> std::io::Result<()> {
+    let mut upstream = connect(backend).await?;
+
+    match client {
+        Accepted::Tcp(mut client) => tokio::io::copy_bidirectional(&mut client, &mut upstream).await?,
+        Accepted::Unix(mut client) => tokio::io::copy_bidirectional(&mut client, &mut upstream).await?,
+    };
+    Ok(())
+}
+
+/// Removes a socket file left behind by a previous run. A socket something is
+/// still listening on is left alone, and binding reports it as in use.
+#[cfg(unix)]
+fn remove_stale(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if meta.file_type().is_socket() && std::os::unix::net::UnixStream::connect(path).is_err() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
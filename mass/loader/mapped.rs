@@ -0,0 +1,60 @@
+use crate::modules::cache;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+
+/// Module sources at least this large are mapped instead of read.
+const THRESHOLD: u64 = 256 * 1024;
+
+/// How much is mapped at most. Mappings live for the rest of the process, so
+/// past this files are read instead.
+const MAX_MAPPED: u64 = 1 << 30;
+
+type Key = (PathBuf, u64, Option<SystemTime>);
+
+#[derive(Default)]
+struct Mapped {
+    files: HashMap<Key, &'static [u8]>,
+    bytes: u64,
+}
+
+/// Every file mapped so far, by path, size and modification time. Mappings are
+/// never unmapped: V8 holds on to the source it compiled from for as long as
+/// the module lives, and a file that changes is mapped again under a new key
+/// instead of being remapped in place. [`MAX_MAPPED`] bounds what that leaks.
+static MAPPED: LazyLock<Mutex<Mapped>> = LazyLock::new(Default::default);
+
+/// The contents of `path`, mapped into memory when it is a module cache file
+/// of at least [`THRESHOLD`] bytes. `None` for anything else, which is read.
+pub fn map(path: &Path) -> std::io::Result<Option<&'static [u8]>> {
+    if !path.starts_with(cache::cache_dir()) {
+        return Ok(None);
+    }
+
+    let file = std::fs::File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.len() < THRESHOLD {
+        return Ok(None);
+    }
+
+    let key = (path.to_path_buf(), metadata.len(), metadata.modified().ok());
+    let mut mapped = MAPPED.lock().unwrap();
+    if let Some(bytes) = mapped.files.get(&key) {
+        return Ok(Some(bytes));
+    }
+    if mapped.bytes + metadata.len() > MAX_MAPPED {
+        return Ok(None);
+    }
+
+    // SAFETY: only module cache files get here, and the cache only ever
+    // replaces one by renaming a new file over it, so a mapped file is never
+    // truncated or written to in place. A replaced one stays mapped from the
+    // unlinked inode.
+    let map = unsafe { Mmap::map(&file)? };
+    let bytes: &'static [u8] = Box::leak(Box::new(map));
+    mapped.files.insert(key, bytes);
+    mapped.bytes += metadata.len();
+    Ok(Some(bytes))
+}
@@ -1,12 +1,25 @@
-mod cache;
+mod mapped;
 
+use crate::modules::audit::AuditLog;
+use crate::modules::cache;
+use crate::modules::errors::CacheError;
+use crate::modules::http_cache::{self, Body, Policy};
+use crate::modules::quota::RateLimiter;
+use crate::modules::sandbox::FsRoot;
 use data_url::DataUrl;
 use deno_error::JsErrorBox;
+use lru::LruCache;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
 use tokio::fs;
 
 use deno_core::{
-    ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType, RequestedModuleType,
-    ResolutionKind, futures::FutureExt,
+    ModuleCodeBytes, ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType,
+    RequestedModuleType, ResolutionKind, futures::FutureExt,
 };
 
 #[derive(Debug, thiserror::Error, deno_error::JsError)]
@@ -19,11 +32,141 @@ pub struct LoadFailedError {
     source: std::io::Error,
 }
 
-pub struct ExtendedModuleLoader;
+/// The module that first imported each one, so a module that fails to load
+/// can be reported along with how it was reached.
+static IMPORTERS: LazyLock<Mutex<HashMap<ModuleSpecifier, ModuleSpecifier>>> = LazyLock::new(Default::default);
+static FAILED: Mutex<Option<ModuleSpecifier>> = Mutex::new(None);
+
+/// The module that last failed to load followed by the modules that imported
+/// it, up to the main module.
+pub fn failed_import_chain() -> Option<Vec<ModuleSpecifier>> {
+    let failed = FAILED.lock().unwrap().clone()?;
+    let importers = IMPORTERS.lock().unwrap();
+
+    let mut chain = vec![failed];
+    while let Some(importer) = importers.get(&chain[chain.len() - 1]) {
+        if chain.contains(importer) {
+            break;
+        }
+        chain.push(importer.clone());
+    }
+    Some(chain)
+}
+
+/// A module's source, owned or mapped straight from disk.
+#[derive(Clone)]
+enum Source {
+    Owned(Arc<[u8]>),
+    Mapped(&'static [u8]),
+}
+
+impl From<Vec<u8>> for Source {
+    fn from(bytes: Vec<u8>) -> Self { Self::Owned(bytes.into()) }
+}
+
+impl Source {
+    /// Large files in the module cache are mapped rather than read, so V8
+    /// compiles them without them ever being copied onto the heap. Anything
+    /// else could be truncated under the mapping, and is always read.
+    async fn read(path: &Path) -> std::io::Result<Self> {
+        match mapped::map(path)? {
+            Some(bytes) => Ok(Self::Mapped(bytes)),
+            None => Ok(fs::read(path).await?.into()),
+        }
+    }
+
+    fn into_code(self) -> ModuleSourceCode {
+        ModuleSourceCode::Bytes(match self {
+            Self::Owned(bytes) => ModuleCodeBytes::Arc(bytes),
+            Self::Mapped(bytes) => ModuleCodeBytes::Static(bytes),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct CachedModule {
+    source: Source,
+    redirect: Option<ModuleSpecifier>,
+    /// [`cache::purges`] of its host when it was loaded.
+    purges: u64,
+}
+
+/// In-memory copy of remote modules, shared by every worker the supervisor boots so
+/// a restarted isolate doesn't go back to disk or the network for its graph.
+#[derive(Clone, Default)]
+pub struct ModuleCache(Arc<Mutex<HashMap<ModuleSpecifier, CachedModule>>>);
+
+impl ModuleCache {
+    /// A module whose host was purged since is gone, like it is from disk.
+    fn get(&self, specifier: &ModuleSpecifier) -> Option<CachedModule> {
+        let mut modules = self.0.lock().unwrap();
+        if modules.get(specifier)?.purges != cache::purges(specifier) {
+            modules.remove(specifier);
+            return None;
+        }
+        modules.get(specifier).cloned()
+    }
+
+    fn insert(&self, specifier: ModuleSpecifier, source: Source, redirect: Option<ModuleSpecifier>) {
+        let purges = cache::purges(&specifier);
+        let module = CachedModule {
+            source,
+            redirect,
+            purges,
+        };
+        self.0.lock().unwrap().insert(specifier, module);
+    }
+
+    /// Where `specifier` was redirected to when it was fetched.
+    pub fn redirect(&self, specifier: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+        self.0.lock().unwrap().get(specifier)?.redirect.clone()
+    }
+
+    /// Forgets every module, so the next isolate loads its graph through the
+    /// HTTP cache again, picking up what changed upstream.
+    pub fn clear(&self) { self.0.lock().unwrap().clear(); }
+}
+
+/// (specifier, referrer) pairs whose resolution is remembered, enough for every
+/// import edge of a large graph.
+const RESOLVED_CAPACITY: NonZeroUsize = NonZeroUsize::new(16_384).unwrap();
+
+pub struct ExtendedModuleLoader {
+    fs_root: FsRoot,
+    modules: ModuleCache,
+    audit: AuditLog,
+    quota: RateLimiter,
+    /// Resolved imports, so an edge seen again isn't parsed and joined again.
+    resolved: RefCell<LruCache<(String, String), ModuleSpecifier>>,
+}
+
+impl ExtendedModuleLoader {
+    pub fn new(fs_root: FsRoot, modules: ModuleCache, audit: AuditLog, quota: RateLimiter) -> Self {
+        Self {
+            fs_root,
+            modules,
+            audit,
+            quota,
+            resolved: RefCell::new(LruCache::new(RESOLVED_CAPACITY)),
+        }
+    }
+}
 
 impl ModuleLoader for ExtendedModuleLoader {
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> { crate::stack::source_map(file_name) }
+
     fn resolve(&self, specifier: &str, referrer: &str, _kind: ResolutionKind) -> Result<ModuleSpecifier, JsErrorBox> {
-        deno_core::resolve_import(specifier, referrer).map_err(JsErrorBox::from_err)
+        let key = (specifier.to_string(), referrer.to_string());
+        if let Some(resolved) = self.resolved.borrow_mut().get(&key) {
+            return Ok(resolved.clone());
+        }
+
+        let resolved = deno_core::resolve_import(specifier, referrer).map_err(JsErrorBox::from_err)?;
+        if let Ok(referrer) = ModuleSpecifier::parse(referrer) {
+            IMPORTERS.lock().unwrap().entry(resolved.clone()).or_insert(referrer);
+        }
+        self.resolved.borrow_mut().put(key, resolved.clone());
+        Ok(resolved)
     }
 
     fn load(
@@ -31,43 +174,48 @@ impl ModuleLoader for ExtendedModuleLoader {
         requested_module_type: RequestedModuleType,
     ) -> ModuleLoadResponse {
         let module_specifier = module_specifier.clone();
+        let fs_root = self.fs_root.clone();
+        let modules = self.modules.clone();
+        let audit = self.audit.clone();
+        let quota = self.quota.clone();
+        let failed = module_specifier.clone();
 
         let future = async move {
             let mut redirect_module_url = None;
 
-            let bytes = match module_specifier.scheme() {
+            let source = match module_specifier.scheme() {
                 "http" | "https" => {
-                    let cache_path = cache::path_for(&module_specifier);
-
-                    if cache_path.exists() {
-                        println!("loading {module_specifier}");
-
-                        if let Ok(final_url) = cache::get_final_url(&module_specifier).await {
-                            if final_url != module_specifier {
-                                redirect_module_url = Some(final_url);
-                            }
-                        }
-
-                        fs::read(&cache_path).await.map_err(|e| JsErrorBox::new("CacheError", e.to_string()))?
+                    if let Some(cached) = modules.get(&module_specifier) {
+                        redirect_module_url = cached.redirect;
+                        cached.source
                     } else {
-                        println!("fetching {module_specifier}");
-
-                        let res = reqwest::get(module_specifier.clone()).await.map_err(|e| JsErrorBox::new("RequestError", e.to_string()))?;
-                        let res = res.error_for_status().map_err(|e| JsErrorBox::new("HttpError", e.to_string()))?;
-
-                        let final_url = res.url().clone();
-                        let redirect_url = if final_url != module_specifier { Some(final_url.clone()) } else { None };
-                        let body = res.bytes().await.map_err(|e| JsErrorBox::new("ResponseError", e.to_string()))?.to_vec();
+                        let host = module_specifier.host_str().unwrap_or_default();
+                        let response = http_cache::fetch(&module_specifier, Policy::default(), &quota, || {
+                            println!("fetching {module_specifier}");
+                            audit.record("net", host, "loader", None);
+                        })
+                        .await
+                        .map_err(JsErrorBox::from_err)?;
 
-                        if let Err(err) = cache::cache_url(&module_specifier, redirect_url.as_ref(), &body).await {
-                            eprintln!("cache write failed for {}: {err}", module_specifier);
-                        }
+                        let source = match response.body {
+                            Body::Cached(cache_path) => {
+                                println!("loading {module_specifier}");
+                                Source::read(&cache_path).await.map_err(|source| {
+                                    JsErrorBox::from_err(CacheError {
+                                        url: module_specifier.to_string(),
+                                        source,
+                                    })
+                                })?
+                            }
+                            Body::Fetched(body) => Source::from(body),
+                        };
 
-                        if let Some(redirect) = redirect_url {
-                            redirect_module_url = Some(redirect);
+                        if response.url != module_specifier {
+                            redirect_module_url = Some(response.url);
                         }
+                        modules.insert(module_specifier.clone(), source.clone(), redirect_module_url.clone());
 
-                        body
+                        source
                     }
                 }
 
@@ -75,7 +223,7 @@ impl ModuleLoader for ExtendedModuleLoader {
                     let url = DataUrl::process(module_specifier.as_str()).map_err(|_| JsErrorBox::new("DataUrlError", "Not a valid data URL."))?;
                     let (bytes, _) = url.decode_to_vec().map_err(|_| JsErrorBox::new("DataUrlError", "Failed to decode data URL."))?;
 
-                    bytes
+                    bytes.into()
                 }
 
                 "file" => {
@@ -83,7 +231,15 @@ impl ModuleLoader for ExtendedModuleLoader {
                         .to_file_path()
                         .map_err(|_| JsErrorBox::generic(format!("Provided module specifier \"{module_specifier}\" is not a file URL.")))?;
 
-                    std::fs::read(path).map_err(|source| {
+                    let path = fs_root.confine(&path).map_err(|source| {
+                        JsErrorBox::from_err(LoadFailedError {
+                            specifier: module_specifier.clone(),
+                            source,
+                        })
+                    })?;
+
+                    audit.record("read", &path.to_string_lossy(), "loader", None);
+                    Source::read(&path).await.map_err(|source| {
                         JsErrorBox::from_err(LoadFailedError {
                             specifier: module_specifier.clone(),
                             source,
@@ -137,15 +293,21 @@ impl ModuleLoader for ExtendedModuleLoader {
             if let Some(redirect_module_url) = redirect_module_url {
                 Ok(ModuleSource::new_with_redirect(
                     module_type,
-                    ModuleSourceCode::Bytes(bytes.into_boxed_slice().into()),
+                    source.into_code(),
                     &module_specifier,
                     &redirect_module_url,
                     None,
                 ))
             } else {
-                Ok(ModuleSource::new(module_type, ModuleSourceCode::Bytes(bytes.into_boxed_slice().into()), &module_specifier, None))
+                Ok(ModuleSource::new(module_type, source.into_code(), &module_specifier, None))
             }
         }
+        .map(move |result: Result<ModuleSource, JsErrorBox>| {
+            if result.is_err() {
+                *FAILED.lock().unwrap() = Some(failed);
+            }
+            result
+        })
         .boxed_local();
 
         ModuleLoadResponse::Async(future)
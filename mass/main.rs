@@ -1,11 +1,80 @@
+mod addons;
+mod broadcast;
+mod bundle;
+#[path = "../build/bundler.rs"]
+mod bundler;
+mod cache;
+mod cli;
+mod config;
+mod crash;
+mod dev;
+mod diagnostics;
+mod doctor;
+mod front;
+mod info;
+mod install;
+mod listener;
 mod loader;
 mod modules;
+#[path = "../build/npm.rs"]
+mod npm;
+mod permissions;
 mod snapshot;
+mod stack;
 mod stardust;
+mod supervisor;
+mod telemetry;
+mod watchdog;
+
+use clap::Parser;
 
 #[tokio::main]
 async fn main() {
-    if let Err(error) = stardust::start_runtime().await {
-        eprintln!("{error:?}");
-    };
+    let cli = cli::Cli::parse();
+    diagnostics::init(&cli);
+    // A broken config file is reported by whatever loads it next.
+    let telemetry = config::Config::load(&cli.config)
+        .map(|config| config.telemetry)
+        .unwrap_or_default();
+    telemetry.init();
+    crash::install(cli.crash_dir.clone(), &telemetry);
+
+    match cli.command {
+        Some(cli::Command::Doctor) => doctor::run().await,
+        Some(cli::Command::Dev(ref args)) => {
+            if let Err(error) = dev::run(cli.clone(), args.clone()).await {
+                eprintln!("{}", diagnostics::format(&error));
+                std::process::exit(1);
+            }
+        }
+        Some(cli::Command::Bundle(ref args)) => {
+            if let Err(error) = bundle::run(&cli, args).await {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        Some(cli::Command::Cache(ref command)) => {
+            if let Err(error) = cache::run(&cli, command).await {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        Some(cli::Command::Info(ref args)) => {
+            if let Err(error) = info::run(&cli, args).await {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        Some(cli::Command::Install(args)) => {
+            if let Err(error) = install::run(&args, &cli.config).await {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        None => {
+            if let Err(error) = supervisor::supervise(cli).await {
+                eprintln!("{}", diagnostics::format(&error));
+            };
+        }
+    }
 }
@@ -1,64 +1,499 @@
-use deno_core::{Extension, extension, op2};
+#[path = "modules/analysis.rs"]
+pub mod analysis;
+#[path = "modules/assets.rs"]
+pub mod assets;
+#[path = "modules/audit.rs"]
+pub mod audit;
+#[path = "modules/batch.rs"]
+pub mod batch;
+#[path = "modules/budget.rs"]
+pub mod budget;
+#[path = "modules/bundle.rs"]
+pub mod bundle;
+#[path = "modules/cache.rs"]
+pub mod cache;
+#[path = "modules/changes.rs"]
+pub mod changes;
+#[path = "modules/compress.rs"]
+pub mod compress;
+#[path = "modules/context.rs"]
+pub mod context;
+#[path = "modules/data.rs"]
+pub mod data;
+#[path = "modules/download.rs"]
+pub mod download;
+#[path = "modules/embed.rs"]
+pub mod embed;
+#[path = "modules/errors.rs"]
+pub mod errors;
+#[path = "modules/files.rs"]
+pub mod files;
+#[path = "modules/fingerprint.rs"]
+pub mod fingerprint;
+#[path = "modules/forge.rs"]
+pub mod forge;
+#[path = "modules/http_cache.rs"]
+pub mod http_cache;
+#[path = "modules/jobs.rs"]
+pub mod jobs;
+#[path = "modules/listen.rs"]
+pub mod listen;
+#[path = "modules/log.rs"]
+pub mod log;
+#[path = "modules/metrics.rs"]
+pub mod metrics;
+#[path = "modules/net.rs"]
+pub mod net;
+#[path = "modules/plugins.rs"]
+pub mod plugins;
+#[path = "modules/quota.rs"]
+pub mod quota;
+#[path = "modules/rank.rs"]
+pub mod rank;
+#[path = "modules/recycle.rs"]
+pub mod recycle;
+#[path = "modules/redis.rs"]
+pub mod redis;
+#[path = "modules/sandbox.rs"]
+pub mod sandbox;
+#[path = "modules/sse.rs"]
+pub mod sse;
+#[path = "modules/stream.rs"]
+pub mod stream;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[path = "modules/uring.rs"]
+pub mod uring;
+#[path = "modules/vector.rs"]
+pub mod vector;
+#[path = "modules/walk.rs"]
+pub mod walk;
+
+use audit::AuditLog;
+use context::Contexts;
+use data::DataDir;
+use deno_core::{Extension, OpState, extension, op2, v8};
 use deno_error::JsErrorBox;
+use deno_runtime::deno_permissions::{PermissionCheckError, PermissionsContainer};
+use errors::{CacheError, DownloadError, ExtractError};
 use flate2::read::GzDecoder;
+use jobs::JobQueue;
+use log::LogSink;
+use metrics::{Heartbeat, OpTimings};
+use net::NetPolicy;
+use plugins::Plugins;
+use quota::RateLimiter;
+use recycle::{DrainSignal, RecyclePolicy};
+use redis::Redis;
+use sandbox::FsRoot;
+use std::cell::RefCell;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::rc::Rc;
 use std::{collections::HashMap, fs, path::Path};
 use tar::Archive;
+use vector::VectorIndexes;
+use walk::WalkPolicy;
+
+/// Records `op`'s access to `value` on behalf of the current request.
+fn audit(state: &OpState, permission: &str, value: &str, op: &str) {
+    let context = state.borrow::<Contexts>().current();
+    state
+        .borrow::<AuditLog>()
+        .record_for(context, permission, value, op, None);
+}
+
+/// Checks `op`'s access to `url` against the isolate's permissions, as
+/// `fetch` does, so `--allow-net` and `--prompt` cover the ops too.
+fn check_net(state: &OpState, url: &reqwest::Url, op: &str) -> Result<(), JsErrorBox> {
+    let mut permissions = state.borrow::<PermissionsContainer>().clone();
+    permissions.check_net_url(url, op).map_err(|err| match err {
+        PermissionCheckError::PermissionDenied(err) => JsErrorBox::new("PermissionDenied", err.to_string()),
+        err => JsErrorBox::from_err(err),
+    })
+}
+
+fn confine(state: &OpState, path: &str, access: &str, op: &str) -> Result<String, JsErrorBox> {
+    let path = state.borrow::<FsRoot>().confine(path).map_err(JsErrorBox::from_err)?;
+    let path = path.to_string_lossy().into_owned();
+
+    audit(state, access, &path, op);
+    Ok(path)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StardustOptions {
+    pub fs_root: FsRoot,
+    pub net: NetPolicy,
+    pub timings: OpTimings,
+    pub heartbeat: Heartbeat,
+    pub log: LogSink,
+    pub audit: AuditLog,
+    pub recycle: RecyclePolicy,
+    pub drain: DrainSignal,
+    pub data: DataDir,
+    pub quota: RateLimiter,
+    pub redis: Redis,
+    pub listen: listen::ServeOptions,
+    pub fronted: context::Fronted,
+    pub walk: WalkPolicy,
+    /// Extensions added after this one, see [`Plugins`].
+    pub plugins: Plugins,
+}
 
 #[op2(fast)]
 fn op_pid() -> u32 { std::process::id() }
 
+#[op2(fast)]
+fn op_net_egress(state: &mut OpState, bytes: f64) -> f64 {
+    state.borrow_mut::<NetPolicy>().charge(bytes as u64).as_millis() as f64
+}
+
+/// Takes a request from the quota of `host` and returns how many
+/// milliseconds to wait before sending it.
+#[op2(fast)]
+fn op_net_acquire(state: &mut OpState, #[string] host: &str) -> f64 {
+    state.borrow::<RateLimiter>().acquire(host).as_secs_f64() * 1000.0
+}
+
+#[op2]
+#[serde]
+fn op_net_quota(state: &mut OpState, #[string] host: Option<String>) -> Vec<quota::Quota> {
+    state.borrow::<RateLimiter>().quota(host.as_deref())
+}
+
+#[op2(fast)]
+fn op_log(state: &mut OpState, #[string] level: &str, #[string] message: &str) -> Result<(), JsErrorBox> {
+    state
+        .borrow::<LogSink>()
+        .write(level, message, state.borrow::<Contexts>().current())
+        .map_err(JsErrorBox::from_err)
+}
+
+/// Registers the context of a request being handled, returning the id JS
+/// switches to it with.
+#[op2]
+#[smi]
+fn op_context_open(state: &mut OpState, #[serde] context: context::RequestContext) -> u32 {
+    state.borrow_mut::<Contexts>().open(context)
+}
+
+#[op2(fast)]
+fn op_context_switch(state: &mut OpState, #[smi] id: u32) { state.borrow_mut::<Contexts>().switch(id); }
+
+#[op2(fast)]
+fn op_context_close(state: &mut OpState, #[smi] id: u32) { state.borrow_mut::<Contexts>().close(id); }
+
+/// Whether the context headers on a request were set by the front proxy.
+#[op2(fast)]
+fn op_context_trusted(state: &mut OpState) -> bool { state.borrow::<context::Fronted>().0 }
+
+#[op2(fast)]
+fn op_heartbeat(state: &mut OpState) { state.borrow::<Heartbeat>().beat(); }
+
+#[op2]
+#[serde]
+fn op_resource_usage(scope: &mut v8::HandleScope, state: &mut OpState) -> metrics::ResourceUsage {
+    let heap = scope.get_heap_statistics();
+
+    metrics::ResourceUsage {
+        rss: metrics::resident_set_size(),
+        open_fds: metrics::open_file_descriptors(),
+        heap: metrics::HeapUsage {
+            total: heap.total_heap_size(),
+            used: heap.used_heap_size(),
+            limit: heap.heap_size_limit(),
+            external: heap.external_memory(),
+        },
+        ops: state.borrow::<OpTimings>().snapshot(),
+    }
+}
+
+#[op2(fast)]
+fn op_job_complete(scope: &mut v8::HandleScope, state: &mut OpState) {
+    let heap = scope.get_heap_statistics();
+
+    state
+        .borrow::<RecyclePolicy>()
+        .complete_job(heap.used_heap_size() as u64);
+}
+
+#[op2(async)]
+async fn op_drain_requested(state: Rc<RefCell<OpState>>) {
+    let signal = state.borrow().borrow::<DrainSignal>().clone();
+    signal.requested().await;
+}
+
+fn timer(state: &OpState, name: &'static str) -> metrics::OpTimer { state.borrow::<OpTimings>().start(name) }
+
 #[op2]
 #[string]
-fn op_extract_tar_gz(#[string] tar_gz_path: String, #[string] extract_to: String) -> Result<String, JsErrorBox> {
+fn op_extract_tar_gz(
+    state: &mut OpState, #[string] tar_gz_path: String, #[string] extract_to: String,
+) -> Result<String, JsErrorBox> {
+    let _timer = timer(state, "op_extract_tar_gz");
+    let tar_gz_path = confine(state, &tar_gz_path, "read", "op_extract_tar_gz")?;
+    let extract_to = confine(state, &extract_to, "write", "op_extract_tar_gz")?;
+
     let tar_file = fs::File::open(&tar_gz_path).map_err(JsErrorBox::from_err)?;
     let tar = GzDecoder::new(tar_file);
     let mut archive = Archive::new(tar);
+    let failed = |entry: String| move |source| JsErrorBox::from_err(ExtractError { entry, source });
 
-    archive.unpack(&extract_to).map_err(JsErrorBox::from_err)?;
+    fs::create_dir_all(&extract_to).map_err(failed(String::new()))?;
+    for entry in archive.entries().map_err(failed(String::new()))? {
+        let mut entry = entry.map_err(failed(String::new()))?;
+        let name = entry
+            .path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        entry.unpack_in(&extract_to).map_err(failed(name))?;
+    }
     Ok(format!("Extracted {} to {}", tar_gz_path, extract_to))
 }
 
 #[op2]
 #[serde]
-fn op_analyze_repository(#[string] repo_path: String) -> Result<HashMap<String, serde_json::Value>, JsErrorBox> {
-    let mut analysis = HashMap::new();
-
-    let file_count = count_files_recursive(&repo_path).map_err(JsErrorBox::from_err)?;
-    analysis.insert("file_count".to_string(), serde_json::Value::Number(file_count.into()));
-
-    let languages = detect_languages(&repo_path).map_err(JsErrorBox::from_err)?;
-    analysis.insert(
-        "languages".to_string(),
-        serde_json::Value::Array(
-            languages
-                .into_iter()
-                .map(|lang| serde_json::Value::String(lang))
-                .collect(),
-        ),
-    );
+fn op_analyze_repository(
+    state: &mut OpState, #[string] repo_path: String, #[serde] limits: Option<analysis::AnalysisLimits>,
+) -> Result<analysis::Analysis, JsErrorBox> {
+    let _timer = timer(state, "op_analyze_repository");
+    let repo_path = confine(state, &repo_path, "read", "op_analyze_repository")?;
+    analysis::analyze(&repo_path, &limits.unwrap_or_default(), state.borrow::<WalkPolicy>())
+        .map_err(JsErrorBox::from_err)
+}
+
+/// Content hashes of every file in the repository at `repo_path`, for JS to
+/// diff against a stored fingerprint and re-analyze only what changed.
+#[op2(async)]
+#[serde]
+async fn op_fingerprint_repo(
+    state: Rc<RefCell<OpState>>, #[string] repo_path: String,
+) -> Result<std::collections::BTreeMap<String, String>, JsErrorBox> {
+    let _timer = timer(&state.borrow(), "op_fingerprint_repo");
+    let repo_path = confine(&state.borrow(), &repo_path, "read", "op_fingerprint_repo")?;
+    let policy = state.borrow().borrow::<WalkPolicy>().clone();
+
+    tokio::task::spawn_blocking(move || fingerprint::fingerprint(Path::new(&repo_path), &policy))
+        .await
+        .map_err(|err| JsErrorBox::generic(err.to_string()))?
+        .map_err(JsErrorBox::from_err)
+}
+
+/// Queues `sources`, local paths or git URLs, for analysis in the background
+/// and returns the batch to pull events from with `op_batch_next`.
+#[op2]
+#[smi]
+fn op_batch_analyze(
+    state: &mut OpState, #[serde] sources: Vec<String>, #[serde] options: Option<batch::BatchOptions>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let _timer = timer(state, "op_batch_analyze");
+    let options = options.unwrap_or_default();
+    let concurrency = options
+        .concurrency
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+
+    let work_dir = match &options.work_dir {
+        Some(dir) => Some(confine(state, dir, "write", "op_batch_analyze")?),
+        None => None,
+    };
+
+    let jobs = sources
+        .into_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let resolved = if batch::Source::is_remote(&source) {
+                let Some(work_dir) = &work_dir else {
+                    return Err(JsErrorBox::type_error(format!(
+                        "{source} is remote but no workDir was given"
+                    )));
+                };
 
-    let config_files = find_config_files(&repo_path).map_err(JsErrorBox::from_err)?;
-    analysis.insert(
-        "config_files".to_string(),
-        serde_json::Value::Array(
-            config_files
-                .into_iter()
-                .map(|file| serde_json::Value::String(file))
-                .collect(),
-        ),
+                let url = batch::Source::remote_url(&source)
+                    .ok_or_else(|| JsErrorBox::type_error(format!("{source} is not a git URL")))?;
+                check_net(state, &url, "op_batch_analyze")?;
+                audit(state, "net", url.host_str().unwrap_or_default(), "op_batch_analyze");
+                audit(state, "run", "git", "op_batch_analyze");
+
+                let dest = Path::new(work_dir).join(batch::Source::checkout_name(index, &source));
+                Ok(batch::Source::Remote {
+                    url: source.clone(),
+                    dest,
+                })
+            } else {
+                state
+                    .borrow::<FsRoot>()
+                    .confine(&source)
+                    .inspect(|path| audit(state, "read", &path.to_string_lossy(), "op_batch_analyze"))
+                    .map(batch::Source::Local)
+            };
+            Ok((source, resolved))
+        })
+        .collect::<Result<Vec<_>, JsErrorBox>>()?;
+
+    let batch = batch::Batch::spawn(
+        jobs,
+        concurrency,
+        options.limits.unwrap_or_default(),
+        state.borrow::<WalkPolicy>().clone(),
+        analysis::analyze,
     );
+    Ok(state.resource_table.add(batch))
+}
 
-    let repo_size = calculate_directory_size(&repo_path).map_err(JsErrorBox::from_err)?;
-    analysis.insert("size_bytes".to_string(), serde_json::Value::Number(repo_size.into()));
+/// The next progress event of a batch, closing it once every repository is done.
+#[op2(async)]
+#[serde]
+async fn op_batch_next(
+    state: Rc<RefCell<OpState>>, #[smi] rid: deno_core::ResourceId,
+) -> Result<Option<batch::BatchEvent>, JsErrorBox> {
+    let batch = state
+        .borrow()
+        .resource_table
+        .get::<batch::Batch>(rid)
+        .map_err(JsErrorBox::from_err)?;
 
-    Ok(analysis)
+    let event = batch.next().await;
+    if event.is_none() {
+        let _ = state.borrow_mut().resource_table.take::<batch::Batch>(rid);
+    }
+    Ok(event)
 }
 
+/// Starts streaming `url` to `dest` in the background and returns the
+/// download to pull its progress from with `op_download_next`.
 #[op2]
-#[string]
+#[smi]
+fn op_download(
+    state: &mut OpState, #[string] url: String, #[string] dest: String,
+    #[serde] options: Option<download::DownloadOptions>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let _timer = timer(state, "op_download");
+    let options = options.unwrap_or_default();
+    let parsed = reqwest::Url::parse(&url)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+        .ok_or_else(|| JsErrorBox::type_error(format!("{url} is not an http or https URL")))?;
+    if let Some(sha256) = &options.sha256
+        && !(sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        return Err(JsErrorBox::type_error(format!("{sha256} is not a hex sha256")));
+    }
+
+    let dest = confine(state, &dest, "write", "op_download")?;
+    check_net(state, &parsed, "op_download")?;
+    audit(state, "net", parsed.host_str().unwrap_or_default(), "op_download");
+
+    let quota = state.borrow::<RateLimiter>().clone();
+    let download = download::Download::spawn(quota, parsed, dest.into(), options);
+    Ok(state.resource_table.add(download))
+}
+
+/// The next progress event of a download, closing it once it's over. A
+/// failed download throws.
+#[op2(async)]
+#[serde]
+async fn op_download_next(
+    state: Rc<RefCell<OpState>>, #[smi] rid: deno_core::ResourceId,
+) -> Result<Option<download::DownloadEvent>, JsErrorBox> {
+    let download = state
+        .borrow()
+        .resource_table
+        .get::<download::Download>(rid)
+        .map_err(JsErrorBox::from_err)?;
+
+    let event = download.next().await;
+    if event.is_none() {
+        let _ = state.borrow_mut().resource_table.take::<download::Download>(rid);
+    }
+    event.transpose().map_err(|source| {
+        let url = download.url().to_string();
+        JsErrorBox::from_err(DownloadError { url, source })
+    })
+}
+
+/// Downloads and unpacks `owner/repo` at `reference` from `provider` into a
+/// fresh directory under the filesystem root, or the system temp directory
+/// without one.
+#[op2(async)]
+#[serde]
+async fn op_fetch_repo(
+    state: Rc<RefCell<OpState>>, #[serde] provider: forge::Forge, #[string] owner: String, #[string] repo: String,
+    #[string] reference: Option<String>, #[serde] options: Option<forge::FetchOptions>,
+) -> Result<forge::FetchedRepo, JsErrorBox> {
+    fetch_repo(state, provider, owner, repo, reference, options.unwrap_or_default()).await
+}
+
+#[op2(async)]
+#[serde]
+async fn op_fetch_github_repo(
+    state: Rc<RefCell<OpState>>, #[string] owner: String, #[string] repo: String, #[string] reference: Option<String>,
+    #[serde] options: Option<forge::FetchOptions>,
+) -> Result<forge::FetchedRepo, JsErrorBox> {
+    fetch_repo(
+        state,
+        forge::Forge::GitHub,
+        owner,
+        repo,
+        reference,
+        options.unwrap_or_default(),
+    )
+    .await
+}
+
+async fn fetch_repo(
+    state: Rc<RefCell<OpState>>, provider: forge::Forge, owner: String, repo: String, reference: Option<String>,
+    options: forge::FetchOptions,
+) -> Result<forge::FetchedRepo, JsErrorBox> {
+    let _timer = timer(&state.borrow(), "op_fetch_repo");
+
+    let quota = state.borrow().borrow::<RateLimiter>().clone();
+    let dest = {
+        let state = state.borrow();
+        let base = state
+            .borrow::<FsRoot>()
+            .root()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(std::env::temp_dir);
+        let dest = forge::checkout_dir(&base, &owner, &repo);
+        confine(&state, &dest.to_string_lossy(), "write", "op_fetch_repo")?;
+        let api = forge::api_url(provider, &options);
+        let api =
+            reqwest::Url::parse(api).map_err(|err| JsErrorBox::type_error(format!("invalid API URL {api}: {err}")))?;
+        check_net(&state, &api, "op_fetch_repo")?;
+        audit(&state, "net", &forge::api_host(provider, &options), "op_fetch_repo");
+        dest
+    };
+
+    let (reference, commit, archive, rate_limit) =
+        forge::download(provider, &owner, &repo, reference.as_deref(), &options, quota)
+            .await
+            .map_err(|err| JsErrorBox::generic(err.to_string()))?;
+
+    let archive_bytes = archive.len() as u64;
+    let path = dest.clone();
+    tokio::task::spawn_blocking(move || forge::extract(&archive, &path))
+        .await
+        .map_err(|err| JsErrorBox::generic(err.to_string()))?
+        .map_err(JsErrorBox::from_err)?;
+
+    Ok(forge::FetchedRepo {
+        path: dest,
+        provider,
+        owner,
+        repo,
+        reference,
+        commit,
+        archive_bytes,
+        rate_limit,
+    })
+}
+
+#[op2]
+#[serde]
 fn op_get_important_files_by_pattern(
-    #[string] repo_path: String, #[bigint] max_files: u64,
-) -> Result<String, JsErrorBox> {
+    state: &mut OpState, #[string] repo_path: String, #[number] max_files: u64,
+) -> Result<Vec<files::ImportantFile>, JsErrorBox> {
+    let _timer = timer(state, "op_get_important_files_by_pattern");
+    let repo_path = confine(state, &repo_path, "read", "op_get_important_files_by_pattern")?;
     let mut important_files = Vec::new();
 
     let important_patterns = vec![
@@ -81,258 +516,715 @@ fn op_get_important_files_by_pattern(
         "server.*",
     ];
 
-    for entry in fs::read_dir(&repo_path).map_err(JsErrorBox::from_err)? {
-        let entry = entry.map_err(JsErrorBox::from_err)?;
-        let path = entry.path();
-
-        if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            for pattern in &important_patterns {
-                let matches = if pattern.contains("*") {
-                    let prefix = pattern.replace("*", "");
-                    file_name.starts_with(&prefix)
-                } else {
-                    file_name == *pattern
-                };
+    // Only the top level is looked at, so subdirectories are never listed.
+    let policy = state.borrow::<WalkPolicy>();
+    let max_bytes = policy.max_file_size.min(100_000);
+    let root_files = policy.walk(Path::new(&repo_path)).list(Path::new(&repo_path));
 
-                if matches {
-                    if let Ok(metadata) = entry.metadata() {
-                        if metadata.len() > 100_000 {
-                            important_files.push(format!("{}:[File too large: {} bytes]", file_name, metadata.len()));
-                        } else if let Ok(content) = fs::read_to_string(&path) {
-                            let truncated_content = if content.len() > 5000 {
-                                format!(
-                                    "{}...\n[Content truncated - {} total chars]",
-                                    &content[..5000],
-                                    content.len()
-                                )
-                            } else {
-                                content
-                            };
-                            important_files.push(format!("{}:{}", file_name, truncated_content));
-                        }
-                    }
-
-                    if important_files.len() >= max_files as usize {
-                        break;
-                    }
-                }
-            }
-            if important_files.len() >= max_files as usize {
-                break;
-            }
+    for file in root_files.map_err(JsErrorBox::from_err)? {
+        if important_files.len() >= max_files as usize {
+            break;
+        }
+
+        let Ok(path) = state.borrow::<FsRoot>().confine(&file.path) else {
+            continue;
+        };
+        let file_name = file.name;
+
+        let matches = important_patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => file_name.starts_with(prefix),
+                None => file_name == *pattern,
+            });
+
+        if matches && let Some(file) = files::read(file_name, &path, max_bytes, Some(5000)) {
+            important_files.push(file);
         }
     }
 
-    Ok(important_files.join("\n---FILE_SEPARATOR---\n"))
+    Ok(important_files)
 }
 
 #[op2]
-#[string]
-fn op_get_important_files(#[string] repo_path: String, #[serde] file_paths: Vec<String>) -> Result<String, JsErrorBox> {
-    let mut important_files = Vec::new();
+#[serde]
+fn op_get_important_files(
+    state: &mut OpState, #[string] repo_path: String, #[serde] file_paths: Vec<String>,
+) -> Result<Vec<files::ImportantFile>, JsErrorBox> {
+    let _timer = timer(state, "op_get_important_files");
+    let repo_path = confine(state, &repo_path, "read", "op_get_important_files")?;
     let repo_path = Path::new(&repo_path);
 
-    for file_path in file_paths {
-        let full_path = repo_path.join(&file_path);
+    // Files above the walk policy's size limit are skipped; long content is
+    // left whole for the token budgeting to handle.
+    let max_bytes = state.borrow::<WalkPolicy>().max_file_size;
+    Ok(file_paths
+        .into_iter()
+        .filter_map(|file_path| {
+            let full_path = state.borrow::<FsRoot>().confine(repo_path.join(&file_path)).ok()?;
+            files::read(file_path, &full_path, max_bytes, None)
+        })
+        .collect())
+}
 
-        if !full_path.exists() {
-            continue;
-        }
+/// Cuts `files` down to `max_tokens` of `model`'s tokenizer, highest
+/// `priority` first.
+#[op2]
+#[serde]
+fn op_fit_to_budget(
+    state: &mut OpState, #[serde] files: Vec<budget::FilePayload>, #[string] model: String, #[number] max_tokens: u64,
+) -> budget::Budgeted {
+    let _timer = timer(state, "op_fit_to_budget");
+    budget::fit(files, &model, max_tokens as usize)
+}
+
+#[op2(async)]
+#[serde]
+async fn op_embed(
+    state: Rc<RefCell<OpState>>, #[serde] texts: Vec<String>, #[serde] options: embed::EmbedOptions,
+) -> Result<Vec<Vec<f32>>, JsErrorBox> {
+    let _timer = timer(&state.borrow(), "op_embed");
+    let mut embeddings = Vec::with_capacity(texts.len());
+
+    match options.provider {
+        embed::Provider::OpenAi => {
+            let endpoint = options.endpoint();
+            let url = reqwest::Url::parse(&endpoint)
+                .map_err(|err| JsErrorBox::type_error(format!("invalid embedding endpoint {endpoint}: {err}")))?;
+            check_net(&state.borrow(), &url, "op_embed")?;
+            audit(&state.borrow(), "net", url.host_str().unwrap_or(&endpoint), "op_embed");
+
+            let quota = state.borrow().borrow::<RateLimiter>().clone();
+            for batch in texts.chunks(options.batch_size()) {
+                let bytes = batch.iter().map(String::len).sum::<usize>() as u64;
+                let wait = state.borrow_mut().borrow_mut::<NetPolicy>().charge(bytes);
+                tokio::time::sleep(wait).await;
 
-        if full_path.is_file() {
-            if let Ok(metadata) = fs::metadata(&full_path) {
-                // Skip very large files (>500KB for LLM processing)
-                if metadata.len() > 500_000 {
-                    important_files.push(format!("{}:[File too large: {} bytes]", file_path, metadata.len()));
-                    continue;
-                }
-
-                // Try to read file content
-                match fs::read_to_string(&full_path) {
-                    Ok(content) => {
-                        // For very long content, we'll handle truncation later in the token counting logic
-                        important_files.push(format!("{}:{}", file_path, content));
-                    }
-                    Err(_) => {
-                        // If we can't read as text, it might be binary
-                        important_files.push(format!("{}:[Binary file - {} bytes]", file_path, metadata.len()));
-                    }
-                }
+                let connection = quota.connect(&url).await;
+                let vectors = embed::http_batch(connection.client(), &options, batch)
+                    .await
+                    .map_err(|err| JsErrorBox::generic(err.to_string()))?;
+                embeddings.extend(vectors);
+            }
+        }
+        embed::Provider::Onnx => {
+            let dir = confine(&state.borrow(), &options.model, "read", "op_embed")?;
+            for batch in texts.chunks(options.batch_size()) {
+                let (dir, batch) = (std::path::PathBuf::from(&dir), batch.to_vec());
+                let vectors = tokio::task::spawn_blocking(move || embed::onnx_batch(&dir, &batch))
+                    .await
+                    .map_err(|err| JsErrorBox::generic(err.to_string()))?
+                    .map_err(|err| JsErrorBox::generic(err.to_string()))?;
+                embeddings.extend(vectors);
             }
         }
     }
 
-    Ok(important_files.join("\n---FILE_SEPARATOR---\n"))
+    Ok(embeddings)
 }
 
 #[op2]
-#[string]
-fn op_cleanup_temp_directory(#[string] temp_dir: String) -> Result<String, JsErrorBox> {
-    if Path::new(&temp_dir).exists() {
-        fs::remove_dir_all(&temp_dir).map_err(JsErrorBox::from_err)?;
-        Ok(format!("Cleaned up temporary directory: {}", temp_dir))
-    } else {
-        Ok("Directory does not exist".to_string())
-    }
+#[number]
+fn op_vector_index_build(
+    state: &mut OpState, #[string] name: String, #[serde] items: Vec<vector::Item>,
+    #[serde] options: Option<vector::IndexOptions>,
+) -> Result<u64, JsErrorBox> {
+    let _timer = timer(state, "op_vector_index_build");
+    audit(state, "write", &name, "op_vector_index_build");
+
+    let options = options.unwrap_or_default();
+    let count = state
+        .borrow_mut::<VectorIndexes>()
+        .build(&name, items, &options)
+        .map_err(JsErrorBox::from_err)?;
+    Ok(count as u64)
 }
 
-fn count_files_recursive(dir_path: &str) -> Result<u64, std::io::Error> {
-    let mut count = 0;
-
-    fn visit_dir(dir: &Path, count: &mut u64) -> Result<(), std::io::Error> {
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if matches!(
-                        dir_name,
-                        "node_modules" | "target" | ".git" | "__pycache__" | "dist" | "build"
-                    ) {
-                        continue;
-                    }
-                }
-
-                if path.is_dir() {
-                    visit_dir(&path, count)?;
-                } else {
-                    *count += 1;
-                }
-            }
-        }
-        Ok(())
-    }
+#[op2]
+#[number]
+fn op_vector_index_add(
+    state: &mut OpState, #[string] name: String, #[serde] items: Vec<vector::Item>,
+) -> Result<u64, JsErrorBox> {
+    let _timer = timer(state, "op_vector_index_add");
+    audit(state, "write", &name, "op_vector_index_add");
 
-    visit_dir(Path::new(dir_path), &mut count)?;
-    Ok(count)
-}
-
-fn detect_languages(repo_path: &str) -> Result<Vec<String>, std::io::Error> {
-    let mut languages = std::collections::HashSet::new();
-
-    fn scan_directory(dir: &Path, languages: &mut std::collections::HashSet<String>) -> Result<(), std::io::Error> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.is_dir() {
-                if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if !matches!(dir_name, "node_modules" | "target" | ".git" | "__pycache__") {
-                        scan_directory(&path, languages)?;
-                    }
-                }
-            } else if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
-                match extension {
-                    "js" | "mjs" | "jsx" => {
-                        languages.insert("JavaScript".to_string());
-                    }
-                    "ts" | "tsx" => {
-                        languages.insert("TypeScript".to_string());
-                    }
-                    "py" => {
-                        languages.insert("Python".to_string());
-                    }
-                    "rs" => {
-                        languages.insert("Rust".to_string());
-                    }
-                    "go" => {
-                        languages.insert("Go".to_string());
-                    }
-                    "java" => {
-                        languages.insert("Java".to_string());
-                    }
-                    "cpp" | "cc" | "cxx" => {
-                        languages.insert("C++".to_string());
-                    }
-                    "c" => {
-                        languages.insert("C".to_string());
-                    }
-                    "cs" => {
-                        languages.insert("C#".to_string());
-                    }
-                    "php" => {
-                        languages.insert("PHP".to_string());
-                    }
-                    "rb" => {
-                        languages.insert("Ruby".to_string());
-                    }
-                    _ => {}
-                }
-            }
-        }
-        Ok(())
+    let count = state
+        .borrow_mut::<VectorIndexes>()
+        .add(&name, items)
+        .map_err(JsErrorBox::from_err)?;
+    Ok(count as u64)
+}
+
+#[op2]
+#[serde]
+fn op_vector_index_query(
+    state: &mut OpState, #[string] name: String, #[serde] vector: Vec<f32>, #[number] k: u64,
+    #[serde] ef: Option<usize>,
+) -> Result<Vec<vector::Hit>, JsErrorBox> {
+    let _timer = timer(state, "op_vector_index_query");
+    audit(state, "read", &name, "op_vector_index_query");
+
+    state
+        .borrow_mut::<VectorIndexes>()
+        .query(&name, vector, k as usize, ef)
+        .map_err(JsErrorBox::from_err)
+}
+
+#[op2]
+#[serde]
+fn op_rank_files(
+    state: &mut OpState, #[string] repo_path: String, #[string] query: String, #[number] limit: u64,
+    #[serde] options: Option<rank::RankOptions>,
+) -> Result<Vec<rank::RankedFile>, JsErrorBox> {
+    let _timer = timer(state, "op_rank_files");
+    let repo_path = confine(state, &repo_path, "read", "op_rank_files")?;
+    let limit = limit as usize;
+
+    let rank::RankOptions { index, vector } = options.unwrap_or_default();
+    let similarity = match (index, vector) {
+        (Some(index), Some(vector)) => state
+            .borrow_mut::<VectorIndexes>()
+            .query(&index, vector, limit * 4, None)
+            .map_err(JsErrorBox::from_err)?
+            .into_iter()
+            .map(|hit| (hit.id, hit.score))
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    let fs_root = state.borrow::<FsRoot>();
+    let policy = state.borrow::<WalkPolicy>();
+    rank::rank(Path::new(&repo_path), &query, limit, &similarity, policy, |path| {
+        fs_root.confine(path).is_ok()
+    })
+    .map_err(JsErrorBox::from_err)
+}
+
+/// What changed in the working tree at `repo_path` since `base_ref`.
+#[op2]
+#[serde]
+fn op_analyze_changes(
+    state: &mut OpState, #[string] repo_path: String, #[string] base_ref: String,
+) -> Result<changes::Changes, JsErrorBox> {
+    let _timer = timer(state, "op_analyze_changes");
+    let repo_path = confine(state, &repo_path, "read", "op_analyze_changes")?;
+    audit(state, "run", "git", "op_analyze_changes");
+
+    changes::analyze(Path::new(&repo_path), &base_ref, analysis::language_for_extension).map_err(JsErrorBox::from_err)
+}
+
+#[op2]
+#[smi]
+fn op_open_file_stream(
+    state: &mut OpState, #[string] repo_path: String, #[serde] file_paths: Vec<String>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let repo_path = confine(state, &repo_path, "read", "op_open_file_stream")?;
+    let fs_root = state.borrow::<FsRoot>().clone();
+    let max_bytes = state.borrow::<WalkPolicy>().max_file_size;
+    let stream = stream::FileStream::new(repo_path.into(), fs_root, max_bytes, file_paths);
+    Ok(state.resource_table.add(stream))
+}
+
+/// Hands the next file of a stream to JS, closing the stream once it's drained.
+#[op2]
+#[serde]
+fn op_next_chunk(
+    state: &mut OpState, #[smi] rid: deno_core::ResourceId,
+) -> Result<Option<files::ImportantFile>, JsErrorBox> {
+    let _timer = timer(state, "op_next_chunk");
+    let stream = state
+        .resource_table
+        .get::<stream::FileStream>(rid)
+        .map_err(JsErrorBox::from_err)?;
+
+    let chunk = stream.next();
+    if chunk.is_none() {
+        let _ = state.resource_table.take::<stream::FileStream>(rid);
     }
+    Ok(chunk)
+}
 
-    scan_directory(Path::new(repo_path), &mut languages)?;
-    Ok(languages.into_iter().collect())
+/// Opens a stream compressing whatever is written to it with `format`.
+#[op2]
+#[smi]
+fn op_compress_stream(
+    state: &mut OpState, #[serde] format: compress::Format, #[serde] options: Option<compress::CodecOptions>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let _timer = timer(state, "op_compress_stream");
+    let stream = compress::CodecStream::compress(format, &options.unwrap_or_default()).map_err(JsErrorBox::from_err)?;
+    Ok(state.resource_table.add(stream))
 }
 
-fn find_config_files(repo_path: &str) -> Result<Vec<String>, std::io::Error> {
-    let mut config_files = Vec::new();
-    let config_patterns = vec![
-        "package.json",
-        "Cargo.toml",
-        "pyproject.toml",
-        "requirements.txt",
-        "go.mod",
-        "pom.xml",
-        "build.gradle",
-        "Dockerfile",
-        "docker-compose.yml",
-    ];
+/// Opens a stream decompressing whatever `format` data is written to it.
+#[op2]
+#[smi]
+fn op_decompress_stream(
+    state: &mut OpState, #[serde] format: compress::Format, #[serde] options: Option<compress::CodecOptions>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let _timer = timer(state, "op_decompress_stream");
+    let stream =
+        compress::CodecStream::decompress(format, &options.unwrap_or_default()).map_err(JsErrorBox::from_err)?;
+    Ok(state.resource_table.add(stream))
+}
 
-    for entry in fs::read_dir(repo_path)? {
-        let entry = entry?;
-        let path = entry.path();
+/// Feeds `chunk` through a compression stream and returns what came out.
+#[op2]
+#[buffer]
+fn op_codec_write(
+    state: &mut OpState, #[smi] rid: deno_core::ResourceId, #[buffer] chunk: &[u8],
+) -> Result<Vec<u8>, JsErrorBox> {
+    let _timer = timer(state, "op_codec_write");
+    let stream = state
+        .resource_table
+        .get::<compress::CodecStream>(rid)
+        .map_err(JsErrorBox::from_err)?;
 
-        if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            if config_patterns.contains(&file_name) {
-                config_files.push(file_name.to_string());
-            }
-        }
+    stream.write(chunk).map_err(JsErrorBox::from_err)
+}
+
+/// Ends a compression stream, closing it, and returns the rest of its output.
+#[op2]
+#[buffer]
+fn op_codec_finish(state: &mut OpState, #[smi] rid: deno_core::ResourceId) -> Result<Vec<u8>, JsErrorBox> {
+    let _timer = timer(state, "op_codec_finish");
+    let stream = state
+        .resource_table
+        .take::<compress::CodecStream>(rid)
+        .map_err(JsErrorBox::from_err)?;
+
+    stream.finish().map_err(JsErrorBox::from_err)
+}
+
+/// Queues `payload` durably, returning the job's id.
+#[op2]
+#[number]
+fn op_queue_enqueue(
+    state: &mut OpState, #[string] queue: String, #[serde] payload: serde_json::Value,
+    #[serde] options: Option<jobs::EnqueueOptions>,
+) -> Result<i64, JsErrorBox> {
+    let _timer = timer(state, "op_queue_enqueue");
+    state
+        .borrow::<JobQueue>()
+        .enqueue(&queue, &payload, options.unwrap_or_default())
+        .map_err(JsErrorBox::from_err)
+}
+
+/// Leases the next due job of `queue` for `lease_ms`, `null` when none is.
+#[op2]
+#[serde]
+fn op_queue_claim(
+    state: &mut OpState, #[string] queue: String, #[number] lease_ms: u64,
+) -> Result<Option<jobs::Job>, JsErrorBox> {
+    let _timer = timer(state, "op_queue_claim");
+    state
+        .borrow::<JobQueue>()
+        .claim(&queue, lease_ms)
+        .map_err(JsErrorBox::from_err)
+}
+
+/// `false` when `lease` no longer holds the job.
+#[op2(fast)]
+fn op_queue_ack(state: &mut OpState, #[number] id: i64, lease: u32) -> Result<bool, JsErrorBox> {
+    let _timer = timer(state, "op_queue_ack");
+    state.borrow::<JobQueue>().ack(id, lease).map_err(JsErrorBox::from_err)
+}
+
+/// `null` when `lease` no longer holds the job.
+#[op2]
+#[serde]
+fn op_queue_fail(
+    state: &mut OpState, #[number] id: i64, lease: u32, #[string] error: String,
+) -> Result<Option<jobs::Failed>, JsErrorBox> {
+    let _timer = timer(state, "op_queue_fail");
+    state
+        .borrow::<JobQueue>()
+        .fail(id, lease, &error)
+        .map_err(JsErrorBox::from_err)
+}
+
+fn redis(state: &Rc<RefCell<OpState>>, op: &str) -> Result<Redis, JsErrorBox> {
+    let state = state.borrow();
+    let redis = state.borrow::<Redis>().clone();
+    // Without a server the command fails on its own, asking for --redis-url.
+    if let Some(server) = redis.server() {
+        check_net(&state, &server, op)?;
     }
+    audit(&state, "net", &redis.host(), op);
+    Ok(redis)
+}
 
-    Ok(config_files)
+#[op2(async)]
+#[string]
+async fn op_redis_get(state: Rc<RefCell<OpState>>, #[string] key: String) -> Result<Option<String>, JsErrorBox> {
+    let reply = redis(&state, "op_redis_get")?.command(&["GET", &key]).await;
+    Ok(reply.map_err(JsErrorBox::from_err)?.into_string())
 }
 
-fn calculate_directory_size(dir_path: &str) -> Result<u64, std::io::Error> {
-    let mut total_size = 0;
+/// Sets `key`, expiring it after `ttl_ms` when given.
+#[op2(async)]
+async fn op_redis_set(
+    state: Rc<RefCell<OpState>>, #[string] key: String, #[string] value: String, #[serde] ttl_ms: Option<u64>,
+) -> Result<(), JsErrorBox> {
+    let redis = redis(&state, "op_redis_set")?;
+    let ttl = ttl_ms.map(|ttl| ttl.to_string());
+    let reply = match &ttl {
+        Some(ttl) => redis.command(&["SET", &key, &value, "PX", ttl]).await,
+        None => redis.command(&["SET", &key, &value]).await,
+    };
+    reply.map(drop).map_err(JsErrorBox::from_err)
+}
 
-    fn visit_dir(dir: &Path, total: &mut u64) -> Result<(), std::io::Error> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+#[op2(async)]
+async fn op_redis_expire(
+    state: Rc<RefCell<OpState>>, #[string] key: String, #[number] ttl_ms: u64,
+) -> Result<bool, JsErrorBox> {
+    let reply = redis(&state, "op_redis_expire")?
+        .command(&["PEXPIRE", &key, &ttl_ms.to_string()])
+        .await;
+    Ok(reply.map_err(JsErrorBox::from_err)?.into_integer() == 1)
+}
 
-            if path.is_dir() {
-                if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if !matches!(dir_name, "node_modules" | "target" | ".git" | "__pycache__") {
-                        visit_dir(&path, total)?;
-                    }
-                }
-            } else if let Ok(metadata) = entry.metadata() {
-                *total += metadata.len();
-            }
-        }
-        Ok(())
+#[op2(async)]
+async fn op_redis_del(state: Rc<RefCell<OpState>>, #[string] key: String) -> Result<bool, JsErrorBox> {
+    let reply = redis(&state, "op_redis_del")?.command(&["DEL", &key]).await;
+    Ok(reply.map_err(JsErrorBox::from_err)?.into_integer() > 0)
+}
+
+/// Publishes `message` on `channel`, returning how many subscribers got it.
+#[op2(async)]
+#[number]
+async fn op_redis_publish(
+    state: Rc<RefCell<OpState>>, #[string] channel: String, #[string] message: String,
+) -> Result<i64, JsErrorBox> {
+    let reply = redis(&state, "op_redis_publish")?
+        .command(&["PUBLISH", &channel, &message])
+        .await;
+    Ok(reply.map_err(JsErrorBox::from_err)?.into_integer())
+}
+
+#[op2(async)]
+#[smi]
+async fn op_redis_subscribe(
+    state: Rc<RefCell<OpState>>, #[serde] channels: Vec<String>,
+) -> Result<deno_core::ResourceId, JsErrorBox> {
+    let subscription = redis(&state, "op_redis_subscribe")?
+        .subscribe(&channels)
+        .await
+        .map_err(JsErrorBox::from_err)?;
+    Ok(state.borrow_mut().resource_table.add(subscription))
+}
+
+/// The next message of a subscription, closing it once the server hangs up.
+#[op2(async)]
+#[serde]
+async fn op_redis_next_message(
+    state: Rc<RefCell<OpState>>, #[smi] rid: deno_core::ResourceId,
+) -> Result<Option<redis::Message>, JsErrorBox> {
+    let subscription = state
+        .borrow()
+        .resource_table
+        .get::<redis::Subscription>(rid)
+        .map_err(JsErrorBox::from_err)?;
+
+    let message = subscription.next().await.map_err(JsErrorBox::from_err)?;
+    if message.is_none() {
+        let _ = state.borrow_mut().resource_table.take::<redis::Subscription>(rid);
+    }
+    Ok(message)
+}
+
+#[op2]
+#[string]
+fn op_sse_encode(#[serde] event: sse::Event) -> String { sse::encode(&event) }
+
+/// `Deno.serve` options for where this isolate should listen.
+#[op2]
+#[serde]
+fn op_listen_options(state: &mut OpState) -> listen::ServeOptions { state.borrow::<listen::ServeOptions>().clone() }
+
+/// Answers a request for `path`, as taken from the URL, with the file it names
+/// under the static directory `root`.
+#[op2]
+#[serde]
+fn op_serve_static(
+    state: &mut OpState, #[string] root: String, #[string] path: String,
+    #[serde] request: Option<assets::StaticRequest>,
+) -> Result<assets::StaticResponse, JsErrorBox> {
+    let _timer = timer(state, "op_serve_static");
+    let root = confine(state, &root, "read", "op_serve_static")?;
+    let Some(file) = assets::relative(&path) else {
+        return Ok(assets::StaticResponse::empty(404));
+    };
+
+    let (mut response, body) =
+        assets::serve(Path::new(&root), &file, &request.unwrap_or_default()).map_err(JsErrorBox::from_err)?;
+    response.body = body.map(|body| state.resource_table.add(body));
+    Ok(response)
+}
+
+/// Hands the next chunk of a static file's body to JS, closing it once it's
+/// all been read.
+#[op2]
+#[serde]
+fn op_static_read(
+    state: &mut OpState, #[smi] rid: deno_core::ResourceId,
+) -> Result<Option<deno_core::ToJsBuffer>, JsErrorBox> {
+    let _timer = timer(state, "op_static_read");
+    let body = state
+        .resource_table
+        .get::<assets::StaticBody>(rid)
+        .map_err(JsErrorBox::from_err)?;
+
+    let chunk = body.read();
+    if !matches!(chunk, Ok(Some(_))) {
+        let _ = state.resource_table.take::<assets::StaticBody>(rid);
     }
+    chunk.map_err(JsErrorBox::from_err)
+}
+
+/// Fetches every module URL in `urls` into the module cache, skipping the
+/// ones already there.
+#[op2(async)]
+#[serde]
+async fn op_cache_warm(
+    state: Rc<RefCell<OpState>>, #[serde] urls: Vec<String>, #[serde] options: Option<cache::WarmOptions>,
+) -> cache::WarmReport {
+    let _timer = timer(&state.borrow(), "op_cache_warm");
+    let mut denied = Vec::new();
+    let (quota, audit, urls) = {
+        let state = state.borrow();
+        // URLs that don't parse are left for `warm` to report.
+        let urls = urls
+            .into_iter()
+            .filter(|url| match reqwest::Url::parse(url) {
+                Ok(parsed) => check_net(&state, &parsed, "op_cache_warm")
+                    .map_err(|err| {
+                        denied.push(cache::WarmFailure {
+                            url: url.clone(),
+                            error: err.to_string(),
+                        })
+                    })
+                    .is_ok(),
+                Err(_) => true,
+            })
+            .collect();
+        (
+            state.borrow::<RateLimiter>().clone(),
+            state.borrow::<AuditLog>().clone(),
+            urls,
+        )
+    };
+
+    let mut report = cache::warm(urls, options.unwrap_or_default(), &quota, &audit).await;
+    report.failed.extend(denied);
+    report
+}
 
-    visit_dir(Path::new(dir_path), &mut total_size)?;
-    Ok(total_size)
+/// GETs `url` through the module cache the way the module loader does, asking
+/// the origin only once the cached copy is stale, and then conditionally.
+#[op2(async)]
+#[serde]
+async fn op_cache_fetch(
+    state: Rc<RefCell<OpState>>, #[string] url: String, #[serde] policy: Option<http_cache::Policy>,
+) -> Result<http_cache::Text, JsErrorBox> {
+    let _timer = timer(&state.borrow(), "op_cache_fetch");
+    let parsed = reqwest::Url::parse(&url)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+        .ok_or_else(|| JsErrorBox::type_error(format!("{url} is not an http or https URL")))?;
+    check_net(&state.borrow(), &parsed, "op_cache_fetch")?;
+    let quota = state.borrow().borrow::<RateLimiter>().clone();
+
+    let host = parsed.host_str().unwrap_or_default();
+    let response = http_cache::fetch(&parsed, policy.unwrap_or_default(), &quota, || {
+        audit(&state.borrow(), "net", host, "op_cache_fetch")
+    })
+    .await
+    .map_err(JsErrorBox::from_err)?;
+
+    response
+        .text()
+        .await
+        .map_err(|source| JsErrorBox::from_err(CacheError { url, source }))
+}
+
+/// Drops every module cached from `host`, e.g. a CDN that served broken
+/// ones, so the next isolate fetches them again. Returns how many were
+/// dropped.
+#[op2(fast)]
+#[number]
+fn op_cache_purge(state: &mut OpState, #[string] host: &str) -> Result<u64, JsErrorBox> {
+    let _timer = timer(state, "op_cache_purge");
+    audit(
+        state,
+        "write",
+        &cache::cache_dir().join(host).to_string_lossy(),
+        "op_cache_purge",
+    );
+
+    cache::purge(host)
+        .map(|purged| purged as u64)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::InvalidInput => JsErrorBox::type_error(err.to_string()),
+            _ => JsErrorBox::from_err(err),
+        })
+}
+
+/// How many modules the module cache holds and how large they are, host by
+/// host.
+#[op2]
+#[serde]
+fn op_cache_stats(state: &mut OpState) -> Result<cache::CacheStats, JsErrorBox> {
+    let _timer = timer(state, "op_cache_stats");
+    cache::stats().map_err(JsErrorBox::from_err)
+}
+
+/// Bundles `entry` the way `mass bundle` does and hands the code back, e.g. to
+/// load a plugin out of a fetched repository. Only the entry and project
+/// directory are checked against the filesystem root; esbuild reads whatever
+/// they import.
+#[op2(async)]
+#[serde]
+async fn op_bundle(
+    state: Rc<RefCell<OpState>>, #[string] entry: String, #[serde] options: Option<bundle::BundleOptions>,
+) -> Result<bundle::Bundle, JsErrorBox> {
+    let _timer = timer(&state.borrow(), "op_bundle");
+    let options = options.unwrap_or_default();
+
+    let (entry, dir) = {
+        let state = state.borrow();
+        let entry = confine(&state, &entry, "read", "op_bundle")?;
+        let dir = match &options.dir {
+            Some(dir) => confine(&state, dir, "read", "op_bundle")?,
+            None => Path::new(&entry)
+                .parent()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        audit(&state, "run", "esbuild", "op_bundle");
+        (entry, dir)
+    };
+
+    bundle::bundle(Path::new(&entry), Path::new(&dir), &options)
+        .await
+        .map_err(|err| JsErrorBox::generic(err.to_string()))
+}
+
+#[op2]
+#[string]
+fn op_cleanup_temp_directory(state: &mut OpState, #[string] temp_dir: String) -> Result<String, JsErrorBox> {
+    let _timer = timer(state, "op_cleanup_temp_directory");
+    let temp_dir = confine(state, &temp_dir, "write", "op_cleanup_temp_directory")?;
+    if Path::new(&temp_dir).exists() {
+        fs::remove_dir_all(&temp_dir).map_err(JsErrorBox::from_err)?;
+        Ok(format!("Cleaned up temporary directory: {}", temp_dir))
+    } else {
+        Ok("Directory does not exist".to_string())
+    }
 }
 
 extension!(
     stardust,
     ops = [
         op_pid,
+        op_net_egress,
+        op_net_acquire,
+        op_net_quota,
+        op_log,
+        op_context_open,
+        op_context_switch,
+        op_context_close,
+        op_context_trusted,
+        op_heartbeat,
+        op_resource_usage,
+        op_job_complete,
+        op_drain_requested,
         op_extract_tar_gz,
         op_analyze_repository,
+        op_fingerprint_repo,
+        op_fetch_repo,
+        op_fetch_github_repo,
+        op_batch_analyze,
+        op_batch_next,
+        op_download,
+        op_download_next,
         op_get_important_files,
         op_get_important_files_by_pattern,
+        op_fit_to_budget,
+        op_embed,
+        op_vector_index_build,
+        op_vector_index_add,
+        op_vector_index_query,
+        op_rank_files,
+        op_analyze_changes,
+        op_open_file_stream,
+        op_next_chunk,
+        op_compress_stream,
+        op_decompress_stream,
+        op_codec_write,
+        op_codec_finish,
+        op_queue_enqueue,
+        op_queue_claim,
+        op_queue_ack,
+        op_queue_fail,
+        op_redis_get,
+        op_redis_set,
+        op_redis_expire,
+        op_redis_del,
+        op_redis_publish,
+        op_redis_subscribe,
+        op_redis_next_message,
+        op_sse_encode,
+        op_listen_options,
+        op_serve_static,
+        op_static_read,
+        op_cache_warm,
+        op_cache_fetch,
+        op_cache_purge,
+        op_cache_stats,
+        op_bundle,
         op_cleanup_temp_directory
     ],
     esm_entry_point = "ext:stardust/mass/runtime/entry.js",
     esm = ["mass/runtime/entry.js", "mass/runtime/snapshot/server.min.js"],
+    options = { options: StardustOptions },
+    state = |state, options| {
+        state.put(options.options.fs_root);
+        state.put(options.options.net);
+        state.put(options.options.timings);
+        state.put(options.options.heartbeat);
+        state.put(options.options.log);
+        state.put(options.options.audit);
+        state.put(options.options.recycle);
+        state.put(options.options.drain);
+        state.put(JobQueue::new(options.options.data.clone()));
+        state.put(VectorIndexes::new(options.options.data));
+        state.put(options.options.quota);
+        state.put(options.options.redis);
+        state.put(options.options.listen);
+        state.put(options.options.fronted);
+        state.put(options.options.walk);
+        state.put(Contexts::default());
+    },
 );
 
-pub fn init_extension() -> Vec<Extension> { vec![stardust::init()] }
+pub fn init_extension(mut options: StardustOptions) -> Vec<Extension> {
+    let plugins = std::mem::take(&mut options.plugins);
+    let mut extensions = vec![stardust::init(options)];
+    extensions.extend(plugins.extensions());
+    extensions
+}
+
+/// Identifies the op surface a snapshot was built against, so a binary can refuse a
+/// snapshot whose extension no longer lines up with its own.
+pub fn fingerprint() -> String {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+
+    // Plugins are left out: an isolate with any never starts from the snapshot.
+    let extension = stardust::init(StardustOptions::default());
+    extension.name.hash(&mut hasher);
+    extension.ops.iter().for_each(|decl| decl.name.hash(&mut hasher));
+
+    format!("{:016x}", hasher.finish())
+}
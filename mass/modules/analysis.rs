@@ -0,0 +1,177 @@
+use super::errors::AnalysisError;
+use super::walk::WalkPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const CONFIG_FILES: &[&str] = &[
+    "package.json",
+    "Cargo.toml",
+    "pyproject.toml",
+    "requirements.txt",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "Dockerfile",
+    "docker-compose.yml",
+];
+
+/// How far one analysis may go before it stops and returns what it has,
+/// marked `truncated`. `null` lifts a limit.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnalysisLimits {
+    pub max_files: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub time_budget_ms: Option<u64>,
+}
+
+impl Default for AnalysisLimits {
+    fn default() -> Self {
+        Self {
+            max_files: Some(1_000_000),
+            max_bytes: None,
+            time_budget_ms: Some(60_000),
+        }
+    }
+}
+
+/// Files and bytes of one language in a repository.
+#[derive(Debug, Default, Serialize)]
+struct LanguageStats {
+    files: u64,
+    bytes: u64,
+}
+
+/// What a truncated analysis never got to.
+#[derive(Debug, Default, Serialize)]
+struct Skipped {
+    /// Directories that were never listed.
+    directories: u64,
+    /// Entries of directories being listed when a limit was hit.
+    entries: u64,
+}
+
+pub fn language_for_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "js" | "mjs" | "jsx" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "py" => "Python",
+        "rs" => "Rust",
+        "go" => "Go",
+        "java" => "Java",
+        "cpp" | "cc" | "cxx" => "C++",
+        "c" => "C",
+        "cs" => "C#",
+        "php" => "PHP",
+        "rb" => "Ruby",
+        _ => return None,
+    })
+}
+
+/// What `op_analyze_repository` returns. Serialized straight into V8 rather
+/// than through a `serde_json::Value`, which matters once `language_breakdown`
+/// and friends reach megabytes.
+#[derive(Debug, Serialize)]
+pub struct Analysis {
+    file_count: u64,
+    languages: Vec<&'static str>,
+    primary_language: Option<&'static str>,
+    language_breakdown: BTreeMap<&'static str, LanguageStats>,
+    config_files: Vec<String>,
+    size_bytes: u64,
+    truncated: bool,
+    truncated_by: Option<&'static str>,
+    skipped: Skipped,
+}
+
+#[derive(Default)]
+struct Walk {
+    files: u64,
+    bytes: u64,
+    languages: BTreeMap<&'static str, LanguageStats>,
+    config_files: Vec<String>,
+    truncated: Option<&'static str>,
+    skipped: Skipped,
+}
+
+impl Walk {
+    fn limit_reached(&self, limits: &AnalysisLimits, started: Instant) -> Option<&'static str> {
+        if limits.max_files.is_some_and(|max| self.files >= max) {
+            Some("max_files")
+        } else if limits.max_bytes.is_some_and(|max| self.bytes >= max) {
+            Some("max_bytes")
+        } else if limits
+            .time_budget_ms
+            .is_some_and(|budget| started.elapsed() >= Duration::from_millis(budget))
+        {
+            Some("time_budget")
+        } else {
+            None
+        }
+    }
+}
+
+/// File count, languages, config files and size of the repository at
+/// `repo_path`, in one walk under `policy` that stops at the first of `limits`
+/// it reaches.
+pub fn analyze(repo_path: &str, limits: &AnalysisLimits, policy: &WalkPolicy) -> Result<Analysis, AnalysisError> {
+    let started = Instant::now();
+    let root = Path::new(repo_path);
+    let mut walk = Walk::default();
+    let mut walker = policy.walk(root);
+
+    while let Some(dir) = walker.next_dir() {
+        if walk.truncated.is_some() {
+            walk.skipped.directories += 1;
+            continue;
+        }
+
+        for file in walker.list(&dir).map_err(AnalysisError::at(&dir))? {
+            if walk.truncated.is_none() {
+                walk.truncated = walk.limit_reached(limits, started);
+            }
+            if walk.truncated.is_some() {
+                walk.skipped.entries += 1;
+                continue;
+            }
+
+            if dir == root && CONFIG_FILES.contains(&file.name.as_str()) {
+                walk.config_files.push(file.name.clone());
+            }
+
+            walk.files += 1;
+            walk.bytes += file.len;
+
+            if let Some(language) = file
+                .path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(language_for_extension)
+            {
+                let stats = walk.languages.entry(language).or_default();
+                stats.files += 1;
+                stats.bytes += file.len;
+            }
+        }
+    }
+
+    let primary_language = walk
+        .languages
+        .iter()
+        .max_by_key(|(_, stats)| (stats.bytes, stats.files))
+        .map(|(language, _)| *language);
+
+    Ok(Analysis {
+        file_count: walk.files,
+        languages: walk.languages.keys().copied().collect(),
+        primary_language,
+        language_breakdown: walk.languages,
+        config_files: walk.config_files,
+        size_bytes: walk.bytes,
+        truncated: walk.truncated.is_some(),
+        truncated_by: walk.truncated,
+        skipped: walk.skipped,
+    })
+}
@@ -0,0 +1,253 @@
+use deno_core::{Resource, ResourceId, ToJsBuffer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// How much of a static file is handed to JS at a time.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// The conditional and range headers of the request being served.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StaticRequest {
+    pub if_none_match: Option<String>,
+    pub range: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaticResponse {
+    pub status: u16,
+    pub headers: BTreeMap<&'static str, String>,
+    /// The [`StaticBody`] to read the body from with `op_static_read`.
+    pub body: Option<ResourceId>,
+}
+
+impl StaticResponse {
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: BTreeMap::new(),
+            body: None,
+        }
+    }
+}
+
+/// The bytes of a static file being served, read a chunk at a time as JS
+/// pulls them with `op_static_read`, so a large file is never held in memory.
+pub struct StaticBody {
+    file: RefCell<File>,
+    remaining: Cell<u64>,
+}
+
+impl Resource for StaticBody {
+    fn name(&self) -> Cow<'_, str> { "staticBody".into() }
+}
+
+impl StaticBody {
+    /// The next chunk of the body, `None` once it has all been read.
+    pub fn read(&self) -> std::io::Result<Option<ToJsBuffer>> {
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        let mut chunk = Vec::new();
+        let read = (&mut *self.file.borrow_mut())
+            .take(remaining.min(CHUNK_SIZE))
+            .read_to_end(&mut chunk)?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining.set(remaining - read as u64);
+        Ok(Some(chunk.into()))
+    }
+}
+
+/// `path` from a URL as a path under the static root, `None` when it would
+/// leave it.
+pub fn relative(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment if segment.contains('\0') => return None,
+            segment => relative.push(segment),
+        }
+    }
+    Some(relative)
+}
+
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" | "cjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json; charset=utf-8",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A single `bytes=` range as inclusive offsets into a file of `len` bytes.
+/// `Some(None)` when the range can't be satisfied; `None` when the header
+/// isn't one this understands, which serves the whole file.
+fn parse_range(range: &str, len: u64) -> Option<Option<(u64, u64)>> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(None);
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len.saturating_sub(1)),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len.saturating_sub(1))),
+    };
+
+    if start > end || start >= len {
+        return Some(None);
+    }
+    Some(Some((start, end)))
+}
+
+/// `path` with its symlinks resolved, `None` when it's missing or resolves to
+/// somewhere outside `root`, which has to be canonical already.
+fn resolve(root: &Path, path: &Path) -> std::io::Result<Option<PathBuf>> {
+    match path.canonicalize() {
+        Ok(path) if path.starts_with(root) => Ok(Some(path)),
+        Ok(_) => Ok(None),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// `file` under `root`, answered the way a static file server would: an
+/// `index.html` for directories, 304 for a matching `ETag`, and 206 or 416 for
+/// `Range` requests. The body is left in the returned [`StaticBody`] for JS
+/// to stream.
+pub fn serve(
+    root: &Path, file: &Path, request: &StaticRequest,
+) -> std::io::Result<(StaticResponse, Option<StaticBody>)> {
+    let root = root.canonicalize()?;
+    let Some(mut path) = resolve(&root, &root.join(file))? else {
+        return Ok((StaticResponse::empty(404), None));
+    };
+
+    if path.is_dir() {
+        path = match resolve(&root, &path.join("index.html"))? {
+            Some(index) if index.is_file() => index,
+            _ => return Ok((StaticResponse::empty(404), None)),
+        };
+    }
+
+    let mut file = File::open(&path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        return Ok((StaticResponse::empty(404), None));
+    }
+
+    let len = meta.len();
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let etag = format!("W/\"{len:x}-{:x}\"", modified.as_nanos());
+
+    let mut headers = BTreeMap::new();
+    headers.insert("etag", etag.clone());
+    headers.insert("accept-ranges", "bytes".to_string());
+    headers.insert("cache-control", "no-cache".to_string());
+
+    if let Some(tags) = &request.if_none_match
+        && tags.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        })
+    {
+        return Ok((
+            StaticResponse {
+                status: 304,
+                headers,
+                body: None,
+            },
+            None,
+        ));
+    }
+
+    headers.insert("content-type", content_type(&path).to_string());
+
+    let (status, start, end) = match request.range.as_deref().and_then(|range| parse_range(range, len)) {
+        None => (200, 0, len.saturating_sub(1)),
+        Some(Some((start, end))) => {
+            headers.insert("content-range", format!("bytes {start}-{end}/{len}"));
+            (206, start, end)
+        }
+        Some(None) => {
+            headers.insert("content-range", format!("bytes */{len}"));
+            return Ok((
+                StaticResponse {
+                    status: 416,
+                    headers,
+                    body: None,
+                },
+                None,
+            ));
+        }
+    };
+
+    let size = if len == 0 { 0 } else { end - start + 1 };
+    file.seek(SeekFrom::Start(start))?;
+    headers.insert("content-length", size.to_string());
+
+    let body = StaticBody {
+        file: RefCell::new(file),
+        remaining: Cell::new(size),
+    };
+    Ok((
+        StaticResponse {
+            status,
+            headers,
+            body: None,
+        },
+        Some(body),
+    ))
+}
@@ -0,0 +1,75 @@
+use super::context::RequestContext;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: u128,
+    permission: &'a str,
+    value: &'a str,
+    source: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack: Option<&'a str>,
+    #[serde(flatten)]
+    context: Option<&'a RequestContext>,
+}
+
+/// JSONL record of every resource a job touched: paths read or written, hosts
+/// contacted and commands spawned, along with who asked for it. mass's own ops
+/// record each access; Deno APIs only the first one to each resource, as their
+/// checks go through a prompt whose answer is remembered.
+#[derive(Clone, Default)]
+pub struct AuditLog(Option<Arc<Mutex<std::fs::File>>>);
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AuditLog").field(&self.0.is_some()).finish()
+    }
+}
+
+impl AuditLog {
+    #[cfg(runtime)]
+    pub fn open(path: &std::path::Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Some(Arc::new(Mutex::new(file)))))
+    }
+
+    #[cfg(runtime)]
+    pub fn is_enabled(&self) -> bool { self.0.is_some() }
+
+    pub fn record(&self, permission: &str, value: &str, source: &str, stack: Option<&str>) {
+        self.record_for(None, permission, value, source, stack);
+    }
+
+    /// [`AuditLog::record`] on behalf of the request `context` belongs to.
+    pub fn record_for(
+        &self, context: Option<&RequestContext>, permission: &str, value: &str, source: &str, stack: Option<&str>,
+    ) {
+        let Some(file) = &self.0 else {
+            return;
+        };
+
+        let record = AuditRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            permission,
+            value,
+            source,
+            stack,
+            context,
+        };
+
+        let Ok(mut line) = serde_json::to_vec(&record) else {
+            return;
+        };
+
+        line.push(b'\n');
+        if let Err(err) = file.lock().unwrap().write_all(&line) {
+            eprintln!("failed to write permission audit record: {err}");
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use super::analysis::{Analysis, AnalysisLimits};
+use super::errors::AnalysisError;
+use super::walk::WalkPolicy;
+use deno_core::Resource;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Mutex, Semaphore, mpsc};
+use tokio::task::AbortHandle;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOptions {
+    /// Repositories analyzed at once, defaults to the number of CPUs.
+    pub concurrency: Option<usize>,
+    /// Where remote repositories are cloned, required when any are queued.
+    pub work_dir: Option<String>,
+    /// Limits each repository's analysis is held to.
+    pub limits: Option<AnalysisLimits>,
+}
+
+/// Where a queued repository comes from, resolved and confined before the
+/// batch starts.
+pub enum Source {
+    Local(PathBuf),
+    Remote { url: String, dest: PathBuf },
+}
+
+impl Source {
+    pub fn is_remote(source: &str) -> bool {
+        ["https://", "http://", "git@", "ssh://"]
+            .iter()
+            .any(|scheme| source.starts_with(scheme))
+    }
+
+    /// The URL of a remote `source`, taking scp-like `git@host:path` as
+    /// `ssh://git@host/path`.
+    pub fn remote_url(source: &str) -> Option<reqwest::Url> {
+        let url = match source.strip_prefix("git@").and_then(|rest| rest.split_once(':')) {
+            Some((host, path)) => reqwest::Url::parse(&format!("ssh://git@{host}/{path}")),
+            None => reqwest::Url::parse(source),
+        };
+        url.ok()
+    }
+
+    /// `<index>-<repository name>`, so two repositories with the same name don't
+    /// share a checkout.
+    pub fn checkout_name(index: usize, url: &str) -> String {
+        let name = url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default();
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{index}-{name}")
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchEvent {
+    Started {
+        index: usize,
+        source: String,
+    },
+    Finished {
+        index: usize,
+        source: String,
+        path: PathBuf,
+        analysis: Analysis,
+        completed: usize,
+        total: usize,
+    },
+    Failed {
+        index: usize,
+        source: String,
+        error: String,
+        completed: usize,
+        total: usize,
+    },
+}
+
+/// Repositories analyzed on the blocking pool, at most `concurrency` at a
+/// time. JS pulls events with `op_batch_next` as they happen; closing the
+/// resource abandons whatever hasn't started yet.
+pub struct Batch {
+    events: Mutex<mpsc::UnboundedReceiver<BatchEvent>>,
+    tasks: Vec<AbortHandle>,
+}
+
+impl Resource for Batch {
+    fn name(&self) -> Cow<'_, str> { "batch".into() }
+
+    fn close(self: Rc<Self>) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Batch {
+    pub fn spawn(
+        jobs: Vec<(String, std::io::Result<Source>)>, concurrency: usize, limits: AnalysisLimits, policy: WalkPolicy,
+        analyze: fn(&str, &AnalysisLimits, &WalkPolicy) -> Result<Analysis, AnalysisError>,
+    ) -> Self {
+        let (sender, events) = mpsc::unbounded_channel();
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let completed = Arc::new(AtomicUsize::new(0));
+        let total = jobs.len();
+
+        let tasks = jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, (source, resolved))| {
+                let (sender, permits, completed) = (sender.clone(), permits.clone(), completed.clone());
+                let policy = policy.clone();
+
+                tokio::spawn(async move {
+                    let Ok(_permit) = permits.acquire_owned().await else {
+                        return;
+                    };
+                    let _ = sender.send(BatchEvent::Started {
+                        index,
+                        source: source.clone(),
+                    });
+
+                    let result = tokio::task::spawn_blocking(move || {
+                        let path = match resolved? {
+                            Source::Local(path) => path,
+                            Source::Remote { url, dest } => clone(&url, &dest)?,
+                        };
+                        let analysis = analyze(&path.to_string_lossy(), &limits, &policy).map_err(Error::other)?;
+                        Ok::<_, Error>((path, analysis))
+                    })
+                    .await
+                    .unwrap_or_else(|err| Err(Error::other(err)));
+
+                    let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    let _ = sender.send(match result {
+                        Ok((path, analysis)) => BatchEvent::Finished {
+                            index,
+                            source,
+                            path,
+                            analysis,
+                            completed,
+                            total,
+                        },
+                        Err(err) => BatchEvent::Failed {
+                            index,
+                            source,
+                            error: err.to_string(),
+                            completed,
+                            total,
+                        },
+                    });
+                })
+                .abort_handle()
+            })
+            .collect();
+
+        Self {
+            events: Mutex::new(events),
+            tasks,
+        }
+    }
+
+    /// The next event, `None` once every repository has finished or failed.
+    pub async fn next(&self) -> Option<BatchEvent> { self.events.lock().await.recv().await }
+}
+
+/// Shallow clone of `url` into `dest`, replacing an earlier checkout.
+fn clone(url: &str, dest: &Path) -> std::io::Result<PathBuf> {
+    if dest.exists() {
+        std::fs::remove_dir_all(dest)?;
+    }
+
+    let output = Command::new("git")
+        .args(["clone", "--depth", "1", "--", url])
+        .arg(dest)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "git clone {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(dest.to_path_buf())
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use tiktoken_rs::CoreBPE;
+
+/// Files smaller than this many tokens aren't worth truncating into whatever
+/// budget is left; they're dropped instead.
+const MIN_TRUNCATED_TOKENS: usize = 64;
+
+static O200K: LazyLock<CoreBPE> = LazyLock::new(|| tiktoken_rs::o200k_base().expect("o200k_base ranks"));
+static CL100K: LazyLock<CoreBPE> = LazyLock::new(|| tiktoken_rs::cl100k_base().expect("cl100k_base ranks"));
+
+/// Counts tokens the way a model family does: exactly for OpenAI encodings,
+/// from a characters-per-token ratio for models whose tokenizer isn't public.
+pub enum Tokenizer {
+    Bpe(&'static CoreBPE),
+    Estimate { chars_per_token: f64 },
+}
+
+impl Tokenizer {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        let model = model.rsplit('/').next().unwrap_or_default();
+
+        if ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"]
+            .iter()
+            .any(|family| model.starts_with(family))
+        {
+            Self::Bpe(&O200K)
+        } else if ["gpt-4", "gpt-3.5", "text-embedding"]
+            .iter()
+            .any(|family| model.starts_with(family))
+        {
+            Self::Bpe(&CL100K)
+        } else if model.starts_with("claude") {
+            Self::Estimate { chars_per_token: 3.5 }
+        } else {
+            Self::Estimate { chars_per_token: 4.0 }
+        }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Bpe(bpe) => bpe.encode_ordinary(text).len(),
+            Self::Estimate { chars_per_token } => (text.chars().count() as f64 / chars_per_token).ceil() as usize,
+        }
+    }
+
+    /// The longest prefix of `text` that stays within `tokens`.
+    pub fn truncate(&self, text: &str, tokens: usize) -> String {
+        match self {
+            Self::Bpe(bpe) => {
+                let encoded = bpe.encode_ordinary(text);
+                let end = tokens.min(encoded.len());
+                // A cut through a multi-byte character fails to decode, so back off.
+                (0..=end)
+                    .rev()
+                    .find_map(|end| bpe.decode(encoded[..end].to_vec()).ok())
+                    .unwrap_or_default()
+            }
+            Self::Estimate { chars_per_token } => {
+                let chars = (tokens as f64 * chars_per_token) as usize;
+                text.chars().take(chars).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FilePayload {
+    pub path: String,
+    pub content: String,
+    /// Higher goes first; files of equal priority keep their order.
+    #[serde(default)]
+    pub priority: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FittedFile {
+    pub path: String,
+    pub content: String,
+    pub tokens: usize,
+    pub original_tokens: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Budgeted {
+    pub files: Vec<FittedFile>,
+    pub dropped: Vec<String>,
+    pub total_tokens: usize,
+    pub max_tokens: usize,
+}
+
+/// Takes files by priority while they fit whole. One that doesn't is truncated
+/// when enough budget is left for that to be useful, and dropped otherwise.
+pub fn fit(mut files: Vec<FilePayload>, model: &str, max_tokens: usize) -> Budgeted {
+    let tokenizer = Tokenizer::for_model(model);
+    files.sort_by(|a, b| b.priority.total_cmp(&a.priority));
+
+    let mut fitted = Vec::new();
+    let mut dropped = Vec::new();
+    let mut remaining = max_tokens;
+
+    for file in files {
+        let original_tokens = tokenizer.count(&file.content);
+
+        if original_tokens <= remaining {
+            remaining -= original_tokens;
+            fitted.push(FittedFile {
+                path: file.path,
+                content: file.content,
+                tokens: original_tokens,
+                original_tokens,
+                truncated: false,
+            });
+            continue;
+        }
+
+        let marker = format!("\n[Content truncated - {original_tokens} total tokens]");
+        let available = remaining.saturating_sub(tokenizer.count(&marker));
+        if available < MIN_TRUNCATED_TOKENS {
+            dropped.push(file.path);
+            continue;
+        }
+
+        let content = tokenizer.truncate(&file.content, available) + marker.as_str();
+        let tokens = tokenizer.count(&content).min(remaining);
+        remaining -= tokens;
+        fitted.push(FittedFile {
+            path: file.path,
+            content,
+            tokens,
+            original_tokens,
+            truncated: true,
+        });
+    }
+
+    Budgeted {
+        files: fitted,
+        dropped,
+        total_tokens: max_tokens - remaining,
+        max_tokens,
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BundleOptions {
+    /// Project whose pkg.toml `[build]` settings and node_modules are used,
+    /// the entry's directory by default.
+    pub dir: Option<String>,
+    pub minify: bool,
+    /// Whether to return a source map along with the code.
+    pub sourcemap: bool,
+    /// Imports left for the runtime to resolve, on top of pkg.toml's.
+    pub external: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Bundle {
+    pub code: String,
+    pub map: Option<String>,
+}
+
+/// `entry` bundled to ESM by the managed esbuild service with the settings of
+/// the project at `dir`, kept in memory rather than written out.
+pub async fn bundle(entry: &Path, dir: &Path, options: &BundleOptions) -> Result<Bundle, Box<dyn Error>> {
+    let esbuild = crate::bundler::esbuild().ok_or("esbuild not found, see `mass doctor`")?;
+
+    let mut flags = crate::bundler::flags(dir, options.minify)?;
+    flags.extend(options.external.iter().map(|name| format!("--external:{name}")));
+    if options.sourcemap {
+        flags.push("--sourcemap=external".into());
+    }
+
+    // Only names the outputs; nothing is written there.
+    let out = dir.join("bundle.js").to_string_lossy().into_owned();
+    let entries = vec![(out, entry.to_string_lossy().into_owned())];
+
+    let mut bundle = Bundle {
+        code: String::new(),
+        map: None,
+    };
+    for output in crate::bundler::bundle(&esbuild, flags, entries).await? {
+        let contents = String::from_utf8(output.contents)?;
+        if output.path.ends_with(".map") {
+            bundle.map = Some(contents);
+        } else {
+            bundle.code = contents;
+        }
+    }
+    Ok(bundle)
+}
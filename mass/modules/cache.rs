@@ -0,0 +1,720 @@
+use super::audit::AuditLog;
+use super::http_cache::{self, Policy, Status};
+use super::quota::RateLimiter;
+use deno_core::futures::{StreamExt, stream};
+#[cfg(runtime)]
+use flate2::Compression;
+#[cfg(runtime)]
+use flate2::read::GzDecoder;
+#[cfg(runtime)]
+use flate2::write::GzEncoder;
+use reqwest::Url;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+#[cfg(runtime)]
+use std::io::Read;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::{fs, io::AsyncWriteExt};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entries (
+    url TEXT PRIMARY KEY,
+    host TEXT NOT NULL,
+    final_url TEXT,
+    headers TEXT NOT NULL DEFAULT '{}',
+    hash TEXT,
+    size INTEGER,
+    cached_at INTEGER NOT NULL,
+    accessed_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_host ON entries (host);
+";
+
+/// One connection per process; SQLite's own locking keeps processes sharing
+/// the cache directory consistent.
+static INDEX: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// How many times each host has been [`purge`]d by this process, so the
+/// copies of its modules held in memory can tell they're gone.
+static PURGES: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+
+/// A per-domain `_metadata` entry from before the index, read once to migrate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheEntry {
+    pub original_url: String,
+    pub final_url: Option<String>,
+}
+
+fn url_to_filename(url: &Url) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(url.path().as_bytes());
+    if let Some(q) = url.query() {
+        hasher.update(b"?");
+        hasher.update(q.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// The name modules were cached under before keys moved to blake3.
+fn legacy_filename(url: &Url) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.path().as_bytes());
+    if let Some(q) = url.query() {
+        hasher.update(b"?");
+        hasher.update(q.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Integrity hash of a cached body, as stored in the index and export archives.
+fn content_hash(data: &[u8]) -> String { format!("blake3:{}", blake3::hash(data).to_hex()) }
+
+/// Whether `data` matches `hash`, either a [`content_hash`] or the bare sha256
+/// hex recorded before it.
+#[cfg(runtime)]
+fn verify(hash: &str, data: &[u8]) -> bool {
+    match hash.strip_prefix("blake3:") {
+        Some(_) => content_hash(data) == hash,
+        None => hex::encode(Sha256::digest(data)) == hash,
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+pub fn cache_dir() -> PathBuf { PathBuf::from("./cache") }
+
+pub fn index_path() -> PathBuf { cache_dir().join("index.sqlite") }
+
+fn host_dir(url: &Url) -> PathBuf { cache_dir().join(url.host_str().unwrap_or("unknown-host")) }
+
+pub fn path_for(url: &Url) -> PathBuf { host_dir(url).join(url_to_filename(url)) }
+
+fn legacy_path_for(url: &Url) -> PathBuf { host_dir(url).join(legacy_filename(url)) }
+
+/// Where `url` is cached, `None` when it isn't. A module still under its
+/// sha256 name is moved to [`path_for`] on the way, so it's found directly
+/// next time, and one the index has no row for gets one.
+pub fn locate(url: &Url) -> Option<PathBuf> {
+    let path = find(url)?;
+    if let Err(err) = backfill(url, &path) {
+        eprintln!("MASS couldn't index cached {url}: {err}");
+    }
+    Some(path)
+}
+
+fn find(url: &Url) -> Option<PathBuf> {
+    let path = path_for(url);
+    if path.is_file() {
+        return Some(path);
+    }
+
+    let legacy = legacy_path_for(url);
+    if !legacy.is_file() {
+        return None;
+    }
+    match std::fs::rename(&legacy, &path) {
+        Ok(()) => Some(path),
+        Err(_) => Some(legacy),
+    }
+}
+
+/// Adds the index row of a module cached without one, e.g. by a version that
+/// kept no metadata, so export, stats and purge see it too. It's dated by the
+/// file, as what it was downloaded with is lost.
+fn backfill(url: &Url, path: &Path) -> std::io::Result<()> {
+    let indexed = with_index(|db| {
+        db.query_row("SELECT 1 FROM entries WHERE url = ?1", [url.as_str()], |_| Ok(()))
+            .optional()
+    })?;
+    if indexed.is_some() {
+        return Ok(());
+    }
+
+    let body = std::fs::read(path)?;
+    let cached_at = std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+
+    with_index(|db| {
+        db.execute(
+            "INSERT OR IGNORE INTO entries (url, host, hash, size, cached_at, accessed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                url.as_str(),
+                url.host_str().unwrap_or("unknown-host"),
+                content_hash(&body),
+                body.len() as i64,
+                cached_at,
+                now()
+            ],
+        )
+    })?;
+    Ok(())
+}
+
+fn open() -> std::io::Result<Connection> {
+    std::fs::create_dir_all(cache_dir())?;
+
+    let mut db = Connection::open(index_path()).map_err(Error::other)?;
+    db.busy_timeout(std::time::Duration::from_secs(5))
+        .and_then(|()| db.pragma_update(None, "journal_mode", "WAL"))
+        .and_then(|()| db.execute_batch(SCHEMA))
+        .map_err(Error::other)?;
+
+    migrate(&mut db)?;
+    Ok(db)
+}
+
+/// Folds every `<host>/_metadata` blob into the index in one transaction and
+/// removes them once it commits. A blob that can't be decoded is set aside as
+/// `_metadata.corrupt` rather than retried on every start.
+fn migrate(db: &mut Connection) -> std::io::Result<()> {
+    let Ok(hosts) = std::fs::read_dir(cache_dir()) else {
+        return Ok(());
+    };
+
+    let mut migrated = Vec::new();
+    let tx = db.transaction().map_err(Error::other)?;
+    let now = now();
+
+    for host in hosts.flatten() {
+        let path = host.path().join("_metadata");
+        let Ok(bytes) = std::fs::read(&path) else {
+            continue;
+        };
+
+        let entries: BTreeMap<String, CacheEntry> = match postcard::from_bytes(&bytes) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("MASS set aside unreadable cache metadata {}: {err}", path.display());
+                std::fs::rename(&path, path.with_extension("corrupt"))?;
+                continue;
+            }
+        };
+
+        for entry in entries.values() {
+            let Ok(url) = Url::parse(&entry.original_url) else {
+                continue;
+            };
+            let body = find(&url).and_then(|path| std::fs::read(path).ok());
+
+            tx.execute(
+                "INSERT OR IGNORE INTO entries (url, host, final_url, hash, size, cached_at, accessed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+                params![
+                    entry.original_url,
+                    url.host_str().unwrap_or("unknown-host"),
+                    entry.final_url,
+                    body.as_deref().map(content_hash),
+                    body.as_ref().map(|body| body.len() as i64),
+                    now
+                ],
+            )
+            .map_err(Error::other)?;
+        }
+
+        migrated.push(path);
+    }
+
+    tx.commit().map_err(Error::other)?;
+    for path in migrated {
+        let _ = std::fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+/// Runs `f` against the index, opening (and migrating) it on first use.
+fn with_index<T>(f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> std::io::Result<T> {
+    let mut index = INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+    let db = match index.take() {
+        Some(db) => db,
+        None => open()?,
+    };
+
+    f(index.insert(db)).map_err(Error::other)
+}
+
+pub async fn cache_url(
+    original_url: &Url, final_url: Option<&Url>, headers: &BTreeMap<String, String>, data: &[u8],
+) -> std::io::Result<PathBuf> {
+    let cache_path = path_for(original_url);
+    write_atomic(&cache_path, data).await?;
+
+    let final_url = final_url.filter(|url| *url != original_url).map(Url::to_string);
+    let headers = serde_json::to_string(headers).map_err(Error::other)?;
+    let hash = content_hash(data);
+    let now = now();
+
+    with_index(|db| {
+        let tx = db.transaction()?;
+        tx.execute(
+            "INSERT INTO entries (url, host, final_url, headers, hash, size, cached_at, accessed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+             ON CONFLICT (url) DO UPDATE SET
+                final_url = excluded.final_url, headers = excluded.headers, hash = excluded.hash,
+                size = excluded.size, cached_at = excluded.cached_at, accessed_at = excluded.accessed_at",
+            params![
+                original_url.as_str(),
+                original_url.host_str().unwrap_or("unknown-host"),
+                final_url,
+                headers,
+                hash,
+                data.len() as i64,
+                now
+            ],
+        )?;
+        tx.commit()
+    })?;
+
+    Ok(cache_path)
+}
+
+/// What the index knows about a cached response.
+#[derive(Clone, Debug, Default)]
+pub struct Entry {
+    /// Where the URL redirected to, when it did.
+    pub final_url: Option<Url>,
+    pub headers: BTreeMap<String, String>,
+    /// Unix time in milliseconds it was downloaded or last revalidated.
+    pub cached_at: i64,
+}
+
+/// How long ago `entry` was downloaded or last revalidated.
+pub fn age(entry: &Entry) -> std::time::Duration {
+    std::time::Duration::from_millis(now().saturating_sub(entry.cached_at).max(0) as u64)
+}
+
+/// The index row of `original_url`, marking it as used.
+pub fn entry(original_url: &Url) -> std::io::Result<Option<Entry>> {
+    let row = with_index(|db| {
+        db.execute(
+            "UPDATE entries SET accessed_at = ?2 WHERE url = ?1",
+            params![original_url.as_str(), now()],
+        )?;
+        db.query_row(
+            "SELECT final_url, headers, cached_at FROM entries WHERE url = ?1",
+            [original_url.as_str()],
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            },
+        )
+        .optional()
+    })?;
+
+    Ok(row.map(|(final_url, headers, cached_at)| Entry {
+        final_url: final_url.and_then(|url| Url::parse(&url).ok()),
+        headers: serde_json::from_str(&headers).unwrap_or_default(),
+        cached_at,
+    }))
+}
+
+/// Records that the origin confirmed `original_url`'s cached body is current,
+/// along with the headers it confirmed it with.
+pub fn revalidated(original_url: &Url, headers: &BTreeMap<String, String>) -> std::io::Result<()> {
+    let headers = serde_json::to_string(headers).map_err(Error::other)?;
+    with_index(|db| {
+        db.execute(
+            "UPDATE entries SET headers = ?2, cached_at = ?3, accessed_at = ?3 WHERE url = ?1",
+            params![original_url.as_str(), headers, now()],
+        )
+    })?;
+    Ok(())
+}
+
+/// How many times `url`'s host has been [`purge`]d, for telling whether a
+/// copy of it held since is still current.
+#[cfg(runtime)]
+pub fn purges(url: &Url) -> u64 {
+    let purges = PURGES.lock().unwrap_or_else(PoisonError::into_inner);
+    purges
+        .get(url.host_str().unwrap_or("unknown-host"))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Drops every module cached from `host`, on disk and in the index, so the
+/// next load fetches them again. Returns how many modules were dropped.
+pub fn purge(host: &str) -> std::io::Result<usize> {
+    // Whatever isn't a host on its own could name something else in the cache
+    // directory, the index included.
+    let valid = Url::parse(&format!("http://{host}/"))
+        .ok()
+        .is_some_and(|url| url.host_str() == Some(host));
+    if !valid {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{host} is not a host name"),
+        ));
+    }
+
+    let purged = with_index(|db| db.execute("DELETE FROM entries WHERE host = ?1", [host]))?;
+    *PURGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(host.to_string())
+        .or_default() += 1;
+
+    match std::fs::remove_dir_all(cache_dir().join(host)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+        _ => Ok(purged),
+    }
+}
+
+/// What's cached from one host.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostStats {
+    pub host: String,
+    pub modules: i64,
+    pub bytes: i64,
+    /// Unix time in milliseconds one of its modules was last downloaded or
+    /// revalidated.
+    pub cached_at: i64,
+    /// Unix time in milliseconds one of its modules was last used.
+    pub accessed_at: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub modules: i64,
+    pub bytes: i64,
+    /// Every host with modules cached, the largest first.
+    pub hosts: Vec<HostStats>,
+}
+
+/// What the module cache holds, host by host.
+pub fn stats() -> std::io::Result<CacheStats> {
+    let hosts = with_index(|db| {
+        let mut stmt = db.prepare(
+            "SELECT host, COUNT(*), COALESCE(SUM(size), 0), MAX(cached_at), MAX(accessed_at)
+             FROM entries GROUP BY host ORDER BY SUM(size) DESC, host",
+        )?;
+        let hosts = stmt
+            .query_map([], |row| {
+                Ok(HostStats {
+                    host: row.get(0)?,
+                    modules: row.get(1)?,
+                    bytes: row.get(2)?,
+                    cached_at: row.get(3)?,
+                    accessed_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hosts)
+    })?;
+
+    Ok(CacheStats {
+        modules: hosts.iter().map(|host| host.modules).sum(),
+        bytes: hosts.iter().map(|host| host.bytes).sum(),
+        hosts,
+    })
+}
+
+/// `mass cache warm`'s manifest: a JSON array of module URLs, or an object
+/// listing them under `modules`.
+#[cfg(runtime)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum WarmManifest {
+    List(Vec<String>),
+    Object { modules: Vec<String> },
+}
+
+#[cfg(runtime)]
+impl WarmManifest {
+    pub fn read(path: &Path) -> std::io::Result<Vec<String>> {
+        let manifest =
+            serde_json::from_slice(&std::fs::read(path)?).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        Ok(match manifest {
+            Self::List(modules) | Self::Object { modules } => modules,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WarmOptions {
+    /// How many modules are fetched at once.
+    pub concurrency: usize,
+}
+
+impl Default for WarmOptions {
+    fn default() -> Self { Self { concurrency: 16 } }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmFailure {
+    pub url: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WarmReport {
+    /// Modules downloaded by this run.
+    pub fetched: Vec<String>,
+    /// Modules that were already in the cache, fresh or revalidated.
+    pub cached: Vec<String>,
+    pub failed: Vec<WarmFailure>,
+}
+
+/// Fetches `url` into the cache the way the module loader would, unless a
+/// fresh copy is already there. `true` when it was downloaded.
+async fn warm_one(quota: &RateLimiter, audit: &AuditLog, url: &Url) -> std::io::Result<bool> {
+    let host = url.host_str().unwrap_or_default();
+    let response = http_cache::fetch(url, Policy::default(), quota, || {
+        audit.record("net", host, "cache warm", None)
+    })
+    .await
+    .map_err(Error::other)?;
+
+    match response.status {
+        Status::Fetched => Ok(true),
+        Status::Uncached => Err(Error::other("could not be written to the cache")),
+        Status::Fresh | Status::Revalidated | Status::Stale => Ok(false),
+    }
+}
+
+/// Fetches every module in `urls` into the cache, `options.concurrency` at a
+/// time, so a container image can be built with the cache already hot.
+pub async fn warm(mut urls: Vec<String>, options: WarmOptions, quota: &RateLimiter, audit: &AuditLog) -> WarmReport {
+    urls.sort();
+    urls.dedup();
+
+    let results = stream::iter(urls)
+        .map(|url| async {
+            let result = match Url::parse(&url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => warm_one(quota, audit, &parsed).await,
+                Ok(_) => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "only http and https modules are cached",
+                )),
+                Err(err) => Err(Error::new(ErrorKind::InvalidInput, err)),
+            };
+            (url, result)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut report = WarmReport::default();
+    for (url, result) in results {
+        match result {
+            Ok(true) => report.fetched.push(url),
+            Ok(false) => report.cached.push(url),
+            Err(err) => report.failed.push(WarmFailure {
+                url,
+                error: err.to_string(),
+            }),
+        }
+    }
+    report
+}
+
+/// An index row as it travels in an export archive. `file` is where the body
+/// sits under `modules/` in the archive and under the cache directory.
+#[cfg(runtime)]
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedEntry {
+    url: String,
+    file: String,
+    final_url: Option<String>,
+    headers: BTreeMap<String, String>,
+    hash: String,
+    cached_at: i64,
+}
+
+#[cfg(runtime)]
+fn relative_path(path: &Path) -> Option<String> {
+    let path = path.strip_prefix(cache_dir()).ok()?;
+    Some(path.to_str()?.replace('\\', "/"))
+}
+
+/// Writes every cached module, or only those from `hosts` when any are given,
+/// with their redirects and headers into a gzipped tarball at `out`. Returns
+/// how many modules went in.
+#[cfg(runtime)]
+pub fn export(out: &Path, hosts: &[String]) -> std::io::Result<usize> {
+    let rows = with_index(|db| {
+        let mut stmt = db.prepare(
+            "SELECT url, host, final_url, headers, hash, cached_at FROM entries WHERE hash IS NOT NULL ORDER BY url",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    })?;
+
+    let mut entries = Vec::new();
+    for (url, host, final_url, headers, hash, cached_at) in rows {
+        if !hosts.is_empty() && !hosts.contains(&host) {
+            continue;
+        }
+        let Some(file) = Url::parse(&url)
+            .ok()
+            .and_then(|url| locate(&url))
+            .and_then(|path| relative_path(&path))
+        else {
+            continue;
+        };
+
+        entries.push(ExportedEntry {
+            url,
+            file,
+            final_url,
+            headers: serde_json::from_str(&headers).unwrap_or_default(),
+            hash,
+            cached_at,
+        });
+    }
+
+    let mut archive = tar::Builder::new(GzEncoder::new(std::fs::File::create(out)?, Compression::default()));
+    let index = serde_json::to_vec_pretty(&entries).map_err(Error::other)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(index.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, "index.json", index.as_slice())?;
+
+    for entry in &entries {
+        archive.append_path_with_name(cache_dir().join(&entry.file), format!("modules/{}", entry.file))?;
+    }
+
+    archive.into_inner()?.finish()?;
+    Ok(entries.len())
+}
+
+/// Unpacks an [`export`] archive into the cache and merges its entries into
+/// the index. Every body is checked against the hash it was exported with,
+/// and only written where its URL is cached here.
+#[cfg(runtime)]
+pub fn import(archive: &Path) -> std::io::Result<usize> {
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(archive)?));
+    let mut entries: Option<HashMap<String, ExportedEntry>> = None;
+    let mut imported = Vec::new();
+
+    for file in archive.entries()? {
+        let mut file = file?;
+        let name = file.path()?.to_string_lossy().replace('\\', "/");
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        if name == "index.json" {
+            let index: Vec<ExportedEntry> =
+                serde_json::from_slice(&bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            entries = Some(index.into_iter().map(|entry| (entry.file.clone(), entry)).collect());
+            continue;
+        }
+
+        let Some(entries) = entries.as_mut() else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "index.json must come first in a cache archive",
+            ));
+        };
+        let Some(entry) = name.strip_prefix("modules/").and_then(|file| entries.remove(file)) else {
+            continue;
+        };
+        // Archives from before blake3 keys carry modules under their sha256 name.
+        let Some(path) = Url::parse(&entry.url)
+            .ok()
+            .filter(|url| {
+                [path_for(url), legacy_path_for(url)]
+                    .iter()
+                    .any(|path| relative_path(path).as_deref() == Some(entry.file.as_str()))
+            })
+            .map(|url| path_for(&url))
+        else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not where {} is cached", entry.file, entry.url),
+            ));
+        };
+        if !verify(&entry.hash, &bytes) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} does not match its hash", entry.url),
+            ));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, &bytes)?;
+        std::fs::rename(&tmp, &path)?;
+
+        imported.push((entry, bytes.len() as i64));
+    }
+
+    let now = now();
+    with_index(|db| {
+        let tx = db.transaction()?;
+        for (entry, size) in &imported {
+            let host = Url::parse(&entry.url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string));
+            tx.execute(
+                "INSERT INTO entries (url, host, final_url, headers, hash, size, cached_at, accessed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT (url) DO UPDATE SET
+                    final_url = excluded.final_url, headers = excluded.headers, hash = excluded.hash,
+                    size = excluded.size, cached_at = excluded.cached_at",
+                params![
+                    entry.url,
+                    host.as_deref().unwrap_or("unknown-host"),
+                    entry.final_url,
+                    serde_json::to_string(&entry.headers).unwrap_or_default(),
+                    entry.hash,
+                    size,
+                    entry.cached_at,
+                    now
+                ],
+            )?;
+        }
+        tx.commit()
+    })?;
+
+    Ok(imported.len())
+}
+
+pub async fn write_atomic(path: &PathBuf, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f = fs::File::create(&tmp).await?;
+        f.write_all(bytes).await?;
+        f.flush().await?;
+    }
+
+    fs::rename(&tmp, &path).await
+}
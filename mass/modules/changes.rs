@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::process::Command;
+
+/// Changed files larger than this are listed without their content.
+const MAX_CONTENT_BYTES: u64 = 500_000;
+
+#[derive(Debug, Serialize)]
+pub struct ChangedFile {
+    pub path: String,
+    /// git's status letter: `A`dded, `M`odified, `D`eleted, ... Renames are
+    /// reported as a deletion plus an addition.
+    pub status: String,
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
+    pub language: Option<&'static str>,
+    pub size: Option<u64>,
+    pub content: Option<String>,
+    pub skipped: Option<String>,
+}
+
+/// One diff hunk, the unit changed code is chunked into.
+#[derive(Debug, Serialize)]
+pub struct Hunk {
+    pub path: String,
+    pub header: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Changes {
+    pub base: String,
+    pub files: Vec<ChangedFile>,
+    pub languages: BTreeMap<&'static str, usize>,
+    pub hunks: Vec<Hunk>,
+}
+
+fn git(repo: &Path, args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Everything that differs between `base` and the working tree of `repo`.
+pub fn analyze(repo: &Path, base: &str, language: impl Fn(&str) -> Option<&'static str>) -> std::io::Result<Changes> {
+    if base.is_empty() || base.starts_with('-') {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid base ref {base:?}"),
+        ));
+    }
+
+    let mut numstat = HashMap::new();
+    for line in git(repo, &["diff", "--numstat", "--no-renames", base, "--"])?.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(added), Some(deleted), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        // Binary files report `-` for both counts.
+        numstat.insert(path.to_string(), (added.parse().ok(), deleted.parse().ok()));
+    }
+
+    let mut files = Vec::new();
+    let mut languages = BTreeMap::new();
+
+    for line in git(repo, &["diff", "--name-status", "--no-renames", base, "--"])?.lines() {
+        let Some((status, path)) = line.split_once('\t') else {
+            continue;
+        };
+
+        let status = status.chars().next().map(String::from).unwrap_or_default();
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let language = language(extension);
+        if let Some(language) = language {
+            *languages.entry(language).or_default() += 1;
+        }
+
+        let (additions, deletions) = numstat.get(path).copied().unwrap_or_default();
+
+        let full = repo.join(path);
+        let size = std::fs::metadata(&full).ok().map(|metadata| metadata.len());
+        let (content, skipped) = match size {
+            None => (None, Some("deleted".to_string())),
+            Some(size) if size > MAX_CONTENT_BYTES => (None, Some(format!("too large: {size} bytes"))),
+            Some(size) => match std::fs::read_to_string(&full) {
+                Ok(content) => (Some(content), None),
+                Err(_) => (None, Some(format!("binary: {size} bytes"))),
+            },
+        };
+
+        files.push(ChangedFile {
+            path: path.to_string(),
+            status,
+            additions,
+            deletions,
+            language,
+            size,
+            content,
+            skipped,
+        });
+    }
+
+    let hunks = hunks(&git(repo, &["diff", "--no-renames", "--unified=3", base, "--"])?);
+    Ok(Changes {
+        base: base.to_string(),
+        files,
+        languages,
+        hunks,
+    })
+}
+
+/// Splits a unified diff into its hunks, tagged with the file they belong to.
+fn hunks(diff: &str) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut path = String::new();
+    // `---`/`+++` only name files between `diff --git` and the first hunk;
+    // inside one they're removed or added lines.
+    let mut in_header = false;
+
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+        } else if line.starts_with("@@") {
+            in_header = false;
+            hunks.push(Hunk {
+                path: path.clone(),
+                header: line.to_string(),
+                text: String::new(),
+            });
+        } else if in_header {
+            if let Some(source) = line.strip_prefix("--- ") {
+                path = source.strip_prefix("a/").unwrap_or(source).to_string();
+            } else if let Some(target) = line.strip_prefix("+++ ")
+                && target != "/dev/null"
+            {
+                // Deleted files keep the path they had before.
+                path = target.strip_prefix("b/").unwrap_or(target).to_string();
+            }
+        } else if let Some(hunk) = hunks.last_mut() {
+            hunk.text.push_str(line);
+            hunk.text.push('\n');
+        }
+    }
+
+    hunks
+}
@@ -0,0 +1,200 @@
+use deno_core::Resource;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{Error, ErrorKind, Write};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CodecOptions {
+    /// Compression level, 0-9 for gzip, 1-22 for zstd and 0-11 for brotli.
+    /// Ignored when decompressing.
+    pub level: Option<u32>,
+    /// Fails decompression once it has put out more than this, so a small
+    /// artifact can't expand into the whole disk.
+    pub max_bytes: Option<u64>,
+}
+
+/// Brotli's window, as `lgwin`: 4MiB, what the `brotli` CLI uses.
+const BROTLI_WINDOW: u32 = 22;
+const BROTLI_BUFFER: usize = 64 * 1024;
+
+/// The output a codec writes into, until JS takes it. Once more than
+/// `limit` has been written in all, every write fails, so a decoder stops
+/// as soon as it crosses the limit rather than after a whole chunk.
+struct Capped {
+    output: Vec<u8>,
+    limit: Option<u64>,
+    written: u64,
+}
+
+impl Capped {
+    fn new(limit: Option<u64>) -> Self {
+        Self {
+            output: Vec::new(),
+            limit,
+            written: 0,
+        }
+    }
+}
+
+impl Write for Capped {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.written + buf.len() as u64;
+        if let Some(limit) = self.limit
+            && written > limit
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("decompressed to more than {limit} bytes"),
+            ));
+        }
+
+        self.written = written;
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+/// An encoder or decoder writing into the output JS is handed next.
+enum Codec {
+    GzipEncoder(flate2::write::GzEncoder<Capped>),
+    GzipDecoder(flate2::write::MultiGzDecoder<Capped>),
+    ZstdEncoder(zstd::stream::write::Encoder<'static, Capped>),
+    /// The raw writer rather than `write::Decoder`, whose `finish` is the
+    /// only way to learn the last frame was cut short.
+    ZstdDecoder(zstd::stream::zio::Writer<Capped, zstd::stream::raw::Decoder<'static>>),
+    BrotliEncoder(Box<brotli::CompressorWriter<Capped>>),
+    BrotliDecoder(Box<brotli::DecompressorWriter<Capped>>),
+}
+
+impl Codec {
+    fn encoder(format: Format, level: Option<u32>) -> std::io::Result<Self> {
+        let output = Capped::new(None);
+        Ok(match format {
+            Format::Gzip => {
+                let level = flate2::Compression::new(level.unwrap_or(6).min(9));
+                Self::GzipEncoder(flate2::write::GzEncoder::new(output, level))
+            }
+            Format::Zstd => {
+                let level = level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |level| level.clamp(1, 22) as i32);
+                Self::ZstdEncoder(zstd::stream::write::Encoder::new(output, level)?)
+            }
+            Format::Brotli => Self::BrotliEncoder(Box::new(brotli::CompressorWriter::new(
+                output,
+                BROTLI_BUFFER,
+                level.unwrap_or(5).min(11),
+                BROTLI_WINDOW,
+            ))),
+        })
+    }
+
+    fn decoder(format: Format, max_bytes: Option<u64>) -> std::io::Result<Self> {
+        let output = Capped::new(max_bytes);
+        Ok(match format {
+            Format::Gzip => Self::GzipDecoder(flate2::write::MultiGzDecoder::new(output)),
+            Format::Zstd => Self::ZstdDecoder(zstd::stream::zio::Writer::new(
+                output,
+                zstd::stream::raw::Decoder::new()?,
+            )),
+            Format::Brotli => Self::BrotliDecoder(Box::new(brotli::DecompressorWriter::new(output, BROTLI_BUFFER))),
+        })
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        let capped = match self {
+            Self::GzipEncoder(codec) => codec.get_mut(),
+            Self::GzipDecoder(codec) => codec.get_mut(),
+            Self::ZstdEncoder(codec) => codec.get_mut(),
+            Self::ZstdDecoder(codec) => codec.writer_mut(),
+            Self::BrotliEncoder(codec) => codec.get_mut(),
+            Self::BrotliDecoder(codec) => codec.get_mut(),
+        };
+        &mut capped.output
+    }
+
+    fn write_all(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::GzipEncoder(codec) => codec.write_all(chunk),
+            Self::GzipDecoder(codec) => codec.write_all(chunk),
+            Self::ZstdEncoder(codec) => codec.write_all(chunk),
+            Self::ZstdDecoder(codec) => codec.write_all(chunk),
+            Self::BrotliEncoder(codec) => codec.write_all(chunk),
+            Self::BrotliDecoder(codec) => codec.write_all(chunk),
+        }
+    }
+
+    /// Ends the stream, returning whatever output was still held back.
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        let capped = match self {
+            Self::GzipEncoder(codec) => codec.finish()?,
+            Self::GzipDecoder(codec) => codec.finish()?,
+            Self::ZstdEncoder(codec) => codec.finish()?,
+            Self::ZstdDecoder(mut codec) => {
+                codec.finish()?;
+                codec.into_inner().0
+            }
+            Self::BrotliEncoder(codec) => (*codec).into_inner(),
+            Self::BrotliDecoder(codec) => (*codec)
+                .into_inner()
+                .map_err(|_| Error::new(ErrorKind::UnexpectedEof, "brotli stream ended early"))?,
+        };
+        Ok(capped.output)
+    }
+}
+
+/// A compression or decompression stream JS feeds chunks through with
+/// `op_codec_write`, taking whatever output each produced, until
+/// `op_codec_finish` ends it.
+pub struct CodecStream {
+    codec: RefCell<Option<Codec>>,
+}
+
+impl Resource for CodecStream {
+    fn name(&self) -> Cow<'_, str> { "codecStream".into() }
+}
+
+impl CodecStream {
+    pub fn compress(format: Format, options: &CodecOptions) -> std::io::Result<Self> {
+        Ok(Self {
+            codec: RefCell::new(Some(Codec::encoder(format, options.level)?)),
+        })
+    }
+
+    pub fn decompress(format: Format, options: &CodecOptions) -> std::io::Result<Self> {
+        Ok(Self {
+            codec: RefCell::new(Some(Codec::decoder(format, options.max_bytes)?)),
+        })
+    }
+
+    /// Feeds `chunk` in and returns the output it produced, which may be none.
+    pub fn write(&self, chunk: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut codec = self.codec.borrow_mut();
+        let codec = codec
+            .as_mut()
+            .ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "stream already finished"))?;
+
+        codec.write_all(chunk)?;
+        Ok(std::mem::take(codec.output()))
+    }
+
+    /// Ends the stream and returns the rest of its output.
+    pub fn finish(&self) -> std::io::Result<Vec<u8>> {
+        let codec = self
+            .codec
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "stream already finished"))?;
+        codec.finish()
+    }
+}
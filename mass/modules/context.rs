@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Headers the front proxy and `MASS.context.fromRequest` carry a request's
+/// context in.
+#[cfg(runtime)]
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+#[cfg(runtime)]
+pub const TENANT_ID_HEADER: &str = "x-tenant-id";
+/// Unix time in milliseconds the request has to be answered by.
+#[cfg(runtime)]
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Who a piece of work is being done for, attached to the log and audit records
+/// written on its behalf.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RequestContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// Unix time in milliseconds, see [`DEADLINE_HEADER`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+}
+
+/// Whether requests reach the isolate through the host's front proxy, which
+/// drops the context headers clients send and sets its own. Only then are
+/// they to be believed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fronted(pub bool);
+
+/// The contexts of the requests an isolate is handling, by the id JS refers to
+/// them with. JS switches to a request's context before calling into an op on
+/// its behalf, which is the one ops then attribute their records to.
+#[derive(Debug, Default)]
+pub struct Contexts {
+    next: u32,
+    open: HashMap<u32, Rc<RequestContext>>,
+    current: Option<Rc<RequestContext>>,
+}
+
+impl Contexts {
+    /// Ids start at 1, leaving 0 for no context.
+    pub fn open(&mut self, context: RequestContext) -> u32 {
+        self.next = self.next.checked_add(1).unwrap_or(1);
+        self.open.insert(self.next, Rc::new(context));
+        self.next
+    }
+
+    pub fn close(&mut self, id: u32) {
+        if let Some(context) = self.open.remove(&id)
+            && self
+                .current
+                .as_ref()
+                .is_some_and(|current| Rc::ptr_eq(current, &context))
+        {
+            self.current = None;
+        }
+    }
+
+    /// Makes `id` the current context, or clears it for 0 and closed ids.
+    pub fn switch(&mut self, id: u32) { self.current = self.open.get(&id).cloned(); }
+
+    pub fn current(&self) -> Option<&RequestContext> { self.current.as_deref() }
+}
@@ -0,0 +1,38 @@
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+/// Where ops keep state that outlives a worker: vector indexes and the like.
+/// Unset when running without one, in which case those ops fail.
+#[derive(Clone, Debug, Default)]
+pub struct DataDir(Option<PathBuf>);
+
+impl DataDir {
+    #[cfg(runtime)]
+    pub fn new(dir: Option<PathBuf>) -> Self { Self(dir.or_else(default_dir)) }
+
+    /// `subdir` under the data directory, created on first use.
+    pub fn join(&self, subdir: &str) -> std::io::Result<PathBuf> {
+        let Some(root) = &self.0 else {
+            return Err(Error::new(ErrorKind::NotFound, "no data directory, pass --data-dir"));
+        };
+
+        let dir = root.join(subdir);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// `mass` under the platform data directory.
+#[cfg(runtime)]
+fn default_dir() -> Option<PathBuf> {
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?),
+        None if cfg!(target_os = "macos") => {
+            PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+        }
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+
+    Some(data.join("mass"))
+}
@@ -0,0 +1,248 @@
+use super::quota::RateLimiter;
+use deno_core::Resource;
+use reqwest::header::{ACCEPT_ENCODING, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::AbortHandle;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DownloadOptions {
+    /// Picks up where an earlier, interrupted download to the same path left
+    /// off, with a range request for the rest. It starts over when the file
+    /// changed on the server since.
+    pub resume: bool,
+    /// Hex sha256 the file must have, or it's thrown away.
+    pub sha256: Option<String>,
+    /// Fails the download once it's larger than this.
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    Progress {
+        /// Bytes on disk so far, those of a resumed download included.
+        received: u64,
+        /// `None` when the server didn't say.
+        total: Option<u64>,
+    },
+    Finished {
+        path: PathBuf,
+        bytes: u64,
+        sha256: String,
+        /// Whether it carried on from an earlier download.
+        resumed: bool,
+    },
+}
+
+/// A download streaming to disk in the background. JS pulls its progress
+/// with `op_download_next`; closing the resource cancels it, leaving what was
+/// received to resume from.
+pub struct Download {
+    url: Url,
+    events: Mutex<mpsc::Receiver<std::io::Result<DownloadEvent>>>,
+    task: AbortHandle,
+}
+
+impl Resource for Download {
+    fn name(&self) -> Cow<'_, str> { "download".into() }
+
+    fn close(self: Rc<Self>) { self.task.abort(); }
+}
+
+impl Download {
+    pub fn spawn(quota: RateLimiter, url: Url, dest: PathBuf, options: DownloadOptions) -> Self {
+        let (sender, events) = mpsc::channel(32);
+
+        let task = tokio::spawn({
+            let url = url.clone();
+            async move {
+                let result = download(&quota, &url, &dest, &options, &sender).await;
+                let _ = sender.send(result).await;
+            }
+        })
+        .abort_handle();
+
+        Self {
+            url,
+            events: Mutex::new(events),
+            task,
+        }
+    }
+
+    pub fn url(&self) -> &Url { &self.url }
+
+    /// The next event, `None` once the download has finished or failed.
+    pub async fn next(&self) -> Option<std::io::Result<DownloadEvent>> { self.events.lock().await.recv().await }
+}
+
+/// Where a download to `dest` is written until it's complete and verified.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut path = dest.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+/// Where the ETag or Last-Modified a partial download was fetched under is
+/// kept, to resume it only from the same version of the file.
+fn validator_path(part: &Path) -> PathBuf {
+    let mut path = part.as_os_str().to_owned();
+    path.push(".validator");
+    PathBuf::from(path)
+}
+
+/// What `If-Range` can check the response was for: a strong ETag, or else its
+/// Last-Modified date.
+fn validator(res: &reqwest::Response) -> Option<String> {
+    let headers = res.headers();
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Removes a download's partial file and its validator.
+async fn discard(part: &Path) {
+    let _ = fs::remove_file(part).await;
+    let _ = fs::remove_file(validator_path(part)).await;
+}
+
+fn too_large(url: &Url, max_bytes: u64) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{url} is larger than {max_bytes} bytes"),
+    )
+}
+
+/// Hashes what an earlier download already wrote to `path`.
+async fn hash_existing(path: &Path, hasher: &mut Sha256) -> std::io::Result<()> {
+    let mut file = fs::File::open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+async fn download(
+    quota: &RateLimiter, url: &Url, dest: &Path, options: &DownloadOptions,
+    events: &mpsc::Sender<std::io::Result<DownloadEvent>>,
+) -> std::io::Result<DownloadEvent> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let part = part_path(dest);
+    // Without a validator there's no telling the rest would be of the same
+    // file, so it starts over.
+    let stored = match options.resume {
+        true => fs::read_to_string(validator_path(&part)).await.ok(),
+        false => None,
+    };
+    let mut offset = match &stored {
+        Some(_) => fs::metadata(&part).await.map(|meta| meta.len()).unwrap_or_default(),
+        None => 0,
+    };
+
+    // Ranges are of the body as sent, which has to be the file itself.
+    let connection = quota.connect(url).await;
+    let mut res = loop {
+        let mut request = connection.client().get(url.clone()).header(ACCEPT_ENCODING, "identity");
+        if offset > 0
+            && let Some(validator) = &stored
+        {
+            request = request
+                .header(RANGE, format!("bytes={offset}-"))
+                .header(IF_RANGE, validator.as_str());
+        }
+
+        let res = request.send().await.map_err(Error::other)?;
+        if res.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+            offset = 0;
+            continue;
+        }
+        break res.error_for_status().map_err(Error::other)?;
+    };
+    // A 200 to an If-Range request is the whole of a file that changed.
+    if res.status() != StatusCode::PARTIAL_CONTENT {
+        offset = 0;
+    }
+    if offset == 0 {
+        match validator(&res) {
+            Some(validator) => fs::write(validator_path(&part), validator).await?,
+            None => {
+                let _ = fs::remove_file(validator_path(&part)).await;
+            }
+        }
+    }
+
+    let total = res.content_length().map(|length| offset + length);
+    if let (Some(max_bytes), Some(total)) = (options.max_bytes, total)
+        && total > max_bytes
+    {
+        return Err(too_large(url, max_bytes));
+    }
+
+    let mut hasher = Sha256::new();
+    let mut file = if offset > 0 {
+        hash_existing(&part, &mut hasher).await?;
+        fs::OpenOptions::new().append(true).open(&part).await?
+    } else {
+        fs::File::create(&part).await?
+    };
+
+    let mut received = offset;
+    while let Some(chunk) = res.chunk().await.map_err(Error::other)? {
+        received += chunk.len() as u64;
+        if let Some(max_bytes) = options.max_bytes
+            && received > max_bytes
+        {
+            drop(file);
+            discard(&part).await;
+            return Err(too_large(url, max_bytes));
+        }
+
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        // Progress isn't worth holding the download up for; JS only needs
+        // the latest.
+        let _ = events.try_send(Ok(DownloadEvent::Progress { received, total }));
+    }
+    file.flush().await?;
+    drop(file);
+    drop(connection);
+
+    let sha256 = hex::encode(hasher.finalize());
+    if let Some(expected) = &options.sha256
+        && !expected.eq_ignore_ascii_case(&sha256)
+    {
+        discard(&part).await;
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{url} has sha256 {sha256}, expected {expected}"),
+        ));
+    }
+
+    fs::rename(&part, dest).await?;
+    let _ = fs::remove_file(validator_path(&part)).await;
+    Ok(DownloadEvent::Finished {
+        path: dest.to_path_buf(),
+        bytes: received,
+        sha256,
+        resumed: offset > 0,
+    })
+}
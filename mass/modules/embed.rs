@@ -0,0 +1,223 @@
+use serde::Deserialize;
+use std::path::Path;
+#[cfg(feature = "onnx")]
+use std::path::PathBuf;
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_BATCH_SIZE: usize = 64;
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// Any `/embeddings` endpoint speaking the OpenAI request format.
+    #[default]
+    OpenAi,
+    /// A local ONNX sentence-embedding model, needs the `onnx` feature.
+    Onnx,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedOptions {
+    #[serde(default)]
+    pub provider: Provider,
+    /// Model name for HTTP providers, or a directory holding `model.onnx` and
+    /// `tokenizer.json` for ONNX.
+    pub model: String,
+    /// Defaults to `MASS_EMBED_BASE_URL`, then the OpenAI API.
+    pub base_url: Option<String>,
+    /// Defaults to `MASS_EMBED_API_KEY`, then `OPENAI_API_KEY`.
+    pub api_key: Option<String>,
+    pub batch_size: Option<usize>,
+    pub dimensions: Option<usize>,
+}
+
+impl EmbedOptions {
+    pub fn batch_size(&self) -> usize { self.batch_size.filter(|size| *size > 0).unwrap_or(DEFAULT_BATCH_SIZE) }
+
+    pub fn endpoint(&self) -> String {
+        let base = self
+            .base_url
+            .clone()
+            .or_else(|| std::env::var("MASS_EMBED_BASE_URL").ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        format!("{}/embeddings", base.trim_end_matches('/'))
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Embeds one batch against an OpenAI-compatible endpoint. Rate limits and
+/// server errors are retried with backoff, honouring `Retry-After`.
+pub async fn http_batch(
+    client: &reqwest::Client, options: &EmbedOptions, batch: &[String],
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    let api_key = options
+        .api_key
+        .clone()
+        .or_else(|| std::env::var("MASS_EMBED_API_KEY").ok())
+        .or_else(|| std::env::var("OPENAI_API_KEY").ok());
+
+    let mut body = serde_json::json!({ "model": options.model, "input": batch });
+    if let Some(dimensions) = options.dimensions {
+        body["dimensions"] = dimensions.into();
+    }
+
+    let endpoint = options.endpoint();
+    let mut delay = RETRY_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client.post(&endpoint).json(&body);
+        if let Some(api_key) = &api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let retry = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                let mut data = response.json::<EmbeddingResponse>().await?.data;
+                if data.len() != batch.len() {
+                    return Err(format!(
+                        "{endpoint} returned {} embeddings for {} inputs",
+                        data.len(),
+                        batch.len()
+                    )
+                    .into());
+                }
+
+                data.sort_by_key(|embedding| embedding.index);
+                return Ok(data.into_iter().map(|embedding| embedding.embedding).collect());
+            }
+            Ok(response) if response.status() == 429 || response.status().is_server_error() => {
+                let after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok()?.parse().ok())
+                    .map(Duration::from_secs);
+                (format!("{endpoint} returned {}", response.status()), after)
+            }
+            Ok(response) => {
+                let status = response.status();
+                return Err(format!(
+                    "{endpoint} returned {status}: {}",
+                    response.text().await.unwrap_or_default()
+                )
+                .into());
+            }
+            Err(err) => (err.to_string(), None),
+        };
+
+        if attempt == MAX_ATTEMPTS {
+            return Err(format!("embedding request failed after {MAX_ATTEMPTS} attempts: {}", retry.0).into());
+        }
+
+        tokio::time::sleep(retry.1.unwrap_or(delay)).await;
+        delay *= 2;
+    }
+
+    unreachable!()
+}
+
+/// The model and tokenizer files of an ONNX model directory.
+#[cfg(feature = "onnx")]
+pub fn onnx_files(dir: &Path) -> (PathBuf, PathBuf) { (dir.join("model.onnx"), dir.join("tokenizer.json")) }
+
+#[cfg(feature = "onnx")]
+pub fn onnx_batch(dir: &Path, batch: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error + Send + Sync>> {
+    use ort::{session::Session, value::Tensor};
+    use std::collections::HashMap;
+    use std::sync::{Arc, LazyLock, Mutex};
+
+    type Loaded = Arc<Mutex<(Session, tokenizers::Tokenizer)>>;
+    static MODELS: LazyLock<Mutex<HashMap<PathBuf, Loaded>>> = LazyLock::new(Default::default);
+
+    let loaded = {
+        let mut models = MODELS.lock().unwrap();
+        match models.get(dir) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let (model, tokenizer) = onnx_files(dir);
+                let session = Session::builder()?.commit_from_file(model)?;
+                let tokenizer = tokenizers::Tokenizer::from_file(tokenizer)?;
+                let loaded = Arc::new(Mutex::new((session, tokenizer)));
+                models.insert(dir.to_path_buf(), loaded.clone());
+                loaded
+            }
+        }
+    };
+
+    let mut loaded = loaded.lock().unwrap();
+    let (session, tokenizer) = &mut *loaded;
+
+    let mut tokenizer = tokenizer.clone();
+    tokenizer.with_padding(Some(Default::default()));
+    let encodings = tokenizer.encode_batch(batch.to_vec(), true)?;
+
+    let rows = encodings.len();
+    let len = encodings.first().map_or(0, |encoding| encoding.len());
+    let flat = |field: fn(&tokenizers::Encoding) -> &[u32]| -> Vec<i64> {
+        encodings
+            .iter()
+            .flat_map(|encoding| field(encoding).iter().map(|&v| v as i64))
+            .collect()
+    };
+
+    let ids = flat(tokenizers::Encoding::get_ids);
+    let mask = flat(tokenizers::Encoding::get_attention_mask);
+    let types = flat(tokenizers::Encoding::get_type_ids);
+
+    let mut inputs = vec![
+        ("input_ids", Tensor::from_array(([rows, len], ids))?.into_dyn()),
+        (
+            "attention_mask",
+            Tensor::from_array(([rows, len], mask.clone()))?.into_dyn(),
+        ),
+    ];
+    if session.inputs.iter().any(|input| input.name == "token_type_ids") {
+        inputs.push(("token_type_ids", Tensor::from_array(([rows, len], types))?.into_dyn()));
+    }
+
+    let outputs = session.run(inputs)?;
+    let (shape, hidden) = outputs[0].try_extract_tensor::<f32>()?;
+    let dim = *shape.last().ok_or("model output has no dimensions")? as usize;
+
+    // Mean-pool the token states under the attention mask, then L2-normalize.
+    Ok((0..rows)
+        .map(|row| {
+            let mut pooled = vec![0f32; dim];
+            let mut count = 0f32;
+            for token in 0..len {
+                if mask[row * len + token] == 0 {
+                    continue;
+                }
+                count += 1.0;
+                let offset = (row * len + token) * dim;
+                pooled
+                    .iter_mut()
+                    .zip(&hidden[offset..offset + dim])
+                    .for_each(|(sum, v)| *sum += v);
+            }
+
+            pooled.iter_mut().for_each(|v| *v /= count.max(1.0));
+            let norm = pooled.iter().map(|v| v * v).sum::<f32>().sqrt().max(f32::EPSILON);
+            pooled.iter_mut().for_each(|v| *v /= norm);
+            pooled
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "onnx"))]
+pub fn onnx_batch(_dir: &Path, _batch: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error + Send + Sync>> {
+    Err("local ONNX embeddings need mass built with the `onnx` feature".into())
+}
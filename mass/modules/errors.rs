@@ -0,0 +1,81 @@
+use std::io;
+use std::path::Path;
+
+/// Reading a module from the remote module cache failed. `error.url` is the
+/// module being loaded.
+#[derive(Debug, thiserror::Error, deno_error::JsError)]
+#[class("CacheError")]
+#[property("code" = "ERR_MASS_CACHE")]
+#[property("url" = self.url.clone())]
+#[error("failed to read {url} from the module cache: {source}")]
+pub struct CacheError {
+    pub url: String,
+    #[source]
+    pub source: io::Error,
+}
+
+/// Fetching a URL through the HTTP cache failed, with no cached copy to fall
+/// back on.
+#[derive(Debug, thiserror::Error, deno_error::JsError)]
+pub enum FetchError {
+    /// The request couldn't be sent, or the connection dropped.
+    #[class("RequestError")]
+    #[error("{0}")]
+    Request(reqwest::Error),
+    /// The origin answered with an error status.
+    #[class("HttpError")]
+    #[error("{0}")]
+    Status(reqwest::Error),
+    /// The response body couldn't be read.
+    #[class("ResponseError")]
+    #[error("{0}")]
+    Body(reqwest::Error),
+}
+
+/// A download failed. `error.url` is what was being downloaded.
+#[derive(Debug, thiserror::Error, deno_error::JsError)]
+#[class("DownloadError")]
+#[property("code" = "ERR_MASS_DOWNLOAD")]
+#[property("url" = self.url.clone())]
+#[error("failed to download {url}: {source}")]
+pub struct DownloadError {
+    pub url: String,
+    #[source]
+    pub source: io::Error,
+}
+
+/// Unpacking an archive failed. `error.entry` is the path inside the archive,
+/// empty when the archive itself couldn't be read.
+#[derive(Debug, thiserror::Error, deno_error::JsError)]
+#[class("ExtractError")]
+#[property("code" = "ERR_MASS_EXTRACT")]
+#[property("entry" = self.entry.clone())]
+#[error("failed to extract {entry}: {source}")]
+pub struct ExtractError {
+    pub entry: String,
+    #[source]
+    pub source: io::Error,
+}
+
+/// Walking a repository for analysis failed. `error.file` is the file or
+/// directory it failed on.
+#[derive(Debug, thiserror::Error, deno_error::JsError)]
+#[class("AnalysisError")]
+#[property("code" = "ERR_MASS_ANALYSIS")]
+#[property("file" = self.file.clone())]
+#[error("failed to analyze {file}: {source}")]
+pub struct AnalysisError {
+    pub file: String,
+    #[source]
+    pub source: io::Error,
+}
+
+impl AnalysisError {
+    /// For `map_err`: the error `path` failed with.
+    pub fn at(path: &Path) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Self {
+            file: path.to_string_lossy().into_owned(),
+            source,
+        }
+    }
+}
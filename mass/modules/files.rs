@@ -0,0 +1,59 @@
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Skipped {
+    TooLarge,
+    Binary,
+}
+
+/// A file handed to JS for analysis. `content` is `None` exactly when the file
+/// was `skipped`, and cut to the op's character limit when `truncated`.
+#[derive(Debug, Serialize)]
+pub struct ImportantFile {
+    pub path: String,
+    pub size: u64,
+    pub truncated: bool,
+    pub content: Option<String>,
+    pub skipped: Option<Skipped>,
+}
+
+/// Reads `full_path` as text, reported under `path`. Files above `max_bytes`
+/// and non-UTF-8 files come back skipped, and content longer than `max_chars`
+/// is truncated. `None` when it isn't a regular file.
+pub fn read(path: String, full_path: &Path, max_bytes: u64, max_chars: Option<usize>) -> Option<ImportantFile> {
+    let metadata = std::fs::metadata(full_path)
+        .ok()
+        .filter(|metadata| metadata.is_file())?;
+    let size = metadata.len();
+
+    let skipped = |reason| ImportantFile {
+        path: path.clone(),
+        size,
+        truncated: false,
+        content: None,
+        skipped: Some(reason),
+    };
+
+    if size > max_bytes {
+        return Some(skipped(Skipped::TooLarge));
+    }
+
+    let Ok(mut content) = std::fs::read_to_string(full_path) else {
+        return Some(skipped(Skipped::Binary));
+    };
+
+    let cut = max_chars.and_then(|max_chars| content.char_indices().nth(max_chars).map(|(index, _)| index));
+    if let Some(cut) = cut {
+        content.truncate(cut);
+    }
+
+    Some(ImportantFile {
+        path,
+        size,
+        truncated: cut.is_some(),
+        content: Some(content),
+        skipped: None,
+    })
+}
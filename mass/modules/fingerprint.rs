@@ -0,0 +1,88 @@
+use super::walk::WalkPolicy;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+fn files(root: &Path, policy: &WalkPolicy) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut walker = policy.walk(root);
+
+    while let Some(dir) = walker.next_dir() {
+        files.extend(walker.list(&dir)?.into_iter().map(|file| file.path));
+    }
+
+    Ok(files)
+}
+
+fn hash(path: &Path) -> std::io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(fs::File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// The hashes of `chunk`, in order.
+#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+fn hash_chunk(chunk: &[PathBuf]) -> Vec<std::io::Result<String>> { chunk.iter().map(|path| hash(path)).collect() }
+
+/// The hashes of `chunk`, in order, reading small files through io_uring when
+/// the kernel allows it.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn hash_chunk(chunk: &[PathBuf]) -> Vec<std::io::Result<String>> {
+    use super::uring::{BATCH, Reader};
+
+    let Some(mut reader) = Reader::new() else {
+        return chunk.iter().map(|path| hash(path)).collect();
+    };
+
+    chunk
+        .chunks(BATCH)
+        .flat_map(|batch| {
+            let read = reader.read(batch);
+            read.into_iter().zip(batch).map(|(read, path)| match read? {
+                Some(bytes) => Ok(blake3::hash(&bytes).to_hex().to_string()),
+                None => hash(path),
+            })
+        })
+        .collect()
+}
+
+/// The blake3 hash of every file under `root`, keyed by its `/`-separated
+/// path relative to `root`, hashed on as many threads as there are CPUs.
+/// Files of any size are hashed; `policy` only decides which are walked.
+pub fn fingerprint(root: &Path, policy: &WalkPolicy) -> std::io::Result<BTreeMap<String, String>> {
+    let files = files(root, policy)?;
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk = files.len().div_ceil(threads).max(1);
+    let hashes = Mutex::new(BTreeMap::new());
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(chunk)
+            .map(|chunk| {
+                let hashes = &hashes;
+                scope.spawn(move || {
+                    for (path, hash) in chunk.iter().zip(hash_chunk(chunk)) {
+                        // Deleted since it was listed.
+                        let hash = match hash {
+                            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                            hash => hash?,
+                        };
+                        let relative = path.strip_prefix(root).unwrap_or(path);
+                        let relative = relative.to_string_lossy().replace('\\', "/");
+                        hashes.lock().unwrap().insert(relative, hash);
+                    }
+                    Ok::<_, std::io::Error>(())
+                })
+            })
+            .collect();
+
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| Err(std::io::Error::other("hashing panicked")))
+        })
+    })?;
+
+    Ok(hashes.into_inner().unwrap())
+}
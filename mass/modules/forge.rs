@@ -0,0 +1,348 @@
+use super::quota::{Connection, RateLimiter};
+use flate2::read::GzDecoder;
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tar::Archive;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Longest wait for an exhausted rate limit to reset before giving up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Hosts repositories can be fetched from as an archive.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    GitHub,
+    GitLab,
+    Bitbucket,
+}
+
+impl Forge {
+    fn default_api(self) -> &'static str {
+        match self {
+            Forge::GitHub => "https://api.github.com",
+            Forge::GitLab => "https://gitlab.com/api/v4",
+            Forge::Bitbucket => "https://api.bitbucket.org/2.0",
+        }
+    }
+
+    fn token_var(self) -> &'static str {
+        match self {
+            Forge::GitHub => "GITHUB_TOKEN",
+            Forge::GitLab => "GITLAB_TOKEN",
+            Forge::Bitbucket => "BITBUCKET_TOKEN",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Forge::GitHub => "GitHub",
+            Forge::GitLab => "GitLab",
+            Forge::Bitbucket => "Bitbucket",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchOptions {
+    /// Defaults to `GITHUB_TOKEN`, `GITLAB_TOKEN` or `BITBUCKET_TOKEN` from
+    /// the environment.
+    pub token: Option<String>,
+    /// API root, for self-hosted instances.
+    pub api_url: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Unix time, in seconds, the window resets at.
+    pub reset: Option<u64>,
+}
+
+impl RateLimit {
+    /// GitHub and Bitbucket send `X-RateLimit-*`, GitLab `RateLimit-*`.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let number = |name: &str| {
+            [format!("x-ratelimit-{name}"), format!("ratelimit-{name}")]
+                .iter()
+                .find_map(|name| headers.get(name))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+        };
+
+        Self {
+            limit: number("limit"),
+            remaining: number("remaining"),
+            reset: number("reset"),
+        }
+    }
+
+    fn until_reset(&self) -> Option<Duration> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(Duration::from_secs(self.reset?).saturating_sub(now))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchedRepo {
+    pub path: PathBuf,
+    pub provider: Forge,
+    pub owner: String,
+    pub repo: String,
+    /// The ref asked for, or the default branch when none was.
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Full commit the ref resolved to, which is what was downloaded.
+    pub commit: String,
+    pub archive_bytes: u64,
+    pub rate_limit: RateLimit,
+}
+
+/// GitLab owners may be nested groups, everything else is a single segment.
+fn valid_name(forge: Forge, name: &str) -> bool {
+    let segment = |segment: &str| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+
+    match forge {
+        Forge::GitLab => name.split('/').all(segment),
+        _ => segment(name),
+    }
+}
+
+struct Client {
+    forge: Forge,
+    api: String,
+    token: Option<String>,
+    quota: RateLimiter,
+    /// The connection the last response came over, held while its body is read.
+    connection: Option<Connection>,
+    rate_limit: RateLimit,
+}
+
+impl Client {
+    fn new(forge: Forge, options: &FetchOptions, quota: RateLimiter) -> Self {
+        let token = options
+            .token
+            .clone()
+            .or_else(|| std::env::var(forge.token_var()).ok())
+            .filter(|token| !token.is_empty());
+
+        Self {
+            forge,
+            api: api_url(forge, options).trim_end_matches('/').to_string(),
+            token,
+            quota,
+            connection: None,
+            rate_limit: RateLimit::default(),
+        }
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<reqwest::Url, BoxError> {
+        let mut url = reqwest::Url::parse(&self.api)?;
+        url.path_segments_mut()
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid {} API url", self.forge.name()),
+                )
+            })?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// GETs `url`, waiting out an exhausted rate limit once when it resets soon.
+    async fn get(&mut self, url: reqwest::Url, accept: &str) -> Result<reqwest::Response, BoxError> {
+        let mut waited = false;
+        loop {
+            // Let go of the last one first, or a cap of one could never be met.
+            self.connection = None;
+            let connection = self.quota.connect(&url).await;
+            let mut request = connection
+                .client()
+                .get(url.clone())
+                .header(USER_AGENT, concat!("mass/", env!("CARGO_PKG_VERSION")))
+                .header(ACCEPT, accept);
+            if let Some(token) = &self.token {
+                request = match self.forge {
+                    Forge::GitLab => request.header("PRIVATE-TOKEN", token),
+                    _ => request.header(AUTHORIZATION, format!("Bearer {token}")),
+                };
+            }
+
+            let response = request.send().await?;
+            self.connection = Some(connection);
+            let status = response.status();
+            self.rate_limit = RateLimit::from_headers(response.headers());
+
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let limited = status.as_u16() == 429 || (status.as_u16() == 403 && self.rate_limit.remaining == Some(0));
+            if limited
+                && !waited
+                && let Some(wait) = self
+                    .rate_limit
+                    .until_reset()
+                    .filter(|wait| *wait <= MAX_RATE_LIMIT_WAIT)
+            {
+                waited = true;
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            let forge = self.forge.name();
+            let body = response.text().await.unwrap_or_default();
+            return Err(match (limited, self.rate_limit.reset) {
+                (true, Some(reset)) => format!(
+                    "{forge} rate limit exhausted until {reset}, set {} to raise it",
+                    self.forge.token_var()
+                ),
+                _ => format!("{forge} returned {status} for {url}: {}", body.trim()),
+            }
+            .into());
+        }
+    }
+
+    async fn json(&mut self, segments: &[&str]) -> Result<serde_json::Value, BoxError> {
+        let url = self.url(segments)?;
+        Ok(self.get(url, "application/json").await?.json().await?)
+    }
+
+    /// The default branch of `owner/repo`.
+    async fn default_branch(&mut self, owner: &str, repo: &str) -> Result<String, BoxError> {
+        let project = format!("{owner}/{repo}");
+        let (segments, field): (Vec<&str>, &[&str]) = match self.forge {
+            Forge::GitHub => (vec!["repos", owner, repo], &["default_branch"]),
+            Forge::GitLab => (vec!["projects", &project], &["default_branch"]),
+            Forge::Bitbucket => (vec!["repositories", owner, repo], &["mainbranch", "name"]),
+        };
+
+        let meta = self.json(&segments).await?;
+        field
+            .iter()
+            .try_fold(&meta, |value, key| value.get(key))
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} did not report a default branch for {project}", self.forge.name()).into())
+    }
+
+    /// The commit `reference` points at in `owner/repo`.
+    async fn resolve(&mut self, owner: &str, repo: &str, reference: &str) -> Result<String, BoxError> {
+        let project = format!("{owner}/{repo}");
+        let (segments, field): (Vec<&str>, &str) = match self.forge {
+            Forge::GitHub => (vec!["repos", owner, repo, "commits", reference], "sha"),
+            Forge::GitLab => (vec!["projects", &project, "repository", "commits", reference], "id"),
+            Forge::Bitbucket => (vec!["repositories", owner, repo, "commit", reference], "hash"),
+        };
+
+        let commit = self.json(&segments).await?;
+        commit
+            .get(field)
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} could not resolve {reference} in {project}", self.forge.name()).into())
+    }
+
+    /// The gzipped tarball of `owner/repo` at `commit`.
+    async fn archive(&mut self, owner: &str, repo: &str, commit: &str) -> Result<Vec<u8>, BoxError> {
+        let url = match self.forge {
+            Forge::GitHub => self.url(&["repos", owner, repo, "tarball", commit])?,
+            Forge::GitLab => {
+                let project = format!("{owner}/{repo}");
+                let mut url = self.url(&["projects", &project, "repository", "archive.tar.gz"])?;
+                url.query_pairs_mut().append_pair("sha", commit);
+                url
+            }
+            // Bitbucket serves archives from the site, not the API.
+            Forge::Bitbucket => {
+                let site = match self.api.strip_suffix("/2.0") {
+                    Some(api) => api.replacen("://api.", "://", 1),
+                    None => self.api.clone(),
+                };
+                reqwest::Url::parse(&format!("{site}/{owner}/{repo}/get/{commit}.tar.gz"))?
+            }
+        };
+
+        Ok(self.get(url, "application/octet-stream").await?.bytes().await?.to_vec())
+    }
+}
+
+/// The API root used for `forge`.
+pub fn api_url(forge: Forge, options: &FetchOptions) -> &str {
+    options.api_url.as_deref().unwrap_or(forge.default_api())
+}
+
+/// The API host, for the audit log.
+pub fn api_host(forge: Forge, options: &FetchOptions) -> String {
+    let api = api_url(forge, options);
+    reqwest::Url::parse(api)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| api.to_string())
+}
+
+/// Resolves `reference` (the default branch when unset) to a commit and
+/// downloads the archive of that commit. Returns the ref, the commit, the
+/// archive and the rate limit left afterwards.
+pub async fn download(
+    forge: Forge, owner: &str, repo: &str, reference: Option<&str>, options: &FetchOptions, quota: RateLimiter,
+) -> Result<(String, String, Vec<u8>, RateLimit), BoxError> {
+    if !valid_name(forge, owner) || !valid_name(Forge::GitHub, repo) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid repository {owner}/{repo}")).into());
+    }
+
+    let mut client = Client::new(forge, options, quota);
+    let reference = match reference {
+        Some(reference) => reference.to_string(),
+        None => client.default_branch(owner, repo).await?,
+    };
+    let commit = client.resolve(owner, repo, &reference).await?;
+    let archive = client.archive(owner, repo, &commit).await?;
+
+    Ok((reference, commit, archive, client.rate_limit))
+}
+
+/// Unpacks a forge tarball into `dest`, dropping the single top-level
+/// directory every host nests the tree under.
+pub fn extract(archive: &[u8], dest: &Path) -> std::io::Result<()> {
+    let mut staging = dest.as_os_str().to_owned();
+    staging.push(".unpack");
+    let staging = PathBuf::from(staging);
+
+    let result = Archive::new(GzDecoder::new(archive)).unpack(&staging).and_then(|()| {
+        let top = std::fs::read_dir(&staging)?
+            .filter_map(Result::ok)
+            .find(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "archive has no top-level directory"))?;
+
+        std::fs::rename(top.path(), dest)
+    });
+
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// A fresh directory for a checkout of `owner/repo` under `base`.
+pub fn checkout_dir(base: &Path, owner: &str, repo: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let owner = owner.replace('/', "-");
+    base.join(format!("mass-{owner}-{repo}-{}-{nanos:x}", std::process::id()))
+}
@@ -0,0 +1,213 @@
+use super::cache::{self, Entry};
+use super::errors::FetchError;
+use super::quota::RateLimiter;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long what's in the cache can be used without asking the origin again.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Policy {
+    /// Seconds a response that doesn't give a `max-age` of its own stays
+    /// fresh. `None`, which modules are loaded with, keeps it fresh for as
+    /// long as it's cached.
+    pub max_age: Option<u64>,
+}
+
+/// How long a cached response is fresh for, from the `Cache-Control` it was
+/// stored with.
+enum Lifetime {
+    Forever,
+    For(Duration),
+    Revalidate,
+}
+
+impl Policy {
+    fn lifetime(&self, headers: &BTreeMap<String, String>) -> Lifetime {
+        let default = match self.max_age {
+            Some(secs) => Lifetime::For(Duration::from_secs(secs)),
+            None => Lifetime::Forever,
+        };
+        let Some(cache_control) = headers.get("cache-control") else {
+            return default;
+        };
+
+        let mut max_age = None;
+        for directive in cache_control.split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                None if directive == "no-cache" || directive == "no-store" => return Lifetime::Revalidate,
+                None if directive == "immutable" => return Lifetime::Forever,
+                Some(("max-age", secs)) => max_age = secs.trim_matches('"').parse().ok(),
+                _ => {}
+            }
+        }
+
+        max_age.map_or(default, |secs| Lifetime::For(Duration::from_secs(secs)))
+    }
+
+    fn is_fresh(&self, entry: &Entry) -> bool {
+        match self.lifetime(&entry.headers) {
+            Lifetime::Forever => true,
+            Lifetime::For(max_age) => cache::age(entry) < max_age,
+            Lifetime::Revalidate => false,
+        }
+    }
+}
+
+/// Where a [`fetch`]ed response came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    /// Cached and still fresh, the origin wasn't asked.
+    Fresh,
+    /// Cached, and the origin answered 304 Not Modified.
+    Revalidated,
+    /// Cached but stale, served because the origin couldn't be reached or
+    /// answered with a server error.
+    Stale,
+    /// Downloaded and written to the cache.
+    Fetched,
+    /// Downloaded, but writing it to the cache failed.
+    Uncached,
+}
+
+pub enum Body {
+    /// The cached copy on disk.
+    Cached(PathBuf),
+    /// What was just downloaded.
+    Fetched(Vec<u8>),
+}
+
+pub struct Response {
+    /// Where the response was found, after redirects.
+    pub url: Url,
+    pub status: Status,
+    pub body: Body,
+}
+
+/// A [`Response`] as `MASS.cache.fetch` hands it to JS.
+#[derive(Debug, Serialize)]
+pub struct Text {
+    pub url: String,
+    pub status: Status,
+    /// The body, decoded as UTF-8.
+    pub body: String,
+}
+
+impl Response {
+    fn cached(url: &Url, path: PathBuf, entry: Entry, status: Status) -> Self {
+        Self {
+            url: entry.final_url.unwrap_or_else(|| url.clone()),
+            status,
+            body: Body::Cached(path),
+        }
+    }
+
+    pub async fn text(self) -> std::io::Result<Text> {
+        let body = match self.body {
+            Body::Cached(path) => tokio::fs::read(path).await?,
+            Body::Fetched(body) => body,
+        };
+
+        Ok(Text {
+            url: self.url.to_string(),
+            status: self.status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+fn headers(res: &reqwest::Response) -> BTreeMap<String, String> {
+    res.headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Falls back to the stale copy of `url` when there is one, rather than
+/// failing with `err`.
+fn stale(url: &Url, cached: Option<(PathBuf, Entry)>, err: FetchError) -> Result<Response, FetchError> {
+    let Some((path, entry)) = cached else {
+        return Err(err);
+    };
+
+    eprintln!("MASS serving stale {url} from the cache: {err}");
+    Ok(Response::cached(url, path, entry, Status::Stale))
+}
+
+/// GETs `url` through the module cache, the one layer both the module loader
+/// and cached fetches from JS go through. A fresh copy is served from disk; a
+/// stale one is revalidated with the ETag and Last-Modified it was stored
+/// with, and only downloaded again when the origin says it changed.
+/// `on_request` is called before anything is sent to the origin.
+pub async fn fetch(
+    url: &Url, policy: Policy, quota: &RateLimiter, on_request: impl FnOnce(),
+) -> Result<Response, FetchError> {
+    let mut cached = cache::locate(url).map(|path| (path, cache::entry(url).ok().flatten().unwrap_or_default()));
+    if let Some((path, entry)) = cached.as_ref()
+        && policy.is_fresh(entry)
+    {
+        return Ok(Response::cached(url, path.clone(), entry.clone(), Status::Fresh));
+    }
+
+    on_request();
+    let connection = quota.connect(url).await;
+    let mut request = connection.client().get(url.clone());
+    if let Some((_, entry)) = &cached {
+        if let Some(etag) = entry.headers.get(ETAG.as_str()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = entry.headers.get(LAST_MODIFIED.as_str()) {
+            request = request.header(IF_MODIFIED_SINCE, modified);
+        }
+    }
+
+    let res = match request.send().await {
+        Ok(res) => res,
+        Err(err) => return stale(url, cached, FetchError::Request(err)),
+    };
+
+    if res.status() == StatusCode::NOT_MODIFIED
+        && let Some((path, mut entry)) = cached.take()
+    {
+        entry.headers.extend(headers(&res));
+        drop(connection);
+        if let Err(err) = cache::revalidated(url, &entry.headers) {
+            eprintln!("cache write failed for {url}: {err}");
+        }
+        return Ok(Response::cached(url, path, entry, Status::Revalidated));
+    }
+
+    let res = match res.error_for_status() {
+        Ok(res) => res,
+        Err(err) if err.status().is_some_and(|status| status.is_server_error()) => {
+            return stale(url, cached, FetchError::Status(err));
+        }
+        Err(err) => return Err(FetchError::Status(err)),
+    };
+
+    let final_url = res.url().clone();
+    let headers = headers(&res);
+    let body = res.bytes().await.map_err(FetchError::Body)?.to_vec();
+    drop(connection);
+
+    let redirect = (final_url != *url).then(|| final_url.clone());
+    let status = match cache::cache_url(url, redirect.as_ref(), &headers, &body).await {
+        Ok(_) => Status::Fetched,
+        Err(err) => {
+            eprintln!("cache write failed for {url}: {err}");
+            Status::Uncached
+        }
+    };
+
+    Ok(Response {
+        url: final_url,
+        status,
+        body: Body::Fetched(body),
+    })
+}
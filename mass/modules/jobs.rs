@@ -0,0 +1,265 @@
+use super::data::DataDir;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::io::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    backoff_ms INTEGER NOT NULL,
+    max_backoff_ms INTEGER NOT NULL,
+    run_at INTEGER NOT NULL,
+    lease_until INTEGER,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS jobs_ready ON jobs (queue, status, run_at);
+";
+
+/// How a job is retried when it fails: up to `max_attempts` runs in total,
+/// waiting `backoff_ms` doubled after every failure, capped at
+/// `max_backoff_ms`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff_ms: 1_000,
+            max_backoff_ms: 300_000,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EnqueueOptions {
+    /// Hold the job back this long before it can be claimed.
+    pub delay_ms: u64,
+    #[serde(flatten)]
+    pub retry: RetryPolicy,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: i64,
+    pub queue: String,
+    pub payload: serde_json::Value,
+    /// Runs so far, including the one just claimed.
+    pub attempts: u32,
+    pub max_attempts: u32,
+    /// Unix time in milliseconds the claim expires at, after which another
+    /// worker may claim the job again.
+    pub lease_until: i64,
+    /// Identifies this claim. `ack` and `fail` need it, so a worker whose
+    /// lease ran out can't settle the job for whoever claimed it next.
+    pub lease: u32,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Failed {
+    /// Scheduled to run again at `run_at`, Unix time in milliseconds.
+    Retrying { run_at: i64 },
+    /// Out of attempts, kept for inspection but never claimed again.
+    Dead,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Durable work queue in `<data>/queue/jobs.sqlite`. A claim is a lease: a worker
+/// that dies before acking leaves the job to be claimed again once the lease
+/// runs out, so nothing queued is lost to a restart.
+pub struct JobQueue {
+    data: DataDir,
+    db: OnceCell<Connection>,
+}
+
+impl JobQueue {
+    pub fn new(data: DataDir) -> Self {
+        Self {
+            data,
+            db: OnceCell::new(),
+        }
+    }
+
+    fn db(&self) -> std::io::Result<&Connection> {
+        if let Some(db) = self.db.get() {
+            return Ok(db);
+        }
+
+        let path = self.data.join("queue")?.join("jobs.sqlite");
+        let db = Connection::open(path).map_err(Error::other)?;
+        db.busy_timeout(std::time::Duration::from_secs(5))
+            .and_then(|()| db.pragma_update(None, "journal_mode", "WAL"))
+            .and_then(|()| db.execute_batch(SCHEMA))
+            .map_err(Error::other)?;
+
+        Ok(self.db.get_or_init(|| db))
+    }
+
+    pub fn enqueue(&self, queue: &str, payload: &serde_json::Value, options: EnqueueOptions) -> std::io::Result<i64> {
+        let db = self.db()?;
+        let now = now();
+        let retry = options.retry;
+
+        db.execute(
+            "INSERT INTO jobs (queue, payload, status, max_attempts, backoff_ms, max_backoff_ms, run_at, created_at, updated_at)
+             VALUES (?1, ?2, 'pending', ?3, ?4, ?5, ?6, ?7, ?7)",
+            params![
+                queue,
+                payload.to_string(),
+                retry.max_attempts.max(1),
+                retry.backoff_ms as i64,
+                retry.max_backoff_ms as i64,
+                now + options.delay_ms as i64,
+                now
+            ],
+        )
+        .map_err(Error::other)?;
+
+        Ok(db.last_insert_rowid())
+    }
+
+    /// Leases the oldest job of `queue` that is due, or whose previous lease
+    /// expired, for `lease_ms`. An expired lease on a job's last attempt
+    /// counts as a failed run, so the job goes dead instead.
+    pub fn claim(&self, queue: &str, lease_ms: u64) -> std::io::Result<Option<Job>> {
+        let db = self.db()?;
+        let now = now();
+        let lease_until = now + lease_ms as i64;
+
+        db.execute(
+            "UPDATE jobs SET status = 'dead', lease_until = NULL, last_error = 'lease expired', updated_at = ?2
+             WHERE queue = ?1 AND status = 'claimed' AND lease_until <= ?2 AND attempts >= max_attempts",
+            params![queue, now],
+        )
+        .map_err(Error::other)?;
+
+        // Every claim bumps `attempts`, which makes it the lease token.
+        let row = db
+            .query_row(
+                "UPDATE jobs SET status = 'claimed', attempts = attempts + 1, lease_until = ?3, updated_at = ?2
+                 WHERE id = (
+                     SELECT id FROM jobs
+                     WHERE queue = ?1
+                       AND ((status = 'pending' AND run_at <= ?2) OR (status = 'claimed' AND lease_until <= ?2))
+                     ORDER BY run_at, id
+                     LIMIT 1
+                 )
+                 RETURNING id, payload, attempts, max_attempts, last_error",
+                params![queue, now, lease_until],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u32>(2)?,
+                        row.get::<_, u32>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(Error::other)?;
+
+        let Some((id, payload, attempts, max_attempts, last_error)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(Job {
+            id,
+            queue: queue.to_string(),
+            payload: serde_json::from_str(&payload).map_err(Error::other)?,
+            attempts,
+            max_attempts,
+            lease_until,
+            lease: attempts,
+            last_error,
+        }))
+    }
+
+    /// Marks a claimed job done. `false` when `lease` no longer holds it, e.g.
+    /// because the lease expired and someone else took it.
+    pub fn ack(&self, id: i64, lease: u32) -> std::io::Result<bool> {
+        let updated = self
+            .db()?
+            .execute(
+                "UPDATE jobs SET status = 'done', lease_until = NULL, updated_at = ?2
+                 WHERE id = ?1 AND status = 'claimed' AND attempts = ?3",
+                params![id, now(), lease],
+            )
+            .map_err(Error::other)?;
+        Ok(updated > 0)
+    }
+
+    /// Records a failed run and schedules the next attempt by the job's retry
+    /// policy. `None` when `lease` no longer holds the job.
+    pub fn fail(&self, id: i64, lease: u32, error: &str) -> std::io::Result<Option<Failed>> {
+        let db = self.db()?;
+        let policy = db
+            .query_row(
+                "SELECT attempts, max_attempts, backoff_ms, max_backoff_ms FROM jobs
+                 WHERE id = ?1 AND status = 'claimed' AND attempts = ?2",
+                params![id, lease],
+                |row| {
+                    Ok((
+                        row.get::<_, u32>(0)?,
+                        row.get::<_, u32>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(Error::other)?;
+
+        let Some((attempts, max_attempts, backoff_ms, max_backoff_ms)) = policy else {
+            return Ok(None);
+        };
+
+        let now = now();
+        if attempts >= max_attempts {
+            db.execute(
+                "UPDATE jobs SET status = 'dead', lease_until = NULL, last_error = ?2, updated_at = ?3
+                 WHERE id = ?1 AND status = 'claimed' AND attempts = ?4",
+                params![id, error, now, lease],
+            )
+            .map_err(Error::other)?;
+            return Ok(Some(Failed::Dead));
+        }
+
+        let backoff = backoff_ms
+            .saturating_mul(1 << attempts.saturating_sub(1).min(32))
+            .min(max_backoff_ms);
+        let run_at = now + backoff;
+        db.execute(
+            "UPDATE jobs SET status = 'pending', lease_until = NULL, last_error = ?2, run_at = ?3, updated_at = ?4
+             WHERE id = ?1 AND status = 'claimed' AND attempts = ?5",
+            params![id, error, run_at, now, lease],
+        )
+        .map_err(Error::other)?;
+
+        Ok(Some(Failed::Retrying { run_at }))
+    }
+}
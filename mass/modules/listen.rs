@@ -0,0 +1,156 @@
+#[cfg(runtime)]
+use serde::Deserialize;
+use serde::Serialize;
+#[cfg(runtime)]
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+#[cfg(runtime)]
+use std::str::FromStr;
+
+/// Where the JS server accepts connections, from `--listen`.
+#[cfg(runtime)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listen {
+    /// `[hostname:]port`, bound by `Deno.serve` itself.
+    Tcp { hostname: String, port: u16 },
+    /// `unix:PATH`
+    Unix(PathBuf),
+    /// `systemd`: the first socket passed in by systemd socket activation.
+    Systemd,
+}
+
+#[cfg(runtime)]
+impl Default for Listen {
+    fn default() -> Self {
+        Self::Tcp {
+            hostname: "0.0.0.0".to_string(),
+            port: 8080,
+        }
+    }
+}
+
+#[cfg(runtime)]
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "systemd" {
+            return Ok(Self::Systemd);
+        }
+        if let Some(path) = value.strip_prefix("unix:") {
+            return match path {
+                "" => Err("unix: needs a socket path".to_string()),
+                path => Ok(Self::Unix(path.into())),
+            };
+        }
+
+        let (hostname, port) = match value.rsplit_once(':') {
+            Some((hostname, port)) => (hostname.trim_matches(['[', ']']).to_string(), port),
+            None => ("0.0.0.0".to_string(), value),
+        };
+        let port = port
+            .parse()
+            .map_err(|_| format!("expected [hostname:]port, unix:PATH or systemd, got {value:?}"))?;
+
+        Ok(Self::Tcp { hostname, port })
+    }
+}
+
+#[cfg(runtime)]
+impl Listen {
+    /// Sockets the host accepts on and forwards to the worker, so they survive
+    /// worker restarts and reloads.
+    pub fn is_proxied(&self) -> bool { !matches!(self, Self::Tcp { .. }) }
+
+    /// Options for `Deno.serve`. Proxied listeners never reach a worker; it's
+    /// handed the private socket the host forwards to instead.
+    pub fn serve_options(&self, tls: Option<Tls>) -> ServeOptions {
+        match self {
+            Self::Tcp { hostname, port } => ServeOptions {
+                hostname: Some(hostname.clone()),
+                port: Some(*port),
+                cert: tls.as_ref().map(|tls| tls.cert.clone()),
+                key: tls.map(|tls| tls.key),
+                ..Default::default()
+            },
+            Self::Unix(path) => ServeOptions {
+                transport: Some("unix"),
+                path: Some(path.clone()),
+                ..Default::default()
+            },
+            Self::Systemd => ServeOptions::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ServeOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// `[tls]` in mass.toml. The certificate chain and private key each come from
+/// a PEM file or inline PEM; files are re-read whenever a worker boots, so a
+/// renewed certificate is picked up on `SIGHUP`.
+#[cfg(runtime)]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub cert_pem: Option<String>,
+    pub key_pem: Option<String>,
+}
+
+#[cfg(runtime)]
+#[derive(Clone, Debug)]
+pub struct Tls {
+    pub cert: String,
+    pub key: String,
+}
+
+#[cfg(runtime)]
+fn pem(file: &Option<PathBuf>, inline: &Option<String>, what: &str) -> std::io::Result<Option<String>> {
+    match (file, inline) {
+        (Some(_), Some(_)) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("tls: set either {what} or {what}_pem, not both"),
+        )),
+        (Some(path), None) => std::fs::read_to_string(path)
+            .map(Some)
+            .map_err(|err| Error::new(err.kind(), format!("tls {what} {}: {err}", path.display()))),
+        (None, inline) => Ok(inline.clone()),
+    }
+}
+
+#[cfg(runtime)]
+impl TlsConfig {
+    pub fn is_set(&self) -> bool {
+        self.cert.is_some() || self.key.is_some() || self.cert_pem.is_some() || self.key_pem.is_some()
+    }
+
+    /// The PEM to serve with, `None` when TLS isn't configured.
+    pub fn load(&self) -> std::io::Result<Option<Tls>> {
+        match (
+            pem(&self.cert, &self.cert_pem, "cert")?,
+            pem(&self.key, &self.key_pem, "key")?,
+        ) {
+            (Some(cert), Some(key)) => Ok(Some(Tls { cert, key })),
+            (None, None) => Ok(None),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "tls needs both a certificate and a key",
+            )),
+        }
+    }
+}
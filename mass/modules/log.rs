@@ -0,0 +1,72 @@
+use super::context::RequestContext;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    timestamp: u128,
+    level: &'a str,
+    isolate: u64,
+    message: &'a str,
+    #[serde(flatten)]
+    context: Option<&'a RequestContext>,
+}
+
+/// Destination for structured console records. Every isolate writes through its own
+/// `LogSink` but the underlying writer is shared, so lines never interleave.
+#[derive(Clone, Default)]
+pub struct LogSink {
+    isolate: u64,
+    writer: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+}
+
+impl std::fmt::Debug for LogSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogSink").field("isolate", &self.isolate).finish()
+    }
+}
+
+impl LogSink {
+    #[cfg(runtime)]
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            isolate: 0,
+            writer: Some(Arc::new(Mutex::new(writer))),
+        }
+    }
+
+    #[cfg(runtime)]
+    pub fn for_isolate(&self, isolate: u64) -> Self {
+        Self {
+            isolate,
+            writer: self.writer.clone(),
+        }
+    }
+
+    #[cfg(runtime)]
+    pub fn is_enabled(&self) -> bool { self.writer.is_some() }
+
+    pub fn write(&self, level: &str, message: &str, context: Option<&RequestContext>) -> std::io::Result<()> {
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+
+        let record = LogRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            level,
+            isolate: self.isolate,
+            message,
+            context,
+        };
+
+        let mut writer = writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
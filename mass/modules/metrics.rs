@@ -0,0 +1,158 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct OpTiming {
+    pub calls: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+const HISTORY_LEN: usize = 32;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OpCall {
+    pub name: &'static str,
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, Default)]
+struct Timings {
+    ops: BTreeMap<&'static str, OpTiming>,
+    current: Option<(&'static str, Instant)>,
+    history: VecDeque<OpCall>,
+}
+
+/// Cumulative wall-clock timings for the stardust ops, keyed by op name, plus the
+/// op currently on the stack so a watchdog on another thread can name it.
+#[derive(Clone, Debug, Default)]
+pub struct OpTimings(Arc<Mutex<Timings>>);
+
+pub struct OpTimer {
+    name: &'static str,
+    started: Instant,
+    timings: OpTimings,
+}
+
+impl OpTimings {
+    pub fn start(&self, name: &'static str) -> OpTimer {
+        let started = Instant::now();
+        self.0.lock().unwrap().current = Some((name, started));
+
+        OpTimer {
+            name,
+            started,
+            timings: self.clone(),
+        }
+    }
+
+    pub fn record(&self, name: &'static str, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let mut timings = self.0.lock().unwrap();
+        let timing = timings.ops.entry(name).or_default();
+
+        timing.calls += 1;
+        timing.total_ms += ms;
+        timing.max_ms = timing.max_ms.max(ms);
+        timings.current = None;
+
+        if timings.history.len() == HISTORY_LEN {
+            timings.history.pop_front();
+        }
+        timings.history.push_back(OpCall { name, duration_ms: ms });
+    }
+
+    pub fn current(&self) -> Option<(&'static str, Duration)> {
+        let timings = self.0.lock().unwrap();
+        timings.current.map(|(name, started)| (name, started.elapsed()))
+    }
+
+    /// [`Self::current`] for the panic hook, which may fire while the lock is held;
+    /// `None` when it is.
+    #[cfg(runtime)]
+    pub fn try_current(&self) -> Option<(&'static str, Duration)> {
+        let timings = self.0.try_lock().ok()?;
+        timings.current.map(|(name, started)| (name, started.elapsed()))
+    }
+
+    /// The most recent op calls, oldest first. Uses `try_lock` because it is read from
+    /// the panic hook, which may fire while the lock is held.
+    #[cfg(runtime)]
+    pub fn history(&self) -> Vec<OpCall> {
+        match self.0.try_lock() {
+            Ok(timings) => timings.history.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, OpTiming> { self.0.lock().unwrap().ops.clone() }
+}
+
+impl Drop for OpTimer {
+    fn drop(&mut self) { self.timings.record(self.name, self.started.elapsed()); }
+}
+
+/// Last time the JS event loop got around to running a timer callback.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    epoch: Instant,
+    last: Arc<AtomicU64>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            last: Default::default(),
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.last
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(runtime)]
+    pub fn lag(&self) -> Duration {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.epoch.elapsed().saturating_sub(last)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResourceUsage {
+    pub rss: Option<u64>,
+    pub open_fds: Option<u64>,
+    pub heap: HeapUsage,
+    pub ops: BTreeMap<&'static str, OpTiming>,
+}
+
+/// V8's heap statistics, in bytes.
+#[derive(Serialize, Debug)]
+pub struct HeapUsage {
+    pub total: usize,
+    pub used: usize,
+    pub limit: usize,
+    pub external: usize,
+}
+
+pub fn resident_set_size() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+pub fn open_file_descriptors() -> Option<u64> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else {
+        "/dev/fd"
+    };
+    Some(std::fs::read_dir(dir).ok()?.count() as u64)
+}
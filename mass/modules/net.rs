@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+/// Egress accounting for the JS `fetch()` interceptor. Outbound body bytes are
+/// charged against a one second window; once the window is spent the caller is told
+/// how long to wait before sending.
+#[derive(Clone, Debug)]
+pub struct NetPolicy {
+    max_egress: Option<u64>,
+    window_start: Instant,
+    window_sent: u64,
+}
+
+impl Default for NetPolicy {
+    fn default() -> Self { Self::new(None) }
+}
+
+impl NetPolicy {
+    pub fn new(max_egress: Option<u64>) -> Self {
+        Self {
+            max_egress,
+            window_start: Instant::now(),
+            window_sent: 0,
+        }
+    }
+
+    pub fn charge(&mut self, bytes: u64) -> Duration {
+        let Some(limit) = self.max_egress.filter(|limit| *limit > 0) else {
+            return Duration::ZERO;
+        };
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.window_sent = 0;
+        }
+
+        self.window_sent += bytes;
+        if self.window_sent <= limit {
+            return Duration::ZERO;
+        }
+
+        let overflow = self.window_sent - limit;
+        let windows = overflow.div_ceil(limit);
+        Duration::from_secs(windows).saturating_sub(self.window_start.elapsed())
+    }
+}
@@ -0,0 +1,106 @@
+use deno_core::{Extension, ExtensionFileSource};
+#[cfg(runtime)]
+use serde::Deserialize;
+use std::borrow::Cow;
+#[cfg(runtime)]
+use std::collections::HashSet;
+#[cfg(runtime)]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A `[[plugins]]` entry in mass.toml: ES modules evaluated in every isolate
+/// after mass's own runtime, so they can build on its ops and extend `MASS`.
+#[cfg(runtime)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginManifest {
+    pub name: String,
+    /// The module evaluated at startup.
+    pub entry: PathBuf,
+    /// Further modules the entry can import as `ext:<name>/<file name>`.
+    #[serde(default)]
+    pub modules: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(not(runtime), allow(dead_code))]
+struct Plugin {
+    name: &'static str,
+    /// Specifier and source of each module, the entry point first.
+    modules: Vec<(&'static str, Arc<str>)>,
+}
+
+/// Every plugin an isolate is extended with, read once when the server starts.
+#[derive(Clone, Debug, Default)]
+pub struct Plugins {
+    manifests: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Reads the modules of each manifest. Names and specifiers live for the
+    /// rest of the process, as deno_core wants them `'static`.
+    #[cfg(runtime)]
+    pub fn load(manifests: &[PluginManifest]) -> std::io::Result<Self> {
+        let mut names = HashSet::new();
+        let mut plugins = Vec::new();
+
+        for manifest in manifests {
+            if !names.insert(manifest.name.as_str()) {
+                return Err(std::io::Error::other(format!(
+                    "plugin {} is declared twice",
+                    manifest.name
+                )));
+            }
+
+            let mut modules = Vec::new();
+            for path in std::iter::once(&manifest.entry).chain(&manifest.modules) {
+                let Some(file_name) = path.file_name() else {
+                    return Err(std::io::Error::other(format!(
+                        "plugin {}: {} is not a file",
+                        manifest.name,
+                        path.display()
+                    )));
+                };
+                let code = std::fs::read_to_string(path).map_err(|err| {
+                    std::io::Error::new(
+                        err.kind(),
+                        format!("plugin {}: {}: {err}", manifest.name, path.display()),
+                    )
+                })?;
+                let specifier = format!("ext:{}/{}", manifest.name, file_name.to_string_lossy());
+                modules.push((&*specifier.leak(), Arc::from(code)));
+            }
+
+            plugins.push(Plugin {
+                name: manifest.name.clone().leak(),
+                modules,
+            });
+        }
+
+        Ok(Self { manifests: plugins })
+    }
+
+    /// Whether an isolate gets nothing beyond mass's own extension, and so can
+    /// start from the snapshot, which holds only that.
+    #[cfg(runtime)]
+    pub fn is_empty(&self) -> bool { self.manifests.is_empty() }
+
+    pub fn extensions(&self) -> Vec<Extension> {
+        self.manifests
+            .iter()
+            .map(|plugin| {
+                let esm_files = plugin
+                    .modules
+                    .iter()
+                    .map(|(specifier, code)| ExtensionFileSource::new_computed(specifier, code.clone()))
+                    .collect::<Vec<_>>();
+                Extension {
+                    name: plugin.name,
+                    esm_files: Cow::Owned(esm_files),
+                    esm_entry_point: plugin.modules.first().map(|(specifier, _)| *specifier),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A token bucket: `burst` requests at once, refilled at `rate` per second.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Limit {
+    pub rate: f64,
+    pub burst: f64,
+}
+
+/// `[rate_limit]` in mass.toml. Hosts without an entry of their own fall back
+/// to `default`, and aren't limited when that is unset too.
+#[derive(Clone, Debug, Deserialize)]
+pub struct QuotaConfig {
+    pub default: Option<Limit>,
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Limit>,
+    /// Outbound requests in flight at once, across every host.
+    #[serde(default = "QuotaConfig::default_max_connections")]
+    pub max_connections: usize,
+    /// Outbound requests in flight at once to any one host.
+    #[serde(default = "QuotaConfig::default_max_connections_per_host")]
+    pub max_connections_per_host: usize,
+}
+
+impl QuotaConfig {
+    fn default_max_connections() -> usize { 256 }
+
+    fn default_max_connections_per_host() -> usize { 32 }
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            default: None,
+            hosts: BTreeMap::new(),
+            max_connections: Self::default_max_connections(),
+            max_connections_per_host: Self::default_max_connections_per_host(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Limit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.refilled = now;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Quota {
+    pub host: String,
+    pub rate: f64,
+    pub burst: f64,
+    /// Requests that can go out right now; negative while callers are queued.
+    pub remaining: f64,
+}
+
+/// Outbound request budget per host, shared by module fetches, registry calls,
+/// JS `fetch()` and the ops that talk to remote APIs. The host's own requests
+/// also share one HTTP client and its connection pool, and go out at most
+/// `max_connections` at a time, see [`RateLimiter::connect`].
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    config: Arc<QuotaConfig>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    client: reqwest::Client,
+    connections: Arc<Semaphore>,
+    host_connections: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self { Self::new(QuotaConfig::default()) }
+}
+
+/// Leave to send one request with [`Connection::client`], taken from the caps
+/// until it's dropped. Keep it around until the response body has been read.
+#[derive(Debug)]
+pub struct Connection {
+    client: reqwest::Client,
+    _host: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+impl Connection {
+    pub fn client(&self) -> &reqwest::Client { &self.client }
+}
+
+impl RateLimiter {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            connections: Arc::new(Semaphore::new(config.max_connections.max(1))),
+            config: Arc::new(config),
+            buckets: Default::default(),
+            client: reqwest::Client::new(),
+            host_connections: Default::default(),
+        }
+    }
+
+    /// The client outbound requests share, for ones that manage their own
+    /// concurrency.
+    pub fn client(&self) -> &reqwest::Client { &self.client }
+
+    fn limit_for(&self, host: &str) -> Option<Limit> {
+        self.config
+            .hosts
+            .get(host)
+            .or(self.config.default.as_ref())
+            .copied()
+            .filter(|limit| limit.rate > 0.0)
+    }
+
+    /// Takes a token for `host` and returns how long to wait before sending.
+    /// Tokens are taken even when none are left so queued callers go out in
+    /// order instead of racing for the next refill.
+    pub fn acquire(&self, host: &str) -> Duration {
+        let Some(limit) = self.limit_for(host) else {
+            return Duration::ZERO;
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(host.to_string()).or_insert_with(|| Bucket {
+            limit,
+            tokens: limit.burst.max(1.0),
+            refilled: Instant::now(),
+        });
+
+        bucket.refill();
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / limit.rate)
+        }
+    }
+
+    /// [`acquire`](Self::acquire) for the host of `url`, then waits.
+    pub async fn wait(&self, url: &str) {
+        let Some(host) = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        else {
+            return;
+        };
+
+        let wait = self.acquire(&host);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// [`wait`](Self::wait)s for `url`'s host, then for a connection to it
+    /// within both caps. The host's is taken first, so requests queued on a
+    /// busy host don't hold up ones to the others.
+    pub async fn connect(&self, url: &reqwest::Url) -> Connection {
+        self.wait(url.as_str()).await;
+
+        let host = url.host_str().unwrap_or_default().to_string();
+        let semaphore = self
+            .host_connections
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.config.max_connections_per_host.max(1))))
+            .clone();
+
+        // Neither semaphore is ever closed.
+        let host = semaphore.acquire_owned().await.expect("connection semaphore closed");
+        let global = self
+            .connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("connection semaphore closed");
+
+        Connection {
+            client: self.client.clone(),
+            _host: host,
+            _global: global,
+        }
+    }
+
+    /// What's left for `host`, or every host seen so far without one. Hosts
+    /// nothing limits are left out.
+    pub fn quota(&self, host: Option<&str>) -> Vec<Quota> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let quota = |host: &str, bucket: &mut Bucket| {
+            bucket.refill();
+            Quota {
+                host: host.to_string(),
+                rate: bucket.limit.rate,
+                burst: bucket.limit.burst,
+                remaining: bucket.tokens,
+            }
+        };
+
+        match host {
+            Some(host) => match buckets.get_mut(host) {
+                Some(bucket) => vec![quota(host, bucket)],
+                None => self
+                    .limit_for(host)
+                    .map(|limit| Quota {
+                        host: host.to_string(),
+                        rate: limit.rate,
+                        burst: limit.burst,
+                        remaining: limit.burst.max(1.0),
+                    })
+                    .into_iter()
+                    .collect(),
+            },
+            None => {
+                let mut all: Vec<Quota> = buckets.iter_mut().map(|(host, bucket)| quota(host, bucket)).collect();
+                all.sort_by(|a, b| a.host.cmp(&b.host));
+                all
+            }
+        }
+    }
+}
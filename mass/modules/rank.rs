@@ -0,0 +1,203 @@
+use super::walk::WalkPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Entry points and manifests, slightly preferred whatever the question.
+const IMPORTANT_STEMS: &[&str] = &["main", "index", "app", "server", "lib", "mod", "readme"];
+const IMPORTANT_NAMES: &[&str] = &[
+    "package.json",
+    "cargo.toml",
+    "pyproject.toml",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "dockerfile",
+];
+
+/// Keywords that introduce a definition in the languages the analysis knows.
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn",
+    "function",
+    "class",
+    "def",
+    "struct",
+    "enum",
+    "trait",
+    "interface",
+    "type",
+    "impl",
+    "func",
+    "const",
+    "let",
+    "var",
+    "module",
+];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RankOptions {
+    /// Vector index whose ids are repository-relative paths, queried with
+    /// `vector` to add embedding similarity to the score.
+    pub index: Option<String>,
+    pub vector: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Signals {
+    pub filename: f32,
+    pub symbols: f32,
+    pub content: f32,
+    pub embedding: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankedFile {
+    pub path: String,
+    pub score: f32,
+    pub signals: Signals,
+}
+
+/// Lowercase search terms of `text`, split on punctuation, `snake_case` and
+/// `camelCase` boundaries. Terms shorter than two characters are dropped.
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+
+    for c in text.chars() {
+        if !c.is_alphanumeric() || (c.is_uppercase() && previous_lower) {
+            if current.len() > 1 {
+                terms.push(std::mem::take(&mut current));
+            }
+            current.clear();
+        }
+        if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+
+    if current.len() > 1 {
+        terms.push(current);
+    }
+    terms
+}
+
+fn filename_score(relative: &str, query: &[String]) -> f32 {
+    let path = Path::new(relative);
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let stem = path.file_stem().and_then(|n| n.to_str()).unwrap_or_default();
+    let stem_terms = terms(stem);
+
+    let dir_terms: Vec<String> = path
+        .parent()
+        .map(|parent| terms(&parent.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut score = 0.0;
+    for term in query {
+        if stem_terms.contains(term) {
+            score += 3.0;
+        } else if stem.to_lowercase().contains(term.as_str()) {
+            score += 1.5;
+        }
+        if dir_terms.contains(term) {
+            score += 1.0;
+        }
+    }
+
+    let stem = stem.to_lowercase();
+    if IMPORTANT_NAMES.contains(&name.as_str()) || IMPORTANT_STEMS.contains(&stem.as_str()) {
+        score += 0.5;
+    }
+    score
+}
+
+/// Scores definitions naming a query term highly and plain mentions a little,
+/// with diminishing returns so one huge file can't win on volume alone.
+fn content_scores(content: &str, query: &[String]) -> (f32, f32) {
+    let mut symbols = 0usize;
+    let mut mentions: HashMap<&str, usize> = HashMap::new();
+
+    for line in content.lines() {
+        let words: Vec<&str> = line
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|w| !w.is_empty())
+            .collect();
+        let definition = words
+            .iter()
+            .position(|word| DEFINITION_KEYWORDS.contains(word))
+            .and_then(|position| words.get(position + 1));
+
+        if let Some(name) = definition {
+            let name_terms = terms(name);
+            symbols += query.iter().filter(|term| name_terms.contains(term)).count();
+        }
+
+        for word in &words {
+            for term in terms(word) {
+                if let Some(term) = query.iter().find(|query| **query == term) {
+                    *mentions.entry(term.as_str()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let content = mentions.values().map(|count| (1.0 + *count as f32).ln()).sum::<f32>() * 0.5;
+    ((1.0 + symbols as f32).ln() * 2.0, content)
+}
+
+/// Walks `root` under `policy` and ranks each file against `query`, adding
+/// `similarity` (by repository-relative path) from an embedding search when
+/// one was run. Files above the policy's size limit are ranked by name only.
+pub fn rank(
+    root: &Path, query: &str, limit: usize, similarity: &HashMap<String, f32>, policy: &WalkPolicy,
+    confine: impl Fn(&Path) -> bool,
+) -> std::io::Result<Vec<RankedFile>> {
+    let query = terms(query);
+    let mut ranked = Vec::new();
+    let mut walker = policy.walk(root);
+
+    while let Some(dir) = walker.next_dir() {
+        for file in walker.list(&dir)? {
+            let path = file.path;
+            if !confine(&path) {
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            let mut signals = Signals {
+                filename: filename_score(&relative, &query),
+                embedding: similarity.get(&relative).copied().unwrap_or_default() * 5.0,
+                ..Default::default()
+            };
+
+            if file.len <= policy.max_file_size
+                && let Ok(content) = std::fs::read_to_string(&path)
+            {
+                (signals.symbols, signals.content) = content_scores(&content, &query);
+            }
+
+            let score = signals.filename + signals.symbols + signals.content + signals.embedding;
+            if score > 0.0 {
+                ranked.push(RankedFile {
+                    path: relative,
+                    score,
+                    signals,
+                });
+            }
+        }
+    }
+
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+    ranked.truncate(limit);
+    Ok(ranked)
+}
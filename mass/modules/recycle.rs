@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::{Notify, watch};
+
+#[derive(Debug, Default)]
+struct RecycleState {
+    jobs: AtomicU64,
+    baseline_heap: AtomicU64,
+    requested: AtomicBool,
+    due: Notify,
+}
+
+/// Decides when an isolate has served enough jobs, or grown its heap enough, that
+/// it should be drained and replaced with a fresh one.
+#[derive(Clone, Debug, Default)]
+pub struct RecyclePolicy {
+    max_jobs: Option<u64>,
+    max_heap_growth: Option<u64>,
+    state: Arc<RecycleState>,
+}
+
+impl RecyclePolicy {
+    #[cfg(runtime)]
+    pub fn new(max_jobs: Option<u64>, max_heap_growth_mb: Option<u64>) -> Self {
+        Self {
+            max_jobs,
+            max_heap_growth: max_heap_growth_mb.map(|mb| mb * 1024 * 1024),
+            state: Default::default(),
+        }
+    }
+
+    /// Same limits with zeroed counters, for the next isolate to start serving.
+    #[cfg(runtime)]
+    pub fn fresh(&self) -> Self {
+        Self {
+            max_jobs: self.max_jobs,
+            max_heap_growth: self.max_heap_growth,
+            state: Default::default(),
+        }
+    }
+
+    /// Counts a finished job, telling the host once the isolate is due for
+    /// recycling. The isolate carries on serving until the host has a fresh
+    /// one ready and drains it.
+    pub fn complete_job(&self, used_heap: u64) {
+        let jobs = self.state.jobs.fetch_add(1, Ordering::Relaxed) + 1;
+        let baseline =
+            match self
+                .state
+                .baseline_heap
+                .compare_exchange(0, used_heap, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => used_heap,
+                Err(baseline) => baseline,
+            };
+
+        let due = self.max_jobs.is_some_and(|max| jobs >= max)
+            || self
+                .max_heap_growth
+                .is_some_and(|max| used_heap.saturating_sub(baseline) >= max);
+
+        if due && !self.state.requested.swap(true, Ordering::Relaxed) {
+            self.state.due.notify_one();
+        }
+    }
+
+    /// Resolves once the isolate is due for recycling.
+    #[cfg(runtime)]
+    pub async fn due(&self) { self.state.due.notified().await }
+}
+
+/// Lets the host ask a running isolate to stop accepting work and drain, e.g. once a
+/// standby worker has taken over its listeners.
+#[derive(Clone, Debug)]
+pub struct DrainSignal(watch::Receiver<bool>);
+
+impl Default for DrainSignal {
+    fn default() -> Self { Self::new().1 }
+}
+
+impl DrainSignal {
+    pub fn new() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (tx, Self(rx))
+    }
+
+    pub async fn requested(mut self) {
+        if self.0.wait_for(|drain| *drain).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use deno_core::Resource;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::rc::Rc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+
+/// A RESP2 reply.
+#[derive(Debug)]
+pub enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl Reply {
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            Reply::Status(status) => Some(status),
+            Reply::Integer(n) => Some(n.to_string()),
+            Reply::Bulk(bytes) => bytes.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+            Reply::Array(_) => None,
+        }
+    }
+
+    pub fn into_integer(self) -> i64 {
+        match self {
+            Reply::Integer(n) => n,
+            _ => 0,
+        }
+    }
+}
+
+fn decode_userinfo(text: &str) -> std::io::Result<Cow<'_, str>> {
+    percent_encoding::percent_decode_str(text)
+        .decode_utf8()
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, format!("redis URL credentials: {err}")))
+}
+
+struct Connection(BufStream<TcpStream>);
+
+impl Connection {
+    /// Connects to a `redis://[user:password@]host[:port][/db]` URL.
+    async fn open(url: &str) -> std::io::Result<Self> {
+        let url = reqwest::Url::parse(url).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        if url.scheme() != "redis" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported redis scheme {}://, only redis:// is", url.scheme()),
+            ));
+        }
+
+        let host = url.host_str().unwrap_or("127.0.0.1");
+        let stream = TcpStream::connect((host, url.port().unwrap_or(6379))).await?;
+        stream.set_nodelay(true)?;
+        let mut connection = Self(BufStream::new(stream));
+
+        // The URL keeps them percent-encoded, as they'd have to be written.
+        if let Some(password) = url.password() {
+            let password = decode_userinfo(password)?;
+            match &*decode_userinfo(url.username())? {
+                "" => connection.command(&["AUTH", &*password]).await?,
+                user => connection.command(&["AUTH", user, &*password]).await?,
+            };
+        }
+
+        let db = url.path().trim_start_matches('/');
+        if !db.is_empty() {
+            connection.command(&["SELECT", db]).await?;
+        }
+
+        Ok(connection)
+    }
+
+    async fn send(&mut self, args: &[&str]) -> std::io::Result<()> {
+        let mut command = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            command.extend_from_slice(arg.as_bytes());
+            command.extend_from_slice(b"\r\n");
+        }
+
+        self.0.write_all(&command).await?;
+        self.0.flush().await
+    }
+
+    async fn command(&mut self, args: &[&str]) -> std::io::Result<Reply> {
+        self.send(args).await?;
+        read_reply(&mut self.0).await
+    }
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> std::io::Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "redis closed the connection"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn length(line: &str) -> std::io::Result<i64> {
+    line.parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("bad redis length {line:?}")))
+}
+
+/// Reads one reply. Error replies become `io::Error`s.
+fn read_reply(stream: &mut BufStream<TcpStream>) -> Pin<Box<dyn Future<Output = std::io::Result<Reply>> + '_>> {
+    Box::pin(async move {
+        let line = read_line(stream).await?;
+        let (kind, rest) = line.split_at_checked(1).unwrap_or(("", ""));
+
+        match kind {
+            "+" => Ok(Reply::Status(rest.to_string())),
+            "-" => Err(Error::other(format!("redis: {rest}"))),
+            ":" => Ok(Reply::Integer(length(rest)?)),
+            "$" => {
+                let Ok(len) = usize::try_from(length(rest)?) else {
+                    return Ok(Reply::Bulk(None));
+                };
+                let mut bytes = vec![0; len + 2];
+                stream.read_exact(&mut bytes).await?;
+                bytes.truncate(len);
+                Ok(Reply::Bulk(Some(bytes)))
+            }
+            "*" => {
+                let Ok(len) = usize::try_from(length(rest)?) else {
+                    return Ok(Reply::Array(None));
+                };
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(read_reply(stream).await?);
+                }
+                Ok(Reply::Array(Some(items)))
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unexpected redis reply {line:?}"),
+            )),
+        }
+    })
+}
+
+/// The isolate's Redis connection, opened on first use and reopened after an
+/// error. Unset without `--redis-url`, in which case the ops fail.
+#[derive(Clone, Default)]
+pub struct Redis {
+    url: Option<String>,
+    connection: Rc<tokio::sync::Mutex<Option<Connection>>>,
+}
+
+impl std::fmt::Debug for Redis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Redis").field(&self.host()).finish()
+    }
+}
+
+impl Redis {
+    #[cfg(runtime)]
+    pub fn new(url: Option<String>) -> Self {
+        Self {
+            url,
+            connection: Default::default(),
+        }
+    }
+
+    fn url(&self) -> std::io::Result<&str> {
+        self.url
+            .as_deref()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no redis server configured, pass --redis-url"))
+    }
+
+    /// The configured server, for permission checks.
+    pub fn server(&self) -> Option<reqwest::Url> { self.url.as_deref().and_then(|url| reqwest::Url::parse(url).ok()) }
+
+    /// Host of the configured server, for the audit log.
+    pub fn host(&self) -> String {
+        self.server()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    pub async fn command(&self, args: &[&str]) -> std::io::Result<Reply> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(Connection::open(self.url()?).await?);
+        }
+
+        let result = connection.as_mut().unwrap().command(args).await;
+        // An error reply leaves the connection usable; anything else may have left
+        // it mid-reply.
+        if let Err(err) = &result
+            && err.kind() != ErrorKind::Other
+        {
+            *connection = None;
+        }
+        result
+    }
+
+    /// A connection of its own subscribed to `channels`.
+    pub async fn subscribe(&self, channels: &[String]) -> std::io::Result<Subscription> {
+        let mut connection = Connection::open(self.url()?).await?;
+        let args: Vec<&str> = std::iter::once("SUBSCRIBE")
+            .chain(channels.iter().map(String::as_str))
+            .collect();
+
+        connection.send(&args).await?;
+        for _ in channels {
+            read_reply(&mut connection.0).await?;
+        }
+
+        Ok(Subscription {
+            connection: tokio::sync::Mutex::new(connection),
+            closed: Cell::new(false),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub channel: String,
+    pub message: String,
+}
+
+/// A subscribed connection JS pulls messages from with `op_redis_next_message`.
+pub struct Subscription {
+    connection: tokio::sync::Mutex<Connection>,
+    closed: Cell<bool>,
+}
+
+impl Resource for Subscription {
+    fn name(&self) -> Cow<'_, str> { "redisSubscription".into() }
+}
+
+impl Subscription {
+    /// The next published message, `None` once the connection is gone.
+    pub async fn next(&self) -> std::io::Result<Option<Message>> {
+        if self.closed.get() {
+            return Ok(None);
+        }
+
+        let mut connection = self.connection.lock().await;
+        loop {
+            let reply = match read_reply(&mut connection.0).await {
+                Ok(reply) => reply,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    self.closed.set(true);
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            };
+
+            let Reply::Array(Some(items)) = reply else {
+                continue;
+            };
+            let mut items = items.into_iter().map(Reply::into_string);
+            if let (Some(Some(kind)), Some(Some(channel)), Some(Some(message))) =
+                (items.next(), items.next(), items.next())
+                && kind == "message"
+            {
+                return Ok(Some(Message { channel, message }));
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, Default)]
+pub struct FsRoot(Option<PathBuf>);
+
+impl FsRoot {
+    #[cfg(runtime)]
+    pub fn new(root: Option<PathBuf>) -> std::io::Result<Self> {
+        match root {
+            Some(root) => Ok(Self(Some(root.canonicalize()?))),
+            None => Ok(Self(None)),
+        }
+    }
+
+    pub fn root(&self) -> Option<&Path> { self.0.as_deref() }
+
+    /// Resolves `path` against the root and rejects anything that escapes it once
+    /// symlinks and `..` are resolved. Paths that don't exist yet are resolved via
+    /// their nearest existing ancestor so ops can still create files or directories.
+    pub fn confine(&self, path: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+        let path = path.as_ref();
+        let Some(root) = &self.0 else {
+            return Ok(path.to_path_buf());
+        };
+
+        let joined = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            root.join(path)
+        };
+        let resolved = canonicalize_lenient(&joined)?;
+
+        if resolved.starts_with(root) {
+            Ok(resolved)
+        } else {
+            Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} escapes the filesystem root {}", path.display(), root.display()),
+            ))
+        }
+    }
+}
+
+fn canonicalize_lenient(path: &Path) -> std::io::Result<PathBuf> {
+    let mut existing = path.to_path_buf();
+    let mut rest = Vec::new();
+
+    while !existing.exists() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                rest.push(name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => break,
+        }
+    }
+
+    let mut resolved = existing.canonicalize()?;
+    for part in rest.into_iter().rev() {
+        match Path::new(&part).components().next() {
+            Some(Component::Normal(_)) => resolved.push(part),
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(resolved)
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+/// One server-sent event. Plain strings are sent as just `data`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Event {
+    Data(String),
+    Full {
+        data: String,
+        event: Option<String>,
+        id: Option<String>,
+        retry: Option<u64>,
+    },
+}
+
+/// Field values can't span lines, so anything after a line break is dropped.
+fn single_line(value: &str) -> &str { value.split(['\r', '\n']).next().unwrap_or_default() }
+
+/// The wire form of `event`, ending in the blank line that dispatches it. Each
+/// line of `data` gets its own `data:` field so clients reassemble it as is.
+pub fn encode(event: &Event) -> String {
+    let (data, event, id, retry) = match event {
+        Event::Data(data) => (data.as_str(), None, None, None),
+        Event::Full { data, event, id, retry } => (data.as_str(), event.as_deref(), id.as_deref(), *retry),
+    };
+
+    let mut encoded = String::with_capacity(data.len() + 16);
+    if let Some(event) = event {
+        encoded.push_str(&format!("event: {}\n", single_line(event)));
+    }
+    if let Some(id) = id {
+        encoded.push_str(&format!("id: {}\n", single_line(id)));
+    }
+    if let Some(retry) = retry {
+        encoded.push_str(&format!("retry: {retry}\n"));
+    }
+
+    let data = data.replace("\r\n", "\n").replace('\r', "\n");
+    for line in data.split('\n') {
+        encoded.push_str("data: ");
+        encoded.push_str(line);
+        encoded.push('\n');
+    }
+
+    encoded.push('\n');
+    encoded
+}
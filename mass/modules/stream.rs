@@ -0,0 +1,47 @@
+use super::files::{self, ImportantFile};
+use super::sandbox::FsRoot;
+use deno_core::Resource;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Important files read one at a time as JS pulls them with `op_next_chunk`,
+/// so only the file being handed over is ever held in memory.
+pub struct FileStream {
+    root: PathBuf,
+    fs_root: FsRoot,
+    /// Files above this size are reported by size instead of read.
+    max_bytes: u64,
+    pending: RefCell<VecDeque<String>>,
+}
+
+impl Resource for FileStream {
+    fn name(&self) -> Cow<'_, str> { "fileStream".into() }
+}
+
+impl FileStream {
+    pub fn new(root: PathBuf, fs_root: FsRoot, max_bytes: u64, paths: Vec<String>) -> Self {
+        Self {
+            root,
+            fs_root,
+            max_bytes,
+            pending: RefCell::new(paths.into()),
+        }
+    }
+
+    /// The next readable file, skipping ones that are missing, not regular
+    /// files or outside the filesystem root. `None` once exhausted.
+    pub fn next(&self) -> Option<ImportantFile> {
+        loop {
+            let path = self.pending.borrow_mut().pop_front()?;
+            let Ok(full_path) = self.fs_root.confine(self.root.join(&path)) else {
+                continue;
+            };
+
+            if let Some(file) = files::read(path, &full_path, self.max_bytes, None) {
+                return Some(file);
+            }
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use super::data::DataDir;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+const MAX_LEVEL: usize = 16;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexOptions {
+    /// Links per node above the base layer; the base layer gets twice as many.
+    #[serde(default = "default_m")]
+    pub m: usize,
+    #[serde(default = "default_ef_construction")]
+    pub ef_construction: usize,
+}
+
+fn default_m() -> usize { 16 }
+
+fn default_ef_construction() -> usize { 200 }
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            m: default_m(),
+            ef_construction: default_ef_construction(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Item {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hit {
+    pub id: String,
+    /// Cosine similarity to the query.
+    pub score: f32,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Node {
+    id: String,
+    vector: Vec<f32>,
+    /// JSON text, since postcard can't encode arbitrary JSON values.
+    metadata: Option<String>,
+    /// Neighbours per layer, from the base layer up.
+    links: Vec<Vec<u32>>,
+    /// Superseded by a later insert under the same id. Still walked through
+    /// while searching, never returned.
+    deleted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Candidate(f32, u32);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering { self.0.total_cmp(&other.0).then(self.1.cmp(&other.1)) }
+}
+
+/// A hierarchical navigable small world graph over unit vectors, compared by
+/// cosine distance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hnsw {
+    dim: usize,
+    m: usize,
+    ef_construction: usize,
+    entry: Option<u32>,
+    nodes: Vec<Node>,
+    rng: u64,
+    #[serde(skip)]
+    ids: HashMap<String, u32>,
+}
+
+impl Hnsw {
+    pub fn new(options: &IndexOptions) -> Self {
+        Self {
+            dim: 0,
+            m: options.m.max(2),
+            ef_construction: options.ef_construction.max(1),
+            entry: None,
+            nodes: Vec::new(),
+            rng: 0x9E37_79B9_7F4A_7C15,
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Number of distinct ids in the index.
+    pub fn count(&self) -> usize { self.ids.len() }
+
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        let vector = &self.nodes[node as usize].vector;
+        1.0 - query.iter().zip(vector).map(|(a, b)| a * b).sum::<f32>()
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64*, seeded per index so rebuilding the same input gives the same graph.
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let bits = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        let uniform = (bits as f64 / (1u64 << 53) as f64).max(f64::MIN_POSITIVE);
+
+        ((-uniform.ln() / (self.m as f64).ln()) as usize).min(MAX_LEVEL)
+    }
+
+    fn search_layer(&self, query: &[f32], entries: &[u32], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entries.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+
+        for &entry in entries {
+            let candidate = Candidate(self.distance(query, entry), entry);
+            candidates.push(Reverse(candidate));
+            results.push(candidate);
+        }
+
+        while let Some(Reverse(closest)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::INFINITY, |worst: &Candidate| worst.0);
+            if closest.0 > worst && results.len() >= ef {
+                break;
+            }
+
+            let Some(links) = self.nodes[closest.1 as usize].links.get(layer) else {
+                continue;
+            };
+
+            for &neighbour in links {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+
+                let candidate = Candidate(self.distance(query, neighbour), neighbour);
+                if results.len() < ef || results.peek().is_some_and(|worst| candidate.0 < worst.0) {
+                    candidates.push(Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Inserts `item`, replacing an earlier one with the same id.
+    pub fn insert(&mut self, item: Item) -> std::io::Result<()> {
+        let vector = normalized(item.vector)?;
+        if self.dim == 0 {
+            self.dim = vector.len();
+        } else if vector.len() != self.dim {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} has {} dimensions, the index has {}",
+                    item.id,
+                    vector.len(),
+                    self.dim
+                ),
+            ));
+        }
+
+        if let Some(&previous) = self.ids.get(&item.id) {
+            self.nodes[previous as usize].deleted = true;
+        }
+
+        let level = self.random_level();
+        let index = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            id: item.id.clone(),
+            metadata: item.metadata.map(|metadata| metadata.to_string()),
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(item.id, index);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(index);
+            return Ok(());
+        };
+
+        let query = self.nodes[index as usize].vector.clone();
+        let top = self.nodes[entry as usize].links.len() - 1;
+        let mut entries = vec![entry];
+
+        for layer in (level + 1..=top).rev() {
+            entries = vec![self.search_layer(&query, &entries, 1, layer)[0].1];
+        }
+
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, &entries, self.ef_construction, layer);
+            let max_links = if layer == 0 { self.m * 2 } else { self.m };
+            let neighbours: Vec<u32> = found.iter().take(self.m).map(|candidate| candidate.1).collect();
+
+            for &neighbour in &neighbours {
+                self.nodes[neighbour as usize].links[layer].push(index);
+                if self.nodes[neighbour as usize].links[layer].len() > max_links {
+                    self.shrink(neighbour, layer, max_links);
+                }
+            }
+
+            self.nodes[index as usize].links[layer] = neighbours;
+            entries = found.iter().map(|candidate| candidate.1).collect();
+        }
+
+        if level > top {
+            self.entry = Some(index);
+        }
+
+        Ok(())
+    }
+
+    /// Keeps only the `max_links` closest neighbours of `node` on `layer`.
+    fn shrink(&mut self, node: u32, layer: usize, max_links: usize) {
+        let vector = self.nodes[node as usize].vector.clone();
+        let mut links: Vec<Candidate> = self.nodes[node as usize].links[layer]
+            .iter()
+            .map(|&neighbour| Candidate(self.distance(&vector, neighbour), neighbour))
+            .collect();
+
+        links.sort();
+        links.truncate(max_links);
+        self.nodes[node as usize].links[layer] = links.into_iter().map(|candidate| candidate.1).collect();
+    }
+
+    pub fn query(&self, vector: Vec<f32>, k: usize, ef: usize) -> std::io::Result<Vec<Hit>> {
+        let Some(entry) = self.entry else {
+            return Ok(Vec::new());
+        };
+
+        let query = normalized(vector)?;
+        if query.len() != self.dim {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("query has {} dimensions, the index has {}", query.len(), self.dim),
+            ));
+        }
+
+        let mut entries = vec![entry];
+        for layer in (1..self.nodes[entry as usize].links.len()).rev() {
+            entries = vec![self.search_layer(&query, &entries, 1, layer)[0].1];
+        }
+
+        // Superseded nodes take up slots in the beam, so widen it by their count.
+        let superseded = self.nodes.len() - self.ids.len();
+        let found = self.search_layer(&query, &entries, ef.max(k) + superseded.min(ef), 0);
+
+        Ok(found
+            .into_iter()
+            .filter(|candidate| !self.nodes[candidate.1 as usize].deleted)
+            .take(k)
+            .map(|candidate| {
+                let node = &self.nodes[candidate.1 as usize];
+                Hit {
+                    id: node.id.clone(),
+                    score: 1.0 - candidate.0,
+                    metadata: node
+                        .metadata
+                        .as_deref()
+                        .and_then(|json| serde_json::from_str(json).ok()),
+                }
+            })
+            .collect())
+    }
+}
+
+fn normalized(mut vector: Vec<f32>) -> std::io::Result<Vec<f32>> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if vector.is_empty() || !norm.is_normal() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "vectors must be non-empty and non-zero",
+        ));
+    }
+
+    vector.iter_mut().for_each(|v| *v /= norm);
+    Ok(vector)
+}
+
+/// Named indexes under `<data dir>/vectors`, loaded on first use and written
+/// back after every change.
+#[derive(Debug, Default)]
+pub struct VectorIndexes {
+    data: DataDir,
+    loaded: HashMap<String, Hnsw>,
+}
+
+impl VectorIndexes {
+    pub fn new(data: DataDir) -> Self {
+        Self {
+            data,
+            loaded: HashMap::new(),
+        }
+    }
+
+    fn path(&self, name: &str) -> std::io::Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid index name {name:?}"),
+            ));
+        }
+
+        Ok(self.data.join("vectors")?.join(format!("{name}.hnsw")))
+    }
+
+    fn load(&mut self, name: &str) -> std::io::Result<Option<&mut Hnsw>> {
+        if !self.loaded.contains_key(name) {
+            let path = self.path(name)?;
+            if !path.exists() {
+                return Ok(None);
+            }
+
+            let mut index: Hnsw = postcard::from_bytes(&std::fs::read(&path)?)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {err}", path.display())))?;
+            index.ids = index
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, node)| !node.deleted)
+                .map(|(position, node)| (node.id.clone(), position as u32))
+                .collect();
+            self.loaded.insert(name.to_string(), index);
+        }
+
+        Ok(self.loaded.get_mut(name))
+    }
+
+    fn save(&self, name: &str) -> std::io::Result<()> {
+        let path = self.path(name)?;
+        let bytes = postcard::to_allocvec(&self.loaded[name]).map_err(Error::other)?;
+
+        let partial = path.with_extension(format!("hnsw.partial-{}", std::process::id()));
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path)
+    }
+
+    /// Replaces the index `name` with one built from `items`.
+    pub fn build(&mut self, name: &str, items: Vec<Item>, options: &IndexOptions) -> std::io::Result<usize> {
+        let mut index = Hnsw::new(options);
+        for item in items {
+            index.insert(item)?;
+        }
+
+        let count = index.count();
+        self.path(name)?;
+        self.loaded.insert(name.to_string(), index);
+        self.save(name)?;
+        Ok(count)
+    }
+
+    /// Adds `items` to the index `name`, creating it with default options.
+    pub fn add(&mut self, name: &str, items: Vec<Item>) -> std::io::Result<usize> {
+        if self.load(name)?.is_none() {
+            self.path(name)?;
+            self.loaded
+                .insert(name.to_string(), Hnsw::new(&IndexOptions::default()));
+        }
+
+        let index = self.loaded.get_mut(name).unwrap();
+        for item in items {
+            index.insert(item)?;
+        }
+
+        let count = index.count();
+        self.save(name)?;
+        Ok(count)
+    }
+
+    pub fn query(&mut self, name: &str, vector: Vec<f32>, k: usize, ef: Option<usize>) -> std::io::Result<Vec<Hit>> {
+        let index = self
+            .load(name)?
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no vector index named {name}")))?;
+        index.query(vector, k, ef.unwrap_or(k * 4).max(16))
+    }
+}
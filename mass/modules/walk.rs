@@ -0,0 +1,115 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `[walk]` in mass.toml: which parts of a repository the analysis, fingerprint,
+/// ranking and important-file ops look at.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalkPolicy {
+    /// Directory names never descended into, wherever they are.
+    pub ignored_dirs: Vec<String>,
+    /// Whether files and directories starting with `.` are walked.
+    pub hidden: bool,
+    /// Files larger than this are still counted and fingerprinted, but their
+    /// content is never read: they're ranked by name only and returned as
+    /// skipped by the important-file ops.
+    pub max_file_size: u64,
+    /// Whether symlinks are followed. Only ones pointing inside the repository
+    /// are, and each directory is walked once however many links reach it.
+    pub follow_symlinks: bool,
+}
+
+impl Default for WalkPolicy {
+    fn default() -> Self {
+        Self {
+            ignored_dirs: ["node_modules", "target", ".git", "__pycache__", "dist", "build"]
+                .map(str::to_string)
+                .into(),
+            hidden: true,
+            max_file_size: 500_000,
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl WalkPolicy {
+    fn skips(&self, name: &str, dir: bool) -> bool {
+        (!self.hidden && name.starts_with('.')) || (dir && self.ignored_dirs.iter().any(|ignored| ignored == name))
+    }
+
+    /// A walk of `root`. Directories are listed one at a time with
+    /// [`Walker::list`], so callers can stop in between.
+    pub fn walk(&self, root: &Path) -> Walker<'_> {
+        let canonical = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        Walker {
+            policy: self,
+            seen: HashSet::from([canonical.clone()]),
+            root: canonical,
+            pending: vec![root.to_path_buf()],
+        }
+    }
+}
+
+/// A regular file the policy lets through.
+#[derive(Debug)]
+pub struct WalkedFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub len: u64,
+}
+
+pub struct Walker<'a> {
+    policy: &'a WalkPolicy,
+    /// Canonical `root`, to keep followed symlinks inside.
+    root: PathBuf,
+    pending: Vec<PathBuf>,
+    /// Canonical directories queued so far, only checked when following symlinks.
+    seen: HashSet<PathBuf>,
+}
+
+impl Walker<'_> {
+    /// The next directory to list, `None` once the walk is done.
+    pub fn next_dir(&mut self) -> Option<PathBuf> { self.pending.pop() }
+
+    /// The files in `dir`, queueing its subdirectories for [`Walker::next_dir`].
+    pub fn list(&mut self, dir: &Path) -> std::io::Result<Vec<WalkedFile>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            let mut file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if !self.policy.follow_symlinks {
+                    continue;
+                }
+                // Dangling, or pointing outside the repository.
+                match fs::canonicalize(&path) {
+                    Ok(target) if target.starts_with(&self.root) => file_type = fs::metadata(&target)?.file_type(),
+                    _ => continue,
+                }
+            }
+
+            if self.policy.skips(&name, file_type.is_dir()) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if self.policy.follow_symlinks && !self.seen.insert(fs::canonicalize(&path)?) {
+                    continue;
+                }
+                self.pending.push(path);
+            } else if file_type.is_file() {
+                // Zero when it was deleted since it was listed.
+                let len = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                files.push(WalkedFile { path, name, len });
+            }
+        }
+
+        Ok(files)
+    }
+}
@@ -0,0 +1,155 @@
+use crate::cli::Cli;
+use crate::modules::audit::AuditLog;
+use deno_error::JsErrorBox;
+use deno_runtime::deno_permissions::prompter::{self, TtyPrompter};
+use deno_runtime::deno_permissions::{
+    GetFormattedStackFn, PermissionPrompter, PermissionState, Permissions, PermissionsContainer, PermissionsOptions,
+    PromptResponse,
+};
+use deno_runtime::permissions::RuntimePermissionDescriptorParser;
+use std::io::IsTerminal;
+use std::sync::Arc;
+
+pub type DescriptorParser = RuntimePermissionDescriptorParser<sys_traits::impls::RealSys>;
+
+/// Unstable Deno APIs the runtime provides: `Deno.openKv` always, so servers
+/// written against Deno KV run as is, and FFI with `--allow-ffi`.
+pub fn features(cli: &Cli) -> Vec<&'static str> {
+    let mut features = vec!["kv"];
+    if cli.allow_ffi.is_some() {
+        features.push("ffi");
+    }
+    features
+}
+
+pub fn container(
+    cli: &Cli, parser: Arc<DescriptorParser>, audit: &AuditLog,
+) -> Result<PermissionsContainer, JsErrorBox> {
+    // Addons are loaded with the same permission as `Deno.dlopen`.
+    let allow_ffi = match (&cli.allow_ffi, &cli.allow_addons) {
+        (None, Some(_)) => Some(vec![]),
+        (allow_ffi, _) => allow_ffi.clone(),
+    };
+    let all_ffi = allow_ffi.as_ref().is_some_and(Vec::is_empty);
+    if cli.allow_net.is_none() && !cli.prompt && all_ffi && !audit.is_enabled() {
+        return Ok(PermissionsContainer::allow_all(parser));
+    }
+
+    if cli.prompt && !std::io::stdin().is_terminal() {
+        return Err(JsErrorBox::type_error("--prompt requires an interactive terminal"));
+    }
+
+    // In prompt mode read, net and run are left unset so the first access to each
+    // resource asks on the TTY; grants are remembered for the rest of the session.
+    let granted = || if cli.prompt { None } else { Some(vec![]) };
+
+    let options = PermissionsOptions {
+        allow_env: Some(vec![]),
+        allow_ffi,
+        allow_import: Some(vec![]),
+        allow_net: cli.allow_net.clone().or_else(granted),
+        allow_read: granted(),
+        allow_run: granted(),
+        allow_sys: Some(vec![]),
+        allow_write: Some(vec![]),
+        prompt: cli.prompt,
+        ..Default::default()
+    };
+
+    let permissions =
+        Permissions::from_options(parser.as_ref(), &options).map_err(|err| JsErrorBox::generic(err.to_string()))?;
+    if !audit.is_enabled() {
+        return Ok(PermissionsContainer::new(parser, permissions));
+    }
+
+    // Audited, JS runs against a container granting none of read, write, net
+    // and run, so every check of a resource not yet accessed ends up in
+    // `AuditPrompter`, whatever API or op it came from.
+    let audited = PermissionsOptions {
+        allow_net: None,
+        allow_read: None,
+        allow_run: None,
+        allow_write: None,
+        prompt: true,
+        ..options
+    };
+    let audited =
+        Permissions::from_options(parser.as_ref(), &audited).map_err(|err| JsErrorBox::generic(err.to_string()))?;
+
+    prompter::set_prompter(Box::new(AuditPrompter {
+        audit: audit.clone(),
+        policy: PermissionsContainer::new(parser.clone(), permissions),
+        prompt: cli.prompt,
+    }));
+    Ok(PermissionsContainer::new(parser, audited))
+}
+
+/// Answers the prompts of an audited container as `policy` would have
+/// answered its checks, asking on the TTY only in prompt mode, and records
+/// each access it allows. The container remembers what was allowed, so a
+/// resource is recorded the first time it's accessed.
+struct AuditPrompter {
+    audit: AuditLog,
+    policy: PermissionsContainer,
+    prompt: bool,
+}
+
+impl AuditPrompter {
+    fn query(&self, name: &str, value: Option<&str>) -> Option<PermissionState> {
+        match name {
+            "read" => self.policy.query_read(value).ok(),
+            "write" => self.policy.query_write(value).ok(),
+            "net" => self.policy.query_net(value).ok(),
+            "run" => self.policy.query_run(value).ok(),
+            "ffi" => self.policy.query_ffi(value).ok(),
+            "import" => self.policy.query_import(value).ok(),
+            "sys" => self.policy.query_sys(value).ok(),
+            "env" => Some(self.policy.query_env(value)),
+            _ => None,
+        }
+    }
+}
+
+impl PermissionPrompter for AuditPrompter {
+    fn prompt(
+        &mut self, message: &str, name: &str, api_name: Option<&str>, is_unary: bool,
+        get_stack: Option<GetFormattedStackFn>,
+    ) -> PromptResponse {
+        let value = requested(message, name);
+        let stack = get_stack.map(|get_stack| get_stack());
+
+        let response = match self.query(name, value) {
+            Some(PermissionState::Granted) => PromptResponse::Allow,
+            Some(PermissionState::Prompt) if self.prompt => {
+                let get_stack = stack
+                    .clone()
+                    .map(|stack| Box::new(move || stack) as GetFormattedStackFn);
+                TtyPrompter.prompt(message, name, api_name, is_unary, get_stack)
+            }
+            _ => PromptResponse::Deny,
+        };
+
+        if response != PromptResponse::Deny {
+            let stack = stack.map(|stack| stack.join("\n"));
+            self.audit.record(
+                name,
+                value.unwrap_or_default(),
+                api_name.unwrap_or("permissions"),
+                stack.as_deref(),
+            );
+        }
+        response
+    }
+}
+
+/// The resource a prompt is about, from its `<name> access to "<value>"`
+/// message; `None` for access to everything of its kind.
+fn requested<'a>(message: &'a str, name: &str) -> Option<&'a str> {
+    let value = message.strip_prefix(name)?.strip_prefix(" access to ")?;
+    Some(
+        value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value),
+    )
+}
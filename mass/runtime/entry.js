@@ -1,30 +1,563 @@
+import { core } from 'ext:core/mod.js';
 import server from './snapshot/server.min.js';
 
 import {
   op_pid,
+  op_net_egress,
+  op_net_acquire,
+  op_net_quota,
+  op_log,
+  op_context_open,
+  op_context_switch,
+  op_context_close,
+  op_context_trusted,
+  op_heartbeat,
+  op_resource_usage,
+  op_job_complete,
+  op_drain_requested,
   op_extract_tar_gz,
   op_analyze_repository,
+  op_fingerprint_repo,
+  op_fetch_repo,
+  op_fetch_github_repo,
+  op_batch_analyze,
+  op_batch_next,
+  op_download,
+  op_download_next,
   op_get_important_files,
   op_get_important_files_by_pattern,
+  op_fit_to_budget,
+  op_embed,
+  op_vector_index_build,
+  op_vector_index_add,
+  op_vector_index_query,
+  op_rank_files,
+  op_analyze_changes,
+  op_open_file_stream,
+  op_next_chunk,
+  op_compress_stream,
+  op_decompress_stream,
+  op_codec_write,
+  op_codec_finish,
+  op_queue_enqueue,
+  op_queue_claim,
+  op_queue_ack,
+  op_queue_fail,
+  op_redis_get,
+  op_redis_set,
+  op_redis_expire,
+  op_redis_del,
+  op_redis_publish,
+  op_redis_subscribe,
+  op_redis_next_message,
+  op_sse_encode,
+  op_listen_options,
+  op_serve_static,
+  op_static_read,
+  op_cache_warm,
+  op_cache_fetch,
+  op_cache_purge,
+  op_cache_stats,
+  op_bundle,
   op_cleanup_temp_directory,
 } from 'ext:core/ops';
 
+const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+function interceptFetch() {
+  const fetch = globalThis.fetch;
+
+  globalThis.fetch = async function (input, init) {
+    const request = new Request(input, init);
+
+    const wait = op_net_acquire(new URL(request.url).hostname);
+    if (wait > 0) await sleep(wait);
+    if (!request.body) return fetch(request);
+
+    // The body is charged chunk by chunk as it goes out, rather than read into
+    // memory up front just to learn its size.
+    const body = request.body.pipeThrough(
+      new TransformStream({
+        async transform(chunk, controller) {
+          const wait = op_net_egress(chunk.byteLength);
+          if (wait > 0) await sleep(wait);
+          controller.enqueue(chunk);
+        },
+      }),
+    );
+    return fetch(new Request(request, { body, duplex: 'half' }));
+  };
+}
+
+const { AsyncVariable, setAsyncContext } = core;
+const requestContext = new AsyncVariable();
+
+/** Points ops at the context of whichever request is running right now. */
+const switchContext = () => op_context_switch(requestContext.get()?.id ?? 0);
+
+/**
+ * The tenant comes from `tenantId(request)` when given, otherwise from the
+ * header only when the front proxy set it: a client can send anything.
+ */
+function contextFromRequest(request, { tenantId } = {}) {
+  const deadline = Number(request.headers.get('x-request-deadline'));
+  return {
+    requestId: request.headers.get('x-request-id') ?? crypto.randomUUID(),
+    tenantId: tenantId ? tenantId(request) : op_context_trusted() ? request.headers.get('x-tenant-id') : null,
+    deadline: deadline > 0 ? deadline : null,
+  };
+}
+
+function runInContext(context, fn) {
+  const id = op_context_open(context);
+  const previous = requestContext.enter({ ...context, id });
+
+  let result;
+  try {
+    result = fn();
+  } catch (error) {
+    op_context_close(id);
+    throw error;
+  } finally {
+    setAsyncContext(previous);
+  }
+  return Promise.resolve(result).finally(() => op_context_close(id));
+}
+
+const serveInContext = (handler, options) => (request, info) =>
+  runInContext(contextFromRequest(request, options), () => handler(request, info));
+
+/** `area` with every function switching to the calling request's context first. */
+function scoped(area) {
+  return Object.fromEntries(
+    Object.entries(area).map(([name, fn]) => [
+      name,
+      (...args) => {
+        switchContext();
+        return fn(...args);
+      },
+    ]),
+  );
+}
+
+const LOG_LEVELS = { debug: 'debug', log: 'info', info: 'info', warn: 'warn', error: 'error', trace: 'debug' };
+const formatArgs = args => args.map(arg => (typeof arg === 'string' ? arg : Deno.inspect(arg))).join(' ');
+
+function captureConsole() {
+  for (const [method, level] of Object.entries(LOG_LEVELS)) {
+    console[method] = (...args) => {
+      switchContext();
+      op_log(level, formatArgs(args));
+    };
+  }
+}
+
+function listenAddress({ hostname, port, path, cert }) {
+  if (path) return `unix:${path}`;
+  return `${cert ? 'https' : 'http'}://${hostname}:${port}`;
+}
+
+const servers = new Set();
+const sockets = new Set();
+let draining = false;
+
+function drain() {
+  if (draining) return;
+  draining = true;
+
+  console.log('MASS recycling isolate, draining in-flight requests');
+  for (const server of servers) server.shutdown();
+  // Open sockets would hold shutdown() forever; clients reconnect to the new isolate.
+  for (const socket of sockets) socket.close(1001, 'server restarting');
+}
+
+// Once the isolate is due for recycling, the host boots its replacement and
+// only then asks this one to drain.
+function completeJob() {
+  op_job_complete();
+}
+
+function trackServers() {
+  const serve = Deno.serve;
+  const reusePort = Deno.build.os === 'linux';
+
+  const track = handler => async (request, info) => {
+    try {
+      return await handler(request, info);
+    } finally {
+      completeJob();
+    }
+  };
+
+  Deno.serve = function (options, handler) {
+    let server;
+
+    if (typeof options === 'function') server = serve({ reusePort }, track(options));
+    else if (typeof handler === 'function') server = serve({ reusePort, ...options }, track(handler));
+    else server = serve({ reusePort, ...options, handler: track(options.handler) });
+
+    servers.add(server);
+    server.finished.then(() => servers.delete(server));
+    return server;
+  };
+
+  const drainRequested = op_drain_requested();
+  Deno.core.unrefOpPromise(drainRequested);
+  drainRequested.then(drain);
+}
+
+const SOCKET_EVENTS = { onOpen: 'open', onMessage: 'message', onClose: 'close', onError: 'error' };
+
+// Upgrades `request` to a WebSocket, calling `handlers` as `(event, socket)`.
+// The socket is closed with 1001 when the isolate drains.
+function upgradeWebSocket(request, { protocol, idleTimeout, ...handlers } = {}) {
+  if (draining) return new Response('server restarting', { status: 503 });
+
+  const { socket, response } = Deno.upgradeWebSocket(request, { protocol, idleTimeout });
+  sockets.add(socket);
+  socket.addEventListener('close', () => sockets.delete(socket));
+
+  for (const [name, type] of Object.entries(SOCKET_EVENTS)) {
+    const handler = handlers[name];
+    if (handler) socket.addEventListener(type, event => handler(event, socket));
+  }
+
+  return response;
+}
+
+async function* streamFiles(repoPath, filePaths) {
+  const rid = op_open_file_stream(repoPath, filePaths);
+
+  try {
+    for (let chunk = op_next_chunk(rid); chunk !== null; chunk = op_next_chunk(rid)) {
+      yield chunk;
+    }
+  } finally {
+    Deno.core.tryClose(rid);
+  }
+}
+
+const toBytes = chunk => (typeof chunk === 'string' ? new TextEncoder().encode(chunk) : chunk);
+
+function codecStream(open, format, options) {
+  let rid = null;
+
+  const run = (fn, controller) => {
+    try {
+      const output = fn();
+      if (output.length > 0) controller.enqueue(output);
+    } catch (err) {
+      if (rid !== null) Deno.core.tryClose(rid);
+      rid = null;
+      throw err;
+    }
+  };
+
+  return new TransformStream({
+    start() {
+      rid = open(format, options ?? null);
+    },
+    transform(chunk, controller) {
+      run(() => op_codec_write(rid, toBytes(chunk)), controller);
+    },
+    flush(controller) {
+      run(() => {
+        const output = op_codec_finish(rid);
+        rid = null;
+        return output;
+      }, controller);
+    },
+    cancel() {
+      if (rid !== null) Deno.core.tryClose(rid);
+      rid = null;
+    },
+  });
+}
+
+function codecAll(open, data, format, options) {
+  const rid = open(format, options ?? null);
+  let head;
+
+  try {
+    head = op_codec_write(rid, toBytes(data));
+  } catch (err) {
+    Deno.core.tryClose(rid);
+    throw err;
+  }
+
+  const tail = op_codec_finish(rid);
+  const output = new Uint8Array(head.length + tail.length);
+  output.set(head);
+  output.set(tail, head.length);
+  return output;
+}
+
+async function* subscribeRedis(...channels) {
+  const rid = await op_redis_subscribe(channels);
+
+  try {
+    for (let message = await op_redis_next_message(rid); message !== null; message = await op_redis_next_message(rid)) {
+      yield message;
+    }
+  } finally {
+    Deno.core.tryClose(rid);
+  }
+}
+
+const SSE_HEADERS = {
+  'content-type': 'text/event-stream; charset=utf-8',
+  // no-transform keeps Deno.serve from compressing the body, which would hold
+  // events back until a compression block fills.
+  'cache-control': 'no-cache, no-transform',
+  connection: 'keep-alive',
+  'x-accel-buffering': 'no',
+};
+
+function sseEvent(value) {
+  if (typeof value === 'string') return value;
+  return { ...value, data: typeof value.data === 'string' ? value.data : JSON.stringify(value.data) };
+}
+
+function sseResponse(events, { keepAlive = 15000, headers = {} } = {}) {
+  const encoder = new TextEncoder();
+  const iterator = events[Symbol.asyncIterator]?.() ?? events[Symbol.iterator]();
+  let timer;
+
+  const stop = () => clearInterval(timer);
+  const body = new ReadableStream({
+    start(controller) {
+      // A comment right away sends the headers before the first event exists.
+      controller.enqueue(encoder.encode(': connected\n\n'));
+      if (keepAlive > 0) {
+        timer = setInterval(() => {
+          try {
+            controller.enqueue(encoder.encode(': keep-alive\n\n'));
+          } catch {
+            stop();
+          }
+        }, keepAlive);
+      }
+    },
+    async pull(controller) {
+      try {
+        const { value, done } = await iterator.next();
+        if (done) {
+          stop();
+          controller.close();
+        } else {
+          controller.enqueue(encoder.encode(op_sse_encode(sseEvent(value))));
+        }
+      } catch (error) {
+        stop();
+        controller.error(error);
+      }
+    },
+    async cancel() {
+      stop();
+      await iterator.return?.();
+    },
+  });
+
+  return new Response(body, { headers: { ...SSE_HEADERS, ...headers } });
+}
+
+function serveStatic(request, root, { prefix = '' } = {}) {
+  const { pathname } = new URL(request.url);
+  if (!pathname.startsWith(prefix)) return new Response(null, { status: 404 });
+
+  let path;
+  try {
+    path = decodeURIComponent(pathname.slice(prefix.length));
+  } catch {
+    return new Response(null, { status: 400 });
+  }
+
+  const { status, headers, body: rid } = op_serve_static(root, path, {
+    ifNoneMatch: request.headers.get('if-none-match'),
+    range: request.headers.get('range'),
+  });
+  if (rid === null || request.method === 'HEAD') {
+    if (rid !== null) Deno.core.tryClose(rid);
+    return new Response(null, { status, headers });
+  }
+
+  const body = new ReadableStream({
+    type: 'bytes',
+    pull(controller) {
+      const chunk = op_static_read(rid);
+      if (chunk === null) controller.close();
+      else controller.enqueue(chunk);
+    },
+    cancel() {
+      Deno.core.tryClose(rid);
+    },
+  });
+  return new Response(body, { status, headers });
+}
+
+async function* analyzeBatch(sources, options) {
+  const rid = op_batch_analyze(sources, options);
+
+  try {
+    for (let event = await op_batch_next(rid); event !== null; event = await op_batch_next(rid)) {
+      yield event;
+    }
+  } finally {
+    Deno.core.tryClose(rid);
+  }
+}
+
+async function* download(url, dest, options) {
+  switchContext();
+  const rid = op_download(url, dest, options ?? null);
+
+  try {
+    for (let event = await op_download_next(rid); event !== null; event = await op_download_next(rid)) {
+      yield event;
+    }
+  } finally {
+    Deno.core.tryClose(rid);
+  }
+}
+
 globalThis.MASS = {
   _init: true,
 
   app: server,
   pid: op_pid,
+  usage: op_resource_usage,
+
+  net: {
+    intercept: interceptFetch,
+    quota: op_net_quota,
+  },
+
+  log: {
+    capture: captureConsole,
+  },
+
+  files: {
+    stream: streamFiles,
+  },
+
+  batch: {
+    analyze: analyzeBatch,
+  },
+
+  download,
+
+  compression: {
+    compress: (data, format, options) => codecAll(op_compress_stream, data, format, options),
+    decompress: (data, format, options) => codecAll(op_decompress_stream, data, format, options),
+    compressStream: (format, options) => codecStream(op_compress_stream, format, options),
+    decompressStream: (format, options) => codecStream(op_decompress_stream, format, options),
+  },
+
+  analysis: scoped({
+    analyze: (path, limits) => op_analyze_repository(path, limits ?? null),
+    fingerprint: op_fingerprint_repo,
+    batch: analyzeBatch,
+    changes: op_analyze_changes,
+    rank: (path, query, limit, options) => op_rank_files(path, query, limit, options ?? null),
+    importantFiles: op_get_important_files,
+    importantFilesByPattern: op_get_important_files_by_pattern,
+    fitToBudget: op_fit_to_budget,
+    fetch: (provider, owner, repo, ref, options) => op_fetch_repo(provider, owner, repo, ref ?? null, options ?? null),
+    extract: op_extract_tar_gz,
+    cleanup: op_cleanup_temp_directory,
+  }),
+
+  sse: {
+    stream: sseResponse,
+  },
+
+  static: {
+    serve: serveStatic,
+  },
+
+  cache: scoped({
+    warm: (urls, options) => op_cache_warm(urls, options ?? null),
+    fetch: (url, policy) => op_cache_fetch(url, policy ?? null),
+    purge: op_cache_purge,
+    stats: op_cache_stats,
+  }),
+
+  bundle: (entry, options) => {
+    switchContext();
+    return op_bundle(entry, options ?? null);
+  },
+
+  context: {
+    get: () => {
+      const { id, ...context } = requestContext.get() ?? {};
+      return id ? context : undefined;
+    },
+    run: runInContext,
+    fromRequest: contextFromRequest,
+    serve: serveInContext,
+  },
+
+  ws: {
+    upgrade: upgradeWebSocket,
+    count: () => sockets.size,
+  },
+
+  redis: scoped({
+    get: op_redis_get,
+    set: (key, value, ttlMs) => op_redis_set(key, value, ttlMs ?? null),
+    expire: op_redis_expire,
+    del: op_redis_del,
+    publish: op_redis_publish,
+    subscribe: subscribeRedis,
+  }),
+
+  jobs: {
+    complete: completeJob,
+    track: trackServers,
+    enqueue: (queue, payload, options) => op_queue_enqueue(queue, payload, options ?? null),
+    claim: op_queue_claim,
+    ack: op_queue_ack,
+    fail: op_queue_fail,
+  },
+
+  watchdog: {
+    start: interval => {
+      op_heartbeat();
+      Deno.unrefTimer(setInterval(op_heartbeat, interval));
+    },
+  },
 
   ops: {
     op_extract_tar_gz,
     op_analyze_repository,
+    op_fingerprint_repo,
+    op_fetch_repo,
+    op_fetch_github_repo,
+    op_batch_analyze,
+    op_batch_next,
+    op_download,
+    op_download_next,
     op_get_important_files,
     op_get_important_files_by_pattern,
+    op_fit_to_budget,
+    op_embed,
+    op_vector_index_build,
+    op_vector_index_add,
+    op_vector_index_query,
+    op_rank_files,
+    op_analyze_changes,
+    op_queue_enqueue,
+    op_queue_claim,
+    op_queue_ack,
+    op_queue_fail,
+    op_bundle,
     op_cleanup_temp_directory,
   },
 
   config: {
-    port: () => 8080,
+    port: () => op_listen_options().port ?? 8080,
+    listen: () => op_listen_options(),
+    address: () => listenAddress(op_listen_options()),
     version: () => '0.0.1',
   },
 };
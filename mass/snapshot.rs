@@ -1,4 +1,77 @@
-pub static RUNTIME: Option<&[u8]> = Some(include_bytes!(concat!(
+use crate::modules;
+use deno_core::v8;
+use serde::Deserialize;
+
+static RUNTIME: Option<&[u8]> = Some(include_bytes!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/mass/runtime/snapshot/RUNTIME.bin"
 )));
+
+static METADATA: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/mass/runtime/snapshot/RUNTIME.json"
+));
+
+static SERVER_SOURCE_MAP: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/mass/runtime/snapshot/server.min.js.map"
+));
+
+#[derive(Deserialize)]
+struct SnapshotMetadata {
+    v8: String,
+    /// `None` when the build profile skipped snapshot creation.
+    fingerprint: Option<String>,
+}
+
+fn metadata() -> Result<SnapshotMetadata, String> {
+    serde_json::from_str(METADATA).map_err(|err| format!("unreadable snapshot metadata: {err}"))
+}
+
+pub fn validate() -> Result<(), String> {
+    let metadata = metadata()?;
+    let Some(snapshot_fingerprint) = metadata.fingerprint else {
+        return Err("this build has no snapshot, build with --release to create one".into());
+    };
+
+    if metadata.v8 != v8::VERSION_STRING {
+        return Err(format!(
+            "snapshot was built for V8 {}, running {}",
+            metadata.v8,
+            v8::VERSION_STRING
+        ));
+    }
+
+    let fingerprint = modules::fingerprint();
+    if snapshot_fingerprint != fingerprint {
+        return Err(format!(
+            "snapshot extension fingerprint {snapshot_fingerprint} does not match {fingerprint}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The embedded startup snapshot, or `None` when the build has none or it was built
+/// for a different V8 or set of ops, in which case the runtime cold-initializes its
+/// extensions instead.
+pub fn runtime() -> Option<&'static [u8]> {
+    // Debug builds never create one, so cold initialization is expected there.
+    if metadata().is_ok_and(|metadata| metadata.fingerprint.is_none()) {
+        return None;
+    }
+
+    match validate() {
+        Ok(()) => RUNTIME,
+        Err(reason) => {
+            eprintln!("warning: {reason}; falling back to cold initialization");
+            None
+        }
+    }
+}
+
+/// Source map of the bundled server, for stack frames in `server.min.js` to
+/// point back at the TypeScript under `mass/server`.
+pub fn source_map(file_name: &str) -> Option<&'static [u8]> {
+    file_name.ends_with("/server.min.js").then_some(SERVER_SOURCE_MAP)
+}
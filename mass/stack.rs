@@ -0,0 +1,108 @@
+use crate::snapshot;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use deno_core::error::JsError;
+use sourcemap::SourceMap;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Script name the `mass dev` bundle is evaluated under.
+pub const DEV_SERVER: &str = "[mass:dev-server]";
+
+const INLINE_MAP: &str = "//# sourceMappingURL=data:application/json;base64,";
+
+/// Directory the `sources` of the embedded server map are relative to.
+const SERVER_MAP_DIR: &str = "mass/runtime/snapshot";
+
+/// Inline source map of the bundle `mass dev` last booted, and the directory
+/// its `sources` are relative to.
+static DEV_MAP: RwLock<Option<(Arc<[u8]>, PathBuf)>> = RwLock::new(None);
+
+/// Keeps the inline source map of the `mass dev` bundle read from `path`,
+/// replacing the one from the previous rebuild.
+pub fn set_dev_bundle(path: &Path, code: &str) {
+    let map = code
+        .rfind(INLINE_MAP)
+        .and_then(|start| BASE64.decode(code[start + INLINE_MAP.len()..].trim()).ok());
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    *DEV_MAP.write().unwrap_or_else(PoisonError::into_inner) = map.map(|map| (map.into(), dir));
+}
+
+fn lookup(file_name: &str) -> Option<(Cow<'static, [u8]>, PathBuf)> {
+    if file_name == DEV_SERVER {
+        let dev = DEV_MAP.read().unwrap_or_else(PoisonError::into_inner);
+        let (map, dir) = dev.as_ref()?;
+        return Some((Cow::Owned(map.to_vec()), dir.clone()));
+    }
+
+    snapshot::source_map(file_name).map(|map| (Cow::Borrowed(map), PathBuf::from(SERVER_MAP_DIR)))
+}
+
+/// Source map of a bundled script, for the module loader to hand to V8 so
+/// `error.stack` in JS is mapped as well.
+pub fn source_map(file_name: &str) -> Option<Cow<'static, [u8]>> { lookup(file_name).map(|(map, _)| map) }
+
+/// `source` from a map as a path relative to the working directory.
+fn resolve(dir: &Path, source: &str) -> String {
+    if source.contains("://") {
+        return source.to_string();
+    }
+
+    let mut path = PathBuf::new();
+    for component in dir.join(source).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if path.file_name().is_some() => {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+    path.to_string_lossy().into_owned()
+}
+
+fn remap(error: &mut JsError, maps: &mut HashMap<String, Option<(SourceMap, PathBuf)>>) {
+    for frame in &mut error.frames {
+        let (Some(file_name), Some(line), Some(column)) = (&frame.file_name, frame.line_number, frame.column_number)
+        else {
+            continue;
+        };
+
+        let map = maps.entry(file_name.clone()).or_insert_with(|| {
+            let (map, dir) = lookup(file_name)?;
+            Some((SourceMap::from_slice(&map).ok()?, dir))
+        });
+        let Some((map, dir)) = map else {
+            continue;
+        };
+        let Some(token) = map.lookup_token((line - 1).max(0) as u32, (column - 1).max(0) as u32) else {
+            continue;
+        };
+        let Some(source) = token.get_source() else {
+            continue;
+        };
+
+        frame.file_name = Some(resolve(dir, source));
+        frame.line_number = Some(token.get_src_line() as i64 + 1);
+        frame.column_number = Some(token.get_src_col() as i64 + 1);
+    }
+
+    if let Some(cause) = &mut error.cause {
+        remap(cause, maps);
+    }
+    for error in error.aggregated.iter_mut().flatten() {
+        remap(error, maps);
+    }
+}
+
+/// A copy of `error` with every frame still inside a bundle pointed back at
+/// the source it was built from.
+pub fn remapped(error: &JsError) -> JsError {
+    let mut error = error.clone();
+    remap(&mut error, &mut HashMap::new());
+    error
+}
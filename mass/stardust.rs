@@ -1,18 +1,51 @@
+use crate::addons::AddonLoader;
+use crate::cli::{Cli, LogFormat};
+use crate::config::Config;
+use crate::crash;
+use crate::front::ServerConfig;
 use crate::loader;
+use crate::loader::ModuleCache;
 use crate::modules;
+use crate::modules::StardustOptions;
+use crate::modules::audit::AuditLog;
+use crate::modules::context::Fronted;
+use crate::modules::data::DataDir;
+use crate::modules::listen::{Listen, TlsConfig};
+use crate::modules::log::LogSink;
+use crate::modules::metrics::{Heartbeat, OpTimings};
+use crate::modules::net::NetPolicy;
+use crate::modules::plugins::Plugins;
+use crate::modules::quota::RateLimiter;
+use crate::modules::recycle::{DrainSignal, RecyclePolicy};
+use crate::modules::redis::Redis;
+use crate::modules::sandbox::FsRoot;
+use crate::modules::walk::WalkPolicy;
+use crate::permissions;
 use crate::snapshot;
+use crate::stack;
+use crate::telemetry::TelemetryConfig;
+use crate::watchdog::{Deadline, Watchdog};
 
 use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::oneshot;
 use tokio::time::{Duration, timeout};
 
+use deno_core::CompiledWasmModuleStore;
 use deno_core::FastStaticString;
+use deno_core::ModuleCodeString;
 use deno_core::ModuleSpecifier;
 use deno_core::PollEventLoopOptions;
+use deno_core::SharedArrayBufferStore;
 use deno_core::error::CoreError;
+use deno_error::JsErrorBox;
 use deno_resolver::npm::DenoInNpmPackageChecker;
 use deno_resolver::npm::NpmResolver;
-use deno_runtime::deno_permissions::PermissionsContainer;
+use deno_runtime::BootstrapOptions;
+use deno_runtime::FeatureChecker;
+use deno_runtime::UNSTABLE_FEATURES;
+use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
 use deno_runtime::permissions::RuntimePermissionDescriptorParser;
 use deno_runtime::worker::MainWorker;
 use deno_runtime::worker::WorkerOptions;
@@ -32,9 +65,180 @@ where
     timeout(Duration::from_millis(500), f()).await
 }
 
-pub async fn start_runtime() -> Result<(), CoreError> {
-    let main_module = ModuleSpecifier::parse("file://server.dist.js").unwrap();
+/// `--tls-cert` and `--tls-key` when given, otherwise `[tls]` from the config file.
+pub fn tls_config(cli: &Cli, config: TlsConfig) -> TlsConfig {
+    match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => TlsConfig {
+            cert: Some(cert.clone()),
+            key: Some(key.clone()),
+            ..Default::default()
+        },
+        _ => config,
+    }
+}
+
+/// Resolves the main module, preferring an operator supplied bootstrap script over
+/// the worker compiled into the binary.
+async fn main_module(cli: &Cli) -> Result<(ModuleSpecifier, ModuleCodeString), CoreError> {
+    let Some(path) = &cli.bootstrap else {
+        return Ok((
+            ModuleSpecifier::parse("file://server.dist.js").unwrap(),
+            WORKER_CODE.into(),
+        ));
+    };
+
+    let path = std::path::absolute(path)?;
+    let code = tokio::fs::read_to_string(&path).await?;
+    let specifier = ModuleSpecifier::from_file_path(&path)
+        .map_err(|_| JsErrorBox::generic(format!("Invalid bootstrap path {}", path.display())))?;
+
+    Ok((specifier, code.into()))
+}
+
+/// `stack` is where JavaScript was terminated, empty when the module was
+/// waiting on an op instead.
+fn init_deadline_error(
+    module: &ModuleSpecifier, deadline: Duration, timings: &OpTimings, stack: &[String],
+) -> JsErrorBox {
+    let mut message = format!(
+        "module {module} did not finish initializing within {}s",
+        deadline.as_secs()
+    );
+
+    for frame in stack {
+        message.push_str(&format!("\n    {frame}"));
+    }
+
+    if let Some((op, running)) = timings.current() {
+        message.push_str(&format!("\n    waiting in {op} for {}ms", running.as_millis()));
+    }
+
+    for call in timings.history().iter().rev().take(8) {
+        message.push_str(&format!("\n    after {} ({:.1}ms)", call.name, call.duration_ms));
+    }
+
+    JsErrorBox::new("InitDeadlineError", message)
+}
+
+/// State that outlives a single worker and is handed to every isolate the
+/// supervisor boots.
+#[derive(Clone)]
+pub struct RuntimeContext {
+    pub fs_root: FsRoot,
+    pub modules: ModuleCache,
+    pub log: LogSink,
+    pub audit: AuditLog,
+    pub recycle: RecyclePolicy,
+    pub quota: RateLimiter,
+    /// Where this isolate's server listens. The supervisor swaps a proxied
+    /// `--listen` for the worker's private socket.
+    pub listen: Listen,
+    pub server: ServerConfig,
+    /// Whether the host serves HTTP itself in front of the worker, for
+    /// `[server]` tuning or TLS on a socket the worker can't serve it on.
+    pub front: bool,
+    /// Shared by every isolate so a `BroadcastChannel` reaches all of them.
+    pub broadcast: InMemoryBroadcastChannel,
+    /// Backing stores for `SharedArrayBuffer`s and compiled `WebAssembly.Module`s
+    /// passed between isolates, so they're shared rather than copied or
+    /// recompiled.
+    pub shared_buffers: SharedArrayBufferStore,
+    pub wasm_modules: CompiledWasmModuleStore,
+    pub telemetry: TelemetryConfig,
+    pub walk: WalkPolicy,
+    pub plugins: Plugins,
+}
+
+impl RuntimeContext {
+    /// Whether the host binds the public socket and forwards to the worker.
+    pub fn proxied(&self) -> bool { self.front || self.listen.is_proxied() }
+
+    pub fn new(cli: &Cli) -> Result<Self, CoreError> {
+        let log = match (cli.log_format, &cli.log_file) {
+            (LogFormat::Text, _) => LogSink::default(),
+            (LogFormat::Json, None) => LogSink::new(Box::new(std::io::stdout())),
+            (LogFormat::Json, Some(path)) => {
+                let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                LogSink::new(Box::new(file))
+            }
+        };
+
+        let config = Config::load(&cli.config)?;
+        let listen = cli.listen.clone().unwrap_or_default();
+        let tls = tls_config(cli, config.tls);
+        // Workers load it again when they boot; this only fails startup early.
+        tls.load()?;
+        let front = config.server.is_tuned() || (listen.is_proxied() && tls.is_set());
+
+        let audit = match &cli.audit_permissions {
+            Some(path) => AuditLog::open(path)?,
+            None => AuditLog::default(),
+        };
+
+        Ok(Self {
+            fs_root: FsRoot::new(cli.fs_root.clone())?,
+            modules: ModuleCache::default(),
+            recycle: RecyclePolicy::new(cli.recycle_jobs, cli.recycle_heap_growth),
+            quota: RateLimiter::new(config.rate_limit),
+            listen,
+            server: config.server,
+            front,
+            broadcast: InMemoryBroadcastChannel::default(),
+            shared_buffers: SharedArrayBufferStore::default(),
+            wasm_modules: CompiledWasmModuleStore::default(),
+            telemetry: config.telemetry,
+            walk: config.walk,
+            plugins: Plugins::load(&config.plugins)?,
+            log,
+            audit,
+        })
+    }
+}
+
+/// Channels between the supervisor and one worker: the worker reports when its main
+/// module finished evaluating and listens for a request to drain.
+pub struct WorkerControl {
+    pub ready: oneshot::Sender<()>,
+    pub drain: DrainSignal,
+}
+
+static NEXT_ISOLATE: AtomicU64 = AtomicU64::new(1);
+
+pub async fn start_runtime(cli: &Cli, ctx: RuntimeContext, control: WorkerControl) -> Result<(), CoreError> {
+    let RuntimeContext {
+        fs_root,
+        modules,
+        log,
+        audit,
+        recycle,
+        quota,
+        listen,
+        broadcast,
+        shared_buffers,
+        wasm_modules,
+        telemetry,
+        walk,
+        plugins,
+        front,
+        ..
+    } = ctx;
+    // The snapshot holds mass's own extension only; any more and isolates
+    // initialize cold.
+    let startup_snapshot = if plugins.is_empty() { snapshot::runtime() } else { None };
+    let log = log.for_isolate(NEXT_ISOLATE.fetch_add(1, Ordering::Relaxed));
+    // Read per boot so a reload serves whatever certificate is on disk now.
+    let tls = tls_config(cli, Config::load(&cli.config)?.tls).load()?;
+    let (main_module, main_code) = main_module(cli).await?;
     let permission_desc_parser = Arc::new(RuntimePermissionDescriptorParser::new(sys_traits::impls::RealSys));
+    let permissions = permissions::container(cli, permission_desc_parser, &audit)?;
+    let features = permissions::features(cli);
+    let mut feature_checker = FeatureChecker::default();
+    for feature in &features {
+        feature_checker.enable_feature(feature);
+    }
+    let timings = OpTimings::default();
+    let heartbeat = Heartbeat::default();
+    let data = DataDir::new(cli.data_dir.clone());
 
     let mut worker = MainWorker::bootstrap_from_options(
         &main_module,
@@ -44,46 +248,170 @@ pub async fn start_runtime() -> Result<(), CoreError> {
             sys_traits::impls::RealSys,
         > {
             fs: Arc::new(deno_fs::RealFs),
-            deno_rt_native_addon_loader: None,
-            module_loader: Rc::new(loader::ExtendedModuleLoader),
-            permissions: PermissionsContainer::allow_all(permission_desc_parser),
+            deno_rt_native_addon_loader: cli.allow_addons.as_ref().map(|dirs| {
+                let dirs = if dirs.is_empty() {
+                    vec!["node_modules".into()]
+                } else {
+                    dirs.clone()
+                };
+                Arc::new(AddonLoader::new(dirs)) as _
+            }),
+            module_loader: Rc::new(loader::ExtendedModuleLoader::new(
+                fs_root.clone(),
+                modules,
+                audit.clone(),
+                quota.clone(),
+            )),
+            permissions,
             blob_store: Default::default(),
-            broadcast_channel: Default::default(),
-            feature_checker: Default::default(),
+            broadcast_channel: broadcast,
+            feature_checker: Arc::new(feature_checker),
             node_services: Default::default(),
             npm_process_state_provider: Default::default(),
             root_cert_store_provider: Default::default(),
             fetch_dns_resolver: Default::default(),
-            shared_array_buffer_store: Default::default(),
-            compiled_wasm_module_store: Default::default(),
+            shared_array_buffer_store: Some(shared_buffers),
+            compiled_wasm_module_store: Some(wasm_modules),
             v8_code_cache: Default::default(),
         },
         WorkerOptions {
-            extensions: modules::init_extension(),
-            startup_snapshot: snapshot::RUNTIME,
+            extensions: modules::init_extension(StardustOptions {
+                net: NetPolicy::new(cli.max_egress),
+                timings: timings.clone(),
+                heartbeat: heartbeat.clone(),
+                log: log.clone(),
+                audit: audit.clone(),
+                recycle: recycle.clone(),
+                drain: control.drain,
+                data: data.clone(),
+                quota,
+                redis: Redis::new(cli.redis_url.clone()),
+                listen: listen.serve_options(tls),
+                fronted: Fronted(front),
+                walk,
+                plugins,
+                fs_root,
+            }),
+            bootstrap: BootstrapOptions {
+                unstable_features: UNSTABLE_FEATURES
+                    .iter()
+                    .filter(|feature| features.contains(&feature.name))
+                    .map(|feature| feature.id)
+                    .collect(),
+                otel_config: telemetry.otel_config(),
+                ..Default::default()
+            },
+            startup_snapshot,
+            // Hands audit records the stack of the op whose check they're for.
+            enable_stack_trace_arg_in_ops: audit.is_enabled(),
+            // `Deno.openKv()` without a path opens kv.sqlite3 in here, shared by
+            // every isolate so data survives restarts.
+            origin_storage_dir: data.join("kv").ok(),
             ..Default::default()
         },
     );
 
+    let isolate = worker.js_runtime.v8_isolate().thread_safe_handle();
+    worker
+        .js_runtime
+        .add_near_heap_limit_callback(move |current, _initial| {
+            eprintln!("isolate is near its heap limit ({current} bytes), terminating");
+            isolate.terminate_execution();
+            current * 2
+        });
+
     worker
         .js_runtime
         .execute_script("_init", include_str!("worker/check.js"))
         .map_err(CoreError::from)?;
 
+    if log.is_enabled() {
+        worker
+            .js_runtime
+            .execute_script("_log", "MASS.log.capture()")
+            .map_err(CoreError::from)?;
+    }
+
+    worker
+        .js_runtime
+        .execute_script("_jobs", "MASS.jobs.track()")
+        .map_err(CoreError::from)?;
+
+    // `mass dev` rebuilds the server outside the snapshot; its bundle replaces the
+    // snapshotted app before the worker module picks it up.
+    if let Some(path) = &cli.dev_server {
+        let code = tokio::fs::read_to_string(path).await?;
+        stack::set_dev_bundle(path, &code);
+        worker
+            .js_runtime
+            .execute_script(stack::DEV_SERVER, code)
+            .map_err(CoreError::from)?;
+        worker
+            .js_runtime
+            .execute_script("_dev", "MASS.app = globalThis.__massDevServer.default")
+            .map_err(CoreError::from)?;
+    }
+
+    let _watchdog = match cli.stall_threshold {
+        Some(threshold) => {
+            let threshold = Duration::from_millis(threshold);
+
+            worker
+                .js_runtime
+                .execute_script(
+                    "_watchdog",
+                    format!("MASS.watchdog.start({})", (threshold / 4).as_millis().max(10)),
+                )
+                .map_err(CoreError::from)?;
+
+            Some(Watchdog::spawn(
+                threshold,
+                cli.stall_stack,
+                heartbeat,
+                timings.clone(),
+                &mut worker.js_runtime,
+            ))
+        }
+        None => None,
+    };
+
+    crash::set_worker(main_module.as_str(), &timings);
+
     let id = worker
         .js_runtime
-        .load_main_es_module_from_code(&main_module, WORKER_CODE)
+        .load_main_es_module_from_code(&main_module, main_code)
         .await?;
 
     println!("MASS esm loaded into memory");
 
-    if let Err(_) = with_timeout(|| worker.js_runtime.run_event_loop(PollEventLoopOptions::default())).await {
+    if with_timeout(|| worker.js_runtime.run_event_loop(PollEventLoopOptions::default()))
+        .await
+        .is_err()
+    {
         eprintln!("JavaScript event loop timed out after 500ms");
     }
 
     println!("MASS es_module event loop loaded");
 
-    worker.evaluate_module(id).await?;
+    match cli.init_deadline {
+        Some(deadline) => {
+            let deadline = Duration::from_secs(deadline);
+            // The timeout only fires while the module waits on an op; one
+            // stuck in JavaScript is terminated from another thread instead.
+            let terminate = Deadline::spawn(deadline, &mut worker.js_runtime);
+            let evaluated = timeout(deadline, worker.evaluate_module(id)).await;
+            if let Some(stack) = terminate.stack() {
+                return Err(init_deadline_error(&main_module, deadline, &timings, &stack).into());
+            }
+            match evaluated {
+                Ok(result) => result?,
+                Err(_) => return Err(init_deadline_error(&main_module, deadline, &timings, &[]).into()),
+            }
+        }
+        None => worker.evaluate_module(id).await?,
+    }
+
+    let _ = control.ready.send(());
     worker.run_event_loop(false).await?;
 
     Ok(())
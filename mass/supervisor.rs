@@ -0,0 +1,273 @@
+use crate::broadcast;
+use crate::cli::Cli;
+use crate::diagnostics;
+use crate::front::Front;
+use crate::listener::{self, Public};
+use crate::modules::listen::Listen;
+use crate::modules::recycle::{DrainSignal, RecyclePolicy};
+use crate::stardust::{self, RuntimeContext, WorkerControl};
+
+use deno_core::error::CoreError;
+use std::path::PathBuf;
+use tokio::sync::{oneshot, watch};
+use tokio::time::{Duration, Instant, sleep};
+
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+/// A worker isolate running on its own thread and current-thread runtime, so a
+/// standby can boot next to it during a reload.
+struct Worker {
+    started: Instant,
+    recycle: RecyclePolicy,
+    drain: watch::Sender<bool>,
+    ready: oneshot::Receiver<()>,
+    exited: oneshot::Receiver<Result<(), CoreError>>,
+    /// The private socket the worker serves on behind a proxied `--listen`.
+    socket: Option<PathBuf>,
+}
+
+impl Worker {
+    fn spawn(cli: &Cli, ctx: &RuntimeContext) -> Self {
+        let (drain, drain_signal) = DrainSignal::new();
+        let (ready_tx, ready) = oneshot::channel();
+        let (exited_tx, exited) = oneshot::channel();
+
+        let cli = cli.clone();
+        let socket = ctx.proxied().then(listener::private_socket);
+        let ctx = RuntimeContext {
+            recycle: ctx.recycle.fresh(),
+            listen: socket.clone().map_or_else(|| ctx.listen.clone(), Listen::Unix),
+            ..ctx.clone()
+        };
+        let recycle = ctx.recycle.clone();
+
+        std::thread::spawn(move || {
+            let control = WorkerControl {
+                ready: ready_tx,
+                drain: drain_signal,
+            };
+
+            let result = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime.block_on(stardust::start_runtime(&cli, ctx, control)),
+                Err(err) => Err(err.into()),
+            };
+
+            let _ = exited_tx.send(result);
+        });
+
+        Self {
+            started: Instant::now(),
+            recycle,
+            drain,
+            ready,
+            exited,
+            socket,
+        }
+    }
+
+    /// Points the public listener at this worker, if there is one.
+    fn serve(&self, backend: &Option<watch::Sender<PathBuf>>) {
+        if let (Some(backend), Some(socket)) = (backend, &self.socket) {
+            backend.send_replace(socket.clone());
+        }
+    }
+
+    async fn exit(&mut self) -> Result<(), CoreError> { exited(&mut self.exited).await }
+
+    /// Asks the isolate to stop accepting work and lets it finish in the background.
+    fn retire(mut self) {
+        let _ = self.drain.send(true);
+
+        tokio::spawn(async move {
+            match self.exit().await {
+                Ok(()) => println!("MASS previous worker drained"),
+                Err(error) => eprintln!(
+                    "MASS previous worker failed while draining: {}",
+                    diagnostics::format(&error)
+                ),
+            }
+        });
+    }
+}
+
+/// What a worker thread reported on its way out, borrowing only that much of
+/// the worker so its `ready` can be awaited alongside.
+async fn exited(exited: &mut oneshot::Receiver<Result<(), CoreError>>) -> Result<(), CoreError> {
+    match exited.await {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::other("worker thread exited without reporting").into()),
+    }
+}
+
+/// Boots a standby next to `current` and, once it's ready, points the
+/// listener at it and retires `current`, which keeps serving until then. A
+/// standby that fails to boot leaves `current` in place. `current` is watched
+/// meanwhile: if it exits first, the standby takes its place straight away.
+async fn handover(current: &mut Worker, cli: &Cli, ctx: &RuntimeContext, backend: &Option<watch::Sender<PathBuf>>) {
+    let mut standby = Worker::spawn(cli, ctx);
+
+    tokio::select! {
+        Ok(()) = &mut standby.ready => {
+            println!("MASS standby worker ready, draining the previous one");
+            standby.serve(backend);
+            std::mem::replace(current, standby).retire();
+        }
+        result = exited(&mut standby.exited) => {
+            if let Err(error) = result {
+                let error = diagnostics::format(&error);
+                eprintln!("MASS standby worker failed, keeping the current one: {error}");
+            }
+        }
+        result = current.exit() => {
+            match result {
+                Ok(()) => eprintln!("MASS worker exited while its standby was booting, switching to the standby"),
+                Err(error) => eprintln!(
+                    "MASS worker failed while its standby was booting, switching to the standby: {}",
+                    diagnostics::format(&error)
+                ),
+            }
+            standby.serve(backend);
+            *current = standby;
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn hangup(signal: &mut Option<tokio::signal::unix::Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(socket) = &self.socket {
+            let _ = std::fs::remove_file(socket);
+        }
+    }
+}
+
+/// Resolves once the file at `path` has a newer modification time than the last
+/// one seen. Never resolves without a path.
+async fn rebuilt(path: Option<&std::path::Path>, last: &mut Option<std::time::SystemTime>) {
+    let Some(path) = path else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        sleep(Duration::from_millis(200)).await;
+        let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+
+        if modified.is_some() && modified != *last {
+            *last = modified;
+            return;
+        }
+    }
+}
+
+/// Runs the worker until it exits cleanly, rebooting the isolate with capped
+/// exponential backoff whenever it crashes or is terminated near its heap limit.
+/// `SIGHUP`, a rebuilt `--dev-server` bundle, or an isolate due for recycling
+/// boots a standby and retires the old worker once the standby is ready. The
+/// standby reads the `--bootstrap` entry module from disk again, and after
+/// `SIGHUP` loads its remote modules through the HTTP cache rather than from
+/// memory; without `--bootstrap` it runs the server compiled into the binary.
+///
+/// A unix socket or systemd `--listen` is bound here rather than in the worker,
+/// and every connection is forwarded to whichever worker is current. With
+/// `[server]` tuning, or TLS on such a socket, the host serves HTTP itself and
+/// forwards requests instead.
+pub async fn supervise(cli: Cli) -> Result<(), CoreError> {
+    let ctx = RuntimeContext::new(&cli)?;
+    if cli.broadcast_redis
+        && let Some(url) = &cli.redis_url
+    {
+        broadcast::relay(ctx.broadcast.clone(), url.clone());
+    }
+    let front = ctx.front.then(|| Front::new(&cli, ctx.server.clone())).transpose()?;
+    let backend = if ctx.proxied() {
+        let public = Public::bind(&ctx.listen)?;
+        let (backend, current) = watch::channel(PathBuf::new());
+        match &front {
+            Some(front) => tokio::spawn(front.clone().serve(public, current)),
+            None => tokio::spawn(public.proxy(current)),
+        };
+        Some(backend)
+    } else {
+        None
+    };
+    let mut backoff = MIN_BACKOFF;
+    let mut restarts = 0;
+
+    #[cfg(unix)]
+    let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+    let dev_server = cli.dev_server.as_deref();
+    let mut built = dev_server.and_then(|path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok());
+    let mut current = Worker::spawn(&cli, &ctx);
+    current.serve(&backend);
+
+    loop {
+        #[cfg(unix)]
+        let hangup = hangup(&mut signal);
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<()>();
+
+        let reload = async {
+            tokio::select! {
+                _ = hangup => {
+                    ctx.modules.clear();
+                    "received SIGHUP"
+                }
+                _ = rebuilt(dev_server, &mut built) => "server bundle rebuilt",
+            }
+        };
+        let recycle = current.recycle.clone();
+
+        let result = tokio::select! {
+            result = current.exit() => result,
+            reason = reload => {
+                println!("MASS {reason}, booting a standby worker");
+                if let Some(front) = &front
+                    && let Err(error) = front.reload_tls(&cli)
+                {
+                    eprintln!("MASS failed to reload TLS, keeping the current certificate: {error}");
+                }
+                handover(&mut current, &cli, &ctx, &backend).await;
+                continue;
+            }
+            _ = recycle.due() => {
+                println!("MASS isolate due for recycling, booting its replacement");
+                handover(&mut current, &cli, &ctx, &backend).await;
+                continue;
+            }
+        };
+
+        let error = match result {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        if current.started.elapsed() >= HEALTHY_AFTER {
+            backoff = MIN_BACKOFF;
+            restarts = 0;
+        }
+
+        if cli.max_restarts.is_some_and(|max| restarts >= max) {
+            return Err(error);
+        }
+
+        eprintln!("{}", diagnostics::format(&error));
+        eprintln!("MASS worker exited, restarting in {}ms", backoff.as_millis());
+
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        restarts += 1;
+        current = Worker::spawn(&cli, &ctx);
+        current.serve(&backend);
+    }
+}
@@ -0,0 +1,95 @@
+use deno_runtime::deno_telemetry::{self, OtelConfig, OtelRuntimeConfig};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// `[telemetry]` in mass.toml: one switch per observability subsystem, plus
+/// where exported data goes and how it's labelled. `enabled = false`,
+/// `DO_NOT_TRACK=1` or `MASS_TELEMETRY=0` turn all of it off.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// Export OpenTelemetry metrics from the runtime and JS.
+    pub metrics: bool,
+    /// Export OpenTelemetry traces; off unless asked for.
+    pub traces: bool,
+    /// Write a JSON report into `--crash-dir` when the host panics.
+    pub crash_reports: bool,
+    /// OTLP collector metrics and traces are sent to, e.g.
+    /// `http://localhost:4318`. Nothing is exported without one.
+    pub endpoint: Option<String>,
+    pub service_name: String,
+    /// Attached to everything exported and to crash reports.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            metrics: true,
+            traces: false,
+            crash_reports: true,
+            endpoint: None,
+            service_name: "mass".to_string(),
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+fn opted_out() -> bool {
+    std::env::var("DO_NOT_TRACK").is_ok_and(|value| !value.is_empty() && value != "0")
+        || std::env::var("MASS_TELEMETRY").is_ok_and(|value| value == "0" || value == "false")
+}
+
+impl TelemetryConfig {
+    fn on(&self, subsystem: bool) -> bool { self.enabled && subsystem && !opted_out() }
+
+    pub fn crash_reports(&self) -> bool { self.on(self.crash_reports) }
+
+    fn exporting(&self, subsystem: bool) -> bool { self.endpoint.is_some() && self.on(subsystem) }
+
+    /// What each isolate's `Deno.telemetry` is switched on for.
+    pub fn otel_config(&self) -> OtelConfig {
+        OtelConfig {
+            tracing_enabled: self.exporting(self.traces),
+            metrics_enabled: self.exporting(self.metrics),
+            ..Default::default()
+        }
+    }
+
+    /// Sets up the OpenTelemetry exporters, once, before any isolate starts.
+    pub fn init(&self) {
+        let config = self.otel_config();
+        if !config.tracing_enabled && !config.metrics_enabled {
+            return;
+        }
+
+        let attributes = self
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // SAFETY: called first thing in main, before anything else reads or
+        // writes the environment.
+        unsafe {
+            if let Some(endpoint) = &self.endpoint {
+                std::env::set_var("OTEL_EXPORTER_OTLP_ENDPOINT", endpoint);
+            }
+            std::env::set_var("OTEL_SERVICE_NAME", &self.service_name);
+            if !attributes.is_empty() {
+                std::env::set_var("OTEL_RESOURCE_ATTRIBUTES", attributes);
+            }
+        }
+
+        let runtime = OtelRuntimeConfig {
+            runtime_name: "mass".into(),
+            runtime_version: env!("CARGO_PKG_VERSION").into(),
+        };
+        if let Err(error) = deno_telemetry::init(runtime, config) {
+            eprintln!("MASS failed to start telemetry export: {error}");
+        }
+    }
+}
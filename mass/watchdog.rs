@@ -0,0 +1,183 @@
+use crate::modules::metrics::{Heartbeat, OpTimings};
+
+use deno_core::{JsRuntime, v8};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+thread_local! {
+    /// The main context of the isolate running on this thread, which the
+    /// interrupts below read its stack in.
+    static CONTEXT: RefCell<Option<v8::Global<v8::Context>>> = const { RefCell::new(None) };
+}
+
+/// Watches the JS heartbeat from a separate thread and reports event-loop stalls
+/// longer than `threshold`, naming the op that was running if there was one.
+pub struct Watchdog {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub fn spawn(
+        threshold: Duration, capture_stack: bool, heartbeat: Heartbeat, timings: OpTimings, runtime: &mut JsRuntime,
+    ) -> Self {
+        CONTEXT.set(Some(runtime.main_context()));
+        let isolate = runtime.v8_isolate().thread_safe_handle();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let interval = (threshold / 4).max(Duration::from_millis(10));
+
+        let stop = stopped.clone();
+        std::thread::spawn(move || {
+            let mut reported = false;
+
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(interval);
+                let lag = heartbeat.lag();
+
+                if lag < threshold {
+                    reported = false;
+                    continue;
+                }
+
+                if reported {
+                    continue;
+                }
+
+                reported = true;
+                match timings.current() {
+                    Some((op, running)) => eprintln!(
+                        "event loop stalled for {}ms inside {op} (running for {}ms)",
+                        lag.as_millis(),
+                        running.as_millis()
+                    ),
+                    None => eprintln!("event loop stalled for {}ms in JavaScript", lag.as_millis()),
+                }
+
+                if capture_stack {
+                    isolate.request_interrupt(print_stack, std::ptr::null_mut());
+                }
+            }
+        });
+
+        Self { stopped }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) { self.stopped.store(true, Ordering::Relaxed); }
+}
+
+/// Terminates the isolate once it has spent `deadline` running JavaScript,
+/// keeping the stack it stopped at, unless dropped first.
+pub struct Deadline {
+    _cancel: mpsc::Sender<()>,
+    state: Arc<DeadlineState>,
+}
+
+#[derive(Default)]
+struct DeadlineState {
+    /// Set once the deadline is dropped, for an interrupt already requested.
+    dropped: AtomicBool,
+    stack: Mutex<Option<Vec<String>>>,
+}
+
+impl Deadline {
+    pub fn spawn(deadline: Duration, runtime: &mut JsRuntime) -> Self {
+        CONTEXT.set(Some(runtime.main_context()));
+        let isolate = runtime.v8_isolate().thread_safe_handle();
+        let (cancel, cancelled) = mpsc::channel();
+        let state = Arc::new(DeadlineState::default());
+
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            if cancelled.recv_timeout(deadline) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return;
+            }
+
+            // Terminating from here would win over the interrupt, so the
+            // interrupt terminates once it has the stack.
+            let data = Arc::into_raw(shared) as *mut c_void;
+            if !isolate.request_interrupt(terminate_with_stack, data) {
+                drop(unsafe { Arc::from_raw(data as *const DeadlineState) });
+            }
+        });
+
+        Self { _cancel: cancel, state }
+    }
+
+    /// The frames JavaScript was terminated in, `None` if it wasn't.
+    pub fn stack(&self) -> Option<Vec<String>> { self.state.stack.lock().unwrap().clone() }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) { self.state.dropped.store(true, Ordering::Relaxed); }
+}
+
+/// The current JavaScript stack, innermost frame first.
+fn stack_frames(scope: &mut v8::HandleScope) -> Vec<String> {
+    let Some(trace) = v8::StackTrace::current_stack_trace(scope, 16) else {
+        return Vec::new();
+    };
+
+    let mut frames = Vec::new();
+    for index in 0..trace.get_frame_count() {
+        let Some(frame) = trace.get_frame(scope, index) else {
+            continue;
+        };
+
+        let function = frame
+            .get_function_name(scope)
+            .map(|name| name.to_rust_string_lossy(scope))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "<anonymous>".into());
+        let script = frame
+            .get_script_name(scope)
+            .map(|name| name.to_rust_string_lossy(scope))
+            .unwrap_or_default();
+
+        frames.push(format!(
+            "at {function} ({script}:{}:{})",
+            frame.get_line_number(),
+            frame.get_column()
+        ));
+    }
+    frames
+}
+
+/// The stack of the isolate an interrupt runs on, read in its main context.
+fn interrupted_stack(isolate: &mut v8::Isolate) -> Vec<String> {
+    let scope = &mut unsafe { v8::CallbackScope::new(isolate) };
+    let scope = &mut v8::HandleScope::new(scope);
+    let Some(context) = CONTEXT.with_borrow(|context| context.as_ref().map(|context| v8::Local::new(scope, context)))
+    else {
+        return Vec::new();
+    };
+
+    let scope = &mut v8::ContextScope::new(scope, context);
+    stack_frames(scope)
+}
+
+unsafe extern "C" fn print_stack(isolate: &mut v8::Isolate, _data: *mut c_void) {
+    let frames = interrupted_stack(isolate);
+    if frames.is_empty() {
+        return;
+    }
+
+    eprintln!("stalled JavaScript stack:");
+    for frame in frames {
+        eprintln!("    {frame}");
+    }
+}
+
+unsafe extern "C" fn terminate_with_stack(isolate: &mut v8::Isolate, data: *mut c_void) {
+    let state = unsafe { Arc::from_raw(data as *const DeadlineState) };
+    if state.dropped.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let frames = interrupted_stack(isolate);
+    *state.stack.lock().unwrap() = Some(frames);
+    isolate.terminate_execution();
+}
@@ -2,4 +2,6 @@ if (typeof globalThis.MASS === 'undefined' || !globalThis.MASS._init) {
   throw new ReferenceError('MASS not initialized from snapshot');
 }
 
+MASS.net.intercept();
+
 console.log('MASS initialized from snapshot');
@@ -532,11 +532,11 @@ mcp.registerTool(
       const treeOutput = await getRepositoryTree(tempDir);
 
       // Analyze repository using Rust operations
-      const analysis = await MASS.ops.op_analyze_repository(tempDir);
+      const analysis = await MASS.analysis.analyze(tempDir);
 
       // Use LLM-based intelligent file selection by calling the Python agent
       let selectedFiles = [];
-      let importantFiles = '';
+      let importantFiles = [];
 
       try {
         // First get file selection from LLM
@@ -583,7 +583,7 @@ mcp.registerTool(
 
         // If LLM selection succeeded, get the actual file contents using the new Rust function
         if (selectedFiles.length > 0) {
-          importantFiles = await MASS.ops.op_get_important_files(tempDir, selectedFiles);
+          importantFiles = await MASS.analysis.importantFiles(tempDir, selectedFiles);
         }
       } catch (error) {
         console.error('LLM file selection failed:', error);
@@ -599,7 +599,7 @@ mcp.registerTool(
         tempDir,
         treeStructure: treeOutput,
         analysis,
-        importantFiles: importantFiles.split('\n---FILE_SEPARATOR---\n').filter(f => f.trim()),
+        importantFiles,
         storedAt: new Date().toISOString(),
         workspacePath: tempDir,
       });
@@ -639,8 +639,11 @@ mcp.registerTool(
                 analysis: {
                   file_count: analysis.file_count,
                   languages: analysis.languages,
+                  primary_language: analysis.primary_language,
+                  language_breakdown: analysis.language_breakdown,
                   config_files: analysis.config_files,
                   size_bytes: analysis.size_bytes,
+                  truncated: analysis.truncated,
                   frameworks: analysis.frameworks || [],
                 },
                 generatedTools:
@@ -712,7 +715,7 @@ mcp.registerTool(
         const treeOutput = await getRepositoryTree(tempDir);
 
         // Analyze repository using Rust operations
-        const analysis = await MASS.ops.op_analyze_repository(tempDir);
+        const analysis = await MASS.analysis.analyze(tempDir);
 
         // Read all files into memory for compatibility
         const files = await readDirectoryFiles(tempDir);
@@ -897,6 +900,11 @@ mcp.registerTool(
   },
 );
 
+// The agent takes files as `path:content` strings, with a marker in place of
+// content the op skipped.
+const agentFileEntry = file =>
+  `${file.path}:${file.content ?? `[${file.skipped === 'binary' ? 'Binary file' : 'File too large'}: ${file.size} bytes]`}`;
+
 // Call Intelligent OpenAI Agent for comprehensive analysis and MCP generation
 async function callIntelligentAgent(repoId) {
   const repo = repositories.get(repoId);
@@ -909,7 +917,7 @@ async function callIntelligentAgent(repoId) {
     description: repo.description,
     tree_structure: repo.treeStructure,
     analysis: repo.analysis,
-    important_files: repo.importantFiles.slice(0, 15), // More files for better context
+    important_files: repo.importantFiles.slice(0, 15).map(agentFileEntry), // More files for better context
     workspace_path: repo.workspacePath,
     // OpenAI configuration - using Groq for fast inference
     openai_api_key:
@@ -1611,5 +1619,5 @@ MASS.app.all('/socket', async c => {
   return transport.handleRequest(c);
 });
 
-console.log(`Started server on port ${MASS.config.port()}`);
-Deno.serve({ port: MASS.config.port() }, MASS.app.fetch);
+console.log(`Started server on ${MASS.config.address()}`);
+Deno.serve(MASS.config.listen(), MASS.context.serve(MASS.app.fetch));
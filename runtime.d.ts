@@ -1,16 +1,102 @@
-interface OPS_CONFIG {
-  port(): number;
-  version(): string;
+/// <reference path="./mass/runtime/ops.d.ts" />
+
+// The members of `MASS` that are ops are generated into mass/runtime/ops.d.ts
+// by the build; those that entry.js implements in JS are declared here, into
+// the same interfaces.
+
+interface OPS_WS_HANDLERS {
+  protocol?: string;
+  idleTimeout?: number;
+  onOpen?(event: Event, socket: WebSocket): void;
+  onMessage?(event: MessageEvent, socket: WebSocket): void;
+  onClose?(event: CloseEvent, socket: WebSocket): void;
+  onError?(event: Event, socket: WebSocket): void;
 }
 
-interface OPS_MASS {
-  _init: boolean;
-  pid(): number;
-  config: OPS_CONFIG;
+interface OPS_CONTEXT_OPTIONS {
+  /** Works out the tenant of a request, e.g. from a verified session. */
+  tenantId?: (request: Request) => string | null;
 }
 
 declare global {
+  interface OPS_MASS {
+    _init: boolean;
+    /** Bundles `entry` to ESM with esbuild, like `mass bundle` without writing anything. */
+    bundle(entry: string, options?: MassOps.bundle.BundleOptions): Promise<MassOps.bundle.Bundle>;
+    /**
+     * Streams the URL to `dest`, yielding progress as it goes and `finished` last; a failed download throws a
+     * `DownloadError`. With `resume`, an interrupted download to `dest` carries on with a range request, or starts
+     * over if the file changed on the server since. A file that doesn't match `sha256` or grows past `maxBytes` is
+     * thrown away. Breaking out of the loop cancels it.
+     */
+    download(
+      url: string,
+      dest: string,
+      options?: MassOps.download.DownloadOptions,
+    ): AsyncGenerator<MassOps.download.DownloadEvent>;
+  }
+
+  interface OPS_MASS_CONFIG {
+    port(): number;
+    address(): string;
+    version(): string;
+  }
+
+  interface OPS_MASS_WS {
+    upgrade(request: Request, handlers?: OPS_WS_HANDLERS): Response;
+    count(): number;
+  }
+
+  interface OPS_MASS_STATIC {
+    /** Answers `request` from the files under `root`, after stripping `prefix` from its path. */
+    serve(request: Request, root: string, options?: { prefix?: string }): Response;
+  }
+
+  interface OPS_MASS_ANALYSIS {
+    /** Analyzes local paths or git URLs in the background, yielding progress as it happens. */
+    batch(sources: string[], options?: MassOps.batch.BatchOptions): AsyncGenerator<MassOps.batch.BatchEvent>;
+  }
+
+  interface OPS_MASS_REDIS {
+    subscribe(...channels: string[]): AsyncIterable<{ channel: string; message: string }>;
+  }
+
+  interface OPS_MASS_CONTEXT {
+    /** The context of the request being handled, `undefined` outside of one. */
+    get(): MassOps.context.RequestContext | undefined;
+    /** Runs `fn` as part of the request `context` belongs to, attributing the log and audit records of the ops it calls to it. */
+    run<T>(context: MassOps.context.RequestContext, fn: () => T | Promise<T>): Promise<T>;
+    /**
+     * The context the front proxy attached to `request`, with a fresh request id when it has none. The tenant is
+     * `options.tenantId(request)` when given, and otherwise only taken from `x-tenant-id` behind the front proxy.
+     */
+    fromRequest(request: Request, options?: OPS_CONTEXT_OPTIONS): MassOps.context.RequestContext;
+    /** `handler` running every request in its own context. */
+    serve<A extends unknown[]>(
+      handler: (request: Request, ...args: A) => Response | Promise<Response>,
+      options?: OPS_CONTEXT_OPTIONS,
+    ): (request: Request, ...args: A) => Promise<Response>;
+  }
+
+  interface OPS_MASS_COMPRESSION {
+    compress(data: Uint8Array | string, format: MassOps.compress.Format, options?: MassOps.compress.CodecOptions): Uint8Array;
+    decompress(data: Uint8Array, format: MassOps.compress.Format, options?: MassOps.compress.CodecOptions): Uint8Array;
+    /** Compresses chunk by chunk, e.g. `file.readable.pipeThrough(MASS.compression.compressStream('zstd'))`. */
+    compressStream(
+      format: MassOps.compress.Format,
+      options?: MassOps.compress.CodecOptions,
+    ): TransformStream<Uint8Array | string, Uint8Array>;
+    decompressStream(
+      format: MassOps.compress.Format,
+      options?: MassOps.compress.CodecOptions,
+    ): TransformStream<Uint8Array, Uint8Array>;
+  }
+
   const MASS: OPS_MASS;
+  /** Crate version of the mass build that bundled the server. */
+  const MASS_BUILD_VERSION: string;
+  /** Git commit the server was bundled from, when built inside a checkout. */
+  const MASS_GIT_SHA: string | undefined;
 }
 
 export {};